@@ -9,8 +9,18 @@ use fyrox::{
     scene::{node::Node, Scene},
 };
 
+use crate::error::GameError;
+
 use std::sync::Arc;
 
+// Short losses of ground contact (bumps, steps) shouldn't switch to the falling state
+const AIRBORNE_DELAY: f32 = 0.2;
+
+const WALK_PATH: &str = "data/animations/walk_forward.fbx";
+const IDLE_PATH: &str = "data/animations/idle.fbx";
+const SHOOT_PATH: &str = "data/animations/shoot.fbx";
+const JUMP_PATH: &str = "data/animations/jump.fbx";
+
 // Simple helper method to create a state supplied with PlayAnimation node.
 fn create_play_animation_state(
     animation_resource: Model,
@@ -32,6 +42,33 @@ fn create_play_animation_state(
     (animation, state)
 }
 
+// Animation clips every player is retargeted from, loaded once per level
+pub struct PlayerAnimations {
+    walk: Model,
+    idle: Model,
+    shoot: Model,
+    jump: Model,
+}
+
+impl PlayerAnimations {
+    pub async fn load(resource_manager: ResourceManager) -> Result<Self, GameError> {
+        // Load animations in parallel.
+        let (walk, idle, shoot, jump) = fyrox::core::futures::join!(
+            resource_manager.request_model(WALK_PATH),
+            resource_manager.request_model(IDLE_PATH),
+            resource_manager.request_model(SHOOT_PATH),
+            resource_manager.request_model(JUMP_PATH),
+        );
+
+        Ok(Self {
+            walk: walk.map_err(|_| GameError::resource(WALK_PATH))?,
+            idle: idle.map_err(|_| GameError::resource(IDLE_PATH))?,
+            shoot: shoot.map_err(|_| GameError::resource(SHOOT_PATH))?,
+            jump: jump.map_err(|_| GameError::resource(JUMP_PATH))?,
+        })
+    }
+}
+
 #[derive(Copy, Clone, Default)]
 pub struct PlayerAnimationMachineInput {
     pub walk_forward: bool,
@@ -39,11 +76,16 @@ pub struct PlayerAnimationMachineInput {
     pub jump: bool,
     pub fly: bool,
     pub on_ground: bool,
+    pub reload: bool,
+    pub crouch: bool,
+    pub sprint: bool,
 }
 
 pub struct PlayerAnimationMachine {
     machine: Machine,
     pub jump_animation: Handle<Animation>,
+    walk_animation: Handle<Animation>,
+    air_time: f32,
 }
 
 impl PlayerAnimationMachine {
@@ -70,42 +112,65 @@ impl PlayerAnimationMachine {
     const SHOOT_TO_JUMP: &'static str = "Shoot->Jump";
 
     const JUMP_TO_IDLE: &'static str = "Jump->Idle";
+    const JUMP_TO_FALL: &'static str = "Jump->Fall";
+
+    // Shared by every state that can take off or land
+    const TO_FLY: &'static str = "->Fly";
+    const TO_FALL: &'static str = "->Fall";
+    const LAND_TO_IDLE: &'static str = "Land->Idle";
+    const LAND_TO_WALK: &'static str = "Land->Walk";
 
     // TODO: Jump, handle run and shoot together (blend upper shoot with lower run)
-    // TODO: LATER Death, reload
+    // TODO: LATER Death, reload animation (reloading currently just holds the non-shooting states)
 
-    pub async fn new(
-        scene: &mut Scene,
-        model: Handle<Node>,
-        resource_manager: ResourceManager,
-    ) -> Self {
+    pub fn new(scene: &mut Scene, model: Handle<Node>, animations: &PlayerAnimations) -> Self {
         let mut machine = Machine::new();
 
-        // Load animations in parallel.
-        let (walk_resource, idle_resource, shoot_resource, jump_resource) = fyrox::core::futures::join!(
-            resource_manager.request_model("data/animations/walk_forward.fbx"),
-            resource_manager.request_model("data/animations/idle.fbx"),
-            resource_manager.request_model("data/animations/shoot.fbx"),
-            resource_manager.request_model("data/animations/jump.fbx"),
-        );
-
         // Now create three states with different animations.
-        let (_, idle_state) =
-            create_play_animation_state(idle_resource.unwrap(), "Idle", &mut machine, scene, model);
+        let (_, idle_state) = create_play_animation_state(
+            animations.idle.clone(),
+            "Idle",
+            &mut machine,
+            scene,
+            model,
+        );
 
-        let (walk_animation, walk_state) =
-            create_play_animation_state(walk_resource.unwrap(), "Walk", &mut machine, scene, model);
+        let (walk_animation, walk_state) = create_play_animation_state(
+            animations.walk.clone(),
+            "Walk",
+            &mut machine,
+            scene,
+            model,
+        );
 
         let (shoot_animation, shoot_state) = create_play_animation_state(
-            shoot_resource.unwrap(),
+            animations.shoot.clone(),
             "Shoot",
             &mut machine,
             scene,
             model,
         );
 
-        let (jump_animation, jump_state) =
-            create_play_animation_state(jump_resource.unwrap(), "Jump", &mut machine, scene, model);
+        let (jump_animation, jump_state) = create_play_animation_state(
+            animations.jump.clone(),
+            "Jump",
+            &mut machine,
+            scene,
+            model,
+        );
+
+        // There are no dedicated airborne animations yet. Falling holds the end of the jump and
+        // flying plays the idle animation slowed down.
+        let (fall_animation, fall_state) = create_play_animation_state(
+            animations.jump.clone(),
+            "Fall",
+            &mut machine,
+            scene,
+            model,
+        );
+
+        let (fly_animation, fly_state) =
+            create_play_animation_state(animations.idle.clone(), "Fly", &mut machine, scene, model);
 
         scene.animations.get_mut(shoot_animation).set_speed(4.0);
         scene.animations.get_mut(walk_animation).set_speed(2.0);
@@ -114,6 +179,60 @@ impl PlayerAnimationMachine {
             .get_mut(jump_animation)
             .set_enabled(false)
             .set_loop(false);
+        scene.animations.get_mut(fall_animation).set_loop(false);
+        scene.animations.get_mut(fly_animation).set_speed(0.5);
+
+        // Airborne transitions are added first so they take priority over the grounded ones
+        for (name, source) in [
+            ("Idle->Fly", idle_state),
+            ("Walk->Fly", walk_state),
+            ("Shoot->Fly", shoot_state),
+            ("Jump->Fly", jump_state),
+            ("Fall->Fly", fall_state),
+        ] {
+            machine.add_transition(Transition::new(name, source, fly_state, 0.3, Self::TO_FLY));
+        }
+        for (name, source) in [
+            ("Idle->Fall", idle_state),
+            ("Walk->Fall", walk_state),
+            ("Shoot->Fall", shoot_state),
+            ("Fly->Fall", fly_state),
+        ] {
+            machine.add_transition(Transition::new(
+                name,
+                source,
+                fall_state,
+                0.3,
+                Self::TO_FALL,
+            ));
+        }
+        machine.add_transition(Transition::new(
+            "Jump->Fall",
+            jump_state,
+            fall_state,
+            0.2,
+            Self::JUMP_TO_FALL,
+        ));
+
+        // Landing
+        for (name, source) in [("Fall->Idle", fall_state), ("Fly->Idle", fly_state)] {
+            machine.add_transition(Transition::new(
+                name,
+                source,
+                idle_state,
+                0.2,
+                Self::LAND_TO_IDLE,
+            ));
+        }
+        for (name, source) in [("Fall->Walk", fall_state), ("Fly->Walk", fly_state)] {
+            machine.add_transition(Transition::new(
+                name,
+                source,
+                walk_state,
+                0.2,
+                Self::LAND_TO_WALK,
+            ));
+        }
 
         // // Next, define transitions between states.
         machine.add_transition(Transition::new(
@@ -194,39 +313,86 @@ impl PlayerAnimationMachine {
         Self {
             machine,
             jump_animation,
+            walk_animation,
+            air_time: 0.0,
         }
     }
 
+    // How far through its cycle the walk animation is, from 0 to 1. It keeps playing in the
+    // background when the player isn't walking.
+    pub fn walk_phase(&self, scene: &Scene) -> f32 {
+        let animation = scene.animations.get(self.walk_animation);
+        animation.get_time_position() / animation.length().max(f32::EPSILON)
+    }
+
     pub fn update(&mut self, scene: &mut Scene, dt: f32, input: PlayerAnimationMachineInput) {
+        // Walk cycle plays faster when sprinting and slower when crouched
+        let walk_speed = if input.crouch {
+            1.2
+        } else if input.sprint {
+            3.2
+        } else {
+            2.0
+        };
+        scene
+            .animations
+            .get_mut(self.walk_animation)
+            .set_speed(walk_speed);
+
+        if input.on_ground {
+            self.air_time = 0.0;
+        } else {
+            self.air_time += dt;
+        }
+        let airborne = self.air_time > AIRBORNE_DELAY;
+        let jump_ended = scene.animations.get(self.jump_animation).has_ended();
+        let landed = input.on_ground && !input.fly;
+
         self.machine
+            .set_parameter(Self::TO_FLY, Parameter::Rule(input.fly))
+            .set_parameter(Self::TO_FALL, Parameter::Rule(airborne && !input.fly))
+            .set_parameter(
+                Self::JUMP_TO_FALL,
+                Parameter::Rule(airborne && !input.fly && jump_ended),
+            )
+            .set_parameter(
+                Self::LAND_TO_IDLE,
+                Parameter::Rule(landed && !input.walk_forward),
+            )
+            .set_parameter(
+                Self::LAND_TO_WALK,
+                Parameter::Rule(landed && input.walk_forward),
+            )
             .set_parameter(
                 Self::IDLE_TO_WALK_FORWARD,
                 Parameter::Rule(input.walk_forward && input.on_ground),
             )
-            .set_parameter(Self::IDLE_TO_SHOOT, Parameter::Rule(input.shoot))
+            .set_parameter(
+                Self::IDLE_TO_SHOOT,
+                Parameter::Rule(input.shoot && !input.reload),
+            )
             .set_parameter(Self::IDLE_TO_JUMP, Parameter::Rule(input.jump))
             .set_parameter(Self::WALK_FORWARD_TO_JUMP, Parameter::Rule(input.jump))
             // Set transition parameters.
             .set_parameter(
                 Self::WALK_FORWARD_TO_IDLE,
-                Parameter::Rule(!input.walk_forward || input.fly),
+                Parameter::Rule(!input.walk_forward),
+            )
+            .set_parameter(
+                Self::WALK_FORWARD_TO_SHOOT,
+                Parameter::Rule(input.shoot && !input.reload),
             )
-            .set_parameter(Self::WALK_FORWARD_TO_SHOOT, Parameter::Rule(input.shoot))
             .set_parameter(
                 Self::SHOOT_TO_IDLE,
-                Parameter::Rule(!input.shoot && !input.walk_forward),
+                Parameter::Rule((!input.shoot || input.reload) && !input.walk_forward),
             )
             .set_parameter(
                 Self::SHOOT_TO_WALK_FORWARD,
-                Parameter::Rule(!input.shoot && input.walk_forward),
+                Parameter::Rule((!input.shoot || input.reload) && input.walk_forward),
             )
-            // TODO: Add fall/fly animation
             .set_parameter(
                 Self::JUMP_TO_IDLE,
-                Parameter::Rule(
-                    (!input.jump && input.on_ground)
-                        || scene.animations.get(self.jump_animation).has_ended(),
-                ),
+                Parameter::Rule(input.on_ground && !input.jump && jump_ended),
             )
             // Update machine and evaluate final pose.
             .evaluate_pose(&scene.animations, dt)
@@ -0,0 +1,89 @@
+// Addresses kept off the server, saved to BAN_LIST_PATH whenever the list changes so bans outlive
+// restarts. Bans go by IP address alone, a banned player coming back from another port is still
+// banned. Session tokens only last a connection so there's nothing else to tell players apart by.
+
+use std::{error::Error, fs::File, io::BufReader, net::IpAddr, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+pub const BAN_LIST_PATH: &str = "bans.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    pub address: IpAddr,
+    pub name: String, // What the player went by when banned, empty if they weren't connected
+}
+
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    // A missing file is an empty list, anything else wrong with it is logged and ignored
+    pub fn load() -> Self {
+        if !Path::new(BAN_LIST_PATH).exists() {
+            return Self::default();
+        }
+
+        match read_bans(BAN_LIST_PATH) {
+            Ok(bans) => Self { bans },
+            Err(e) => {
+                warn!("failed to read {}: {}", BAN_LIST_PATH, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn is_banned(&self, address: IpAddr) -> bool {
+        self.bans.iter().any(|ban| ban.address == address)
+    }
+
+    pub fn bans(&self) -> &[Ban] {
+        &self.bans
+    }
+
+    // Returns false if the address was already banned
+    pub fn ban(&mut self, address: IpAddr, name: &str) -> bool {
+        if self.is_banned(address) {
+            return false;
+        }
+
+        self.bans.push(Ban {
+            address,
+            name: name.to_string(),
+        });
+        self.save();
+        true
+    }
+
+    // Returns false if the address wasn't banned
+    pub fn unban(&mut self, address: IpAddr) -> bool {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.address != address);
+        if self.bans.len() == count {
+            return false;
+        }
+
+        self.save();
+        true
+    }
+
+    fn save(&self) {
+        if let Err(e) = write_bans(BAN_LIST_PATH, &self.bans) {
+            warn!("failed to save {}: {}", BAN_LIST_PATH, e);
+        }
+    }
+}
+
+fn read_bans<P: AsRef<Path>>(path: P) -> Result<Vec<Ban>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+fn write_bans<P: AsRef<Path>>(path: P, bans: &[Ban]) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, bans)?;
+    Ok(())
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{collider::InteractionGroups, graph::Graph, node::Node},
+};
+use serde::{Deserialize, Serialize};
+
+// Blocks are placed on a grid of unit cubes
+pub const BLOCK_SIZE: f32 = 1.0;
+// Blocks are stored in cubic chunks of this many blocks per side
+const CHUNK_SIZE: i32 = 16;
+const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+// Hits it takes to break a block that isn't given a type in the level manifest
+const DEFAULT_BLOCK_HEALTH: u32 = 2;
+// Looks of a block that's been hit, the last stage is one hit from breaking
+pub const CRACK_STAGES: u32 = 3;
+
+const NEIGHBOUR_OFFSETS: [Vector3<i32>; 6] = [
+    Vector3::new(1, 0, 0),
+    Vector3::new(-1, 0, 0),
+    Vector3::new(0, 1, 0),
+    Vector3::new(0, -1, 0),
+    Vector3::new(0, 0, 1),
+    Vector3::new(0, 0, -1),
+];
+
+// Hits each type of block takes to break, a block's type is its tag. Clients use it to guess
+// when their own shots will break a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BlockHealth {
+    pub default: u32,
+    pub types: HashMap<String, u32>,
+}
+
+impl Default for BlockHealth {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_BLOCK_HEALTH,
+            types: HashMap::new(),
+        }
+    }
+}
+
+impl BlockHealth {
+    pub fn of(&self, tag: &str) -> u32 {
+        self.types.get(tag).copied().unwrap_or(self.default).max(1)
+    }
+}
+
+// Rounded up so the first hit always shows
+pub fn crack_stage(hits: u32, health: u32) -> u32 {
+    let health = health.max(1);
+    ((hits * CRACK_STAGES + health - 1) / health).clamp(1, CRACK_STAGES)
+}
+
+struct Chunk {
+    blocks: Vec<Handle<Node>>,
+    count: usize,
+}
+
+impl Chunk {
+    fn new() -> Self {
+        Self {
+            blocks: vec![Handle::NONE; CHUNK_VOLUME],
+            count: 0,
+        }
+    }
+}
+
+// Sparse block grid, only chunks that contain blocks are allocated so levels can be any size
+#[derive(Default)]
+pub struct BlockStore {
+    chunks: HashMap<Vector3<i32>, Chunk>,
+    count: usize,
+}
+
+impl BlockStore {
+    pub fn coord_for_position(position: Vector3<f32>) -> Vector3<i32> {
+        Vector3::new(
+            (position.x / BLOCK_SIZE).round() as i32,
+            (position.y / BLOCK_SIZE).round() as i32,
+            (position.z / BLOCK_SIZE).round() as i32,
+        )
+    }
+
+    pub fn position_for_coord(coord: Vector3<i32>) -> Vector3<f32> {
+        coord.map(|c| c as f32 * BLOCK_SIZE)
+    }
+
+    // Chunk key and index of the block inside that chunk
+    fn locate(coord: Vector3<i32>) -> (Vector3<i32>, usize) {
+        let chunk = coord.map(|c| c.div_euclid(CHUNK_SIZE));
+        let local = coord.map(|c| c.rem_euclid(CHUNK_SIZE));
+        let index = (local.x + local.y * CHUNK_SIZE + local.z * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+        (chunk, index)
+    }
+
+    pub fn get(&self, coord: Vector3<i32>) -> Handle<Node> {
+        let (chunk, index) = Self::locate(coord);
+        self.chunks
+            .get(&chunk)
+            .map_or(Handle::NONE, |chunk| chunk.blocks[index])
+    }
+
+    pub fn contains(&self, coord: Vector3<i32>) -> bool {
+        self.get(coord).is_some()
+    }
+
+    // Returns the block that was previously at the coordinate
+    pub fn insert(&mut self, coord: Vector3<i32>, handle: Handle<Node>) -> Handle<Node> {
+        if handle.is_none() {
+            return self.remove(coord);
+        }
+
+        let (chunk, index) = Self::locate(coord);
+        let chunk = self.chunks.entry(chunk).or_insert_with(Chunk::new);
+        let previous = std::mem::replace(&mut chunk.blocks[index], handle);
+
+        if previous.is_none() {
+            chunk.count += 1;
+            self.count += 1;
+        }
+
+        previous
+    }
+
+    pub fn remove(&mut self, coord: Vector3<i32>) -> Handle<Node> {
+        let (key, index) = Self::locate(coord);
+
+        let chunk = match self.chunks.get_mut(&key) {
+            Some(chunk) => chunk,
+            None => return Handle::NONE,
+        };
+
+        let previous = std::mem::replace(&mut chunk.blocks[index], Handle::NONE);
+        if previous.is_some() {
+            chunk.count -= 1;
+            self.count -= 1;
+
+            // Free chunks once they are empty
+            if chunk.count == 0 {
+                self.chunks.remove(&key);
+            }
+        }
+
+        previous
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.count = 0;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Vector3<i32>, Handle<Node>)> + '_ {
+        self.chunks.iter().flat_map(|(key, chunk)| {
+            chunk
+                .blocks
+                .iter()
+                .enumerate()
+                .filter(|(_, handle)| handle.is_some())
+                .map(move |(index, &handle)| {
+                    let index = index as i32;
+                    let local = Vector3::new(
+                        index % CHUNK_SIZE,
+                        (index / CHUNK_SIZE) % CHUNK_SIZE,
+                        index / (CHUNK_SIZE * CHUNK_SIZE),
+                    );
+                    (key * CHUNK_SIZE + local, handle)
+                })
+        })
+    }
+
+    // Blocks on the outside edges of the grid seen from above, at any height
+    #[cfg(feature = "server")]
+    pub fn outer_ring(&self) -> Vec<Handle<Node>> {
+        let mut min = Vector3::repeat(i32::MAX);
+        let mut max = Vector3::repeat(i32::MIN);
+        for (coord, _) in self.iter() {
+            min = min.inf(&coord);
+            max = max.sup(&coord);
+        }
+
+        self.iter()
+            .filter(|(coord, _)| {
+                coord.x == min.x || coord.x == max.x || coord.z == min.z || coord.z == max.z
+            })
+            .map(|(_, handle)| handle)
+            .collect()
+    }
+
+    pub fn neighbours(coord: Vector3<i32>) -> impl Iterator<Item = Vector3<i32>> {
+        NEIGHBOUR_OFFSETS.iter().map(move |offset| coord + offset)
+    }
+
+    // A block with all six sides covered can't be seen from anywhere
+    pub fn is_enclosed(&self, coord: Vector3<i32>) -> bool {
+        Self::neighbours(coord).all(|neighbour| self.contains(neighbour))
+    }
+
+    // Blocks whose centre is within the radius of the given position
+    pub fn within_radius(
+        &self,
+        center: Vector3<f32>,
+        radius: f32,
+    ) -> Vec<(Vector3<i32>, Handle<Node>)> {
+        let min = Self::coord_for_position(center.add_scalar(-radius));
+        let max = Self::coord_for_position(center.add_scalar(radius));
+
+        let mut blocks = Vec::new();
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let coord = Vector3::new(x, y, z);
+                    let handle = self.get(coord);
+
+                    if handle.is_some()
+                        && (Self::position_for_coord(coord) - center).norm() <= radius
+                    {
+                        blocks.push((coord, handle));
+                    }
+                }
+            }
+        }
+
+        blocks
+    }
+}
+
+// Blocks with all six sides covered can't be seen or touched, so they're hidden and taken out of
+// collision checks until one of their neighbours is destroyed
+#[derive(Default)]
+pub struct HiddenBlocks {
+    // Collision groups the colliders of each hidden block had, put back when it's uncovered
+    blocks: HashMap<Handle<Node>, Vec<(Handle<Node>, InteractionGroups)>>,
+    // Blocks the client expects the server to destroy, they stay hidden whatever happens around
+    // them until the prediction is confirmed or given up on
+    #[cfg(not(feature = "server"))]
+    predicted: std::collections::HashSet<Handle<Node>>,
+}
+
+impl HiddenBlocks {
+    // Culling pass over the whole store, done once when the level loads
+    pub fn cull(&mut self, store: &BlockStore, graph: &mut Graph) {
+        for (coord, handle) in store.iter() {
+            if store.is_enclosed(coord) {
+                self.hide(handle, graph);
+            }
+        }
+    }
+
+    fn hide(&mut self, handle: Handle<Node>, graph: &mut Graph) {
+        if self.blocks.contains_key(&handle) {
+            return;
+        }
+
+        let colliders: Vec<Handle<Node>> = graph[handle]
+            .children()
+            .iter()
+            .copied()
+            .filter(|&child| graph[child].is_collider())
+            .collect();
+
+        let mut groups = Vec::new();
+        for collider in colliders {
+            let collider_node = graph[collider].as_collider_mut();
+            groups.push((collider, collider_node.collision_groups()));
+            // No memberships means nothing collides with it and rays pass it by
+            collider_node.set_collision_groups(InteractionGroups::new(0, 0));
+        }
+
+        graph[handle].set_visibility(false);
+        self.blocks.insert(handle, groups);
+    }
+
+    fn reveal(&mut self, handle: Handle<Node>, graph: &mut Graph) {
+        #[cfg(not(feature = "server"))]
+        if self.predicted.contains(&handle) {
+            return;
+        }
+
+        if let Some(groups) = self.blocks.remove(&handle) {
+            for (collider, groups) in groups {
+                graph[collider]
+                    .as_collider_mut()
+                    .set_collision_groups(groups);
+            }
+
+            graph[handle].set_visibility(true);
+        }
+    }
+
+    // Call after a block has been taken out of the store, uncovers the blocks that were buried
+    // behind it
+    pub fn block_removed(
+        &mut self,
+        coord: Vector3<i32>,
+        handle: Handle<Node>,
+        store: &BlockStore,
+        graph: &mut Graph,
+    ) {
+        self.blocks.remove(&handle);
+        #[cfg(not(feature = "server"))]
+        self.predicted.remove(&handle);
+
+        self.reveal_neighbours(coord, store, graph);
+    }
+
+    // Hides a block the local player's shot is expected to destroy, as if it was already gone
+    #[cfg(not(feature = "server"))]
+    pub fn predict_removed(
+        &mut self,
+        coord: Vector3<i32>,
+        handle: Handle<Node>,
+        store: &BlockStore,
+        graph: &mut Graph,
+    ) {
+        self.hide(handle, graph);
+        self.predicted.insert(handle);
+
+        self.reveal_neighbours(coord, store, graph);
+    }
+
+    // The server didn't destroy the block after all. Neighbours that were uncovered stay that way,
+    // showing a few extra blocks doesn't hurt.
+    #[cfg(not(feature = "server"))]
+    pub fn restore(&mut self, handle: Handle<Node>, graph: &mut Graph) {
+        if self.predicted.remove(&handle) {
+            self.reveal(handle, graph);
+        }
+    }
+
+    fn reveal_neighbours(&mut self, coord: Vector3<i32>, store: &BlockStore, graph: &mut Graph) {
+        for neighbour in BlockStore::neighbours(coord) {
+            let neighbour = store.get(neighbour);
+            if neighbour.is_some() {
+                self.reveal(neighbour, graph);
+            }
+        }
+    }
+}
@@ -0,0 +1,486 @@
+// Computer controlled players for the server. Bots are players without a connection: they get a
+// player index and a score entry like everyone else and drive their player with the same events a
+// client would send, which are queued on the level and relayed to clients. Practice runs them on
+// the client too, where there's nobody to relay to.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{node::Node, Scene},
+};
+
+use crate::{
+    collision,
+    game::PlayerScore,
+    level::Level,
+    network_manager::{NetworkManager, NetworkMessage},
+    player::Player,
+    player_event::{PlayerEvent, SerializablePlayerState},
+    skins, GameEngine,
+};
+
+// Bots notice players this far away, and only if they can see them
+const SIGHT_RANGE: f32 = 40.0;
+// Bots try to stay between these distances from their target
+const MIN_TARGET_DISTANCE: f32 = 5.0;
+const MAX_TARGET_DISTANCE: f32 = 12.0;
+// How fast bots turn, in degrees per second
+const TURN_SPEED: f32 = 270.0;
+// Degrees off target that still counts as aiming at it
+const FIRE_CONE: f32 = 6.0;
+// Worst aim error for a bot with no accuracy at all, in degrees
+const MAX_AIM_ERROR: f32 = 10.0;
+// Seconds between bots picking a new aim error
+const AIM_ERROR_INTERVAL: f32 = 0.5;
+// Time between seeing someone and shooting at them, longer for less accurate bots
+const REACTION_TIME: f32 = 0.3;
+const STRAFE_INTERVAL: f32 = 1.5;
+const WANDER_INTERVAL: f32 = 4.0;
+// How far ahead the floor is checked for holes and how far down it may be
+const FLOOR_PROBE_DISTANCE: f32 = 1.5;
+const FLOOR_PROBE_DEPTH: f32 = 4.0;
+// Falling faster than this without ground contact turns the jetpack on
+const RECOVERY_FALL_SPEED: f32 = -3.0;
+// Movement is resent at most this often while it keeps changing, in seconds
+const INPUT_INTERVAL: f32 = 0.05;
+const MIN_LOOK_CHANGE: f32 = 0.5; // Degrees
+
+pub struct Bots {
+    bots: Vec<Bot>,
+    count: usize,
+    accuracy: f32, // 0 to 1
+    rng: Rng,
+}
+
+impl Bots {
+    pub fn new(count: u32, accuracy: f32) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |time| time.as_nanos() as u64);
+
+        Self {
+            bots: Vec::new(),
+            count: count as usize,
+            accuracy: accuracy.clamp(0.0, 1.0),
+            rng: Rng::new(seed),
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub fn spawned(&self) -> usize {
+        self.bots.len()
+    }
+
+    // Stands in for the address in the player list
+    #[cfg(feature = "server")]
+    pub fn describe(&self, index: u32) -> String {
+        if self.bots.iter().any(|bot| bot.index == index) {
+            String::from("bot")
+        } else {
+            String::from("?")
+        }
+    }
+
+    // Call once a new level is loaded. Bots keep their index and name from level to level. Clients
+    // are loading the level too at this point, they're told about bots when they join like any
+    // other player already in the level.
+    pub fn spawn(
+        &mut self,
+        engine: &GameEngine,
+        level: &Level,
+        network_manager: &mut NetworkManager,
+        scores: &mut Vec<PlayerScore>,
+    ) {
+        while self.bots.len() < self.count {
+            let index = network_manager.next_player_index();
+            self.bots.push(Bot::new(index, self.bots.len() + 1));
+        }
+
+        for bot in self.bots.iter_mut() {
+            bot.reset();
+            queue_spawn(engine, level, bot.index);
+
+            // Round wins carry over to the next level
+            let rounds = scores
+                .iter()
+                .find(|score| score.index == bot.index)
+                .map_or(0, |score| score.rounds);
+            scores.retain(|score| score.index != bot.index);
+            scores.push(PlayerScore {
+                index: bot.index,
+                name: bot.name.clone(),
+                rounds,
+                ..Default::default()
+            });
+        }
+
+        if !self.bots.is_empty() {
+            network_manager.send_to_all_reliably(&NetworkMessage::ScoreUpdate {
+                scores: scores.clone(),
+            });
+        }
+    }
+
+    // Practice has no rounds to wait for, a killed bot is put back on its own. Returns whether the
+    // player was one of the bots.
+    #[cfg(not(feature = "server"))]
+    pub fn respawn(&mut self, engine: &GameEngine, level: &Level, index: u32) -> bool {
+        match self.bots.iter_mut().find(|bot| bot.index == index) {
+            Some(bot) => {
+                bot.reset();
+                queue_spawn(engine, level, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        engine: &GameEngine,
+        level: &Level,
+        network_manager: &mut NetworkManager,
+        dt: f32,
+    ) {
+        let scene = &engine.scenes[level.scene];
+
+        for bot in self.bots.iter_mut() {
+            for event in bot.think(scene, level.players(), self.accuracy, &mut self.rng, dt) {
+                level.queue_event(event);
+
+                let message = NetworkMessage::PlayerEvent {
+                    index: bot.index,
+                    event,
+                };
+                match event {
+                    // Sent often, a lost one is replaced soon
+                    PlayerEvent::MoveAnalog { forward, right, .. }
+                        if forward != 0.0 || right != 0.0 =>
+                    {
+                        network_manager.send_to_all_unreliably(&message, 0)
+                    }
+                    _ => network_manager.send_to_all_reliably(&message),
+                }
+            }
+        }
+    }
+}
+
+// What the bot last told everyone it's doing
+#[derive(Default, Clone, Copy, PartialEq)]
+struct BotInput {
+    forward: f32,
+    right: f32,
+    yaw: f32,
+    pitch: f32,
+    shoot: bool,
+    fly: bool,
+}
+
+struct Bot {
+    index: u32,
+    name: String,
+    sent: BotInput,
+    input_timer: f32,
+    target: Option<u32>,
+    reaction_timer: f32,
+    aim_error: (f32, f32), // Yaw and pitch, degrees
+    aim_error_timer: f32,
+    strafe: f32, // 1 or -1
+    strafe_timer: f32,
+    wander_yaw: f32,
+    wander_timer: f32,
+    last_ground: Option<Vector3<f32>>, // Where it last stood, flown back to after falling off
+}
+
+impl Bot {
+    fn new(index: u32, number: usize) -> Self {
+        Self {
+            index,
+            name: format!("Bot {}", number),
+            sent: BotInput::default(),
+            input_timer: 0.0,
+            target: None,
+            reaction_timer: 0.0,
+            aim_error: (0.0, 0.0),
+            aim_error_timer: 0.0,
+            strafe: 1.0,
+            strafe_timer: 0.0,
+            wander_yaw: 0.0,
+            wander_timer: 0.0,
+            last_ground: None,
+        }
+    }
+
+    // Forget everything about the last level
+    fn reset(&mut self) {
+        *self = Self {
+            name: std::mem::take(&mut self.name),
+            ..Self::new(self.index, 0)
+        };
+    }
+
+    // Returns the events to send for this tick
+    fn think(
+        &mut self,
+        scene: &Scene,
+        players: &[Player],
+        accuracy: f32,
+        rng: &mut Rng,
+        dt: f32,
+    ) -> Vec<PlayerEvent> {
+        let me = match players.iter().find(|p| p.index == self.index) {
+            Some(me) => me,
+            None => return Vec::new(), // Dead until the next round
+        };
+
+        let position = me.get_position(scene);
+        let velocity = me.get_velocity(scene);
+        let (eye, _) = me.get_aim(scene);
+        let on_ground = me.has_ground_contact(scene);
+        if on_ground {
+            self.last_ground = Some(position);
+        }
+
+        self.input_timer -= dt;
+        self.reaction_timer -= dt;
+        self.aim_error_timer -= dt;
+        self.strafe_timer -= dt;
+        self.wander_timer -= dt;
+
+        if self.aim_error_timer <= 0.0 {
+            self.aim_error_timer = AIM_ERROR_INTERVAL;
+            let spread = MAX_AIM_ERROR * (1.0 - accuracy);
+            self.aim_error = (rng.range(-spread, spread), rng.range(-spread, spread));
+        }
+        if self.strafe_timer <= 0.0 {
+            self.strafe_timer = STRAFE_INTERVAL * rng.range(0.5, 1.5);
+            self.strafe = if rng.range(0.0, 1.0) < 0.5 { 1.0 } else { -1.0 };
+        }
+        if self.wander_timer <= 0.0 {
+            self.wander_timer = WANDER_INTERVAL * rng.range(0.5, 1.5);
+            self.wander_yaw = rng.range(-180.0, 180.0);
+        }
+
+        // Keep the current target while it's in sight, otherwise go for the closest one in sight
+        let own_index = self.index;
+        let visible = |target: &&Player| {
+            target.index != own_index
+                && can_see(scene, eye, me.collider, target, target.get_position(scene))
+        };
+        let target = players
+            .iter()
+            .filter(|p| Some(p.index) == self.target)
+            .find(visible)
+            .or_else(|| {
+                players.iter().filter(visible).min_by(|a, b| {
+                    let a = (a.get_position(scene) - position).norm();
+                    let b = (b.get_position(scene) - position).norm();
+                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                })
+            });
+        if target.map(|t| t.index) != self.target {
+            self.target = target.map(|t| t.index);
+            self.reaction_timer = REACTION_TIME * (2.0 - accuracy);
+        }
+
+        let mut input = self.sent;
+        input.fly = false;
+        input.shoot = false;
+
+        // Where to look and which way to go, in world space
+        let mut direction;
+        match target {
+            Some(target) => {
+                let offset = target.get_position(scene) - eye;
+                let (yaw, pitch) = look_angles(offset);
+                input.yaw = turn_towards(self.sent.yaw, yaw + self.aim_error.0, TURN_SPEED * dt);
+                input.pitch = (pitch + self.aim_error.1).clamp(-89.0, 89.0);
+
+                let flat = Vector3::new(offset.x, 0.0, offset.z);
+                let distance = flat.norm();
+                let towards = flat.try_normalize(f32::EPSILON).unwrap_or_default();
+                let side = Vector3::new(towards.z, 0.0, -towards.x);
+                direction = side * self.strafe;
+                if distance > MAX_TARGET_DISTANCE {
+                    direction += towards;
+                } else if distance < MIN_TARGET_DISTANCE {
+                    direction -= towards;
+                }
+
+                let aimed =
+                    angle_difference(input.yaw, yaw).abs() < FIRE_CONE + self.aim_error.0.abs();
+                input.shoot = aimed && self.reaction_timer <= 0.0 && me.can_shoot();
+            }
+            None => {
+                input.yaw = turn_towards(self.sent.yaw, self.wander_yaw, TURN_SPEED * dt);
+                input.pitch = 0.0;
+                direction = forward_vector(self.wander_yaw);
+            }
+        }
+
+        if !on_ground && velocity.y < RECOVERY_FALL_SPEED {
+            // Fell off, head back to solid ground
+            input.fly = me.has_fuel();
+            if let Some(last_ground) = self.last_ground {
+                let back = last_ground - position;
+                direction = Vector3::new(back.x, 0.0, back.z);
+            }
+        } else if on_ground {
+            if let Some(flat) = direction.try_normalize(f32::EPSILON) {
+                if !has_floor(scene, position + flat * FLOOR_PROBE_DISTANCE) {
+                    // Hole ahead, go the other way for a while
+                    direction = -direction;
+                    self.strafe = -self.strafe;
+                    self.wander_yaw += 180.0;
+                }
+            }
+        }
+
+        // Movement is relative to where the bot is looking
+        let direction = direction.try_normalize(f32::EPSILON).unwrap_or_default();
+        let look = forward_vector(input.yaw);
+        let side = Vector3::new(look.z, 0.0, -look.x);
+        input.forward = direction.dot(&look);
+        input.right = -direction.dot(&side);
+
+        self.events(input)
+    }
+
+    fn events(&mut self, input: BotInput) -> Vec<PlayerEvent> {
+        let mut events = Vec::new();
+        let index = self.index;
+
+        let moving = input.forward != 0.0 || input.right != 0.0;
+        let was_moving = self.sent.forward != 0.0 || self.sent.right != 0.0;
+        let looked = angle_difference(input.yaw, self.sent.yaw).abs() > MIN_LOOK_CHANGE
+            || (input.pitch - self.sent.pitch).abs() > MIN_LOOK_CHANGE;
+        if (moving || was_moving || looked) && (self.input_timer <= 0.0 || (was_moving && !moving))
+        {
+            self.input_timer = INPUT_INTERVAL;
+            events.push(PlayerEvent::MoveAnalog {
+                index,
+                forward: input.forward,
+                right: input.right,
+                yaw: input.yaw,
+                pitch: input.pitch,
+            });
+            self.sent.forward = input.forward;
+            self.sent.right = input.right;
+            self.sent.yaw = input.yaw;
+            self.sent.pitch = input.pitch;
+        }
+
+        if input.shoot != self.sent.shoot {
+            events.push(PlayerEvent::ShootWeapon {
+                index,
+                active: input.shoot,
+                yaw: self.sent.yaw,
+                pitch: self.sent.pitch,
+            });
+            self.sent.shoot = input.shoot;
+        }
+
+        if input.fly != self.sent.fly {
+            events.push(PlayerEvent::Fly {
+                index,
+                active: input.fly,
+            });
+            self.sent.fly = input.fly;
+        }
+
+        events
+    }
+}
+
+fn queue_spawn(engine: &GameEngine, level: &Level, index: u32) {
+    let position = level.pick_spawn_point(&engine.scenes[level.scene], index);
+    level.queue_event(PlayerEvent::SpawnPlayer {
+        index,
+        state: SerializablePlayerState {
+            position,
+            ..Default::default()
+        },
+        current_player: false,
+        skin: skins::DEFAULT_SKIN,
+    });
+}
+
+// Whether the first thing on the way from the eye to the target is the target
+fn can_see(
+    scene: &Scene,
+    eye: Vector3<f32>,
+    own_collider: Handle<Node>,
+    target: &Player,
+    target_position: Vector3<f32>,
+) -> bool {
+    let offset = target_position - eye;
+    let distance = offset.norm();
+    if distance > SIGHT_RANGE || distance <= f32::EPSILON {
+        return false;
+    }
+
+    collision::cast_ray(
+        &scene.graph,
+        eye,
+        offset,
+        distance,
+        collision::SOLID_GROUPS,
+        own_collider,
+    )
+    .map_or(true, |i| i.collider == target.collider)
+}
+
+// Whether there's something to stand on below a point
+fn has_floor(scene: &Scene, point: Vector3<f32>) -> bool {
+    collision::cast_ray(
+        &scene.graph,
+        point + Vector3::new(0.0, 0.5, 0.0),
+        Vector3::new(0.0, -1.0, 0.0),
+        FLOOR_PROBE_DEPTH,
+        collision::GROUND_GROUPS,
+        Handle::NONE,
+    )
+    .is_some()
+}
+
+// Players look along +Z rotated by yaw around the Y axis, positive pitch looks down
+fn forward_vector(yaw: f32) -> Vector3<f32> {
+    let yaw = yaw.to_radians();
+    Vector3::new(yaw.sin(), 0.0, yaw.cos())
+}
+
+fn look_angles(direction: Vector3<f32>) -> (f32, f32) {
+    let flat = (direction.x * direction.x + direction.z * direction.z).sqrt();
+    (
+        direction.x.atan2(direction.z).to_degrees(),
+        -direction.y.atan2(flat).to_degrees(),
+    )
+}
+
+// Shortest way from one yaw to another, between -180 and 180
+fn angle_difference(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
+}
+
+fn turn_towards(from: f32, to: f32, max_step: f32) -> f32 {
+    from + angle_difference(from, to).clamp(-max_step, max_step)
+}
+
+// Xorshift, plenty for picking where a bot wanders off to
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn range(&mut self, min: f32, max: f32) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        let unit = (self.0 >> 40) as f32 / (1u64 << 24) as f32;
+        min + (max - min) * unit
+    }
+}
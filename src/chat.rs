@@ -0,0 +1,206 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use fyrox::{
+    core::{algebra::Vector2, pool::Handle},
+    gui::{
+        message::{MessageDirection, UiMessage},
+        scroll_viewer::{ScrollViewerBuilder, ScrollViewerMessage},
+        text::{TextBuilder, TextMessage},
+        text_box::TextBoxBuilder,
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, UiNode, UserInterface,
+    },
+};
+
+use crate::hud::{move_widget, Anchor};
+
+pub const MAX_CHAT_LENGTH: usize = 120;
+const CHAT_HISTORY: usize = 50;
+const CHAT_WIDTH: f32 = 500.0;
+const CHAT_LOG_HEIGHT: f32 = 150.0;
+const CHAT_INPUT_HEIGHT: f32 = 25.0;
+const KILL_FEED_HEIGHT: f32 = 250.0;
+
+// Flood protection, a player can send at most this many messages per window
+const FLOOD_MESSAGE_LIMIT: usize = 5;
+const FLOOD_WINDOW: Duration = Duration::from_secs(5);
+
+pub struct Chat {
+    log: Handle<UiNode>,
+    log_text: Handle<UiNode>,
+    input: Handle<UiNode>,
+    lines: VecDeque<String>,
+    input_text: String,
+    open: bool,
+}
+
+impl Chat {
+    pub fn new(ctx: &mut BuildContext, window_height: f32) -> Self {
+        let log_text = TextBuilder::new(WidgetBuilder::new())
+            .with_wrap(true)
+            .build(ctx);
+
+        // Placed above the kill feed textbox
+        let log = ScrollViewerBuilder::new(
+            WidgetBuilder::new()
+                .with_width(CHAT_WIDTH)
+                .with_height(CHAT_LOG_HEIGHT)
+                .with_opacity(Some(0.75))
+                .with_desired_position(Self::log_position(window_height)),
+        )
+        .with_content(log_text)
+        .build(ctx);
+
+        let input = TextBoxBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_width(CHAT_WIDTH)
+                .with_height(CHAT_INPUT_HEIGHT)
+                .with_desired_position(Self::input_position(window_height)),
+        )
+        .build(ctx);
+
+        Self {
+            log,
+            log_text,
+            input,
+            lines: VecDeque::new(),
+            input_text: String::new(),
+            open: false,
+        }
+    }
+
+    fn log_position(window_height: f32) -> Vector2<f32> {
+        Anchor::BottomLeft.position(
+            0.0,
+            window_height,
+            Vector2::new(CHAT_WIDTH, CHAT_LOG_HEIGHT),
+            Vector2::new(0.0, -KILL_FEED_HEIGHT - CHAT_INPUT_HEIGHT),
+        )
+    }
+
+    fn input_position(window_height: f32) -> Vector2<f32> {
+        Anchor::BottomLeft.position(
+            0.0,
+            window_height,
+            Vector2::new(CHAT_WIDTH, CHAT_INPUT_HEIGHT),
+            Vector2::new(0.0, -KILL_FEED_HEIGHT),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_height: f32) {
+        move_widget(ui, self.log, Self::log_position(window_height));
+        move_widget(ui, self.input, Self::input_position(window_height));
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self, ui: &UserInterface) {
+        self.open = true;
+        self.input_text.clear();
+
+        ui.send_message(TextMessage::text(
+            self.input,
+            MessageDirection::ToWidget,
+            String::new(),
+        ));
+        ui.send_message(WidgetMessage::visibility(
+            self.input,
+            MessageDirection::ToWidget,
+            true,
+        ));
+        ui.send_message(WidgetMessage::focus(self.input, MessageDirection::ToWidget));
+    }
+
+    pub fn close(&mut self, ui: &UserInterface) {
+        self.open = false;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.input,
+            MessageDirection::ToWidget,
+            false,
+        ));
+        ui.send_message(WidgetMessage::unfocus(
+            self.input,
+            MessageDirection::ToWidget,
+        ));
+    }
+
+    // Closes the input and returns the typed text if there is any
+    pub fn submit(&mut self, ui: &UserInterface) -> Option<String> {
+        self.close(ui);
+
+        let text = self.input_text.trim();
+        if text.is_empty() {
+            None
+        } else {
+            Some(text.chars().take(MAX_CHAT_LENGTH).collect())
+        }
+    }
+
+    pub fn push_line(&mut self, ui: &UserInterface, line: String) {
+        if self.lines.len() >= CHAT_HISTORY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+
+        ui.send_message(TextMessage::text(
+            self.log_text,
+            MessageDirection::ToWidget,
+            self.lines
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ));
+        // Keep newest messages in view
+        ui.send_message(ScrollViewerMessage::vertical_scroll(
+            self.log,
+            MessageDirection::ToWidget,
+            f32::MAX,
+        ));
+    }
+
+    pub fn handle_ui_message(&mut self, message: &UiMessage) {
+        if let Some(TextMessage::Text(text)) = message.data() {
+            if message.destination() == self.input
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.input_text = text.clone();
+            }
+        }
+    }
+}
+
+// Tracks recent message times for a connection on the server
+#[derive(Debug, Default)]
+pub struct FloodGuard {
+    recent: VecDeque<Instant>,
+}
+
+impl FloodGuard {
+    // Returns false if the message should be dropped
+    pub fn allow(&mut self) -> bool {
+        let now = Instant::now();
+
+        while let Some(&time) = self.recent.front() {
+            if now.duration_since(time) > FLOOD_WINDOW {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.recent.len() >= FLOOD_MESSAGE_LIMIT {
+            return false;
+        }
+
+        self.recent.push_back(now);
+        true
+    }
+}
@@ -0,0 +1,253 @@
+// Drop-down console on the client, opened with the console key. Typed commands and the lines of
+// AUTOEXEC_PATH, which is run at startup, are queued up for main to carry out since most of them
+// reach into the connection, the game or the menus.
+
+use std::collections::VecDeque;
+
+use fyrox::{
+    core::{algebra::Vector2, color::Color, pool::Handle},
+    gui::{
+        border::BorderBuilder,
+        brush::Brush,
+        message::{MessageDirection, UiMessage},
+        scroll_viewer::{ScrollViewerBuilder, ScrollViewerMessage},
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        text_box::TextBoxBuilder,
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, UiNode, UserInterface,
+    },
+};
+
+use crate::commands::{self, Command, Parsed};
+
+pub const AUTOEXEC_PATH: &str = "autoexec.cfg";
+
+const CONSOLE_HISTORY: usize = 200;
+const CONSOLE_LOG_HEIGHT: f32 = 300.0;
+const CONSOLE_INPUT_HEIGHT: f32 = 25.0;
+
+pub enum ConsoleCommand {
+    Connect(String),
+    Disconnect,
+    Sensitivity(f32),
+    Fov(f32),
+    NetGraph(bool),
+}
+
+// Numbers have to be finite, NaN would get everywhere
+fn parse_number(args: &str) -> Option<f32> {
+    args.parse().ok().filter(|value: &f32| value.is_finite())
+}
+
+const COMMANDS: &[Command<ConsoleCommand>] = &[
+    Command {
+        name: "connect",
+        args: "<address>",
+        description: "join a server, leaving the current game",
+        parse: |args| (!args.is_empty()).then(|| ConsoleCommand::Connect(args.to_string())),
+    },
+    Command {
+        name: "disconnect",
+        args: "",
+        description: "leave the game and go back to the menu",
+        parse: |_| Some(ConsoleCommand::Disconnect),
+    },
+    Command {
+        name: "sensitivity",
+        args: "<x>",
+        description: "set the mouse sensitivity",
+        parse: |args| parse_number(args).map(ConsoleCommand::Sensitivity),
+    },
+    Command {
+        name: "fov",
+        args: "<degrees>",
+        description: "set the field of view",
+        parse: |args| parse_number(args).map(ConsoleCommand::Fov),
+    },
+    Command {
+        name: "net_graph",
+        args: "<0|1>",
+        description: "hide or show ping, loss and bandwidth",
+        parse: |args| match args {
+            "0" => Some(ConsoleCommand::NetGraph(false)),
+            "1" => Some(ConsoleCommand::NetGraph(true)),
+            _ => None,
+        },
+    },
+];
+
+pub struct ClientConsole {
+    root: Handle<UiNode>,
+    log: Handle<UiNode>,
+    log_text: Handle<UiNode>,
+    input: Handle<UiNode>,
+    lines: VecDeque<String>,
+    input_text: String,
+    queued: VecDeque<String>, // Lines waiting to be run
+    open: bool,
+}
+
+impl ClientConsole {
+    pub fn new(ctx: &mut BuildContext, window_width: f32) -> Self {
+        let log_text = TextBuilder::new(WidgetBuilder::new())
+            .with_wrap(true)
+            .build(ctx);
+        let log = ScrollViewerBuilder::new(WidgetBuilder::new().with_height(CONSOLE_LOG_HEIGHT))
+            .with_content(log_text)
+            .build(ctx);
+        let input =
+            TextBoxBuilder::new(WidgetBuilder::new().with_height(CONSOLE_INPUT_HEIGHT)).build(ctx);
+
+        // Across the top of the window, over everything else
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(window_width)
+                .with_desired_position(Vector2::new(0.0, 0.0))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 220)))
+                .with_child(
+                    StackPanelBuilder::new(WidgetBuilder::new().with_children([log, input]))
+                        .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            log,
+            log_text,
+            input,
+            lines: VecDeque::new(),
+            input_text: String::new(),
+            queued: VecDeque::new(),
+            open: false,
+        }
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        ui.send_message(WidgetMessage::width(
+            self.root,
+            MessageDirection::ToWidget,
+            window_width,
+        ));
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn toggle(&mut self, ui: &UserInterface) {
+        if self.open {
+            self.close(ui);
+        } else {
+            self.open(ui);
+        }
+    }
+
+    pub fn open(&mut self, ui: &UserInterface) {
+        self.open = true;
+        self.clear_input(ui);
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            true,
+        ));
+        ui.send_message(WidgetMessage::focus(self.input, MessageDirection::ToWidget));
+    }
+
+    pub fn close(&mut self, ui: &UserInterface) {
+        self.open = false;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            false,
+        ));
+        ui.send_message(WidgetMessage::unfocus(
+            self.input,
+            MessageDirection::ToWidget,
+        ));
+    }
+
+    // Queues the typed line and stays open for the next one
+    pub fn submit(&mut self, ui: &UserInterface) {
+        // The console key lands in the input when it's used to open the console
+        let line = self
+            .input_text
+            .trim_matches(|c: char| c == '`' || c == '~' || c.is_whitespace())
+            .to_string();
+        self.clear_input(ui);
+        if line.is_empty() {
+            return;
+        }
+
+        self.print(ui, &format!("> {}", line));
+        self.queued.push_back(line);
+    }
+
+    // Run after anything typed before it, a missing script is fine
+    pub fn queue_script(&mut self, ui: &UserInterface, path: &str) {
+        match commands::read_script(path) {
+            Ok(script) => self.queued.extend(script),
+            Err(e) => self.print(ui, &format!("failed to read {}: {}", path, e)),
+        }
+    }
+
+    // Next queued command to carry out, help and mistakes are answered in the log on the way
+    pub fn next_command(&mut self, ui: &UserInterface) -> Option<ConsoleCommand> {
+        while let Some(line) = self.queued.pop_front() {
+            match commands::parse(COMMANDS, &line) {
+                Parsed::Command(command) => return Some(command),
+                Parsed::Reply(reply) => self.print(ui, &reply),
+                Parsed::Nothing => (),
+            }
+        }
+        None
+    }
+
+    pub fn print(&mut self, ui: &UserInterface, text: &str) {
+        for line in text.lines() {
+            if self.lines.len() >= CONSOLE_HISTORY {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.to_string());
+        }
+
+        ui.send_message(TextMessage::text(
+            self.log_text,
+            MessageDirection::ToWidget,
+            self.lines
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ));
+        // Keep the newest lines in view
+        ui.send_message(ScrollViewerMessage::vertical_scroll(
+            self.log,
+            MessageDirection::ToWidget,
+            f32::MAX,
+        ));
+    }
+
+    fn clear_input(&mut self, ui: &UserInterface) {
+        self.input_text.clear();
+        ui.send_message(TextMessage::text(
+            self.input,
+            MessageDirection::ToWidget,
+            String::new(),
+        ));
+    }
+
+    pub fn handle_ui_message(&mut self, message: &UiMessage) {
+        if let Some(TextMessage::Text(text)) = message.data() {
+            if message.destination() == self.input
+                && message.direction() == MessageDirection::FromWidget
+            {
+                self.input_text = text.clone();
+            }
+        }
+    }
+}
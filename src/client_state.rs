@@ -0,0 +1,36 @@
+// Where the client is, from the menu through joining a game to playing it. Input, the cursor grab
+// and the HUD all follow from the state instead of each checking the menus on their own. The game
+// works it out again every frame and main applies whatever changes when it moves to a new one.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientState {
+    Menu,       // Not in a game
+    Connecting, // Waiting for the server to let us in
+    Loading,    // Let in, the level or its state is still on the way
+    Playing,
+    Paused,     // In a game with a menu screen or the map vote up, the game goes on underneath
+    Spectating, // Dead or watching, input moves the spectator camera
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        ClientState::Menu
+    }
+}
+
+impl ClientState {
+    // Game input goes to the player or the spectator camera
+    pub fn takes_input(self) -> bool {
+        matches!(self, ClientState::Playing | ClientState::Spectating)
+    }
+
+    // The cursor is grabbed for looking around, everywhere else it's needed for clicking
+    pub fn captures_cursor(self) -> bool {
+        self.takes_input()
+    }
+
+    // Health, ammo and the crosshair are only of use with a player to control
+    pub fn shows_hud(self) -> bool {
+        self == ClientState::Playing
+    }
+}
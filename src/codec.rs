@@ -0,0 +1,130 @@
+// Serde adapters for what goes over the network, used with #[serde(with = "codec::...")].
+// Positions, velocities and view angles are sent in every event and snapshot so they're packed
+// into fixed point, anything small or needing full precision (impulses) goes through `vector`.
+
+use fyrox::core::algebra::Vector3;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Centimetres (or cm/s) in an i16, good for about 327 m either way of the origin
+const FIXED_SCALE: f32 = 100.0;
+// Angles are wrapped to -180..180 degrees and spread over the whole i16 range, about 0.005 degrees
+// a step
+const ANGLE_SCALE: f32 = 32768.0 / 180.0;
+
+// `as` saturates, so anything out of range ends up at the limit and NaN ends up as 0
+fn to_fixed(value: f32, scale: f32) -> i16 {
+    (value * scale).round() as i16
+}
+
+fn from_fixed(value: i16, scale: f32) -> f32 {
+    value as f32 / scale
+}
+
+// Full precision
+pub mod vector {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        vector: &Vector3<f32>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        (vector.x, vector.y, vector.z).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vector3<f32>, D::Error> {
+        let (x, y, z) = Deserialize::deserialize(deserializer)?;
+        Ok(Vector3::new(x, y, z))
+    }
+}
+
+// Positions and velocities, to the centimetre
+pub mod fixed_vector {
+    use super::*;
+
+    fn encode(vector: &Vector3<f32>) -> [i16; 3] {
+        [
+            to_fixed(vector.x, FIXED_SCALE),
+            to_fixed(vector.y, FIXED_SCALE),
+            to_fixed(vector.z, FIXED_SCALE),
+        ]
+    }
+
+    fn decode([x, y, z]: [i16; 3]) -> Vector3<f32> {
+        Vector3::new(
+            from_fixed(x, FIXED_SCALE),
+            from_fixed(y, FIXED_SCALE),
+            from_fixed(z, FIXED_SCALE),
+        )
+    }
+
+    pub fn serialize<S: Serializer>(
+        vector: &Vector3<f32>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        encode(vector).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vector3<f32>, D::Error> {
+        Deserialize::deserialize(deserializer).map(decode)
+    }
+
+    // For fields only sent when they changed
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            vector: &Option<Vector3<f32>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            vector.as_ref().map(encode).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Vector3<f32>>, D::Error> {
+            Ok(Option::deserialize(deserializer)?.map(decode))
+        }
+    }
+}
+
+// Yaw and pitch in degrees. Yaw comes back wrapped, which doesn't matter for anything it's used for.
+pub mod angle {
+    use super::*;
+
+    fn encode(degrees: f32) -> i16 {
+        to_fixed((degrees + 180.0).rem_euclid(360.0) - 180.0, ANGLE_SCALE)
+    }
+
+    fn decode(value: i16) -> f32 {
+        from_fixed(value, ANGLE_SCALE)
+    }
+
+    pub fn serialize<S: Serializer>(degrees: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        encode(*degrees).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        i16::deserialize(deserializer).map(decode)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            degrees: &Option<f32>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            degrees.map(encode).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<f32>, D::Error> {
+            Ok(Option::deserialize(deserializer)?.map(decode))
+        }
+    }
+}
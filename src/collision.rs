@@ -0,0 +1,93 @@
+// Collider groups, used to tell what a ray hit and to leave out things a ray doesn't care about.
+// Every collider is a member of exactly one group. Groups are bits so there's no way to single out
+// one player's capsule with them, rays that start inside the shooter skip it by handle instead.
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{
+        collider::InteractionGroups,
+        graph::{
+            physics::{Intersection, RayCastOptions},
+            Graph,
+        },
+        node::Node,
+    },
+};
+
+pub const WORLD_GROUP: u32 = 1 << 0; // Walls, floors and anything else that can't be destroyed
+pub const BLOCK_GROUP: u32 = 1 << 1;
+pub const PLAYER_GROUP: u32 = 1 << 2;
+pub const PROJECTILE_GROUP: u32 = 1 << 3;
+pub const ALL_GROUPS: u32 = u32::MAX;
+
+// What shots and line of sight checks stop at
+pub const SOLID_GROUPS: u32 = WORLD_GROUP | BLOCK_GROUP | PLAYER_GROUP;
+// What can be stood on
+pub const GROUND_GROUPS: u32 = WORLD_GROUP | BLOCK_GROUP;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SurfaceKind {
+    World,
+    Block,
+    Player,
+    Projectile,
+}
+
+impl SurfaceKind {
+    pub fn group(self) -> u32 {
+        match self {
+            SurfaceKind::World => WORLD_GROUP,
+            SurfaceKind::Block => BLOCK_GROUP,
+            SurfaceKind::Player => PLAYER_GROUP,
+            SurfaceKind::Projectile => PROJECTILE_GROUP,
+        }
+    }
+
+    // Collisions between groups aren't filtered, only rays are
+    pub fn interaction_groups(self) -> InteractionGroups {
+        InteractionGroups::new(self.group(), ALL_GROUPS)
+    }
+
+    // None for colliders without a group, like buried blocks
+    pub fn of(graph: &Graph, collider: Handle<Node>) -> Option<Self> {
+        let memberships = match graph.try_get(collider)? {
+            Node::Collider(collider) => collider.collision_groups().memberships,
+            _ => return None,
+        };
+
+        [
+            SurfaceKind::World,
+            SurfaceKind::Block,
+            SurfaceKind::Player,
+            SurfaceKind::Projectile,
+        ]
+        .iter()
+        .copied()
+        .find(|kind| memberships & kind.group() != 0)
+    }
+}
+
+// Closest hit along a ray among colliders in the given groups, skipping one collider (usually the
+// capsule of whoever is looking)
+pub fn cast_ray(
+    graph: &Graph,
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_len: f32,
+    groups: u32,
+    ignore: Handle<Node>,
+) -> Option<Intersection> {
+    let mut intersections = Vec::new();
+    graph.physics.cast_ray(
+        RayCastOptions {
+            ray_origin: origin.into(),
+            ray_direction: direction,
+            max_len,
+            groups: InteractionGroups::new(ALL_GROUPS, groups),
+            sort_results: true,
+        },
+        &mut intersections,
+    );
+
+    intersections.into_iter().find(|i| i.collider != ignore)
+}
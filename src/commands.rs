@@ -0,0 +1,75 @@
+// Commands typed into a console, the server's terminal or the client's drop-down one. Each keeps
+// its own list of commands, which turn their arguments into whatever that side carries out. Scripts
+// are files of commands, one per line, that are run as if they were typed in.
+
+use std::{fs, io, path::Path};
+
+pub struct Command<T> {
+    pub name: &'static str,
+    pub args: &'static str, // As written in help, empty if it takes none
+    pub description: &'static str,
+    pub parse: fn(&str) -> Option<T>, // None if the arguments don't make sense
+}
+
+impl<T> Command<T> {
+    fn usage(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+pub enum Parsed<T> {
+    Command(T),
+    Reply(String), // Help or what was wrong with the line, for the console to show
+    Nothing,       // Blank lines and comments
+}
+
+pub fn parse<T>(commands: &[Command<T>], line: &str) -> Parsed<T> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+        return Parsed::Nothing;
+    }
+    let (name, args) = match line.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (line, ""),
+    };
+
+    if name == "help" {
+        return Parsed::Reply(help(commands));
+    }
+    match commands.iter().find(|command| command.name == name) {
+        Some(command) => match (command.parse)(args) {
+            Some(parsed) => Parsed::Command(parsed),
+            None => Parsed::Reply(format!("usage: {}", command.usage())),
+        },
+        None => Parsed::Reply(format!("unknown command {}, try help", name)),
+    }
+}
+
+fn help<T>(commands: &[Command<T>]) -> String {
+    let usages: Vec<String> = commands.iter().map(Command::usage).collect();
+    let width = usages.iter().map(String::len).max().unwrap_or(0);
+
+    let mut help = String::from("commands:");
+    for (command, usage) in commands.iter().zip(usages) {
+        help += &format!(
+            "\n  {:width$}  {}",
+            usage,
+            command.description,
+            width = width
+        );
+    }
+    help
+}
+
+// Lines of the script at the path, a missing script is the same as an empty one
+pub fn read_script<P: AsRef<Path>>(path: P) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(script) => Ok(script.lines().map(String::from).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,47 @@
+// LZ4 compression for big messages like the level state and bursts of spawns. Compressed
+// payloads start with a marker byte, plain ones are sent as they are. No plain message can start
+// with the marker because bincode writes the message variant first as a little endian u32 and
+// there are nowhere near 255 variants, so either end can tell them apart and old clients still
+// get a readable version mismatch.
+
+use std::{borrow::Cow, convert::TryInto};
+
+use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+
+const COMPRESSED_MARKER: u8 = 0xff;
+// Smaller messages barely shrink and aren't worth the time
+const COMPRESSION_THRESHOLD: usize = 256;
+// Largest message accepted, also caps how much a compressed payload may claim to unpack to
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024;
+
+pub fn compress(bytes: Vec<u8>) -> Vec<u8> {
+    if bytes.len() < COMPRESSION_THRESHOLD {
+        return bytes;
+    }
+
+    let mut compressed = vec![COMPRESSED_MARKER];
+    compressed.extend_from_slice(&compress_prepend_size(&bytes));
+
+    // Already dense data can come out bigger
+    if compressed.len() < bytes.len() {
+        compressed
+    } else {
+        bytes
+    }
+}
+
+// None if the payload is compressed but broken or too big
+pub fn decompress(payload: &[u8]) -> Option<Cow<[u8]>> {
+    match payload.split_first() {
+        Some((&COMPRESSED_MARKER, compressed)) => {
+            // Check the size before lz4 allocates room for it
+            let size = u32::from_le_bytes(compressed.get(..4)?.try_into().ok()?) as usize;
+            if size > MAX_MESSAGE_SIZE {
+                return None;
+            }
+
+            decompress_size_prepended(compressed).ok().map(Cow::Owned)
+        }
+        _ => Some(Cow::Borrowed(payload)),
+    }
+}
@@ -0,0 +1,158 @@
+// Admin commands typed into the server's terminal. Lines are read on their own thread and turned
+// into game events, the game loop carries them out like any other event. AUTOEXEC_PATH is run
+// first, so a server can be set up the same way every time it starts.
+
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use tracing::warn;
+
+use crate::{
+    commands::{self, Command, Parsed},
+    game::GameEvent,
+};
+
+pub const AUTOEXEC_PATH: &str = "server_autoexec.cfg";
+
+const DEFAULT_SHUTDOWN_REASON: &str = "The server shut down";
+
+const COMMANDS: &[Command<GameEvent>] = &[
+    Command {
+        name: "list",
+        args: "",
+        description: "show connected players",
+        parse: |_| Some(GameEvent::ListPlayers),
+    },
+    Command {
+        name: "kick",
+        args: "<index>",
+        description: "remove a player from the server",
+        parse: |args| {
+            Some(GameEvent::Kick {
+                index: args.parse().ok()?,
+            })
+        },
+    },
+    Command {
+        name: "ban",
+        args: "<index|ip>",
+        description: "kick a player and keep their address out for good",
+        parse: |args| match (args.parse(), args.parse()) {
+            (Ok(index), _) => Some(GameEvent::Ban { index }),
+            (_, Ok(address)) => Some(GameEvent::BanAddress { address }),
+            _ => None,
+        },
+    },
+    Command {
+        name: "unban",
+        args: "<ip>",
+        description: "let a banned address back in",
+        parse: |args| {
+            Some(GameEvent::Unban {
+                address: args.parse().ok()?,
+            })
+        },
+    },
+    Command {
+        name: "bans",
+        args: "",
+        description: "show banned addresses",
+        parse: |_| Some(GameEvent::ListBans),
+    },
+    Command {
+        name: "map",
+        args: "<name>",
+        description: "switch to another map",
+        parse: |args| {
+            (!args.is_empty()).then(|| GameEvent::ChangeMap {
+                map: args.to_string(),
+            })
+        },
+    },
+    Command {
+        name: "say",
+        args: "<text>",
+        description: "send a chat message to everyone",
+        parse: |args| {
+            (!args.is_empty()).then(|| GameEvent::ServerMessage {
+                text: args.to_string(),
+            })
+        },
+    },
+    Command {
+        name: "shutdown",
+        args: "[why]",
+        description: "disconnect everyone and stop the server, Ctrl+C does too",
+        parse: |args| {
+            Some(GameEvent::Shutdown {
+                reason: if args.is_empty() {
+                    String::from(DEFAULT_SHUTDOWN_REASON)
+                } else {
+                    args.to_string()
+                },
+            })
+        },
+    },
+];
+
+pub fn spawn(sender: Sender<GameEvent>) {
+    let script = commands::read_script(AUTOEXEC_PATH).unwrap_or_else(|e| {
+        warn!("failed to read {}: {}", AUTOEXEC_PATH, e);
+        Vec::new()
+    });
+
+    thread::spawn(move || {
+        for line in script.iter() {
+            if !run(&sender, line) {
+                return;
+            }
+        }
+
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("failed to read console input: {}", e);
+                    return;
+                }
+            };
+
+            if !run(&sender, &line) {
+                return;
+            }
+        }
+    });
+}
+
+// Returns false once the game is gone and there's nothing left to command
+fn run(sender: &Sender<GameEvent>, line: &str) -> bool {
+    match commands::parse(COMMANDS, line) {
+        Parsed::Command(event) => sender.send(event).is_ok(),
+        Parsed::Reply(reply) => {
+            println!("{}", reply);
+            true
+        }
+        Parsed::Nothing => true,
+    }
+}
+
+// Ctrl+C shuts down like the console command so players are told. A second one gives up on that
+// and exits right away.
+pub fn catch_interrupt(sender: Sender<GameEvent>) {
+    let mut interrupted = false;
+    let result = ctrlc::set_handler(move || {
+        if interrupted {
+            std::process::exit(1);
+        }
+        interrupted = true;
+        let _ = sender.send(GameEvent::Shutdown {
+            reason: String::from(DEFAULT_SHUTDOWN_REASON),
+        });
+    });
+    if let Err(e) = result {
+        warn!("failed to catch Ctrl+C: {}", e);
+    }
+}
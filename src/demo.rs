@@ -0,0 +1,323 @@
+// Match recording and playback. Everything the level and game receive is written to a file along
+// with when it arrived, playback feeds it back in at the same pace and watches through the
+// spectator camera.
+
+#[cfg(not(feature = "server"))]
+use std::io::{BufReader, Read};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    thread,
+    time::Instant,
+};
+
+#[cfg(not(feature = "server"))]
+use bincode::deserialize_from;
+use bincode::serialize_into;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+#[cfg(not(feature = "server"))]
+use fyrox::{
+    core::{algebra::Vector2, pool::Handle},
+    event::{ElementState, Event, VirtualKeyCode, WindowEvent},
+    gui::{
+        message::MessageDirection,
+        text::{TextBuilder, TextMessage},
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, HorizontalAlignment, UiNode, UserInterface,
+    },
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+#[cfg(not(feature = "server"))]
+use crate::{
+    game::Game,
+    hud::{move_widget, Anchor},
+    GameEngine, Interface,
+};
+use crate::{game::GameEvent, network_manager::PROTOCOL_VERSION, player_event::PlayerEvent};
+
+const DEMO_MAGIC: &[u8; 4] = b"BFDM";
+#[cfg(not(feature = "server"))]
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+#[cfg(not(feature = "server"))]
+const SEEK_STEP: f32 = 10.0; // Seconds
+#[cfg(not(feature = "server"))]
+const TIMELINE_WIDTH: f32 = 500.0;
+#[cfg(not(feature = "server"))]
+const TIMELINE_TOP: f32 = 40.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DemoEvent {
+    Player(PlayerEvent),
+    Game(GameEvent),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DemoFrame {
+    time: f32, // Seconds since recording started
+    event: DemoEvent,
+}
+
+// Cheap to clone so the level can have its own, frames are written out on a separate thread
+#[derive(Clone)]
+pub struct DemoRecorder {
+    sender: Sender<DemoFrame>,
+    start: Instant,
+}
+
+impl DemoRecorder {
+    pub fn create(path: &str) -> Result<Self, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Failed to create demo {}: {}", path, e))?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(DEMO_MAGIC)
+            .map_err(|e| format!("Failed to write demo {}: {}", path, e))?;
+        serialize_into(&mut writer, &PROTOCOL_VERSION)
+            .map_err(|e| format!("Failed to write demo {}: {}", path, e))?;
+
+        let (sender, receiver) = unbounded();
+        thread::spawn(move || write_frames(writer, receiver));
+
+        info!("recording demo to {}", path);
+
+        Ok(Self {
+            sender,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, event: DemoEvent) {
+        let _ = self.sender.send(DemoFrame {
+            time: self.start.elapsed().as_secs_f32(),
+            event,
+        });
+    }
+}
+
+// Runs until every recorder is dropped
+fn write_frames(mut writer: BufWriter<File>, receiver: Receiver<DemoFrame>) {
+    while let Ok(frame) = receiver.recv() {
+        if let Err(e) = serialize_into(&mut writer, &frame) {
+            warn!("failed to write demo: {}", e);
+            return;
+        }
+
+        // Flush whenever we catch up so not much is lost if the game is killed
+        if receiver.is_empty() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn read_frames(path: &str) -> Result<Vec<DemoFrame>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open demo {}: {}", path, e))?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Failed to read demo {}: {}", path, e))?;
+    if &magic != DEMO_MAGIC {
+        return Err(format!("{} isn't a demo", path));
+    }
+
+    let version: u32 = deserialize_from(&mut reader)
+        .map_err(|e| format!("Failed to read demo {}: {}", path, e))?;
+    if version != PROTOCOL_VERSION {
+        return Err(format!(
+            "Demo {} was recorded with protocol {}, this build uses {}",
+            path, version, PROTOCOL_VERSION
+        ));
+    }
+
+    // Reading stops at the end of the file, or at a frame cut off when the recording was killed
+    let mut frames = Vec::new();
+    while let Ok(frame) = deserialize_from(&mut reader) {
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
+
+// Plays a recorded demo. Controls: F5 pause, F6/F7 slower/faster, left/right arrow to seek.
+#[cfg(not(feature = "server"))]
+pub struct DemoPlayer {
+    frames: Vec<DemoFrame>,
+    next: usize, // Frame to play next
+    time: f32,
+    speed: usize, // Index into SPEEDS
+    paused: bool,
+    waiting: bool, // For a level to load, time doesn't move meanwhile
+    timeline: Handle<UiNode>,
+}
+
+#[cfg(not(feature = "server"))]
+impl DemoPlayer {
+    pub fn load(path: &str, ctx: &mut BuildContext, window_width: f32) -> Result<Self, String> {
+        let frames = read_frames(path)?;
+        info!("playing demo {} ({} events)", path, frames.len());
+
+        let timeline = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_width(TIMELINE_WIDTH)
+                .with_desired_position(Self::timeline_position(window_width)),
+        )
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .build(ctx);
+
+        Ok(Self {
+            frames,
+            next: 0,
+            time: 0.0,
+            speed: 2,
+            paused: false,
+            waiting: false,
+            timeline,
+        })
+    }
+
+    fn timeline_position(window_width: f32) -> Vector2<f32> {
+        Anchor::Top.position(
+            window_width,
+            0.0,
+            Vector2::new(TIMELINE_WIDTH, 0.0),
+            Vector2::new(0.0, TIMELINE_TOP),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        move_widget(ui, self.timeline, Self::timeline_position(window_width));
+    }
+
+    pub fn clean_up(&self, ui: &UserInterface) {
+        ui.send_message(WidgetMessage::remove(
+            self.timeline,
+            MessageDirection::ToWidget,
+        ));
+    }
+
+    fn duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+
+    pub fn process_input(
+        &mut self,
+        event: &Event<()>,
+        engine: &mut GameEngine,
+        game: &mut Game,
+        interface: &mut Interface,
+    ) {
+        let key = match event {
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } if input.state == ElementState::Pressed => input.virtual_keycode,
+            _ => None,
+        };
+
+        match key {
+            Some(VirtualKeyCode::F5) => self.paused = !self.paused,
+            Some(VirtualKeyCode::F6) => self.speed = self.speed.saturating_sub(1),
+            Some(VirtualKeyCode::F7) => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            Some(VirtualKeyCode::Left) => self.seek(self.time - SEEK_STEP, engine, game, interface),
+            Some(VirtualKeyCode::Right) => self.time = (self.time + SEEK_STEP).min(self.duration()),
+            _ => (),
+        }
+    }
+
+    // Events can't be undone, so going back replays everything from the start up to the new time
+    fn seek(
+        &mut self,
+        time: f32,
+        engine: &mut GameEngine,
+        game: &mut Game,
+        interface: &mut Interface,
+    ) {
+        game.leave(engine, interface);
+        self.next = 0;
+        self.time = time.max(0.0);
+    }
+
+    pub fn update(&mut self, dt: f32, engine: &GameEngine, game: &mut Game) {
+        self.waiting = game.is_loading();
+        if !self.paused && !self.waiting {
+            self.time = (self.time + dt * SPEEDS[self.speed]).min(self.duration());
+        }
+
+        while !self.waiting && self.next < self.frames.len() {
+            let frame = &self.frames[self.next];
+            if frame.time > self.time {
+                break;
+            }
+
+            match &frame.event {
+                DemoEvent::Player(event) => match &mut game.level {
+                    Some(level) => level.queue_event(as_spectator(*event)),
+                    None => {
+                        self.waiting = true;
+                        break;
+                    }
+                },
+                // The session these were about is long gone
+                DemoEvent::Game(
+                    GameEvent::Connected
+                    | GameEvent::Disconnected
+                    | GameEvent::Reconnecting { .. }
+                    | GameEvent::Joined,
+                ) => (),
+                // Players belong to the new level, wait for it to load before going on
+                DemoEvent::Game(event @ GameEvent::LoadLevel { .. }) => {
+                    game.queue_event(event.clone());
+                    self.waiting = true;
+                }
+                DemoEvent::Game(event) => game.queue_event(event.clone()),
+            }
+
+            self.next += 1;
+        }
+
+        let state = if self.waiting {
+            " (loading)"
+        } else if self.paused {
+            " (paused)"
+        } else {
+            ""
+        };
+        engine.user_interface.send_message(TextMessage::text(
+            self.timeline,
+            MessageDirection::ToWidget,
+            format!(
+                "Demo {} / {}  x{}{}    F5 pause  F6/F7 speed  Left/Right seek",
+                format_time(self.time),
+                format_time(self.duration()),
+                SPEEDS[self.speed],
+                state
+            ),
+        ));
+    }
+}
+
+// Whoever recorded the demo is just another player when watching it back
+#[cfg(not(feature = "server"))]
+fn as_spectator(event: PlayerEvent) -> PlayerEvent {
+    match event {
+        PlayerEvent::SpawnPlayer {
+            state, index, skin, ..
+        } => PlayerEvent::SpawnPlayer {
+            state,
+            index,
+            current_player: false,
+            skin,
+        },
+        event => event,
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
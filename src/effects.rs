@@ -0,0 +1,338 @@
+// Client side effects for blocks breaking and shots hitting things. Particle systems and decals
+// are built once per level and handed out in turn, so an explosion taking out a pile of blocks or
+// a stream of shots doesn't create a pile of nodes.
+
+use fyrox::{
+    core::{
+        algebra::{UnitQuaternion, Vector3},
+        color::Color,
+        color_gradient::{ColorGradient, GradientPoint},
+        numeric_range::NumericRange,
+        pool::Handle,
+    },
+    engine::resource_manager::ResourceManager,
+    resource::texture::Texture,
+    scene::{
+        base::BaseBuilder,
+        decal::DecalBuilder,
+        graph::Graph,
+        node::Node,
+        particle_system::{
+            emitter::{base::BaseEmitterBuilder, sphere::SphereEmitterBuilder},
+            ParticleSystemBuilder,
+        },
+        sound::{SoundBufferResource, SoundBuilder, Status},
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+use tracing::warn;
+
+use crate::blocks::CRACK_STAGES;
+
+// Once every system is in use the oldest one is restarted
+const DEBRIS_POOL_SIZE: usize = 24;
+const DEBRIS_PARTICLES: u32 = 20;
+// Particle velocities are per frame, acceleration is how much that changes every second
+const DEBRIS_GRAVITY: f32 = -0.16;
+const SPARK_POOL_SIZE: usize = 16;
+const SPARK_PARTICLES: u32 = 12;
+// Oldest bullet hole is moved once this many are in the level
+const DECAL_POOL_SIZE: usize = 64;
+const DECAL_LIFETIME: f32 = 15.0; // Seconds
+const DECAL_SIZE: f32 = 0.12;
+
+pub struct BlockEffects {
+    debris: Vec<Handle<Node>>,
+    next_debris: usize,
+    break_sound: Option<SoundBufferResource>,
+}
+
+impl BlockEffects {
+    pub async fn new(scene: &mut Scene, resource_manager: ResourceManager) -> Self {
+        // Chips of the block itself
+        let texture = resource_manager.request_texture("data/textures/Block.png");
+
+        let debris = (0..DEBRIS_POOL_SIZE)
+            .map(|_| create_debris(&mut scene.graph, texture.clone()))
+            .collect();
+
+        let break_sound = match resource_manager
+            .request_sound_buffer("data/sounds/block_break.wav")
+            .await
+        {
+            Ok(buffer) => Some(buffer),
+            Err(_) => {
+                warn!("failed to load block break sound");
+                None
+            }
+        };
+
+        Self {
+            debris,
+            next_debris: 0,
+            break_sound,
+        }
+    }
+
+    // Darkens a block that's been hit, the further it is from breaking the darker it gets
+    pub fn block_cracked(&self, graph: &mut Graph, block: Handle<Node>, stage: u32) {
+        use std::sync::Arc;
+
+        use fyrox::{
+            core::{parking_lot::Mutex, sstorage::ImmutableString},
+            material::PropertyValue,
+        };
+
+        if !graph.is_valid_handle(block) {
+            return;
+        }
+
+        let shade = (1.0 - stage.min(CRACK_STAGES) as f32 * CRACK_SHADE).max(0.0);
+        let value = (255.0 * shade) as u8;
+        let color = Color::opaque(value, value, value);
+
+        let meshes: Vec<Handle<Node>> = graph
+            .traverse_handle_iter(block)
+            .filter(|&handle| graph[handle].is_mesh())
+            .collect();
+        for mesh in meshes {
+            // Blocks share their material, each one cracks on its own
+            for surface in graph[mesh].as_mesh_mut().surfaces_mut() {
+                let mut material = surface.material().lock().clone();
+                if let Err(e) = material.set_property(
+                    &ImmutableString::new("diffuseColor"),
+                    PropertyValue::Color(color),
+                ) {
+                    warn!("failed to crack block: {:?}", e);
+                }
+                surface.set_material(Arc::new(Mutex::new(material)));
+            }
+        }
+    }
+
+    pub fn block_destroyed(&mut self, scene: &mut Scene, position: Vector3<f32>) {
+        let handle = self.debris[self.next_debris];
+        self.next_debris = (self.next_debris + 1) % self.debris.len();
+
+        let node = &mut scene.graph[handle];
+        node.local_transform_mut().set_position(position);
+        node.set_visibility(true);
+        // Clearing also resets the emitter so it plays the burst again
+        node.as_particle_system_mut().clear_particles();
+
+        if let Some(buffer) = &self.break_sound {
+            SoundBuilder::new(
+                BaseBuilder::new().with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(position)
+                        .build(),
+                ),
+            )
+            .with_play_once(true)
+            .with_buffer(Some(buffer.clone()))
+            .with_radius(2.0)
+            .with_status(Status::Playing)
+            .build(&mut scene.graph);
+        }
+    }
+}
+
+// Confirmation for the shooter that a shot landed, with its own sound for headshots
+pub struct HitSounds {
+    hit: Option<SoundBufferResource>,
+    headshot: Option<SoundBufferResource>,
+}
+
+impl HitSounds {
+    pub async fn new(resource_manager: ResourceManager) -> Self {
+        let (hit, headshot) = fyrox::core::futures::join!(
+            resource_manager.request_sound_buffer("data/sounds/hit.wav"),
+            resource_manager.request_sound_buffer("data/sounds/headshot.wav"),
+        );
+
+        if hit.is_err() || headshot.is_err() {
+            warn!("failed to load hit sounds");
+        }
+
+        Self {
+            hit: hit.ok(),
+            headshot: headshot.ok(),
+        }
+    }
+
+    // Played where the listener is so it's heard however far away the target was
+    pub fn play(&self, scene: &mut Scene, position: Vector3<f32>, headshot: bool) {
+        let buffer = if headshot { &self.headshot } else { &self.hit };
+        if let Some(buffer) = buffer {
+            SoundBuilder::new(
+                BaseBuilder::new().with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(position)
+                        .build(),
+                ),
+            )
+            .with_play_once(true)
+            .with_buffer(Some(buffer.clone()))
+            .with_status(Status::Playing)
+            .build(&mut scene.graph);
+        }
+    }
+}
+
+fn create_debris(graph: &mut Graph, texture: Texture) -> Handle<Node> {
+    // One burst that isn't repeated until the particles are cleared
+    let emitter = SphereEmitterBuilder::new(
+        BaseEmitterBuilder::new()
+            .with_max_particles(DEBRIS_PARTICLES)
+            .with_spawn_rate(DEBRIS_PARTICLES * 60)
+            .with_lifetime_range(NumericRange::new(0.6, 1.0))
+            .with_size_range(NumericRange::new(0.05, 0.12))
+            .with_size_modifier_range(NumericRange::new(-0.001, -0.002))
+            .with_x_velocity_range(NumericRange::new(-0.04, 0.04))
+            .with_y_velocity_range(NumericRange::new(0.02, 0.07))
+            .with_z_velocity_range(NumericRange::new(-0.04, 0.04))
+            .resurrect_particles(false),
+    )
+    .with_radius(0.4)
+    .build();
+
+    let color_gradient = {
+        let mut gradient = ColorGradient::new();
+        gradient.add_point(GradientPoint::new(
+            0.00,
+            Color::from_rgba(255, 255, 255, 255),
+        ));
+        gradient.add_point(GradientPoint::new(
+            0.70,
+            Color::from_rgba(220, 220, 220, 255),
+        ));
+        gradient.add_point(GradientPoint::new(1.00, Color::from_rgba(200, 200, 200, 0)));
+        gradient
+    };
+
+    // Hidden until first used, otherwise the initial burst would show at the origin
+    ParticleSystemBuilder::new(BaseBuilder::new().with_visibility(false))
+        .with_acceleration(Vector3::new(0.0, DEBRIS_GRAVITY, 0.0))
+        .with_color_over_lifetime_gradient(color_gradient)
+        .with_emitters(vec![emitter])
+        .with_texture(texture)
+        .build(graph)
+}
+
+pub struct ImpactEffects {
+    sparks: Vec<Handle<Node>>,
+    next_spark: usize,
+    decals: Vec<(Handle<Node>, f32)>, // Time left before the decal is hidden
+    next_decal: usize,
+}
+
+impl ImpactEffects {
+    pub fn new(scene: &mut Scene, resource_manager: ResourceManager) -> Self {
+        let spark_texture = resource_manager.request_texture("data/textures/spark.png");
+        let decal_texture = resource_manager.request_texture("data/textures/bullet_hole.png");
+
+        let sparks = (0..SPARK_POOL_SIZE)
+            .map(|_| create_sparks(&mut scene.graph, spark_texture.clone()))
+            .collect();
+
+        let decals = (0..DECAL_POOL_SIZE)
+            .map(|_| {
+                let decal = DecalBuilder::new(BaseBuilder::new().with_visibility(false))
+                    .with_diffuse_texture(decal_texture.clone())
+                    .build(&mut scene.graph);
+                (decal, 0.0)
+            })
+            .collect();
+
+        Self {
+            sparks,
+            next_spark: 0,
+            decals,
+            next_decal: 0,
+        }
+    }
+
+    // Sparks at the hit facing out of the surface. Decals are only for level geometry, they'd be
+    // left floating behind anything that moves.
+    pub fn impact(
+        &mut self,
+        scene: &mut Scene,
+        position: Vector3<f32>,
+        normal: Vector3<f32>,
+        decal: bool,
+    ) {
+        // Both effects are built around their local Y axis
+        let rotation = UnitQuaternion::rotation_between(&Vector3::y(), &normal).unwrap_or_else(
+            // Normal pointing straight down
+            || UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI),
+        );
+
+        let handle = self.sparks[self.next_spark];
+        self.next_spark = (self.next_spark + 1) % self.sparks.len();
+
+        let node = &mut scene.graph[handle];
+        node.local_transform_mut()
+            .set_position(position)
+            .set_rotation(rotation);
+        node.set_visibility(true);
+        node.as_particle_system_mut().clear_particles();
+
+        if decal {
+            let (handle, time_left) = &mut self.decals[self.next_decal];
+            self.next_decal = (self.next_decal + 1) % DECAL_POOL_SIZE;
+            *time_left = DECAL_LIFETIME;
+
+            let node = &mut scene.graph[*handle];
+            node.local_transform_mut()
+                .set_position(position)
+                .set_rotation(rotation)
+                .set_scale(Vector3::new(DECAL_SIZE, DECAL_SIZE, DECAL_SIZE));
+            node.set_visibility(true);
+        }
+    }
+
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) {
+        for (handle, time_left) in self.decals.iter_mut() {
+            if *time_left > 0.0 {
+                *time_left -= dt;
+                if *time_left <= 0.0 {
+                    scene.graph[*handle].set_visibility(false);
+                }
+            }
+        }
+    }
+}
+
+fn create_sparks(graph: &mut Graph, texture: Texture) -> Handle<Node> {
+    let emitter = SphereEmitterBuilder::new(
+        BaseEmitterBuilder::new()
+            .with_max_particles(SPARK_PARTICLES)
+            .with_spawn_rate(SPARK_PARTICLES * 60)
+            .with_lifetime_range(NumericRange::new(0.15, 0.35))
+            .with_size_range(NumericRange::new(0.01, 0.025))
+            .with_size_modifier_range(NumericRange::new(-0.0005, -0.001))
+            .with_x_velocity_range(NumericRange::new(-0.02, 0.02))
+            .with_y_velocity_range(NumericRange::new(0.01, 0.04))
+            .with_z_velocity_range(NumericRange::new(-0.02, 0.02))
+            .resurrect_particles(false),
+    )
+    .with_radius(0.01)
+    .build();
+
+    let color_gradient = {
+        let mut gradient = ColorGradient::new();
+        gradient.add_point(GradientPoint::new(0.00, Color::from_rgba(255, 255, 0, 0)));
+        gradient.add_point(GradientPoint::new(0.05, Color::from_rgba(255, 160, 0, 255)));
+        gradient.add_point(GradientPoint::new(0.95, Color::from_rgba(255, 120, 0, 255)));
+        gradient.add_point(GradientPoint::new(1.00, Color::from_rgba(255, 60, 0, 0)));
+        gradient
+    };
+
+    ParticleSystemBuilder::new(BaseBuilder::new().with_visibility(false))
+        .with_acceleration(Vector3::new(0.0, DEBRIS_GRAVITY, 0.0))
+        .with_color_over_lifetime_gradient(color_gradient)
+        .with_emitters(vec![emitter])
+        .with_texture(texture)
+        .build(graph)
+}
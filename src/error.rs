@@ -0,0 +1,46 @@
+// Things that can go wrong at runtime without it being a bug, like a missing asset or a server
+// that can't be reached. They get passed up to whoever can tell the player about it: the menu on
+// clients, the log on the server.
+
+use std::{fmt, sync::mpsc::SendError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameError {
+    Resource(String), // Path of a model, texture or sound that failed to load
+    MissingNode { model: String, node: String },
+    Resolve { address: String, reason: String },
+    Bind { port: u16, reason: String },
+    Channel, // Whoever was listening on the other end is gone
+}
+
+impl GameError {
+    pub fn resource(path: &str) -> Self {
+        GameError::Resource(path.to_string())
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::Resource(path) => write!(f, "Failed to load {}", path),
+            GameError::MissingNode { model, node } => {
+                write!(f, "{} has no node named {}", model, node)
+            }
+            GameError::Resolve { address, reason } => {
+                write!(f, "Failed to resolve {}: {}", address, reason)
+            }
+            GameError::Bind { port, reason } => {
+                write!(f, "Failed to open a socket on port {}: {}", port, reason)
+            }
+            GameError::Channel => write!(f, "Channel closed"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl<T> From<SendError<T>> for GameError {
+    fn from(_: SendError<T>) -> Self {
+        GameError::Channel
+    }
+}
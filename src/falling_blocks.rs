@@ -0,0 +1,80 @@
+// Blocks come loose when the block under them is destroyed. After a moment without support they
+// start falling, everyone turns them into dynamic bodies on SpawnFallingBlock, and once they've
+// dropped out of the level the server destroys them like any other block.
+
+use std::collections::HashMap;
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{graph::Graph, node::Node},
+};
+
+use crate::blocks::BlockStore;
+
+// Seconds a block hangs on after losing its support, so a collapse is easy to see coming
+const FALL_DELAY: f32 = 0.5;
+// Falling blocks below this height have left the level
+const FALL_LIMIT: f32 = -30.0;
+
+#[derive(Default)]
+pub struct FallingBlocks {
+    loosening: HashMap<Vector3<i32>, f32>, // Time left before the block at a coord falls
+    falling: Vec<u32>,                     // Block node indices
+}
+
+impl FallingBlocks {
+    // Call when a block leaves the store, the block resting on it may have to fall
+    pub fn support_removed(&mut self, coord: Vector3<i32>) {
+        self.loosening
+            .entry(coord + Vector3::y())
+            .or_insert(FALL_DELAY);
+    }
+
+    pub fn start_falling(&mut self, index: u32) {
+        if !self.falling.contains(&index) {
+            self.falling.push(index);
+        }
+    }
+
+    // Blocks that have gone without support for long enough, still checked in case there was
+    // nothing above the removed block
+    pub fn update(&mut self, store: &BlockStore, dt: f32) -> Vec<Handle<Node>> {
+        let mut loose = Vec::new();
+
+        self.loosening.retain(|coord, time_left| {
+            *time_left -= dt;
+            if *time_left > 0.0 {
+                return true;
+            }
+
+            let block = store.get(*coord);
+            if block.is_some() && !store.contains(coord - Vector3::y()) {
+                loose.push(block);
+            }
+            false
+        });
+
+        loose
+    }
+
+    // Falling blocks that have dropped out of the level. Ones that were shot on the way down are
+    // just forgotten.
+    pub fn fallen(&mut self, graph: &Graph) -> Vec<u32> {
+        let mut fallen = Vec::new();
+
+        self.falling.retain(|&index| {
+            let handle = graph.handle_from_index(index);
+            if handle.is_none() || !graph.is_valid_handle(handle) {
+                return false;
+            }
+
+            if graph[handle].global_position().y < FALL_LIMIT {
+                fallen.push(index);
+                return false;
+            }
+            true
+        });
+
+        fallen
+    }
+}
@@ -0,0 +1,123 @@
+// Footstep sounds for every player, timed off the walk animation so steps land with the feet. Two
+// steps per walk cycle, picked by what's underfoot.
+
+use fyrox::{
+    core::algebra::Vector3,
+    engine::resource_manager::ResourceManager,
+    scene::{
+        base::BaseBuilder,
+        sound::{SoundBufferResource, SoundBuilder, Status},
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+use tracing::warn;
+
+use crate::collision::{self, SurfaceKind};
+
+const VARIATIONS: usize = 2; // Sounds per surface, played in turn
+const STEP_GAIN: f32 = 0.6;
+const CROUCH_GAIN: f32 = 0.25;
+const STEP_RADIUS: f32 = 1.5;
+// How far below the player's center the ground is looked for
+const GROUND_PROBE: f32 = 1.0;
+
+#[derive(Clone)]
+pub struct FootstepSounds {
+    world: Vec<SoundBufferResource>,
+    block: Vec<SoundBufferResource>,
+}
+
+impl FootstepSounds {
+    pub async fn load(resource_manager: ResourceManager) -> Self {
+        Self {
+            world: load_variations(&resource_manager, "world").await,
+            block: load_variations(&resource_manager, "block").await,
+        }
+    }
+
+    fn for_surface(&self, surface: SurfaceKind) -> &[SoundBufferResource] {
+        match surface {
+            SurfaceKind::Block => &self.block,
+            _ => &self.world,
+        }
+    }
+}
+
+async fn load_variations(
+    resource_manager: &ResourceManager,
+    surface: &str,
+) -> Vec<SoundBufferResource> {
+    let mut sounds = Vec::new();
+    for variation in 1..=VARIATIONS {
+        let path = format!("data/sounds/footstep_{}_{}.wav", surface, variation);
+        match resource_manager.request_sound_buffer(&path).await {
+            Ok(buffer) => sounds.push(buffer),
+            Err(_) => warn!("failed to load {}", path),
+        }
+    }
+    sounds
+}
+
+#[derive(Default)]
+pub struct Footsteps {
+    last_phase: f32,
+    next_variation: usize,
+}
+
+impl Footsteps {
+    // Phase is how far through the walk cycle the walk animation is, from 0 to 1
+    pub fn update(
+        &mut self,
+        scene: &mut Scene,
+        sounds: &FootstepSounds,
+        phase: f32,
+        position: Vector3<f32>,
+        walking: bool, // Moving along the ground
+        crouched: bool,
+    ) {
+        let last_phase = std::mem::replace(&mut self.last_phase, phase);
+        if !walking {
+            return;
+        }
+
+        // One foot comes down at the start of the cycle, the other halfway through
+        let stepped = phase < last_phase || (last_phase < 0.5 && phase >= 0.5);
+        if !stepped {
+            return;
+        }
+
+        // Whatever is under the player, they're usually standing on it
+        let surface = collision::cast_ray(
+            &scene.graph,
+            position,
+            Vector3::new(0.0, -1.0, 0.0),
+            GROUND_PROBE,
+            collision::GROUND_GROUPS,
+            Default::default(),
+        )
+        .and_then(|hit| SurfaceKind::of(&scene.graph, hit.collider))
+        .unwrap_or(SurfaceKind::World);
+
+        let variations = sounds.for_surface(surface);
+        if variations.is_empty() {
+            return;
+        }
+        let buffer = variations[self.next_variation % variations.len()].clone();
+        self.next_variation = self.next_variation.wrapping_add(1);
+
+        SoundBuilder::new(
+            BaseBuilder::new().with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(position)
+                    .build(),
+            ),
+        )
+        .with_play_once(true)
+        .with_buffer(Some(buffer))
+        .with_gain(if crouched { CROUCH_GAIN } else { STEP_GAIN })
+        .with_radius(STEP_RADIUS)
+        .with_status(Status::Playing)
+        .build(&mut scene.graph);
+    }
+}
@@ -0,0 +1,42 @@
+// Several messages in one packet. Framed payloads start with a marker byte followed by each
+// message as a little endian u16 length and the message itself. A lone message is sent as it is,
+// which can't start with the marker for the same reason it can't start with the compression one,
+// so old clients still get a readable version mismatch. Framing happens before compression.
+
+use std::convert::TryInto;
+
+const FRAMED_MARKER: u8 = 0xfe;
+// Longest message that fits in a frame
+pub const MAX_FRAME_SIZE: usize = u16::MAX as usize;
+
+// Messages longer than MAX_FRAME_SIZE have to go in packets of their own
+pub fn frame(messages: &[Vec<u8>]) -> Vec<u8> {
+    if let [message] = messages {
+        return message.clone();
+    }
+
+    let size: usize = messages.iter().map(|message| message.len() + 2).sum();
+    let mut payload = Vec::with_capacity(size + 1);
+    payload.push(FRAMED_MARKER);
+    for message in messages {
+        payload.extend_from_slice(&(message.len() as u16).to_le_bytes());
+        payload.extend_from_slice(message);
+    }
+    payload
+}
+
+// None if the frames don't add up to the payload
+pub fn unframe(payload: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut rest = match payload.split_first() {
+        Some((&FRAMED_MARKER, rest)) => rest,
+        _ => return Some(vec![payload]),
+    };
+
+    let mut messages = Vec::new();
+    while !rest.is_empty() {
+        let length = u16::from_le_bytes(rest.get(..2)?.try_into().ok()?) as usize;
+        messages.push(rest.get(2..2 + length)?);
+        rest = &rest[2 + length..];
+    }
+    Some(messages)
+}
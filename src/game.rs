@@ -1,19 +1,56 @@
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::{
     mpsc::{self, Receiver, Sender},
     Arc, Mutex,
 };
+#[cfg(feature = "server")]
+use std::time::Instant;
 
-use fyrox::scene::Scene;
+use fyrox::{core::color::Color, scene::Scene};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use tracing::info;
+use tracing::{error, trace, warn};
 
 use crate::{
+    bot::Bots,
+    demo::{DemoEvent, DemoRecorder},
+    error::GameError,
     level::{Level, LevelState},
-    network_manager::{NetworkManager, NetworkMessage},
+    match_state::{MatchPhase, PlayerMatchStats},
+    network_manager::{NetworkManager, NetworkMessage, TickRates},
     GameEngine, Interface, Settings,
 };
+#[cfg(not(feature = "server"))]
+use crate::{
+    client_state::ClientState,
+    menu::MAX_PRACTICE_BOTS,
+    player_event::{PlayerEvent, SerializablePlayerState},
+    player_stats::{PlayerStats, StatsBook},
+};
+#[cfg(feature = "server")]
+use crate::{
+    match_state::{MatchState, MatchTransition},
+    metrics::Metrics,
+    rotation::{MapRotation, ServerConfig, SERVER_CONFIG_PATH},
+};
+
+// Index of the local player in practice. Bots take theirs from the network manager, which never
+// hands out 0.
+#[cfg(not(feature = "server"))]
+const PRACTICE_PLAYER: u32 = 0;
+// Height below which a practice player is put back at a spawn point
+#[cfg(not(feature = "server"))]
+const PRACTICE_FALL_LIMIT: f32 = -30.0;
+#[cfg(not(feature = "server"))]
+const PRACTICE_BOT_ACCURACY: f32 = 0.5;
+// Seconds a player killed in practice waits to be put back
+#[cfg(not(feature = "server"))]
+const PRACTICE_RESPAWN_TIME: f32 = 3.0;
 
 pub struct LoadContext {
-    level: Option<((Level, Scene), LevelState)>,
+    level: Option<(Result<(Level, Scene), GameError>, LevelState)>,
 }
 
 pub struct Game {
@@ -22,8 +59,58 @@ pub struct Game {
     pub event_receiver: Receiver<GameEvent>,
     pub server: bool,
     pub settings: Settings,
-    pub active: bool,
+    pub active: bool,     // Cleared when the connection to the server is lost
+    pub rates: TickRates, // From the server config, clients get them when connecting
+    #[cfg(not(feature = "server"))]
+    pub disconnect_reason: Option<String>, // Shown in the menu after the client is sent back to it
+    #[cfg(not(feature = "server"))]
+    practice: bool, // Playing a level on our own without a server
+    #[cfg(not(feature = "server"))]
+    practice_respawns: Vec<(u32, f32)>, // Players killed in practice, with the time left to wait
+    #[cfg(not(feature = "server"))]
+    stats: StatsBook, // Lifetime stats of every profile that has played here
+    #[cfg(not(feature = "server"))]
+    client_state: ClientState,
+    #[cfg(not(feature = "server"))]
+    window_focused: bool,
+    #[cfg(not(feature = "server"))]
+    cursor_in_window: bool,
+    #[cfg(not(feature = "server"))]
+    cursor_freed: bool, // Let go of with the free cursor key while playing
+    #[cfg(not(feature = "server"))]
+    cursor_captured: bool,
+    pub scores: Vec<PlayerScore>,
+    #[cfg(feature = "server")]
+    pub rotation: MapRotation,
+    #[cfg(feature = "server")]
+    pub match_state: MatchState,
+    // Kept while results are shown so players joining then see them too
+    #[cfg(feature = "server")]
+    pub match_summary: Option<Vec<PlayerMatchStats>>,
+    #[cfg(feature = "server")]
+    pub relevancy_radius: f32, // Handed to the network manager for filtering snapshots
+    #[cfg(feature = "server")]
+    pub send_budget: u32, // Handed to the network manager too
+    #[cfg(feature = "server")]
+    pub max_players: u32, // Checked by the network manager when players join, with the password
+    #[cfg(feature = "server")]
+    pub password: Option<String>,
+    #[cfg(feature = "server")]
+    pub observer_password: Option<String>,
+    #[cfg(feature = "server")]
+    pub idle_kick_time: f32, // Handed to the network manager as well
+    bots: Bots, // Only in practice on clients
+    #[cfg(feature = "server")]
+    pub metrics: Metrics, // Shared with the network manager and levels
+    #[cfg(feature = "server")]
+    level_load_started: Instant,
+    // Client copy of the match phase, time left is counted down locally
+    #[cfg(not(feature = "server"))]
+    match_phase: MatchPhase,
+    #[cfg(not(feature = "server"))]
+    phase_time_left: f32,
     load_context: Option<Arc<Mutex<LoadContext>>>,
+    recorder: Option<DemoRecorder>, // Set when recording a demo, every level gets a copy
 }
 
 impl Game {
@@ -36,18 +123,38 @@ impl Game {
 
         let server = cfg!(feature = "server");
 
+        #[cfg(feature = "server")]
+        let config = ServerConfig::read_from_file(SERVER_CONFIG_PATH).unwrap_or_else(|e| {
+            info!("using default server config: {}", e);
+            ServerConfig::default()
+        });
+        #[cfg(feature = "server")]
+        let rotation = MapRotation::new(&config);
+        #[cfg(feature = "server")]
+        let rates = TickRates::new(config.tick_rate, config.sync_rate);
+        #[cfg(not(feature = "server"))]
+        let rates = TickRates::default();
+
+        let recorder = settings
+            .record_demo
+            .as_deref()
+            .and_then(|path| DemoRecorder::create(path).map_err(|e| warn!("{}", e)).ok());
+
         // TODO: Replace this with an event to load level?
         #[cfg(feature = "server")]
         {
+            let map = rotation.current_map().to_string();
             std::thread::spawn(move || {
                 let state = LevelState {
                     destroyed_blocks: Vec::new(),
+                    cracked_blocks: Vec::new(),
                 };
                 let level = fyrox::core::futures::executor::block_on(Level::new(
                     resource_manager,
-                    "block_test",
+                    &map,
                     LevelState {
                         destroyed_blocks: Vec::new(),
+                        cracked_blocks: Vec::new(),
                     },
                 ));
 
@@ -62,7 +169,58 @@ impl Game {
             server,
             settings,
             active: true,
+            rates,
+            #[cfg(not(feature = "server"))]
+            disconnect_reason: None,
+            #[cfg(not(feature = "server"))]
+            practice: false,
+            #[cfg(not(feature = "server"))]
+            practice_respawns: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            stats: StatsBook::load(),
+            #[cfg(not(feature = "server"))]
+            client_state: ClientState::default(),
+            #[cfg(not(feature = "server"))]
+            window_focused: true,
+            #[cfg(not(feature = "server"))]
+            cursor_in_window: true,
+            #[cfg(not(feature = "server"))]
+            cursor_freed: false,
+            #[cfg(not(feature = "server"))]
+            cursor_captured: false,
+            scores: Vec::new(),
+            #[cfg(feature = "server")]
+            rotation,
+            #[cfg(feature = "server")]
+            match_state: MatchState::new(&config),
+            #[cfg(feature = "server")]
+            match_summary: None,
+            #[cfg(feature = "server")]
+            relevancy_radius: config.relevancy_radius,
+            #[cfg(feature = "server")]
+            send_budget: config.send_budget,
+            #[cfg(feature = "server")]
+            max_players: config.max_players,
+            #[cfg(feature = "server")]
+            password: config.password.clone(),
+            #[cfg(feature = "server")]
+            observer_password: config.observer_password.clone(),
+            #[cfg(feature = "server")]
+            idle_kick_time: config.idle_kick_time,
+            #[cfg(feature = "server")]
+            bots: Bots::new(config.bot_count, config.bot_accuracy),
+            #[cfg(not(feature = "server"))]
+            bots: Bots::new(0, PRACTICE_BOT_ACCURACY),
+            #[cfg(feature = "server")]
+            metrics: Metrics::new(config.metrics_port),
+            #[cfg(feature = "server")]
+            level_load_started: Instant::now(),
+            #[cfg(not(feature = "server"))]
+            match_phase: MatchPhase::default(),
+            #[cfg(not(feature = "server"))]
+            phase_time_left: 0.0,
             load_context: load_context,
+            recorder,
         }
     }
 
@@ -72,18 +230,35 @@ impl Game {
         dt: f32,
         network_manager: &mut NetworkManager,
         elapsed_time: f32,
-        interface: &Interface,
+        interface: &mut Interface,
     ) {
         while let Ok(event) = self.event_receiver.try_recv() {
-            // println!("game event received: {:?}", event);
+            trace!("game event received: {:?}", event);
+            #[cfg(feature = "server")]
+            self.metrics.game_event();
+            if let Some(recorder) = &self.recorder {
+                recorder.record(DemoEvent::Game(event.clone()));
+            }
+
             match event {
                 GameEvent::Connected => (),
                 GameEvent::LoadLevel { level, state } => {
+                    #[cfg(not(feature = "server"))]
+                    {
+                        self.save_stats();
+                        interface.results.hide(&engine.user_interface);
+                        interface.map_vote.hide(&engine.user_interface);
+                    }
+
                     let resource_manager = engine.resource_manager.clone();
 
                     let ctx = Arc::new(Mutex::new(LoadContext { level: None }));
 
                     self.load_context = Some(ctx.clone());
+                    #[cfg(feature = "server")]
+                    {
+                        self.level_load_started = Instant::now();
+                    }
 
                     std::thread::spawn(move || {
                         let level = fyrox::core::futures::executor::block_on(Level::new(
@@ -99,84 +274,834 @@ impl Game {
                 }
                 GameEvent::LoadedLevel => {}
                 #[cfg(feature = "server")]
+                GameEvent::PlayerKilled { index, killer } => {
+                    self.record_kill(index, killer);
+                    self.sync_combat_stats();
+                    network_manager.send_to_all_reliably(&NetworkMessage::ScoreUpdate {
+                        scores: self.scores.clone(),
+                    });
+                }
+                // Practice has nobody else to keep the scores or to bring players back
+                #[cfg(not(feature = "server"))]
+                GameEvent::PlayerKilled { index, killer } => {
+                    if self.practice {
+                        self.record_kill(index, killer);
+                        self.practice_respawns.push((index, PRACTICE_RESPAWN_TIME));
+                        self.queue_event(GameEvent::ScoresChanged);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::RoundEnded { winner } => match self.match_state.phase() {
+                    MatchPhase::Active => {
+                        if let Some(transition) = self.match_state.end_round(winner) {
+                            self.apply_match_transition(transition, network_manager);
+                        }
+                    }
+                    // Nobody is left to fight during warmup so start over
+                    MatchPhase::Warmup => self.load_map(self.rotation.current_map().to_string()),
+                    MatchPhase::RoundEnd { .. } => (),
+                },
+                #[cfg(not(feature = "server"))]
+                GameEvent::MatchPhaseChanged { phase, time_left } => {
+                    self.match_phase = phase;
+                    self.phase_time_left = time_left;
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::MatchSummary { winner, stats } => {
+                    interface.results.show(
+                        &engine.user_interface,
+                        winner,
+                        &stats,
+                        &self.scores,
+                        network_manager.player_index,
+                    );
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::MapVoteStarted { maps, time } => {
+                    interface
+                        .map_vote
+                        .show(&mut engine.user_interface, &maps, time);
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::MapVotesChanged { votes } => {
+                    interface
+                        .map_vote
+                        .update_votes(&engine.user_interface, &votes);
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::Chat { index, text } => {
+                    interface.chat.push_line(
+                        &engine.user_interface,
+                        format!("{}: {}", player_name(&self.scores, index), text),
+                    );
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::ScoresChanged => {
+                    interface.nametags.set_names(&self.scores);
+                    if let Some(level) = &mut self.level {
+                        level.set_player_colors(engine, &self.scores);
+                    }
+                    interface.scoreboard.update(
+                        &engine.user_interface,
+                        &self.scores,
+                        network_manager.player_index,
+                    );
+                }
+                #[cfg(feature = "server")]
                 GameEvent::Joined => {}
                 #[cfg(not(feature = "server"))]
                 GameEvent::Disconnected => {
                     self.active = false;
                 }
+                // Listings are printed as they are for whoever typed the command, they don't belong
+                // in the log
+                #[cfg(feature = "server")]
+                GameEvent::ListPlayers => {
+                    if self.scores.is_empty() {
+                        println!("no players connected");
+                    }
+                    for score in self.scores.iter() {
+                        println!(
+                            "{} {} {} ({} rounds, {} kills, {} deaths)",
+                            score.index,
+                            player_name(&self.scores, score.index),
+                            network_manager
+                                .get_address_for_player(score.index)
+                                .map_or_else(
+                                    || self.bots.describe(score.index),
+                                    |address| address.to_string(),
+                                ),
+                            score.rounds,
+                            score.kills,
+                            score.deaths
+                        );
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::Kick { index } => {
+                    if !network_manager.kick(engine, self, index, "Kicked by the server") {
+                        warn!("no player with index {}", index);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::Ban { index } => {
+                    if !network_manager.ban(engine, self, index) {
+                        warn!("no player with index {}", index);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::BanAddress { address } => {
+                    network_manager.ban_address(engine, self, address, "");
+                }
+                #[cfg(feature = "server")]
+                GameEvent::Unban { address } => {
+                    if network_manager.unban(address) {
+                        info!("unbanned {}", address);
+                    } else {
+                        warn!("{} isn't banned", address);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::ListBans => {
+                    if network_manager.bans().is_empty() {
+                        println!("nobody is banned");
+                    }
+                    for ban in network_manager.bans() {
+                        println!("  {} {}", ban.address, ban.name);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::ChangeMap { map } => {
+                    if Path::new(&["data/levels/", &map, ".rgs"].concat()).exists() {
+                        info!("changing map to {}", map);
+                        self.reset_scores(network_manager);
+                        self.load_map(map);
+                    } else {
+                        warn!("no map named {}", map);
+                    }
+                }
+                #[cfg(feature = "server")]
+                GameEvent::ServerMessage { text } => {
+                    info!("[Server] {}", text);
+                    network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
+                        event: GameEvent::ServerMessage { text },
+                    });
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::ServerMessage { text } => {
+                    interface
+                        .chat
+                        .push_line(&engine.user_interface, format!("[Server] {}", text));
+                }
+                #[cfg(feature = "server")]
+                GameEvent::Shutdown { reason } => {
+                    info!("shutting down: {}", reason);
+                    network_manager.shutdown(&reason);
+                    std::process::exit(0);
+                }
+                // Told before the server stops answering, so there's no waiting for a timeout or
+                // trying to reconnect
+                #[cfg(not(feature = "server"))]
+                GameEvent::ServerShutdown { reason } => {
+                    self.disconnect_reason = Some(format!("Server closed: {}", reason));
+                    self.active = false;
+                }
+                #[cfg(not(feature = "server"))]
+                GameEvent::Reconnecting { attempt } => {
+                    interface.chat.push_line(
+                        &engine.user_interface,
+                        format!("Connection lost, reconnecting (attempt {})", attempt),
+                    );
+                }
                 _ => (),
             }
         }
 
         if let Some(ctx) = self.load_context.clone() {
             if let Ok(mut ctx) = ctx.try_lock() {
-                if let Some(((mut new_level, scene), state)) = ctx.level.take() {
-                    if let Some(old_level) = &mut self.level {
-                        old_level.clean_up(engine);
+                match ctx.level.take() {
+                    Some((Err(e), _)) => {
+                        self.load_context = None;
+                        self.report_error(e);
                     }
+                    Some((Ok((mut new_level, scene)), state)) => {
+                        if let Some(old_level) = &mut self.level {
+                            old_level.clean_up(engine);
+                        }
 
-                    #[cfg(not(feature = "server"))]
-                    network_manager.send_to_server_reliably(&NetworkMessage::GameEvent {
-                        event: GameEvent::Joined,
-                    });
+                        #[cfg(not(feature = "server"))]
+                        if !self.practice {
+                            network_manager.send_to_server_reliably(&NetworkMessage::GameEvent {
+                                event: GameEvent::Joined,
+                            });
+                        }
 
-                    #[cfg(feature = "server")]
-                    network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
-                        event: GameEvent::LoadLevel {
-                            level: new_level.name.clone(),
-                            state: new_level.state.clone(),
-                        },
-                    });
+                        #[cfg(feature = "server")]
+                        network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
+                            event: GameEvent::LoadLevel {
+                                level: new_level.name.clone(),
+                                state: new_level.state.clone(),
+                            },
+                        });
 
-                    new_level.scene = engine.scenes.add(scene);
-                    new_level.apply_state(engine, state);
-                    self.level = Some(new_level);
-                    self.load_context = None;
-
-                    // #[cfg(feature = "server")]
-                    // self.set_menu_visible(false);
-                    // self.engine
-                    //     .user_interface
-                    //     .send_message(WidgetMessage::visibility(
-                    //         self.loading_screen.root,
-                    //         MessageDirection::ToWidget,
-                    //         false,
-                    //     ));
-                    // self.menu.sync_to_model(&mut self.engine, true);
-                } else {
-                    // self.loading_screen.set_progress(
-                    //     &self.engine.user_interface,
-                    //     self.engine.resource_manager.state().loading_progress() as f32 / 100.0,
-                    // );
+                        new_level.recorder = self.recorder.clone();
+                        #[cfg(feature = "server")]
+                        {
+                            new_level.metrics = self.metrics.clone();
+                            self.metrics
+                                .record_level_load(self.level_load_started.elapsed());
+                        }
+                        new_level.scene = engine.scenes.add(scene);
+                        new_level.apply_state(engine, state);
+
+                        #[cfg(feature = "server")]
+                        self.bots
+                            .spawn(engine, &new_level, network_manager, &mut self.scores);
+
+                        #[cfg(not(feature = "server"))]
+                        {
+                            if self.practice {
+                                new_level.practice = true;
+                                self.bots.spawn(
+                                    engine,
+                                    &new_level,
+                                    network_manager,
+                                    &mut self.scores,
+                                );
+                                spawn_practice_player(engine, &new_level, self.settings.skin);
+                                self.queue_event(GameEvent::ScoresChanged);
+                            }
+                            new_level.set_player_colors(engine, &self.scores);
+                        }
+
+                        self.level = Some(new_level);
+                        self.load_context = None;
+
+                        // #[cfg(feature = "server")]
+                        // self.set_menu_visible(false);
+                        // self.engine
+                        //     .user_interface
+                        //     .send_message(WidgetMessage::visibility(
+                        //         self.loading_screen.root,
+                        //         MessageDirection::ToWidget,
+                        //         false,
+                        //     ));
+                        // self.menu.sync_to_model(&mut self.engine, true);
+                    }
+                    None => {
+                        // self.loading_screen.set_progress(
+                        //     &self.engine.user_interface,
+                        //     self.engine.resource_manager.state().loading_progress() as f32 / 100.0,
+                        // );
+                    }
                 }
             }
         }
 
+        // Connected players all have a score entry
+        #[cfg(feature = "server")]
+        {
+            if let Some(transition) = self.match_state.update(dt, self.scores.len(), &self.scores) {
+                self.apply_match_transition(transition, network_manager);
+            }
+
+            if self.match_state.shrink_due(dt) {
+                if let Some(level) = &mut self.level {
+                    level.shrink_floor(network_manager);
+                }
+            }
+
+            network_manager.match_clock = self.match_state.time_left();
+        }
+
+        #[cfg(not(feature = "server"))]
+        {
+            self.phase_time_left = (self.phase_time_left - dt).max(0.0);
+            interface.match_status.update(
+                &engine.user_interface,
+                self.match_phase,
+                self.phase_time_left,
+            );
+            interface.map_vote.update(&engine.user_interface, dt);
+
+            if let Some(level) = &mut self.level {
+                level.apply_view_settings(engine, &self.settings);
+            }
+        }
+
+        // Clients only have bots in practice
+        if let Some(level) = &self.level {
+            self.bots.update(engine, level, network_manager, dt);
+        }
+        #[cfg(feature = "server")]
+        self.metrics
+            .set_players(network_manager.player_count(), self.bots.spawned());
+
+        // Nothing kills players who fall out of the level in practice, put them back instead
+        #[cfg(not(feature = "server"))]
+        if self.practice {
+            let fallen: Vec<u32> = self.level.as_ref().map_or_else(Vec::new, |level| {
+                let scene = &engine.scenes[level.scene];
+                level
+                    .players()
+                    .iter()
+                    .filter(|player| player.get_position(scene).y < PRACTICE_FALL_LIMIT)
+                    .map(|player| player.index)
+                    .collect()
+            });
+            for index in fallen {
+                if let Some(level) = &mut self.level {
+                    level.remove_player(engine, index);
+                }
+                self.respawn_practice_player(engine, index);
+            }
+
+            for (_, time_left) in self.practice_respawns.iter_mut() {
+                *time_left -= dt;
+            }
+            let due: Vec<u32> = self
+                .practice_respawns
+                .iter()
+                .filter(|(_, time_left)| *time_left <= 0.0)
+                .map(|&(index, _)| index)
+                .collect();
+            self.practice_respawns
+                .retain(|(_, time_left)| *time_left > 0.0);
+            for index in due {
+                self.respawn_practice_player(engine, index);
+            }
+        }
+
         if let Some(level) = &mut self.level {
-            level.update(
+            let result = level.update(
                 engine,
-                dt,
+                self.rates,
                 network_manager,
                 elapsed_time,
                 &self.event_sender,
                 interface,
             );
+
+            // Only games on a server count, the network manager isn't accepted in practice or demos
+            #[cfg(not(feature = "server"))]
+            {
+                let mut stats = std::mem::take(&mut level.local_stats);
+                if network_manager.is_accepted() {
+                    stats.playtime = dt;
+                    self.stats.add(&self.settings.player_name, &stats);
+                }
+            }
+
+            if let Err(e) = result {
+                self.report_error(e);
+            }
         }
     }
 
+    #[cfg(not(feature = "server"))]
+    pub fn client_state(&self) -> ClientState {
+        self.client_state
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn set_window_focused(&mut self, focused: bool) {
+        self.window_focused = focused;
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn set_cursor_in_window(&mut self, inside: bool) {
+        self.cursor_in_window = inside;
+    }
+
+    // Keys pressed while the cursor is outside the window are meant for something else
+    #[cfg(not(feature = "server"))]
+    pub fn is_focused(&self) -> bool {
+        self.window_focused && self.cursor_in_window
+    }
+
+    // Game input is only taken while playing or spectating in a window that has focus, with the
+    // cursor grabbed for looking around
+    #[cfg(not(feature = "server"))]
+    pub fn takes_input(&self) -> bool {
+        self.is_focused() && self.client_state.takes_input() && !self.cursor_freed
+    }
+
+    // Frees the cursor for clicking around without opening the menu, and grabs it back. Only does
+    // anything while it would be grabbed.
+    #[cfg(not(feature = "server"))]
+    pub fn toggle_cursor(&mut self) {
+        if self.client_state.captures_cursor() {
+            self.cursor_freed = !self.cursor_freed;
+        }
+    }
+
+    // The cursor is let go of whenever the window loses focus so it can be used elsewhere, and
+    // grabbed again when the window gets it back. Returns whether it should be grabbed when that
+    // changed.
+    #[cfg(not(feature = "server"))]
+    pub fn update_cursor_capture(&mut self) -> Option<bool> {
+        let captured =
+            self.client_state.captures_cursor() && self.window_focused && !self.cursor_freed;
+        if captured == self.cursor_captured {
+            return None;
+        }
+        self.cursor_captured = captured;
+        Some(captured)
+    }
+
+    // Works the state out from the connection, the level and whether a menu is up. Returns the new
+    // state when it changed so the cursor and HUD can follow.
+    #[cfg(not(feature = "server"))]
+    pub fn update_client_state(
+        &mut self,
+        network_manager: &NetworkManager,
+        menu_open: bool,
+        watching_demo: bool,
+    ) -> Option<ClientState> {
+        let in_game = network_manager.is_connected() || self.practice || watching_demo;
+
+        let state = if !in_game {
+            ClientState::Menu
+        } else if network_manager.is_connected() && !network_manager.is_accepted() {
+            ClientState::Connecting
+        } else if self.is_loading() || self.level.is_none() {
+            ClientState::Loading
+        } else if menu_open {
+            ClientState::Paused
+        } else if self
+            .level
+            .as_ref()
+            .map_or(false, |level| level.is_spectating())
+        {
+            ClientState::Spectating
+        } else {
+            ClientState::Playing
+        };
+
+        if state == self.client_state {
+            return None;
+        }
+        trace!("client state {:?} -> {:?}", self.client_state, state);
+        self.client_state = state;
+        // Anything that changes the state, like closing the menu, grabs the cursor again
+        self.cursor_freed = false;
+        Some(state)
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn player_stats(&self, profile: &str) -> PlayerStats {
+        self.stats.get(profile)
+    }
+
+    // Stats are kept in memory while playing and written out between levels and on the way out
+    #[cfg(not(feature = "server"))]
+    pub fn save_stats(&mut self) {
+        self.stats.save();
+    }
+
+    // Clients can't play on without whatever failed, they go back to the menu and show the error
+    // there. The server logs it and keeps going with what it has.
+    fn report_error(&mut self, error: GameError) {
+        error!("{}", error);
+
+        #[cfg(not(feature = "server"))]
+        {
+            self.disconnect_reason = Some(error.to_string());
+            self.active = false;
+        }
+    }
+
+    // Drops everything from the last session so the client can sit in the menu and join again
+    #[cfg(not(feature = "server"))]
+    pub fn leave(&mut self, engine: &mut GameEngine, interface: &mut Interface) {
+        self.save_stats();
+        if let Some(level) = &mut self.level {
+            level.clean_up(engine);
+        }
+        self.level = None;
+        // A level still loading in the background is thrown away when it finishes
+        self.load_context = None;
+        self.scores.clear();
+        self.match_phase = MatchPhase::default();
+        self.phase_time_left = 0.0;
+        self.active = true;
+        self.practice = false;
+        self.practice_respawns.clear();
+        self.bots = Bots::new(0, PRACTICE_BOT_ACCURACY);
+        self.rates = TickRates::default();
+
+        // Events from the old session shouldn't leak into the next one
+        while self.event_receiver.try_recv().is_ok() {}
+
+        interface.results.hide(&engine.user_interface);
+        interface.map_vote.hide(&engine.user_interface);
+        interface
+            .scoreboard
+            .update(&engine.user_interface, &self.scores, None);
+        interface.nametags.update(&mut engine.user_interface, &[]);
+    }
+
+    // Loads the practice map and spawns us in it once it's loaded, the network manager isn't used
+    #[cfg(not(feature = "server"))]
+    pub fn start_practice(&mut self, player_name: &str) -> Result<(), String> {
+        let map = self.settings.practice_map.clone();
+        if !Path::new(&["data/levels/", &map, ".rgs"].concat()).exists() {
+            return Err(format!("No map named {} to practice on", map));
+        }
+
+        self.practice = true;
+        self.bots = Bots::new(
+            self.settings.practice_bots.min(MAX_PRACTICE_BOTS),
+            PRACTICE_BOT_ACCURACY,
+        );
+        self.scores = vec![PlayerScore {
+            index: PRACTICE_PLAYER,
+            name: player_name.to_string(),
+            color: Some(self.settings.player_color),
+            ..Default::default()
+        }];
+        self.queue_event(GameEvent::ScoresChanged);
+        self.load_map(map);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn is_practice(&self) -> bool {
+        self.practice
+    }
+
+    // Puts a practice player back at a spawn point, whether it's us or one of the bots
+    #[cfg(not(feature = "server"))]
+    fn respawn_practice_player(&mut self, engine: &GameEngine, index: u32) {
+        if let Some(level) = &self.level {
+            if !self.bots.respawn(engine, level, index) {
+                spawn_practice_player(engine, level, self.settings.skin);
+            }
+        }
+    }
+
+    // A level is being loaded in the background
+    pub fn is_loading(&self) -> bool {
+        self.load_context.is_some()
+    }
+
     pub fn queue_event(&self, event: GameEvent) {
         self.event_sender.send(event).unwrap();
     }
+
+    #[cfg(feature = "server")]
+    fn apply_match_transition(
+        &mut self,
+        transition: MatchTransition,
+        network_manager: &mut NetworkManager,
+    ) {
+        match transition {
+            // Restart the map so everyone spawns in at the same time
+            MatchTransition::RoundStarted => {
+                self.match_summary = None;
+                self.reset_scores(network_manager);
+                self.load_map(self.rotation.current_map().to_string());
+            }
+            MatchTransition::RoundEnded { winner } => {
+                if let Some(winner) = winner {
+                    self.score_mut(winner).rounds += 1;
+                }
+                self.sync_combat_stats();
+                network_manager.send_to_all_reliably(&NetworkMessage::ScoreUpdate {
+                    scores: self.scores.clone(),
+                });
+
+                let stats = self.compile_match_stats();
+                for player in stats.iter() {
+                    info!(
+                        index = player.index,
+                        kills = player.kills,
+                        deaths = player.deaths,
+                        blocks = player.blocks_destroyed,
+                        shots_fired = player.shots_fired,
+                        shots_hit = player.shots_hit,
+                        damage_dealt = player.damage_dealt,
+                        damage_taken = player.damage_taken,
+                        "round stats"
+                    );
+                }
+                network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
+                    event: GameEvent::MatchSummary {
+                        winner,
+                        stats: stats.clone(),
+                    },
+                });
+                self.match_summary = Some(stats);
+
+                if self.rotation.start_vote() {
+                    network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
+                        event: GameEvent::MapVoteStarted {
+                            maps: self.rotation.maps().to_vec(),
+                            time: self.match_state.round_end_time(),
+                        },
+                    });
+                }
+            }
+            MatchTransition::NextLevel => {
+                let map = if self.rotation.is_voting() {
+                    self.rotation.finish_vote()
+                } else {
+                    self.rotation.advance()
+                };
+
+                self.match_summary = None;
+                self.reset_scores(network_manager);
+                self.load_map(map);
+            }
+        }
+
+        network_manager.send_to_all_reliably(&NetworkMessage::GameEvent {
+            event: GameEvent::MatchPhaseChanged {
+                phase: self.match_state.phase(),
+                time_left: self.match_state.time_left(),
+            },
+        });
+    }
+
+    // Accuracy and damage for the scoreboard, call before sending scores
+    #[cfg(feature = "server")]
+    fn sync_combat_stats(&mut self) {
+        if let Some(level) = &self.level {
+            for score in self.scores.iter_mut() {
+                let stats = level.round_stats(score.index);
+                score.shots = stats.shots_fired;
+                score.hits = stats.shots_hit;
+                score.damage = stats.damage_dealt;
+            }
+        }
+    }
+
+    // Kills and deaths come from the scores, the rest from what the level counted this round
+    #[cfg(feature = "server")]
+    fn compile_match_stats(&self) -> Vec<PlayerMatchStats> {
+        self.scores
+            .iter()
+            .map(|score| {
+                let mut stats = self.level.as_ref().map_or_else(
+                    || PlayerMatchStats {
+                        index: score.index,
+                        ..Default::default()
+                    },
+                    |level| level.round_stats(score.index),
+                );
+                stats.kills = score.kills;
+                stats.deaths = score.deaths;
+                stats
+            })
+            .collect()
+    }
+
+    // Server's clock arrives with snapshots, the local countdown only fills the gaps between them
+    #[cfg(not(feature = "server"))]
+    pub fn sync_match_clock(&mut self, time_left: f32) {
+        self.phase_time_left = time_left;
+    }
+
+    fn load_map(&self, level: String) {
+        self.queue_event(GameEvent::LoadLevel {
+            level,
+            state: LevelState {
+                destroyed_blocks: Vec::new(),
+                cracked_blocks: Vec::new(),
+            },
+        });
+    }
+
+    #[cfg(feature = "server")]
+    fn reset_scores(&mut self, network_manager: &mut NetworkManager) {
+        for score in self.scores.iter_mut() {
+            score.kills = 0;
+            score.deaths = 0;
+            score.shots = 0;
+            score.hits = 0;
+            score.damage = 0.0;
+        }
+
+        network_manager.send_to_all_reliably(&NetworkMessage::ScoreUpdate {
+            scores: self.scores.clone(),
+        });
+    }
+
+    fn score_mut(&mut self, index: u32) -> &mut PlayerScore {
+        if let Some(position) = self.scores.iter().position(|score| score.index == index) {
+            &mut self.scores[position]
+        } else {
+            self.scores.push(PlayerScore {
+                index,
+                ..Default::default()
+            });
+            self.scores.last_mut().unwrap()
+        }
+    }
+
+    fn record_kill(&mut self, index: u32, killer: Option<u32>) {
+        self.score_mut(index).deaths += 1;
+
+        // Falling out of the level or shooting yourself doesn't count as a kill
+        if let Some(killer) = killer.filter(|&killer| killer != index) {
+            self.score_mut(killer).kills += 1;
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn spawn_practice_player(engine: &GameEngine, level: &Level, skin: u32) {
+    let position = level.pick_spawn_point(&engine.scenes[level.scene], PRACTICE_PLAYER);
+    level.queue_event(PlayerEvent::SpawnPlayer {
+        index: PRACTICE_PLAYER,
+        state: SerializablePlayerState {
+            position,
+            ..Default::default()
+        },
+        current_player: true,
+        skin,
+    });
+}
+
+// Display name from the score list, every connected player has an entry
+pub fn player_name(scores: &[PlayerScore], index: u32) -> String {
+    scores
+        .iter()
+        .find(|score| score.index == index && !score.name.is_empty())
+        .map_or_else(|| format!("Player {}", index), |score| score.name.clone())
+}
+
+// White leaves models and nametags as they are
+pub fn player_color(scores: &[PlayerScore], index: u32) -> Color {
+    scores
+        .iter()
+        .find(|score| score.index == index)
+        .and_then(|score| score.color)
+        .map_or(Color::WHITE, |[r, g, b]| Color::opaque(r, g, b))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PlayerScore {
+    pub index: u32,
+    pub name: String,
+    pub color: Option<[u8; 3]>, // Picked by the player, bots don't have one
+    pub kills: u32,
+    pub deaths: u32,
+    pub rounds: u32, // Rounds won, kept from one round to the next
+    // Copied from the level whenever scores are sent, so only as fresh as the last kill
+    pub shots: u32,
+    pub hits: u32,
+    pub damage: f32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum GameEvent {
     Connected,
     Disconnected,
+    Reconnecting {
+        attempt: u32, // Client only, sent while trying to get back to a server that timed out
+    },
     LoadLevel {
         level: String, // Sent from server to tell client what to load
         state: LevelState,
     },
     LoadedLevel,
     Joined,
+    PlayerKilled {
+        index: u32,
+        killer: Option<u32>,
+    },
+    ScoresChanged,
+    Chat {
+        index: u32,
+        text: String,
+    },
+    RoundEnded {
+        winner: Option<u32>, // Server only, sent when one player is left standing
+    },
+    MatchPhaseChanged {
+        phase: MatchPhase,
+        time_left: f32,
+    },
+    // Sent when a round ends, shown until the next map loads
+    MatchSummary {
+        winner: Option<u32>,
+        stats: Vec<PlayerMatchStats>,
+    },
+    MapVoteStarted {
+        maps: Vec<String>,
+        time: f32,
+    },
+    MapVotesChanged {
+        votes: Vec<u32>, // Number of votes for each map
+    },
+    // Server is going away, clients go back to the menu and say why
+    ServerShutdown {
+        reason: String,
+    },
+    // Admin commands from the server console, only ServerMessage is sent to clients
+    ListPlayers,
+    Kick {
+        index: u32,
+    },
+    Ban {
+        index: u32,
+    },
+    BanAddress {
+        address: IpAddr,
+    },
+    Unban {
+        address: IpAddr,
+    },
+    ListBans,
+    ChangeMap {
+        map: String,
+    },
+    ServerMessage {
+        text: String,
+    },
+    Shutdown {
+        reason: String,
+    },
 }
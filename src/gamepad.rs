@@ -0,0 +1,135 @@
+use fyrox::core::algebra::Vector2;
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use tracing::warn;
+
+use crate::input::{Action, GamepadSettings};
+
+// Left stick has to move at least this much before new movement is sent
+const MOVE_THRESHOLD: f32 = 0.05;
+
+pub enum GamepadAction {
+    Action(Action, bool), // Same as pressing or releasing the key bound to the action
+    NextWeapon,
+}
+
+pub struct Gamepad {
+    gilrs: Option<Gilrs>,
+    active: Option<GamepadId>, // Last gamepad used, only one is read at a time
+    movement: Vector2<f32>,    // Last movement that was sent
+}
+
+impl Gamepad {
+    pub fn new(settings: &GamepadSettings) -> Self {
+        let gilrs = if settings.enabled {
+            match Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(e) => {
+                    warn!("gamepad support unavailable: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Self {
+            gilrs,
+            active: None,
+            movement: Vector2::default(),
+        }
+    }
+
+    // Drains gamepad events, returning button presses and releases
+    pub fn poll(&mut self) -> Vec<GamepadAction> {
+        let mut actions = Vec::new();
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return actions,
+        };
+
+        while let Some(event) = gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    self.active = Some(event.id);
+                    actions.extend(button_action(button, true));
+                }
+                EventType::ButtonReleased(button, _) => {
+                    actions.extend(button_action(button, false));
+                }
+                EventType::AxisChanged(..) => self.active = Some(event.id),
+                EventType::Disconnected if self.active == Some(event.id) => self.active = None,
+                _ => (),
+            }
+        }
+
+        actions
+    }
+
+    // New movement (x right, y forward) if the left stick moved enough since it was last sent
+    pub fn movement_changed(&mut self, settings: &GamepadSettings) -> Option<Vector2<f32>> {
+        let movement = self.stick(Axis::LeftStickX, Axis::LeftStickY, settings.deadzone);
+
+        // Always report the stick returning to rest so the player doesn't keep walking
+        let stopped = movement == Vector2::default() && self.movement != Vector2::default();
+        if stopped || (movement - self.movement).norm() > MOVE_THRESHOLD {
+            self.movement = movement;
+            Some(movement)
+        } else {
+            None
+        }
+    }
+
+    // Stops movement when input is taken away by menus or chat
+    pub fn release(&mut self) -> Option<Vector2<f32>> {
+        if self.movement != Vector2::default() {
+            self.movement = Vector2::default();
+            Some(self.movement)
+        } else {
+            None
+        }
+    }
+
+    // Yaw and pitch change in degrees for this update, same directions as mouse movement
+    pub fn look(&self, settings: &GamepadSettings, dt: f32) -> Vector2<f32> {
+        let stick = self.stick(Axis::RightStickX, Axis::RightStickY, settings.deadzone);
+        Vector2::new(stick.x, -stick.y) * settings.look_sensitivity * dt
+    }
+
+    fn stick(&self, x: Axis, y: Axis, deadzone: f32) -> Vector2<f32> {
+        match (&self.gilrs, self.active) {
+            (Some(gilrs), Some(id)) => {
+                let gamepad = gilrs.gamepad(id);
+                apply_deadzone(Vector2::new(gamepad.value(x), gamepad.value(y)), deadzone)
+            }
+            _ => Vector2::default(),
+        }
+    }
+}
+
+// Radial deadzone, rescaled so values start from 0 at the edge of the deadzone
+fn apply_deadzone(stick: Vector2<f32>, deadzone: f32) -> Vector2<f32> {
+    let deadzone = deadzone.clamp(0.0, 0.9);
+    let length = stick.norm();
+    if length <= deadzone {
+        return Vector2::default();
+    }
+
+    stick / length * ((length - deadzone) / (1.0 - deadzone)).min(1.0)
+}
+
+fn button_action(button: Button, pressed: bool) -> Option<GamepadAction> {
+    let action = match button {
+        Button::South => Action::Jump,
+        Button::East => Action::Crouch,
+        Button::LeftThumb => Action::Sprint,
+        Button::LeftTrigger2 => Action::Fly,
+        Button::RightTrigger2 => Action::Shoot,
+        Button::LeftTrigger => Action::Aim,
+        Button::West => Action::Reload,
+        Button::RightTrigger => Action::ThrowGrenade,
+        Button::North if pressed => return Some(GamepadAction::NextWeapon),
+        _ => return None,
+    };
+
+    Some(GamepadAction::Action(action, pressed))
+}
@@ -0,0 +1,92 @@
+use std::{
+    ops::{Index, IndexMut},
+    sync::Arc,
+};
+
+use fyrox::{
+    core::{
+        algebra::Vector2,
+        pool::{Handle, Pool},
+        task::TaskPool,
+    },
+    engine::resource_manager::ResourceManager,
+    gui::UserInterface,
+    scene::Scene,
+};
+
+// The server never renders anything, this is only used for UI layout of the (unused) interface
+const FRAME_SIZE: Vector2<f32> = Vector2::new(1280.0, 720.0);
+
+#[derive(Default)]
+pub struct SceneContainer {
+    pool: Pool<Scene>,
+}
+
+impl SceneContainer {
+    pub fn add(&mut self, scene: Scene) -> Handle<Scene> {
+        self.pool.spawn(scene)
+    }
+
+    pub fn remove(&mut self, handle: Handle<Scene>) {
+        self.pool.free(handle);
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Scene> {
+        self.pool.iter_mut()
+    }
+}
+
+impl Index<Handle<Scene>> for SceneContainer {
+    type Output = Scene;
+
+    fn index(&self, index: Handle<Scene>) -> &Self::Output {
+        &self.pool[index]
+    }
+}
+
+impl IndexMut<Handle<Scene>> for SceneContainer {
+    fn index_mut(&mut self, index: Handle<Scene>) -> &mut Self::Output {
+        &mut self.pool[index]
+    }
+}
+
+// Stand in for the engine on dedicated servers. Has the parts of the engine the game logic uses
+// but no window, renderer or sound output so it runs on machines without a display or GPU.
+pub struct HeadlessEngine {
+    pub scenes: SceneContainer,
+    pub resource_manager: ResourceManager,
+    pub user_interface: UserInterface,
+}
+
+impl HeadlessEngine {
+    pub fn new() -> Self {
+        Self {
+            scenes: SceneContainer::default(),
+            resource_manager: ResourceManager::new(Arc::new(TaskPool::new())),
+            user_interface: UserInterface::new(FRAME_SIZE),
+        }
+    }
+
+    pub fn frame_size(&self) -> (u32, u32) {
+        (FRAME_SIZE.x as u32, FRAME_SIZE.y as u32)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.resource_manager.state().update(dt);
+
+        // Steps physics and updates the scene graph
+        for scene in self.scenes.iter_mut() {
+            scene.update(FRAME_SIZE, dt);
+        }
+
+        // Nobody looks at the interface, just keep the message queue from growing
+        self.user_interface.update(FRAME_SIZE, dt);
+        while self.user_interface.poll_message().is_some() {}
+    }
+}
+
+impl Default for HeadlessEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
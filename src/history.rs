@@ -0,0 +1,113 @@
+// Ring buffer of timestamped values, oldest first. Holds the states client reconciliation compares
+// against and the views the killcam plays back, and anything else that needs to look back a little
+// way in time. Pushing past the capacity drops the oldest entry.
+
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    entries: VecDeque<(f32, T)>,
+    capacity: usize,
+}
+
+// Only keeps the latest entry until it's given a capacity
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // Shrinking drops the oldest entries
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn push(&mut self, time: f32, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((time, value));
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() >= self.capacity
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn oldest(&self) -> Option<&T> {
+        self.entries.front().map(|(_, value)| value)
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn oldest_time(&self) -> Option<f32> {
+        self.entries.front().map(|(time, _)| *time)
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn newest_time(&self) -> Option<f32> {
+        self.entries.back().map(|(time, _)| *time)
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn pop_oldest(&mut self) -> Option<T> {
+        self.entries.pop_front().map(|(_, value)| value)
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn drop_older_than(&mut self, time: f32) {
+        while self.entries.front().map_or(false, |(t, _)| *t < time) {
+            self.entries.pop_front();
+        }
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entries.iter_mut().map(|(_, value)| value)
+    }
+
+    // Entries on either side of a time and how far between them it is, 0 to 1. Times outside the
+    // history get the oldest or newest entry on both sides.
+    #[cfg(not(feature = "server"))]
+    pub fn around(&self, time: f32) -> Option<(&T, &T, f32)> {
+        let next = match self.entries.iter().position(|(t, _)| *t >= time) {
+            Some(next) => next,
+            None => {
+                let (_, last) = self.entries.back()?;
+                return Some((last, last, 0.0));
+            }
+        };
+        if next == 0 {
+            let (_, first) = &self.entries[0];
+            return Some((first, first, 0.0));
+        }
+
+        let (from_time, from) = &self.entries[next - 1];
+        let (to_time, to) = &self.entries[next];
+        let t = (time - from_time) / (to_time - from_time).max(f32::EPSILON);
+        Some((from, to, t))
+    }
+}
@@ -0,0 +1,1357 @@
+use fyrox::{
+    core::{algebra::Vector2, color::Color, pool::Handle},
+    gui::{
+        border::BorderBuilder,
+        brush::Brush,
+        button::{ButtonBuilder, ButtonMessage},
+        grid::{Column, GridBuilder, Row},
+        message::{MessageDirection, UiMessage},
+        progress_bar::{ProgressBarBuilder, ProgressBarMessage},
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, HorizontalAlignment, Orientation, Thickness, UiNode, UserInterface,
+        VerticalAlignment,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    game::{player_color, player_name, PlayerScore},
+    match_state::{accuracy, MatchPhase, PlayerMatchStats},
+};
+
+const SCOREBOARD_WIDTH: f32 = 560.0;
+const SCOREBOARD_HEIGHT: f32 = 300.0;
+const RESULTS_TOP: f32 = 40.0;
+const MAP_VOTE_WIDTH: f32 = 300.0;
+const MATCH_STATUS_WIDTH: f32 = 200.0;
+const HIT_MARKER_SIZE: f32 = 24.0;
+const HIT_MARKER_TIME: f32 = 0.15;
+const MAX_DAMAGE_MARKERS: usize = 4;
+const DAMAGE_MARKER_SIZE: f32 = 12.0;
+const DAMAGE_MARKER_RADIUS: f32 = 80.0; // Distance from the center of the screen
+const DAMAGE_MARKER_TIME: f32 = 1.0;
+// Pixels the crosshair lines move out per degree of weapon spread
+const CROSSHAIR_SPREAD_SCALE: f32 = 4.0;
+const CROSSHAIR_SPREAD_SPEED: f32 = 10.0;
+// Below the FPS counter
+const DEBUG_OVERLAY_TOP: f32 = 20.0;
+// Frame time graph, a bar per frame. Bars are full height at PERF_GRAPH_SCALE seconds.
+const PERF_GRAPH_SAMPLES: usize = 100;
+const PERF_BAR_WIDTH: f32 = 2.0;
+const PERF_GRAPH_HEIGHT: f32 = 60.0;
+const PERF_GRAPH_SCALE: f32 = 0.05;
+const PERF_WIDTH: f32 = PERF_GRAPH_SAMPLES as f32 * PERF_BAR_WIDTH;
+const PERF_HEIGHT: f32 = 140.0;
+const PERF_SMOOTHING: f32 = 0.1; // Weight of the newest sample in the averaged times
+const NAMETAG_WIDTH: f32 = 160.0;
+const NAMETAG_HEIGHT: f32 = 20.0;
+const STATUS_WIDTH: f32 = 200.0;
+const GAUGE_HEIGHT: f32 = 18.0;
+
+// Where a widget is placed relative to the window, used to put HUD elements back in place when
+// the window is resized
+#[derive(Debug, Clone, Copy)]
+pub enum Anchor {
+    Top,
+    Center,
+    TopRight,
+    BottomLeft,
+}
+
+impl Anchor {
+    // Top left corner of a widget of the given size, offset is added on top
+    pub fn position(
+        self,
+        window_width: f32,
+        window_height: f32,
+        size: Vector2<f32>,
+        offset: Vector2<f32>,
+    ) -> Vector2<f32> {
+        let position = match self {
+            Anchor::Top => Vector2::new((window_width - size.x) / 2.0, 0.0),
+            Anchor::Center => Vector2::new(
+                (window_width - size.x) / 2.0,
+                (window_height - size.y) / 2.0,
+            ),
+            Anchor::TopRight => Vector2::new(window_width - size.x, 0.0),
+            Anchor::BottomLeft => Vector2::new(0.0, window_height - size.y),
+        };
+        position + offset
+    }
+}
+
+pub fn move_widget(ui: &UserInterface, widget: Handle<UiNode>, position: Vector2<f32>) {
+    ui.send_message(WidgetMessage::desired_position(
+        widget,
+        MessageDirection::ToWidget,
+        position,
+    ));
+}
+
+fn format_accuracy(accuracy: Option<f32>) -> String {
+    accuracy.map_or_else(|| "-".to_string(), |accuracy| format!("{:.0}%", accuracy))
+}
+
+fn format_scores(scores: &[PlayerScore], player_index: Option<u32>) -> String {
+    let mut scores = scores.to_vec();
+    scores.sort_by(|a, b| {
+        b.rounds
+            .cmp(&a.rounds)
+            .then(b.kills.cmp(&a.kills))
+            .then(a.deaths.cmp(&b.deaths))
+    });
+
+    let mut text = format!(
+        "{:<20}{:>8}{:>8}{:>8}{:>8}{:>8}\n",
+        "Player", "Rounds", "Kills", "Deaths", "Acc", "Damage"
+    );
+    for score in scores.iter() {
+        // Mark the local player's row
+        let marker = if Some(score.index) == player_index {
+            ">"
+        } else {
+            " "
+        };
+
+        text += &format!(
+            "{} {:<18}{:>8}{:>8}{:>8}{:>8}{:>8.0}\n",
+            marker,
+            player_name(&scores, score.index),
+            score.rounds,
+            score.kills,
+            score.deaths,
+            format_accuracy(accuracy(score.shots, score.hits)),
+            score.damage
+        );
+    }
+
+    text
+}
+
+// Best player of the round first, damage settles ties
+fn format_match_stats(
+    stats: &[PlayerMatchStats],
+    scores: &[PlayerScore],
+    player_index: Option<u32>,
+) -> String {
+    let mut stats = stats.to_vec();
+    stats.sort_by(|a, b| {
+        b.kills.cmp(&a.kills).then(a.deaths.cmp(&b.deaths)).then(
+            b.damage_dealt
+                .partial_cmp(&a.damage_dealt)
+                .unwrap_or(Ordering::Equal),
+        )
+    });
+
+    let mut text = format!(
+        "{:<20}{:>8}{:>8}{:>8}{:>8}{:>8}\n",
+        "Player", "K/D", "Acc", "Blocks", "Damage", "Taken"
+    );
+    for row in stats.iter() {
+        let marker = if Some(row.index) == player_index {
+            ">"
+        } else {
+            " "
+        };
+
+        text += &format!(
+            "{} {:<18}{:>8}{:>8}{:>8}{:>8.0}{:>8.0}\n",
+            marker,
+            player_name(scores, row.index),
+            format!("{}/{}", row.kills, row.deaths),
+            format_accuracy(row.accuracy()),
+            row.blocks_destroyed,
+            row.damage_dealt,
+            row.damage_taken
+        );
+    }
+
+    text
+}
+
+pub struct Scoreboard {
+    root: Handle<UiNode>,
+    text: Handle<UiNode>,
+}
+
+impl Scoreboard {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let text =
+            TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(10.0))).build(ctx);
+
+        // Hidden until Tab is held
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_width(SCOREBOARD_WIDTH)
+                .with_height(SCOREBOARD_HEIGHT)
+                .with_desired_position(Self::position(window_width, window_height))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 160)))
+                .with_child(text),
+        )
+        .build(ctx);
+
+        Self { root, text }
+    }
+
+    fn position(window_width: f32, window_height: f32) -> Vector2<f32> {
+        Anchor::Center.position(
+            window_width,
+            window_height,
+            Vector2::new(SCOREBOARD_WIDTH, SCOREBOARD_HEIGHT),
+            Vector2::new(0.0, 0.0),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        move_widget(ui, self.root, Self::position(window_width, window_height));
+    }
+
+    pub fn set_visible(&self, ui: &UserInterface, visible: bool) {
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn update(&self, ui: &UserInterface, scores: &[PlayerScore], player_index: Option<u32>) {
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            format_scores(scores, player_index),
+        ));
+    }
+}
+
+// Final scores shown at the end of a round
+pub struct ResultsScreen {
+    root: Handle<UiNode>,
+    title: Handle<UiNode>,
+    text: Handle<UiNode>,
+    visible: bool,
+}
+
+impl ResultsScreen {
+    pub fn new(ctx: &mut BuildContext, window_width: f32) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(10.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+        let text =
+            TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(10.0))).build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_width(SCOREBOARD_WIDTH)
+                .with_height(SCOREBOARD_HEIGHT)
+                .with_desired_position(Self::position(window_width))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(WidgetBuilder::new().with_children([title, text]))
+                        .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            title,
+            text,
+            visible: false,
+        }
+    }
+
+    fn position(window_width: f32) -> Vector2<f32> {
+        Anchor::Top.position(
+            window_width,
+            0.0,
+            Vector2::new(SCOREBOARD_WIDTH, SCOREBOARD_HEIGHT),
+            Vector2::new(0.0, RESULTS_TOP),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        move_widget(ui, self.root, Self::position(window_width));
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &UserInterface,
+        winner: Option<u32>,
+        stats: &[PlayerMatchStats],
+        scores: &[PlayerScore],
+        player_index: Option<u32>,
+    ) {
+        self.visible = true;
+
+        let title = match winner {
+            Some(winner) if Some(winner) == player_index => String::from("You win!"),
+            Some(winner) => format!("{} wins!", player_name(scores, winner)),
+            None => String::from("Draw"),
+        };
+
+        ui.send_message(TextMessage::text(
+            self.title,
+            MessageDirection::ToWidget,
+            title,
+        ));
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            format_match_stats(stats, scores, player_index),
+        ));
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            true,
+        ));
+    }
+
+    pub fn hide(&mut self, ui: &UserInterface) {
+        self.visible = false;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            false,
+        ));
+    }
+}
+
+// Phase and time left shown at the top of the screen
+pub struct MatchStatus {
+    text: Handle<UiNode>,
+    current: String,
+}
+
+impl MatchStatus {
+    pub fn new(ctx: &mut BuildContext, window_width: f32) -> Self {
+        let text = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_width(MATCH_STATUS_WIDTH)
+                .with_desired_position(Self::position(window_width)),
+        )
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .build(ctx);
+
+        Self {
+            text,
+            current: String::new(),
+        }
+    }
+
+    fn position(window_width: f32) -> Vector2<f32> {
+        Anchor::Top.position(
+            window_width,
+            0.0,
+            Vector2::new(MATCH_STATUS_WIDTH, 0.0),
+            Vector2::new(0.0, 10.0),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        move_widget(ui, self.text, Self::position(window_width));
+    }
+
+    pub fn update(&mut self, ui: &UserInterface, phase: MatchPhase, time_left: f32) {
+        let seconds = time_left.ceil() as u32;
+        let time = format!("{}:{:02}", seconds / 60, seconds % 60);
+
+        let status = match phase {
+            MatchPhase::Warmup if time_left > 0.0 => format!("Warmup {}", time),
+            MatchPhase::Warmup => String::from("Waiting for players"),
+            MatchPhase::Active if time_left > 0.0 => time,
+            MatchPhase::Active => String::new(),
+            MatchPhase::RoundEnd { .. } => String::from("Round over"),
+        };
+
+        // Only send a message when the text actually changes
+        if status != self.current {
+            ui.send_message(TextMessage::text(
+                self.text,
+                MessageDirection::ToWidget,
+                status.clone(),
+            ));
+            self.current = status;
+        }
+    }
+}
+
+struct MapOption {
+    button: Handle<UiNode>,
+    text: Handle<UiNode>,
+    name: String,
+}
+
+// Shown at the end of a round so players can pick the next map
+pub struct MapVoteScreen {
+    root: Handle<UiNode>,
+    title: Handle<UiNode>,
+    panel: Handle<UiNode>,
+    options: Vec<MapOption>,
+    choice: Option<u32>,
+    time_left: f32,
+    visible: bool,
+}
+
+impl MapVoteScreen {
+    pub fn new(ctx: &mut BuildContext, window_width: f32) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_margin(Thickness::uniform(10.0))
+                .with_child(title),
+        )
+        .build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_width(MAP_VOTE_WIDTH)
+                .with_desired_position(Self::position(window_width))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(panel),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            title,
+            panel,
+            options: Vec::new(),
+            choice: None,
+            time_left: 0.0,
+            visible: false,
+        }
+    }
+
+    // Below the results screen
+    fn position(window_width: f32) -> Vector2<f32> {
+        Anchor::Top.position(
+            window_width,
+            0.0,
+            Vector2::new(MAP_VOTE_WIDTH, 0.0),
+            Vector2::new(0.0, RESULTS_TOP + SCOREBOARD_HEIGHT + 20.0),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        move_widget(ui, self.root, Self::position(window_width));
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn show(&mut self, ui: &mut UserInterface, maps: &[String], time: f32) {
+        // Options from a previous vote are replaced
+        for option in self.options.drain(..) {
+            ui.send_message(WidgetMessage::remove(
+                option.button,
+                MessageDirection::ToWidget,
+            ));
+        }
+
+        for name in maps.iter() {
+            let ctx = &mut ui.build_ctx();
+            let text = TextBuilder::new(WidgetBuilder::new())
+                .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                .with_text(name)
+                .build(ctx);
+            let button = ButtonBuilder::new(
+                WidgetBuilder::new()
+                    .with_height(30.0)
+                    .with_margin(Thickness::uniform(4.0)),
+            )
+            .with_content(text)
+            .build(ctx);
+
+            ui.send_message(WidgetMessage::link(
+                button,
+                MessageDirection::ToWidget,
+                self.panel,
+            ));
+
+            self.options.push(MapOption {
+                button,
+                text,
+                name: name.clone(),
+            });
+        }
+
+        self.choice = None;
+        self.time_left = time;
+        self.visible = true;
+
+        self.update_votes(ui, &vec![0; maps.len()]);
+        self.update_title(ui);
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            true,
+        ));
+    }
+
+    pub fn hide(&mut self, ui: &UserInterface) {
+        self.visible = false;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            false,
+        ));
+    }
+
+    pub fn update(&mut self, ui: &UserInterface, dt: f32) {
+        if !self.visible {
+            return;
+        }
+
+        // Only update the countdown text when the displayed second changes
+        let previous = self.time_left.ceil();
+        self.time_left = (self.time_left - dt).max(0.0);
+        if self.time_left.ceil() != previous {
+            self.update_title(ui);
+        }
+    }
+
+    pub fn update_votes(&self, ui: &UserInterface, votes: &[u32]) {
+        for (i, (option, count)) in self.options.iter().zip(votes.iter()).enumerate() {
+            // Mark the map the local player voted for
+            let marker = if self.choice == Some(i as u32) {
+                "> "
+            } else {
+                ""
+            };
+
+            ui.send_message(TextMessage::text(
+                option.text,
+                MessageDirection::ToWidget,
+                format!("{}{} ({})", marker, option.name, count),
+            ));
+        }
+    }
+
+    fn update_title(&self, ui: &UserInterface) {
+        ui.send_message(TextMessage::text(
+            self.title,
+            MessageDirection::ToWidget,
+            format!("Vote for the next map ({})", self.time_left.ceil()),
+        ));
+    }
+
+    // Returns the index of the map that was voted for
+    pub fn handle_ui_message(&mut self, message: &UiMessage) -> Option<u32> {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if let Some(choice) = self
+                .options
+                .iter()
+                .position(|option| option.button == message.destination())
+            {
+                self.choice = Some(choice as u32);
+                return self.choice;
+            }
+        }
+
+        None
+    }
+}
+
+// Flashes over the crosshair when the server confirms a hit, red for kills
+pub struct HitMarker {
+    text: Handle<UiNode>,
+    timer: f32,
+}
+
+impl HitMarker {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let text = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_width(HIT_MARKER_SIZE)
+                .with_height(HIT_MARKER_SIZE)
+                .with_desired_position(Self::position(window_width, window_height))
+                .with_visibility(false),
+        )
+        .with_text("X")
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .with_vertical_text_alignment(VerticalAlignment::Center)
+        .build(ctx);
+
+        Self { text, timer: 0.0 }
+    }
+
+    fn position(window_width: f32, window_height: f32) -> Vector2<f32> {
+        Anchor::Center.position(
+            window_width,
+            window_height,
+            Vector2::repeat(HIT_MARKER_SIZE),
+            Vector2::new(0.0, 0.0),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        move_widget(ui, self.text, Self::position(window_width, window_height));
+    }
+
+    pub fn show(&mut self, ui: &UserInterface, killed: bool) {
+        let color = if killed { Color::RED } else { Color::WHITE };
+        ui.send_message(WidgetMessage::foreground(
+            self.text,
+            MessageDirection::ToWidget,
+            Brush::Solid(color),
+        ));
+        ui.send_message(WidgetMessage::visibility(
+            self.text,
+            MessageDirection::ToWidget,
+            true,
+        ));
+        self.timer = HIT_MARKER_TIME;
+    }
+
+    pub fn update(&mut self, ui: &UserInterface, dt: f32) {
+        if self.timer <= 0.0 {
+            return;
+        }
+
+        self.timer -= dt;
+        if self.timer <= 0.0 {
+            ui.send_message(WidgetMessage::visibility(
+                self.text,
+                MessageDirection::ToWidget,
+                false,
+            ));
+        }
+    }
+}
+
+struct DamageMarker {
+    widget: Handle<UiNode>,
+    source: Option<u32>, // Player that did the damage, None if the marker is free
+    timer: f32,
+}
+
+// Red markers around the crosshair pointing towards whoever is shooting the local player
+pub struct DamageIndicator {
+    markers: Vec<DamageMarker>,
+    center: Vector2<f32>,
+}
+
+impl DamageIndicator {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let markers = (0..MAX_DAMAGE_MARKERS)
+            .map(|_| DamageMarker {
+                widget: BorderBuilder::new(
+                    WidgetBuilder::new()
+                        .with_width(DAMAGE_MARKER_SIZE)
+                        .with_height(DAMAGE_MARKER_SIZE)
+                        .with_visibility(false)
+                        .with_background(Brush::Solid(Color::from_rgba(220, 30, 30, 200))),
+                )
+                .build(ctx),
+                source: None,
+                timer: 0.0,
+            })
+            .collect();
+
+        Self {
+            markers,
+            center: Vector2::new(window_width / 2.0, window_height / 2.0),
+        }
+    }
+
+    // Visible markers move to the new center on the next update
+    pub fn resize(&mut self, window_width: f32, window_height: f32) {
+        self.center = Vector2::new(window_width / 2.0, window_height / 2.0);
+    }
+
+    // Reuses the marker already pointing at this source, otherwise the one closest to fading out
+    pub fn show(&mut self, ui: &UserInterface, source: u32) {
+        let marker = match self.markers.iter().position(|m| m.source == Some(source)) {
+            Some(i) => &mut self.markers[i],
+            None => self
+                .markers
+                .iter_mut()
+                .min_by(|a, b| a.timer.partial_cmp(&b.timer).unwrap_or(Ordering::Equal))
+                .unwrap(),
+        };
+
+        marker.source = Some(source);
+        marker.timer = DAMAGE_MARKER_TIME;
+        ui.send_message(WidgetMessage::visibility(
+            marker.widget,
+            MessageDirection::ToWidget,
+            true,
+        ));
+    }
+
+    // Angle is looked up every update so markers follow as the player turns. It's in radians
+    // relative to where the local player is looking, positive to the left.
+    pub fn update<F: Fn(u32) -> Option<f32>>(&mut self, ui: &UserInterface, dt: f32, angle_to: F) {
+        for marker in self.markers.iter_mut() {
+            let source = match marker.source {
+                Some(source) => source,
+                None => continue,
+            };
+
+            marker.timer -= dt;
+            let angle = match angle_to(source) {
+                Some(angle) if marker.timer > 0.0 => angle,
+                // Faded out or the shooter is gone
+                _ => {
+                    marker.source = None;
+                    ui.send_message(WidgetMessage::visibility(
+                        marker.widget,
+                        MessageDirection::ToWidget,
+                        false,
+                    ));
+                    continue;
+                }
+            };
+
+            let offset = Vector2::new(-angle.sin(), -angle.cos()) * DAMAGE_MARKER_RADIUS;
+            move_widget(
+                ui,
+                marker.widget,
+                self.center + offset - Vector2::repeat(DAMAGE_MARKER_SIZE / 2.0),
+            );
+            ui.send_message(WidgetMessage::opacity(
+                marker.widget,
+                MessageDirection::ToWidget,
+                Some((marker.timer / DAMAGE_MARKER_TIME).min(1.0)),
+            ));
+        }
+    }
+}
+
+// Ping, loss and bandwidth to the server with what's buffered, toggled with a key. The level draws
+// colliders, shots and server positions in the scene while it's up.
+pub struct DebugOverlay {
+    text: Handle<UiNode>,
+    visible: bool,
+}
+
+impl DebugOverlay {
+    pub fn new(ctx: &mut BuildContext) -> Self {
+        let text = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_desired_position(Vector2::new(0.0, DEBUG_OVERLAY_TOP))
+                .with_visibility(false),
+        )
+        .build(ctx);
+
+        Self {
+            text,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self, ui: &UserInterface) {
+        self.set_visible(ui, !self.visible);
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+        ui.send_message(WidgetMessage::visibility(
+            self.text,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn update(&self, ui: &UserInterface, stats: Option<String>, details: &str) {
+        let stats = stats.unwrap_or_else(|| String::from("Not connected"));
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            format!("{}\n{}", stats, details),
+        ));
+    }
+}
+
+// Milliseconds, averaged so the numbers can be read
+#[derive(Default)]
+struct PerfTimes {
+    frame: f32,
+    render: f32,
+    update: f32,
+    physics: f32,
+}
+
+fn smooth(average: &mut f32, sample: Duration) {
+    let sample = sample.as_secs_f32() * 1000.0;
+    *average += (sample - *average) * PERF_SMOOTHING;
+}
+
+// Rolling graph of frame times with how long rendering and each part of a tick take, toggled with
+// a key. Game and physics times are per tick, physics being the engine's update of the scenes.
+pub struct PerfHud {
+    root: Handle<UiNode>,
+    text: Handle<UiNode>,
+    bars: Vec<Handle<UiNode>>,
+    frame_times: VecDeque<f32>, // Seconds, newest last
+    last_frame: Option<Instant>,
+    times: PerfTimes,
+    visible: bool,
+}
+
+impl PerfHud {
+    pub fn new(ctx: &mut BuildContext, window_width: f32) -> Self {
+        let text = TextBuilder::new(WidgetBuilder::new()).build(ctx);
+
+        let bars: Vec<Handle<UiNode>> = (0..PERF_GRAPH_SAMPLES)
+            .map(|_| {
+                BorderBuilder::new(
+                    WidgetBuilder::new()
+                        .with_width(PERF_BAR_WIDTH)
+                        .with_height(0.0)
+                        .with_vertical_alignment(VerticalAlignment::Bottom),
+                )
+                .build(ctx)
+            })
+            .collect();
+        let graph = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_height(PERF_GRAPH_HEIGHT)
+                .with_children(bars.iter().copied()),
+        )
+        .with_orientation(Orientation::Horizontal)
+        .build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_width(PERF_WIDTH)
+                .with_height(PERF_HEIGHT)
+                .with_desired_position(Self::position(window_width))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 160)))
+                .with_child(
+                    StackPanelBuilder::new(WidgetBuilder::new().with_children([graph, text]))
+                        .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            text,
+            bars,
+            frame_times: VecDeque::with_capacity(PERF_GRAPH_SAMPLES),
+            last_frame: None,
+            times: PerfTimes::default(),
+            visible: false,
+        }
+    }
+
+    fn position(window_width: f32) -> Vector2<f32> {
+        Anchor::TopRight.position(
+            window_width,
+            0.0,
+            Vector2::new(PERF_WIDTH, PERF_HEIGHT),
+            Vector2::zeros(),
+        )
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32) {
+        move_widget(ui, self.root, Self::position(window_width));
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self, ui: &UserInterface) {
+        self.visible = !self.visible;
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            self.visible,
+        ));
+    }
+
+    // Call after each frame is rendered, frame time is the time since the last one
+    pub fn record_frame(&mut self, render_time: Duration) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            let frame_time = now - last_frame;
+            if self.frame_times.len() >= PERF_GRAPH_SAMPLES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(frame_time.as_secs_f32());
+            smooth(&mut self.times.frame, frame_time);
+        }
+        smooth(&mut self.times.render, render_time);
+    }
+
+    pub fn record_tick(&mut self, update_time: Duration, physics_time: Duration) {
+        smooth(&mut self.times.update, update_time);
+        smooth(&mut self.times.physics, physics_time);
+    }
+
+    pub fn update(&self, ui: &UserInterface) {
+        // Oldest bars on the left, empty until there have been enough frames
+        let empty = PERF_GRAPH_SAMPLES - self.frame_times.len();
+        for (i, &bar) in self.bars.iter().enumerate() {
+            let frame_time = i
+                .checked_sub(empty)
+                .map_or(0.0, |sample| self.frame_times[sample]);
+            let color = if frame_time <= 1.0 / 60.0 {
+                Color::opaque(80, 200, 80)
+            } else if frame_time <= 1.0 / 30.0 {
+                Color::opaque(230, 200, 60)
+            } else {
+                Color::opaque(230, 60, 50)
+            };
+
+            ui.send_message(WidgetMessage::height(
+                bar,
+                MessageDirection::ToWidget,
+                (frame_time / PERF_GRAPH_SCALE).min(1.0) * PERF_GRAPH_HEIGHT,
+            ));
+            ui.send_message(WidgetMessage::background(
+                bar,
+                MessageDirection::ToWidget,
+                Brush::Solid(color),
+            ));
+        }
+
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            format!(
+                "frame {:.1} ms\nrender {:.1} ms\ngame {:.1} ms\nphysics {:.1} ms",
+                self.times.frame, self.times.render, self.times.update, self.times.physics
+            ),
+        ));
+    }
+}
+
+struct Nametag {
+    index: u32,
+    widget: Handle<UiNode>,
+    name: String, // What the widget currently shows
+    color: Color,
+}
+
+// Names floating over other players, widgets are made the first time a player shows up
+#[derive(Default)]
+pub struct Nametags {
+    tags: Vec<Nametag>,
+    names: HashMap<u32, (String, Color)>,
+}
+
+impl Nametags {
+    pub fn set_names(&mut self, scores: &[PlayerScore]) {
+        self.names = scores
+            .iter()
+            .map(|score| {
+                (
+                    score.index,
+                    (
+                        player_name(scores, score.index),
+                        player_color(scores, score.index),
+                    ),
+                )
+            })
+            .collect();
+    }
+
+    // Takes the players to label this frame as (index, screen position, opacity), everyone else
+    // is hidden
+    pub fn update(&mut self, ui: &mut UserInterface, visible: &[(u32, Vector2<f32>, f32)]) {
+        for &(index, position, opacity) in visible {
+            let tag = match self.tags.iter().position(|t| t.index == index) {
+                Some(i) => &mut self.tags[i],
+                None => {
+                    let widget = TextBuilder::new(
+                        WidgetBuilder::new()
+                            .with_width(NAMETAG_WIDTH)
+                            .with_height(NAMETAG_HEIGHT)
+                            .with_visibility(false),
+                    )
+                    .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                    .build(&mut ui.build_ctx());
+
+                    self.tags.push(Nametag {
+                        index,
+                        widget,
+                        name: String::new(),
+                        color: Color::WHITE,
+                    });
+                    self.tags.last_mut().unwrap()
+                }
+            };
+
+            let (name, color) = self
+                .names
+                .get(&index)
+                .cloned()
+                .unwrap_or_else(|| (format!("Player {}", index), Color::WHITE));
+            if name != tag.name {
+                ui.send_message(TextMessage::text(
+                    tag.widget,
+                    MessageDirection::ToWidget,
+                    name.clone(),
+                ));
+                tag.name = name;
+            }
+            if color != tag.color {
+                ui.send_message(WidgetMessage::foreground(
+                    tag.widget,
+                    MessageDirection::ToWidget,
+                    Brush::Solid(color),
+                ));
+                tag.color = color;
+            }
+
+            // Centered above the head
+            move_widget(
+                ui,
+                tag.widget,
+                position - Vector2::new(NAMETAG_WIDTH / 2.0, NAMETAG_HEIGHT),
+            );
+            ui.send_message(WidgetMessage::opacity(
+                tag.widget,
+                MessageDirection::ToWidget,
+                Some(opacity),
+            ));
+            ui.send_message(WidgetMessage::visibility(
+                tag.widget,
+                MessageDirection::ToWidget,
+                true,
+            ));
+        }
+
+        for tag in self.tags.iter() {
+            if !visible.iter().any(|&(index, _, _)| index == tag.index) {
+                ui.send_message(WidgetMessage::visibility(
+                    tag.widget,
+                    MessageDirection::ToWidget,
+                    false,
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum CrosshairStyle {
+    Dot,
+    Cross,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrosshairSettings {
+    pub style: CrosshairStyle,
+    pub color: [u8; 4], // RGBA
+    pub size: f32,      // Length of each line, or diameter of the dot
+    pub thickness: f32,
+    pub gap: f32,      // Distance from the center to where the lines start
+    pub dynamic: bool, // Spread the lines out to show the weapon's spread
+}
+
+impl Default for CrosshairSettings {
+    fn default() -> Self {
+        Self {
+            style: CrosshairStyle::Cross,
+            color: [255, 255, 255, 200],
+            size: 8.0,
+            thickness: 2.0,
+            gap: 4.0,
+            dynamic: true,
+        }
+    }
+}
+
+pub struct Crosshair {
+    lines: [Handle<UiNode>; 4], // Up, down, left, right
+    dot: Handle<UiNode>,
+    settings: CrosshairSettings,
+    center: Vector2<f32>,
+    spread: f32,
+}
+
+impl Crosshair {
+    pub fn new(
+        ctx: &mut BuildContext,
+        window_width: f32,
+        window_height: f32,
+        settings: &CrosshairSettings,
+    ) -> Self {
+        let [r, g, b, a] = settings.color;
+        let brush = Brush::Solid(Color::from_rgba(r, g, b, a));
+        let cross = settings.style == CrosshairStyle::Cross;
+
+        let mut crosshair = Self {
+            lines: [Handle::NONE; 4],
+            dot: Handle::NONE,
+            settings: settings.clone(),
+            center: Vector2::new(window_width / 2.0, window_height / 2.0),
+            spread: 0.0,
+        };
+
+        let sizes = crosshair.line_sizes();
+        let positions = crosshair.line_positions();
+        for (i, line) in crosshair.lines.iter_mut().enumerate() {
+            *line = BorderBuilder::new(
+                WidgetBuilder::new()
+                    .with_width(sizes[i].x)
+                    .with_height(sizes[i].y)
+                    .with_desired_position(positions[i])
+                    .with_visibility(cross)
+                    .with_background(brush.clone()),
+            )
+            .build(ctx);
+        }
+
+        crosshair.dot = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(settings.size)
+                .with_height(settings.size)
+                .with_desired_position(crosshair.center - Vector2::repeat(settings.size / 2.0))
+                .with_visibility(!cross)
+                .with_background(brush),
+        )
+        .build(ctx);
+
+        crosshair
+    }
+
+    // Spread is the weapon's in degrees
+    pub fn update(&mut self, ui: &UserInterface, dt: f32, spread: f32) {
+        if !self.settings.dynamic || self.settings.style != CrosshairStyle::Cross {
+            return;
+        }
+
+        let target = spread * CROSSHAIR_SPREAD_SCALE;
+
+        let previous = self.spread;
+        self.spread += (target - self.spread) * (CROSSHAIR_SPREAD_SPEED * dt).min(1.0);
+
+        // Skip the messages once the lines have settled
+        if (self.spread - previous).abs() > 0.01 {
+            self.move_lines(ui);
+        }
+    }
+
+    pub fn resize(&mut self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        self.center = Vector2::new(window_width / 2.0, window_height / 2.0);
+        self.move_lines(ui);
+        move_widget(
+            ui,
+            self.dot,
+            self.center - Vector2::repeat(self.settings.size / 2.0),
+        );
+    }
+
+    // Only the lines or the dot are ever shown, depending on the style
+    pub fn set_visible(&self, ui: &UserInterface, visible: bool) {
+        let cross = self.settings.style == CrosshairStyle::Cross;
+        for line in self.lines.iter() {
+            ui.send_message(WidgetMessage::visibility(
+                *line,
+                MessageDirection::ToWidget,
+                visible && cross,
+            ));
+        }
+        ui.send_message(WidgetMessage::visibility(
+            self.dot,
+            MessageDirection::ToWidget,
+            visible && !cross,
+        ));
+    }
+
+    fn move_lines(&self, ui: &UserInterface) {
+        for (line, position) in self.lines.iter().zip(self.line_positions()) {
+            move_widget(ui, *line, position);
+        }
+    }
+
+    fn line_sizes(&self) -> [Vector2<f32>; 4] {
+        let vertical = Vector2::new(self.settings.thickness, self.settings.size);
+        let horizontal = Vector2::new(self.settings.size, self.settings.thickness);
+        [vertical, vertical, horizontal, horizontal]
+    }
+
+    // Top left corner of each line
+    fn line_positions(&self) -> [Vector2<f32>; 4] {
+        let offset = self.settings.gap + self.spread;
+        let size = self.settings.size;
+        let half_thickness = self.settings.thickness / 2.0;
+        let (x, y) = (self.center.x, self.center.y);
+
+        [
+            Vector2::new(x - half_thickness, y - offset - size),
+            Vector2::new(x - half_thickness, y + offset),
+            Vector2::new(x - offset - size, y - half_thickness),
+            Vector2::new(x + offset, y - half_thickness),
+        ]
+    }
+}
+
+struct Gauge {
+    bar: Handle<UiNode>,
+    text: Handle<UiNode>,
+}
+
+impl Gauge {
+    fn new(ctx: &mut BuildContext, color: Color) -> (Self, Handle<UiNode>) {
+        let indicator =
+            BorderBuilder::new(WidgetBuilder::new().with_background(Brush::Solid(color)))
+                .build(ctx);
+        let body = BorderBuilder::new(
+            WidgetBuilder::new().with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 120))),
+        )
+        .build(ctx);
+
+        let bar = ProgressBarBuilder::new(WidgetBuilder::new())
+            .with_indicator(indicator)
+            .with_body(body)
+            .with_progress(1.0)
+            .build(ctx);
+        let text = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::left(4.0)))
+            .with_vertical_text_alignment(VerticalAlignment::Center)
+            .build(ctx);
+
+        // Text is drawn on top of the bar
+        let root = GridBuilder::new(
+            WidgetBuilder::new()
+                .with_height(GAUGE_HEIGHT)
+                .with_margin(Thickness::uniform(2.0))
+                .with_child(bar)
+                .with_child(text),
+        )
+        .add_row(Row::stretch())
+        .add_column(Column::stretch())
+        .build(ctx);
+
+        (Self { bar, text }, root)
+    }
+
+    fn set(&self, ui: &UserInterface, value: f32, max: f32, label: &str) {
+        ui.send_message(ProgressBarMessage::progress(
+            self.bar,
+            MessageDirection::ToWidget,
+            (value / max).clamp(0.0, 1.0),
+        ));
+        ui.send_message(TextMessage::text(
+            self.text,
+            MessageDirection::ToWidget,
+            format!("{} {}", label, value.ceil()),
+        ));
+    }
+}
+
+// Ammo, health, armor, stamina and jetpack fuel in the bottom right corner. Sits in a grid covering the
+// whole window so it stays anchored to the corner when the window is resized.
+pub struct StatusBars {
+    root: Handle<UiNode>,
+    ammo: Handle<UiNode>,
+    health: Gauge,
+    armor: Gauge,
+    stamina: Gauge,
+    fuel: Gauge,
+}
+
+impl StatusBars {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let ammo = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(2.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Right)
+            .build(ctx);
+        let (health, health_root) = Gauge::new(ctx, Color::from_rgba(200, 40, 40, 220));
+        let (armor, armor_root) = Gauge::new(ctx, Color::from_rgba(90, 200, 90, 220));
+        let (stamina, stamina_root) = Gauge::new(ctx, Color::from_rgba(220, 200, 60, 220));
+        let (fuel, fuel_root) = Gauge::new(ctx, Color::from_rgba(60, 140, 220, 220));
+
+        let panel = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_width(STATUS_WIDTH)
+                .with_margin(Thickness::uniform(10.0))
+                .with_horizontal_alignment(HorizontalAlignment::Right)
+                .with_vertical_alignment(VerticalAlignment::Bottom)
+                .with_children([ammo, health_root, armor_root, stamina_root, fuel_root]),
+        )
+        .build(ctx);
+
+        let root = GridBuilder::new(
+            WidgetBuilder::new()
+                .with_width(window_width)
+                .with_height(window_height)
+                .with_child(panel),
+        )
+        .add_row(Row::stretch())
+        .add_column(Column::stretch())
+        .build(ctx);
+
+        Self {
+            root,
+            ammo,
+            health,
+            armor,
+            stamina,
+            fuel,
+        }
+    }
+
+    pub fn update(
+        &self,
+        ui: &UserInterface,
+        health: (f32, f32), // Current and max values
+        armor: (f32, f32),
+        stamina: (f32, f32),
+        fuel: (f32, f32),
+    ) {
+        self.health.set(ui, health.0, health.1, "HP");
+        self.armor.set(ui, armor.0, armor.1, "Armor");
+        self.stamina.set(ui, stamina.0, stamina.1, "Stamina");
+        self.fuel.set(ui, fuel.0, fuel.1, "Fuel");
+    }
+
+    pub fn set_visible(&self, ui: &UserInterface, visible: bool) {
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn set_ammo(&self, ui: &UserInterface, text: String) {
+        ui.send_message(TextMessage::text(
+            self.ammo,
+            MessageDirection::ToWidget,
+            text,
+        ));
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        ui.send_message(WidgetMessage::width(
+            self.root,
+            MessageDirection::ToWidget,
+            window_width,
+        ));
+        ui.send_message(WidgetMessage::height(
+            self.root,
+            MessageDirection::ToWidget,
+            window_height,
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use fyrox::event::{
+    DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Crouch,
+    Sprint,
+    Fly,
+    Shoot,
+    Aim,
+    Reload,
+    ThrowGrenade,
+    Weapon1,
+    Weapon2,
+    Chat,
+    Scoreboard,
+    NetStats, // Shows the whole debug overlay now, the name is kept for saved bindings
+    PerfHud,
+    Screenshot,
+    FreeCursor,
+    ThirdPerson,
+    Console,
+}
+
+impl Action {
+    // Order actions are listed in on the controls screen
+    pub const ALL: [Action; 22] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Crouch,
+        Action::Sprint,
+        Action::Fly,
+        Action::Shoot,
+        Action::Aim,
+        Action::Reload,
+        Action::ThrowGrenade,
+        Action::Weapon1,
+        Action::Weapon2,
+        Action::Chat,
+        Action::Scoreboard,
+        Action::NetStats,
+        Action::PerfHud,
+        Action::Screenshot,
+        Action::FreeCursor,
+        Action::ThirdPerson,
+        Action::Console,
+    ];
+
+    // Actions that last while their key is held, let go of when input stops reaching the game so
+    // nothing keeps running with the key still down
+    pub const HELD: [Action; 10] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Crouch,
+        Action::Sprint,
+        Action::Fly,
+        Action::Shoot,
+        Action::Aim,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Jump => "Jump",
+            Action::Crouch => "Crouch",
+            Action::Sprint => "Sprint",
+            Action::Fly => "Jetpack",
+            Action::Shoot => "Shoot",
+            Action::Aim => "Aim",
+            Action::Reload => "Reload",
+            Action::ThrowGrenade => "Throw grenade",
+            Action::Weapon1 => "Weapon 1",
+            Action::Weapon2 => "Weapon 2",
+            Action::Chat => "Chat",
+            Action::Scoreboard => "Scoreboard",
+            Action::NetStats => "Debug overlay",
+            Action::PerfHud => "Performance",
+            Action::Screenshot => "Screenshot",
+            Action::FreeCursor => "Free cursor",
+            Action::ThirdPerson => "Third person",
+            Action::Console => "Console",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+// Keys that can be bound, names are what's written to settings.json
+const KEY_NAMES: [(&str, VirtualKeyCode); 62] = [
+    ("A", VirtualKeyCode::A),
+    ("B", VirtualKeyCode::B),
+    ("C", VirtualKeyCode::C),
+    ("D", VirtualKeyCode::D),
+    ("E", VirtualKeyCode::E),
+    ("F", VirtualKeyCode::F),
+    ("G", VirtualKeyCode::G),
+    ("H", VirtualKeyCode::H),
+    ("I", VirtualKeyCode::I),
+    ("J", VirtualKeyCode::J),
+    ("K", VirtualKeyCode::K),
+    ("L", VirtualKeyCode::L),
+    ("M", VirtualKeyCode::M),
+    ("N", VirtualKeyCode::N),
+    ("O", VirtualKeyCode::O),
+    ("P", VirtualKeyCode::P),
+    ("Q", VirtualKeyCode::Q),
+    ("R", VirtualKeyCode::R),
+    ("S", VirtualKeyCode::S),
+    ("T", VirtualKeyCode::T),
+    ("U", VirtualKeyCode::U),
+    ("V", VirtualKeyCode::V),
+    ("W", VirtualKeyCode::W),
+    ("X", VirtualKeyCode::X),
+    ("Y", VirtualKeyCode::Y),
+    ("Z", VirtualKeyCode::Z),
+    ("Key0", VirtualKeyCode::Key0),
+    ("Key1", VirtualKeyCode::Key1),
+    ("Key2", VirtualKeyCode::Key2),
+    ("Key3", VirtualKeyCode::Key3),
+    ("Key4", VirtualKeyCode::Key4),
+    ("Key5", VirtualKeyCode::Key5),
+    ("Key6", VirtualKeyCode::Key6),
+    ("Key7", VirtualKeyCode::Key7),
+    ("Key8", VirtualKeyCode::Key8),
+    ("Key9", VirtualKeyCode::Key9),
+    ("F1", VirtualKeyCode::F1),
+    ("F2", VirtualKeyCode::F2),
+    ("F3", VirtualKeyCode::F3),
+    ("F4", VirtualKeyCode::F4),
+    ("F5", VirtualKeyCode::F5),
+    ("F6", VirtualKeyCode::F6),
+    ("F7", VirtualKeyCode::F7),
+    ("F8", VirtualKeyCode::F8),
+    ("F9", VirtualKeyCode::F9),
+    ("F10", VirtualKeyCode::F10),
+    ("F11", VirtualKeyCode::F11),
+    ("F12", VirtualKeyCode::F12),
+    ("Space", VirtualKeyCode::Space),
+    ("Tab", VirtualKeyCode::Tab),
+    ("Return", VirtualKeyCode::Return),
+    ("Back", VirtualKeyCode::Back),
+    ("LShift", VirtualKeyCode::LShift),
+    ("RShift", VirtualKeyCode::RShift),
+    ("LControl", VirtualKeyCode::LControl),
+    ("RControl", VirtualKeyCode::RControl),
+    ("LAlt", VirtualKeyCode::LAlt),
+    ("RAlt", VirtualKeyCode::RAlt),
+    ("Up", VirtualKeyCode::Up),
+    ("Down", VirtualKeyCode::Down),
+    ("Left", VirtualKeyCode::Left),
+    ("Right", VirtualKeyCode::Right),
+];
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => KEY_NAMES
+                .iter()
+                .find(|(_, k)| k == key)
+                .map_or_else(|| format!("{:?}", key), |(name, _)| name.to_string()),
+            Binding::Mouse(MouseButton::Left) => String::from("MouseLeft"),
+            Binding::Mouse(MouseButton::Right) => String::from("MouseRight"),
+            Binding::Mouse(MouseButton::Middle) => String::from("MouseMiddle"),
+            Binding::Mouse(MouseButton::Other(button)) => format!("Mouse{}", button),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "MouseLeft" => Some(Binding::Mouse(MouseButton::Left)),
+            "MouseRight" => Some(Binding::Mouse(MouseButton::Right)),
+            "MouseMiddle" => Some(Binding::Mouse(MouseButton::Middle)),
+            _ => {
+                if let Some(button) = name.strip_prefix("Mouse") {
+                    return button
+                        .parse()
+                        .ok()
+                        .map(|b| Binding::Mouse(MouseButton::Other(b)));
+                }
+
+                KEY_NAMES
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|&(_, key)| Binding::Key(key))
+            }
+        }
+    }
+
+    // Escape always opens the menu so it can't be bound
+    pub fn is_bindable(&self) -> bool {
+        *self != Binding::Key(VirtualKeyCode::Escape)
+    }
+}
+
+// Stored in settings.json as action name -> binding name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "HashMap<Action, String>", into = "HashMap<Action, String>")]
+pub struct KeyBindings {
+    bindings: HashMap<Action, Binding>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = [
+            (Action::MoveForward, Binding::Key(VirtualKeyCode::W)),
+            (Action::MoveBackward, Binding::Key(VirtualKeyCode::S)),
+            (Action::MoveLeft, Binding::Key(VirtualKeyCode::A)),
+            (Action::MoveRight, Binding::Key(VirtualKeyCode::D)),
+            (Action::Jump, Binding::Key(VirtualKeyCode::Space)),
+            (Action::Crouch, Binding::Key(VirtualKeyCode::C)),
+            (Action::Sprint, Binding::Key(VirtualKeyCode::LControl)),
+            (Action::Fly, Binding::Key(VirtualKeyCode::LShift)),
+            (Action::Shoot, Binding::Mouse(MouseButton::Left)),
+            (Action::Aim, Binding::Mouse(MouseButton::Right)),
+            (Action::Reload, Binding::Key(VirtualKeyCode::R)),
+            (Action::ThrowGrenade, Binding::Key(VirtualKeyCode::G)),
+            (Action::Weapon1, Binding::Key(VirtualKeyCode::Key1)),
+            (Action::Weapon2, Binding::Key(VirtualKeyCode::Key2)),
+            (Action::Chat, Binding::Key(VirtualKeyCode::Return)),
+            (Action::Scoreboard, Binding::Key(VirtualKeyCode::Tab)),
+            (Action::NetStats, Binding::Key(VirtualKeyCode::F3)),
+            (Action::PerfHud, Binding::Key(VirtualKeyCode::F2)),
+            (Action::Screenshot, Binding::Key(VirtualKeyCode::F12)),
+            (Action::FreeCursor, Binding::Key(VirtualKeyCode::F1)),
+            (Action::ThirdPerson, Binding::Key(VirtualKeyCode::F5)),
+            (Action::Console, Binding::Key(VirtualKeyCode::Grave)),
+        ];
+
+        Self {
+            bindings: bindings.iter().copied().collect(),
+        }
+    }
+}
+
+impl From<HashMap<Action, String>> for KeyBindings {
+    // Unknown names are ignored and missing actions keep their default binding
+    fn from(names: HashMap<Action, String>) -> Self {
+        let mut bindings = Self::default();
+
+        for (action, name) in names {
+            match Binding::from_name(&name) {
+                Some(binding) => {
+                    bindings.bind(action, binding);
+                }
+                None => warn!("unknown binding {} for {:?}", name, action),
+            }
+        }
+
+        bindings
+    }
+}
+
+impl From<KeyBindings> for HashMap<Action, String> {
+    fn from(bindings: KeyBindings) -> Self {
+        bindings
+            .bindings
+            .iter()
+            .map(|(&action, binding)| (action, binding.name()))
+            .collect()
+    }
+}
+
+impl KeyBindings {
+    pub fn binding(&self, action: Action) -> Option<Binding> {
+        self.bindings.get(&action).copied()
+    }
+
+    pub fn action(&self, binding: Binding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(_, &b)| b == binding)
+            .map(|(&action, _)| action)
+    }
+
+    // Other action already using this binding
+    pub fn conflict(&self, action: Action, binding: Binding) -> Option<Action> {
+        self.action(binding).filter(|&other| other != action)
+    }
+
+    // On conflict the two actions swap bindings, the other action is returned so it can be
+    // reported
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Option<Action> {
+        let conflict = self.conflict(action, binding);
+        let previous = self.bindings.insert(action, binding);
+
+        if let Some(other) = conflict {
+            match previous {
+                Some(previous) => self.bindings.insert(other, previous),
+                None => self.bindings.remove(&other),
+            };
+        }
+
+        conflict
+    }
+}
+
+// Binding and press state of a key or mouse button event
+pub fn get_binding(event: &WindowEvent) -> Option<(Binding, ElementState)> {
+    match event {
+        WindowEvent::KeyboardInput { input, .. } => input
+            .virtual_keycode
+            .map(|key| (Binding::Key(key), input.state)),
+        WindowEvent::MouseInput { button, state, .. } => Some((Binding::Mouse(*button), *state)),
+        _ => None,
+    }
+}
+
+// The parts of window and device events the game reacts to, copied out so they can wait in the
+// input queue for the simulation step they belong to. Mouse motion is taken from the device when
+// raw, accelerated motion comes from the cursor and is worked out where the cursor is recentered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    Button(Binding, bool), // Pressed
+    Scroll(f32),
+    MouseMotion(f32, f32),
+}
+
+impl InputEvent {
+    pub fn from_event(event: &Event<()>, raw_mouse: bool) -> Option<Self> {
+        match event {
+            Event::WindowEvent { event, .. } => {
+                if let Some((binding, state)) = get_binding(event) {
+                    return Some(InputEvent::Button(binding, state == ElementState::Pressed));
+                }
+
+                match *event {
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        Some(InputEvent::Scroll(match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y,
+                            MouseScrollDelta::PixelDelta(position) => position.y as f32,
+                        }))
+                    }
+                    _ => None,
+                }
+            }
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } if raw_mouse => Some(InputEvent::MouseMotion(delta.0 as f32, delta.1 as f32)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    pub enabled: bool,
+    pub look_sensitivity: f32, // Degrees per second with the right stick fully pushed
+    pub deadzone: f32,         // Stick movement below this is ignored, 0 to 1
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            look_sensitivity: 180.0,
+            deadzone: 0.15,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// Input waiting for the simulation step it happened during. Steps run in a burst at the start of a
+// frame to catch up with the clock, so applying everything before the burst left the later steps of
+// a slow frame running on input that was already out of date. Events are stamped with when they
+// arrived on the same clock the steps use and each step takes the ones from before it ended.
+
+use std::collections::VecDeque;
+
+use crate::input::InputEvent;
+
+#[derive(Debug, Default)]
+pub struct InputQueue {
+    events: VecDeque<(f32, InputEvent)>, // Arrival time, oldest first
+}
+
+impl InputQueue {
+    pub fn push(&mut self, time: f32, event: InputEvent) {
+        self.events.push_back((time, event));
+    }
+
+    // The next event that arrived by `time`, None once the rest are newer
+    pub fn pop_until(&mut self, time: f32) -> Option<InputEvent> {
+        match self.events.front() {
+            Some(&(arrived, _)) if arrived <= time => self.events.pop_front().map(|(_, e)| e),
+            _ => None,
+        }
+    }
+}
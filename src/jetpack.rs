@@ -0,0 +1,165 @@
+// Jet sound and exhaust for a flying player. Both are built with the player and switched on and
+// off as flying starts and stops, so there's nothing left running once the fuel is gone.
+
+use fyrox::{
+    core::{
+        algebra::Vector3,
+        color::Color,
+        color_gradient::{ColorGradient, GradientPoint},
+        numeric_range::NumericRange,
+        pool::Handle,
+    },
+    engine::resource_manager::ResourceManager,
+    resource::texture::Texture,
+    scene::{
+        base::BaseBuilder,
+        graph::Graph,
+        node::Node,
+        particle_system::{
+            emitter::{base::BaseEmitterBuilder, sphere::SphereEmitterBuilder},
+            ParticleSystemBuilder,
+        },
+        sound::{SoundBufferResource, SoundBuilder, Status},
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+use tracing::warn;
+
+const JET_GAIN: f32 = 0.5;
+const JET_RADIUS: f32 = 3.0;
+// Behind the player's back, relative to the center of the body
+const EXHAUST_HEIGHT: f32 = 0.1;
+const EXHAUST_BACK: f32 = 0.15;
+const EXHAUST_PARTICLES: u32 = 30;
+
+#[derive(Clone)]
+pub struct JetpackResources {
+    sound: Option<SoundBufferResource>,
+    exhaust_texture: Texture,
+}
+
+impl JetpackResources {
+    pub async fn load(resource_manager: ResourceManager) -> Self {
+        let sound = match resource_manager
+            .request_sound_buffer("data/sounds/jetpack.wav")
+            .await
+        {
+            Ok(buffer) => Some(buffer),
+            Err(_) => {
+                warn!("failed to load jetpack sound");
+                None
+            }
+        };
+
+        Self {
+            sound,
+            exhaust_texture: resource_manager.request_texture("data/textures/spark.png"),
+        }
+    }
+}
+
+pub struct Jetpack {
+    sound: Handle<Node>,
+    exhaust: Handle<Node>,
+    active: bool,
+    // Exhaust is part of the third person model, nobody sees it when looking through this player
+    third_person: bool,
+}
+
+impl Jetpack {
+    // Nodes are linked to the body so they follow the player and go away with it
+    pub fn new(graph: &mut Graph, body: Handle<Node>, resources: &JetpackResources) -> Self {
+        let sound = SoundBuilder::new(BaseBuilder::new())
+            .with_buffer(resources.sound.clone())
+            .with_looping(true)
+            .with_gain(JET_GAIN)
+            .with_radius(JET_RADIUS)
+            .with_status(Status::Stopped)
+            .build(graph);
+        graph.link_nodes(sound, body);
+
+        let exhaust = create_exhaust(graph, resources.exhaust_texture.clone());
+        graph.link_nodes(exhaust, body);
+
+        Self {
+            sound,
+            exhaust,
+            active: false,
+            third_person: true,
+        }
+    }
+
+    pub fn set_third_person(&mut self, scene: &mut Scene, third_person: bool) {
+        self.third_person = third_person;
+        scene.graph[self.exhaust].set_visibility(self.active && third_person);
+    }
+
+    // Active while the player is actually flying, not just holding the key with an empty tank
+    pub fn update(&mut self, scene: &mut Scene, active: bool) {
+        if active == self.active {
+            return;
+        }
+        self.active = active;
+
+        let sound = scene.graph[self.sound].as_sound_mut();
+        if active {
+            sound.play();
+        } else {
+            sound.stop();
+        }
+
+        let exhaust = &mut scene.graph[self.exhaust];
+        exhaust.set_visibility(active && self.third_person);
+        if !active {
+            // Otherwise the old puffs would still be there next time the jetpack starts
+            exhaust.as_particle_system_mut().clear_particles();
+        }
+    }
+}
+
+fn create_exhaust(graph: &mut Graph, texture: Texture) -> Handle<Node> {
+    // Steady stream pointing down, velocities are per frame
+    let emitter = SphereEmitterBuilder::new(
+        BaseEmitterBuilder::new()
+            .with_max_particles(EXHAUST_PARTICLES)
+            .with_spawn_rate(EXHAUST_PARTICLES * 4)
+            .with_lifetime_range(NumericRange::new(0.15, 0.3))
+            .with_size_range(NumericRange::new(0.03, 0.05))
+            .with_size_modifier_range(NumericRange::new(0.001, 0.002))
+            .with_x_velocity_range(NumericRange::new(-0.003, 0.003))
+            .with_y_velocity_range(NumericRange::new(-0.05, -0.03))
+            .with_z_velocity_range(NumericRange::new(-0.003, 0.003))
+            .resurrect_particles(true),
+    )
+    .with_radius(0.02)
+    .build();
+
+    let color_gradient = {
+        let mut gradient = ColorGradient::new();
+        gradient.add_point(GradientPoint::new(
+            0.00,
+            Color::from_rgba(255, 240, 180, 255),
+        ));
+        gradient.add_point(GradientPoint::new(
+            0.30,
+            Color::from_rgba(255, 140, 30, 200),
+        ));
+        gradient.add_point(GradientPoint::new(1.00, Color::from_rgba(90, 90, 90, 0)));
+        gradient
+    };
+
+    ParticleSystemBuilder::new(
+        BaseBuilder::new()
+            .with_visibility(false)
+            .with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(Vector3::new(0.0, EXHAUST_HEIGHT, -EXHAUST_BACK))
+                    .build(),
+            ),
+    )
+    .with_color_over_lifetime_gradient(color_gradient)
+    .with_emitters(vec![emitter])
+    .with_texture(texture)
+    .build(graph)
+}
@@ -0,0 +1,93 @@
+// Blocks that throw players into the air. They're nodes in the level scene named with
+// JUMP_PAD_PREFIX and push along their own up axis, so a tilted pad launches players sideways. The
+// strength can be changed by tagging the node with a number. Only the server looks for players on
+// pads, launches go out as ApplyImpulse so everyone moves the player the same way.
+
+use std::collections::HashMap;
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{graph::Graph, node::Node},
+};
+
+use crate::collision;
+
+const JUMP_PAD_PREFIX: &str = "JumpPad";
+// Regular jumps are 0.32
+const DEFAULT_STRENGTH: f32 = 0.8;
+// Distance below a player's center that counts as standing on something, a little past the bottom
+// of the capsule
+const GROUND_PROBE: f32 = 0.5;
+// Seconds before the same player can be launched again, so they aren't launched every frame before
+// leaving the pad
+const LAUNCH_COOLDOWN: f32 = 0.5;
+
+#[derive(Default)]
+pub struct JumpPads {
+    impulses: HashMap<Handle<Node>, Vector3<f32>>, // By pad collider
+    cooldowns: HashMap<u32, f32>,                  // By player index
+}
+
+impl JumpPads {
+    // Global transforms need to be up to date
+    pub fn from_scene(graph: &Graph) -> Self {
+        let mut impulses = HashMap::new();
+
+        for (handle, node) in graph.pair_iter() {
+            if !node.name().starts_with(JUMP_PAD_PREFIX) {
+                continue;
+            }
+
+            let strength = node.tag().parse().unwrap_or(DEFAULT_STRENGTH);
+            let direction = node
+                .up_vector()
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector3::y);
+
+            // Pads are usually blocks, a rigid body with the collider underneath
+            let colliders = std::iter::once(handle)
+                .chain(node.children().iter().copied())
+                .filter(|&collider| graph[collider].is_collider());
+            for collider in colliders {
+                impulses.insert(collider, direction * strength);
+            }
+        }
+
+        Self {
+            impulses,
+            cooldowns: HashMap::new(),
+        }
+    }
+
+    // Impulse for a player standing on a pad, once per cooldown
+    pub fn launch(
+        &mut self,
+        graph: &Graph,
+        index: u32,
+        position: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        if self.impulses.is_empty() || self.cooldowns.contains_key(&index) {
+            return None;
+        }
+
+        let hit = collision::cast_ray(
+            graph,
+            position,
+            Vector3::new(0.0, -1.0, 0.0),
+            GROUND_PROBE,
+            collision::GROUND_GROUPS,
+            Default::default(),
+        )?;
+        let impulse = *self.impulses.get(&hit.collider)?;
+
+        self.cooldowns.insert(index, LAUNCH_COOLDOWN);
+        Some(impulse)
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.cooldowns.retain(|_, time_left| {
+            *time_left -= dt;
+            *time_left > 0.0
+        });
+    }
+}
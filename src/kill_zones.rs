@@ -0,0 +1,98 @@
+// Places in a level that kill players outright. Everything below the level's kill height is the
+// void, and more zones can be marked out with nodes named with LAVA_PREFIX or VOID_PREFIX. A zone
+// node is a unit cube moved and scaled into place, so the box comes from its transform. Only the
+// server looks for players in zones, deaths go out as KillPlayer with the kind of zone so the kill
+// feed can say what happened.
+
+#[cfg(feature = "server")]
+use fyrox::{
+    core::algebra::{Point3, Vector3},
+    scene::graph::Graph,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+const LAVA_PREFIX: &str = "KillLava";
+#[cfg(feature = "server")]
+const VOID_PREFIX: &str = "KillVoid";
+// Used when the level manifest doesn't set a kill height
+#[cfg(feature = "server")]
+pub const DEFAULT_KILL_HEIGHT: f32 = -12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ZoneKind {
+    Void,
+    Lava,
+}
+
+impl ZoneKind {
+    pub fn death_message(self, index: u32) -> String {
+        match self {
+            ZoneKind::Void => format!("Player {} fell out of the world.\n", index),
+            ZoneKind::Lava => format!("Player {} burned in lava.\n", index),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+struct Zone {
+    kind: ZoneKind,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+#[cfg(feature = "server")]
+pub struct KillZones {
+    kill_height: f32,
+    zones: Vec<Zone>,
+}
+
+#[cfg(feature = "server")]
+impl KillZones {
+    // Global transforms need to be up to date
+    pub fn from_scene(graph: &Graph, kill_height: f32) -> Self {
+        let zones = graph
+            .pair_iter()
+            .filter_map(|(_, node)| {
+                let kind = if node.name().starts_with(LAVA_PREFIX) {
+                    ZoneKind::Lava
+                } else if node.name().starts_with(VOID_PREFIX) {
+                    ZoneKind::Void
+                } else {
+                    return None;
+                };
+
+                let transform = node.global_transform();
+                let mut min = Vector3::repeat(f32::MAX);
+                let mut max = Vector3::repeat(f32::MIN);
+                for corner in 0..8 {
+                    let x = if corner & 1 == 0 { -0.5 } else { 0.5 };
+                    let y = if corner & 2 == 0 { -0.5 } else { 0.5 };
+                    let z = if corner & 4 == 0 { -0.5 } else { 0.5 };
+                    let point = transform.transform_point(&Point3::new(x, y, z)).coords;
+                    min = min.inf(&point);
+                    max = max.sup(&point);
+                }
+
+                Some(Zone { kind, min, max })
+            })
+            .collect();
+
+        Self { kill_height, zones }
+    }
+
+    pub fn zone_at(&self, position: Vector3<f32>) -> Option<ZoneKind> {
+        if position.y < self.kill_height {
+            return Some(ZoneKind::Void);
+        }
+
+        self.zones
+            .iter()
+            .find(|zone| {
+                (0..3).all(|axis| {
+                    position[axis] >= zone.min[axis] && position[axis] <= zone.max[axis]
+                })
+            })
+            .map(|zone| zone.kind)
+    }
+}
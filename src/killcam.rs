@@ -0,0 +1,98 @@
+// Replay of the killer's view after the local player dies. The client keeps the last few seconds of
+// where every player was looking from, the replay plays the killer's part back through the
+// spectator camera before normal spectating takes over.
+
+use std::collections::HashMap;
+
+use fyrox::{core::algebra::Vector3, scene::Scene};
+
+use crate::{history::History, network_manager::MAX_TICK_RATE, player::Player};
+
+// Seconds of views kept for the replay
+const HISTORY_LENGTH: f32 = 3.0;
+// Room for the whole length at any tick rate a server would use
+const HISTORY_CAPACITY: usize = (HISTORY_LENGTH * MAX_TICK_RATE as f32) as usize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct View {
+    pub position: Vector3<f32>, // Where the camera was, not the body
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl View {
+    fn lerp(&self, other: &View, t: f32) -> View {
+        View {
+            position: self.position.lerp(&other.position, t),
+            yaw: lerp_angle(self.yaw, other.yaw, t),
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+        }
+    }
+}
+
+// Degrees, the short way around
+fn lerp_angle(from: f32, to: f32, t: f32) -> f32 {
+    let difference = (to - from + 180.0).rem_euclid(360.0) - 180.0;
+    from + difference * t
+}
+
+#[derive(Default)]
+pub struct ViewHistory {
+    time: f32,
+    views: HashMap<u32, History<View>>, // By player index
+}
+
+impl ViewHistory {
+    // Players that are gone keep their history until it's too old, whoever got the kill may have
+    // died in the same moment
+    pub fn record(&mut self, scene: &Scene, players: &[Player], dt: f32) {
+        self.time += dt;
+
+        for player in players.iter() {
+            let view = View {
+                position: scene.graph[player.camera()].global_position(),
+                yaw: player.get_yaw(),
+                pitch: player.get_pitch(),
+            };
+            self.views
+                .entry(player.index)
+                .or_insert_with(|| History::new(HISTORY_CAPACITY))
+                .push(self.time, view);
+        }
+
+        let oldest = self.time - HISTORY_LENGTH;
+        self.views.retain(|_, views| {
+            views.drop_older_than(oldest);
+            !views.is_empty()
+        });
+    }
+
+    pub fn replay(&self, index: u32) -> Option<Killcam> {
+        let views = self.views.get(&index)?;
+
+        Some(Killcam {
+            time: views.oldest_time()?,
+            end: views.newest_time()?,
+            views: views.clone(),
+        })
+    }
+}
+
+pub struct Killcam {
+    views: History<View>,
+    time: f32, // Plays from the oldest view to the newest
+    end: f32,
+}
+
+impl Killcam {
+    // None once the replay is over
+    pub fn update(&mut self, dt: f32) -> Option<View> {
+        self.time += dt;
+        if self.time > self.end {
+            return None;
+        }
+
+        let (from, to, t) = self.views.around(self.time)?;
+        Some(from.lerp(to, t))
+    }
+}
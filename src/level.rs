@@ -1,34 +1,131 @@
 use core::time;
 use std::{
+    cmp::Ordering,
     net::SocketAddr,
     path::PathBuf,
-    sync::mpsc::{self, channel, Receiver, Sender},
+    sync::mpsc::{self, channel, Receiver, Sender, TryRecvError},
     thread::spawn,
 };
 
 use fyrox::{
     core::{
-        algebra::Vector3,
+        algebra::{Vector2, Vector3},
         color::Color,
         pool::{Handle, Pool},
     },
     engine::resource_manager::ResourceManager,
     gui::{message::MessageDirection, text_box::TextBoxMessage},
-    scene::{graph::SubGraph, node::Node, Scene},
+    scene::{debug::Line, graph::Graph, node::Node, rigidbody::RigidBodyType, Scene},
 };
 use serde::{Deserialize, Serialize};
+use tracing::{trace, warn};
 
 use crate::{
+    blocks::{BlockHealth, BlockStore, HiddenBlocks},
+    collision::SurfaceKind,
+    demo::{DemoEvent, DemoRecorder},
+    error::GameError,
     game::GameEvent,
-    network_manager::{NetworkManager, NetworkMessage},
-    player::{self, Player, PlayerState, SYNC_FREQUENCY},
-    player_event::{PlayerEvent, SerializablePlayerState, SerializableVector},
+    network_manager::{NetworkManager, NetworkMessage, TickRates},
+    pickups::Pickups,
+    player::{self, Player, PlayerResources, PlayerState},
+    player_event::PlayerEvent,
+    projectile::{self, Projectile, ProjectileSpawn},
+    snapshot::PlayerSnapshot,
+    spectator::Spectator,
     GameEngine, Interface,
 };
 
+#[cfg(feature = "server")]
+use crate::falling_blocks::FallingBlocks;
+#[cfg(feature = "server")]
+use crate::jump_pads::JumpPads;
+#[cfg(feature = "server")]
+use crate::kill_zones::{KillZones, DEFAULT_KILL_HEIGHT};
+#[cfg(feature = "server")]
+use crate::match_state::PlayerMatchStats;
+#[cfg(feature = "server")]
+use crate::metrics::Metrics;
+use crate::{blocks::crack_stage, kill_zones::ZoneKind};
+#[cfg(not(feature = "server"))]
+use crate::{
+    effects::{BlockEffects, HitSounds, ImpactEffects},
+    game::{player_color, PlayerScore},
+    killcam::{Killcam, ViewHistory},
+    player_stats::PlayerStats,
+    third_person::ThirdPersonCamera,
+};
+
+// Nodes in the level scene named with this prefix (or tagged) are used as spawn points
+const SPAWN_POINT_PREFIX: &str = "SpawnPoint";
+const SPAWN_POINT_TAG: &str = "spawn";
+// Nametags sit this far above the player's center and fade out between these distances
+const NAMETAG_OFFSET: f32 = 0.6;
+const NAMETAG_FADE_START: f32 = 6.0;
+const NAMETAG_FADE_END: f32 = 12.0;
+// Seconds a predicted block stays hidden without the server destroying it
+#[cfg(not(feature = "server"))]
+const PREDICTION_TIMEOUT: f32 = 1.0;
+// Seconds shots stay drawn on the debug overlay
+#[cfg(not(feature = "server"))]
+const DEBUG_SHOT_TIME: f32 = 2.0;
+// Size of the cross drawn where the server last put a remote player
+#[cfg(not(feature = "server"))]
+const DEBUG_MARKER_SIZE: f32 = 0.3;
+
+// Every rigid body the level is built with that isn't tagged as a wall is a block
+fn is_destructible_block(node: &Node) -> bool {
+    node.is_rigid_body() && node.tag() != "wall"
+}
+
+// Spawn that came in before the player resources finished loading
+struct PendingSpawn {
+    index: u32,
+    state: PlayerState,
+    current_player: bool,
+    skin: u32,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LevelState {
     pub destroyed_blocks: Vec<u32>,
+    pub cracked_blocks: Vec<(u32, u32)>, // Block index and crack stage
+}
+
+// Optional extras for a level, read from a json file named after the scene. Clients read it too,
+// to know how many hits their shots need to break a block.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct LevelManifest {
+    block_health: BlockHealth,
+    #[cfg(feature = "server")]
+    kill_height: f32, // Players below this are in the void
+}
+
+impl Default for LevelManifest {
+    fn default() -> Self {
+        Self {
+            block_health: BlockHealth::default(),
+            #[cfg(feature = "server")]
+            kill_height: DEFAULT_KILL_HEIGHT,
+        }
+    }
+}
+
+impl LevelManifest {
+    // Levels don't need one, anything missing is left at the defaults
+    fn load(scene_name: &str) -> Self {
+        let path = ["data/levels/", scene_name, ".json"].concat();
+        let file = match std::fs::File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return Self::default(),
+        };
+
+        serde_json::from_reader(std::io::BufReader::new(file)).unwrap_or_else(|e| {
+            warn!("failed to read {}: {}", path, e);
+            Self::default()
+        })
+    }
 }
 
 pub struct Level {
@@ -38,8 +135,64 @@ pub struct Level {
     receiver: Receiver<PlayerEvent>,
     pub sender: Sender<PlayerEvent>,
     pub state: LevelState,
-    // blocks: Vec<Vec<Vec<Handle<Node>>>>,
-    // hidden_blocks: Vec<SubGraph>,
+    spawn_points: Vec<Vector3<f32>>,
+    projectiles: Vec<Projectile>,
+    next_projectile_id: u32,
+    blocks: BlockStore,
+    hidden_blocks: HiddenBlocks,
+    pickups: Pickups,
+    #[cfg(feature = "server")]
+    jump_pads: JumpPads,
+    #[cfg(feature = "server")]
+    falling_blocks: FallingBlocks,
+    #[cfg(not(feature = "server"))]
+    spectator: Spectator,
+    #[cfg(not(feature = "server"))]
+    third_person: ThirdPersonCamera,
+    #[cfg(not(feature = "server"))]
+    block_effects: BlockEffects,
+    #[cfg(not(feature = "server"))]
+    impact_effects: ImpactEffects,
+    #[cfg(not(feature = "server"))]
+    hit_sounds: HitSounds,
+    #[cfg(not(feature = "server"))]
+    view_history: ViewHistory,
+    #[cfg(not(feature = "server"))]
+    killcam: Option<(Killcam, Vector3<f32>)>, // With where to spectate from once it's over
+    #[cfg(not(feature = "server"))]
+    player_colors: std::collections::HashMap<u32, Color>, // By player index
+    fov: f32, // New player cameras start with this
+    // None until loaded in the background
+    player_resources: Option<PlayerResources>,
+    resource_receiver: Option<Receiver<Result<PlayerResources, GameError>>>,
+    pending_spawns: Vec<PendingSpawn>,
+    pub recorder: Option<DemoRecorder>,
+    #[cfg(feature = "server")]
+    pub metrics: Metrics,
+    damaged_blocks: std::collections::HashMap<u32, u32>, // Hits taken by blocks still standing
+    // Combat of each player since the level loaded, the game adds kills and deaths
+    #[cfg(feature = "server")]
+    round_stats: std::collections::HashMap<u32, PlayerMatchStats>,
+    block_health: BlockHealth,
+    // Hits left on blocks the server has said were cracked, others have their full health
+    #[cfg(not(feature = "server"))]
+    block_hits_left: std::collections::HashMap<u32, u32>,
+    // Blocks hidden ahead of the server destroying them, with the time left to confirm it
+    #[cfg(not(feature = "server"))]
+    predicted_blocks: Vec<(u32, f32)>,
+    // What the local player did since the game last took it for their lifetime stats
+    #[cfg(not(feature = "server"))]
+    pub local_stats: PlayerStats,
+    // Last position the server sent for each player and recent shots, for the debug overlay
+    #[cfg(not(feature = "server"))]
+    server_positions: std::collections::HashMap<u32, Vector3<f32>>,
+    #[cfg(not(feature = "server"))]
+    debug_shots: Vec<(Vector3<f32>, Vector3<f32>, f32)>, // With the time left to draw them
+    // Played without a server, so shots, projectiles and block damage are decided here
+    #[cfg(not(feature = "server"))]
+    pub practice: bool,
+    #[cfg(feature = "server")]
+    kill_zones: KillZones,
 }
 
 impl Level {
@@ -47,34 +200,113 @@ impl Level {
         resource_manager: ResourceManager,
         scene_name: &str,
         state: LevelState,
-    ) -> (Self, Scene) {
+    ) -> Result<(Self, Scene), GameError> {
         let mut scene = Scene::new();
 
         // Load a scene resource and create its instance.
+        let path = ["data/levels/", scene_name, ".rgs"].concat();
         resource_manager
-            .request_model(["data/levels/", scene_name, ".rgs"].concat())
+            .request_model(&path)
             .await
-            .unwrap()
+            .map_err(|_| GameError::Resource(path.clone()))?
             .instantiate_geometry(&mut scene);
 
-        // let mut blocks_3d: Vec<Vec<Vec<Handle<Node>>>> =
-        //     vec![vec![vec![Handle::<Node>::NONE; 100]; 100]; 100];
+        scene.ambient_lighting_color = Color::opaque(255, 255, 255);
 
-        // let blocks: Vec<(Handle<Node>, Vector3<f32>)> = scene
-        //     .graph
-        //     .pair_iter_mut()
-        //     .filter(|(handle, node)| {
-        //         node.tag() != "wall" && node.tag() != "player" && node.is_rigid_body()
-        //     })
-        //     .map(|(handle, node)| (handle, node.global_position()))
-        //     .collect();
+        // Player models and animations load in the background, players spawned before they're
+        // ready wait in the pending queue instead of stalling the game loop
+        let (resource_sender, resource_receiver) = channel();
+        let player_resource_manager = resource_manager.clone();
+        spawn(move || {
+            let resources = fyrox::core::futures::executor::block_on(PlayerResources::load(
+                player_resource_manager,
+            ));
+            // The level may be gone by now
+            let _ = resource_sender.send(resources);
+        });
 
-        // for block in blocks {
-        //     blocks_3d[(block.1.x.round() + 50.0) as usize][(block.1.y.round() + 50.0) as usize]
-        //         [(block.1.z.round() + 50.0) as usize] = block.0;
-        // }
+        // Spawn points are marker nodes placed in the editor, global positions need to be
+        // calculated before they can be read. Same goes for block positions.
+        scene.graph.update_hierarchical_data();
 
-        scene.ambient_lighting_color = Color::opaque(255, 255, 255);
+        let mut blocks = BlockStore::default();
+        for (handle, node) in scene.graph.pair_iter() {
+            if is_destructible_block(node) {
+                blocks.insert(
+                    BlockStore::coord_for_position(node.global_position()),
+                    handle,
+                );
+            }
+        }
+
+        // Colliders of blocks can be shot to pieces, anything else the level is made of can't.
+        // Has to happen before culling, which stores the groups of buried blocks.
+        let level_colliders: Vec<(Handle<Node>, SurfaceKind)> = scene
+            .graph
+            .pair_iter()
+            .filter(|(_, node)| node.is_collider())
+            .map(|(handle, node)| {
+                let kind = match scene.graph.try_get(node.parent()) {
+                    Some(parent) if is_destructible_block(parent) => SurfaceKind::Block,
+                    _ => SurfaceKind::World,
+                };
+                (handle, kind)
+            })
+            .collect();
+        for (handle, kind) in level_colliders {
+            scene.graph[handle]
+                .as_collider_mut()
+                .set_collision_groups(kind.interaction_groups());
+        }
+
+        // Blocks buried inside others don't need rendering or collision checks
+        let mut hidden_blocks = HiddenBlocks::default();
+        hidden_blocks.cull(&blocks, &mut scene.graph);
+
+        let pickups = Pickups::from_scene(&mut scene.graph, &blocks);
+        #[cfg(feature = "server")]
+        let jump_pads = JumpPads::from_scene(&scene.graph);
+        let manifest = LevelManifest::load(scene_name);
+        #[cfg(feature = "server")]
+        let kill_zones = KillZones::from_scene(&scene.graph, manifest.kill_height);
+
+        let spawn_points: Vec<Vector3<f32>> = scene
+            .graph
+            .pair_iter()
+            .filter(|(_, node)| {
+                node.name().starts_with(SPAWN_POINT_PREFIX) || node.tag() == SPAWN_POINT_TAG
+            })
+            .map(|(_, node)| node.global_position())
+            .collect();
+        // Players would have nowhere to go
+        if spawn_points.is_empty() {
+            return Err(GameError::MissingNode {
+                model: path,
+                node: SPAWN_POINT_PREFIX.to_string(),
+            });
+        }
+
+        // Start spectating from above the first spawn point until the local player spawns
+        #[cfg(not(feature = "server"))]
+        let (spectator, third_person) = {
+            let position = spawn_points.first().copied().unwrap_or_default();
+            let skybox = player::create_skybox(resource_manager.clone()).await?;
+            (
+                Spectator::new(
+                    &mut scene,
+                    skybox.clone(),
+                    position + Vector3::new(0.0, 5.0, 0.0),
+                ),
+                ThirdPersonCamera::new(&mut scene, skybox),
+            )
+        };
+
+        #[cfg(not(feature = "server"))]
+        let block_effects = BlockEffects::new(&mut scene, resource_manager.clone()).await;
+        #[cfg(not(feature = "server"))]
+        let impact_effects = ImpactEffects::new(&mut scene, resource_manager.clone());
+        #[cfg(not(feature = "server"))]
+        let hit_sounds = HitSounds::new(resource_manager.clone()).await;
 
         let (sender, receiver) = channel();
 
@@ -86,14 +318,64 @@ impl Level {
             sender: sender,
             state: LevelState {
                 destroyed_blocks: Vec::new(),
+                cracked_blocks: Vec::new(),
             },
-            // blocks: blocks_3d,
-            // hidden_blocks: Vec::new(),
+            spawn_points,
+            projectiles: Vec::new(),
+            next_projectile_id: 0,
+            blocks,
+            hidden_blocks,
+            pickups,
+            #[cfg(feature = "server")]
+            jump_pads,
+            #[cfg(feature = "server")]
+            falling_blocks: FallingBlocks::default(),
+            #[cfg(not(feature = "server"))]
+            spectator,
+            #[cfg(not(feature = "server"))]
+            third_person,
+            #[cfg(not(feature = "server"))]
+            block_effects,
+            #[cfg(not(feature = "server"))]
+            impact_effects,
+            #[cfg(not(feature = "server"))]
+            hit_sounds,
+            #[cfg(not(feature = "server"))]
+            view_history: Default::default(),
+            #[cfg(not(feature = "server"))]
+            killcam: None,
+            #[cfg(not(feature = "server"))]
+            player_colors: Default::default(),
+            fov: player::DEFAULT_FOV,
+            player_resources: None,
+            resource_receiver: Some(resource_receiver),
+            pending_spawns: Vec::new(),
+            recorder: None,
+            #[cfg(feature = "server")]
+            metrics: Metrics::default(),
+            damaged_blocks: Default::default(),
+            #[cfg(feature = "server")]
+            round_stats: Default::default(),
+            block_health: manifest.block_health,
+            #[cfg(not(feature = "server"))]
+            block_hits_left: Default::default(),
+            #[cfg(not(feature = "server"))]
+            predicted_blocks: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            local_stats: PlayerStats::default(),
+            #[cfg(not(feature = "server"))]
+            server_positions: Default::default(),
+            #[cfg(not(feature = "server"))]
+            debug_shots: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            practice: false,
+            #[cfg(feature = "server")]
+            kill_zones,
         };
 
         // level.apply_state(engine, state);
 
-        (level, scene)
+        Ok((level, scene))
     }
 
     pub fn get_player_by_index(&mut self, index: u32) -> Option<&mut Player> {
@@ -104,13 +386,200 @@ impl Level {
         self.players.iter().find(|p| p.collider == collider)
     }
 
+    // Picks the spawn point farthest away from the closest living enemy
+    pub fn pick_spawn_point(&self, scene: &Scene, index: u32) -> Vector3<f32> {
+        let enemies: Vec<Vector3<f32>> = self
+            .players
+            .iter()
+            .filter(|p| p.index != index)
+            .map(|p| p.get_position(scene))
+            .collect();
+
+        if self.spawn_points.is_empty() {
+            // Level has no spawn points so fall back to opposite sides of the map
+            return Vector3::new(5.0 * (-1.0f32).powi(index as i32), 3.0, 1.0);
+        }
+
+        if enemies.is_empty() {
+            return self.spawn_points[index as usize % self.spawn_points.len()];
+        }
+
+        let closest_enemy_distance = |spawn_point: &Vector3<f32>| {
+            enemies
+                .iter()
+                .map(|enemy| (enemy - spawn_point).norm())
+                .fold(f32::MAX, f32::min)
+        };
+
+        *self
+            .spawn_points
+            .iter()
+            .max_by(|a, b| {
+                closest_enemy_distance(a)
+                    .partial_cmp(&closest_enemy_distance(b))
+                    .unwrap_or(Ordering::Equal)
+            })
+            .unwrap()
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn is_spectating(&self) -> bool {
+        self.spectator.is_active()
+    }
+
+    // Takes effect on the next frame, and stays on through respawns
+    #[cfg(not(feature = "server"))]
+    pub fn toggle_third_person(&mut self) {
+        self.third_person.toggle();
+    }
+
+    #[cfg(not(feature = "server"))]
+    pub fn process_spectator_input(
+        &mut self,
+        event: &crate::input::InputEvent,
+        engine: &mut GameEngine,
+        bindings: &crate::input::KeyBindings,
+    ) {
+        // Nothing to control while the killcam is playing
+        if self.killcam.is_some() {
+            return;
+        }
+
+        let scene = &mut engine.scenes[self.scene];
+        self.spectator
+            .process_input(event, scene, &mut self.players, bindings);
+    }
+
+    // Cheap enough to do every frame, which also covers cameras of players that just spawned
+    #[cfg(not(feature = "server"))]
+    pub fn apply_view_settings(&mut self, engine: &mut GameEngine, settings: &crate::Settings) {
+        self.fov = settings.fov;
+
+        let scene = &mut engine.scenes[self.scene];
+        for player in self.players.iter() {
+            player.set_fov(scene, settings.fov, settings.fov_kick);
+        }
+        self.spectator.set_fov(scene, settings.fov);
+        self.third_person.set_fov(scene, settings.fov);
+        // Everything playing in the level scene is an effect, music gets its own gain when it's
+        // added
+        scene
+            .graph
+            .sound_context
+            .state()
+            .set_master_gain(settings.master_volume * settings.effects_volume);
+    }
+
+    // Frames usually land between simulation steps, alpha is how far past the last one. 1 puts
+    // everything back where the simulation has it, which has to happen before steps run since shots
+    // are fired from the camera.
+    #[cfg(not(feature = "server"))]
+    pub fn set_render_alpha(&mut self, engine: &mut GameEngine, alpha: f32) {
+        let scene = &mut engine.scenes[self.scene];
+        for player in self.players.iter() {
+            player.set_render_alpha(scene, alpha);
+        }
+        // Global positions are only worked out when the engine updates, rendering needs them now
+        scene.graph.update_hierarchical_data();
+
+        // The third person camera follows where the player is drawn, so it goes after
+        let local_player = self.players.iter_mut().find(|p| p.is_current_player());
+        self.third_person.update(scene, local_player);
+        if self.third_person.is_shown() {
+            scene.graph.update_hierarchical_data();
+        }
+    }
+
+    // Either the spectator camera, the third person camera or the camera of the player being
+    // looked through
+    #[cfg(not(feature = "server"))]
+    fn active_camera(&self, scene: &Scene) -> Option<Handle<Node>> {
+        [self.spectator.camera(), self.third_person.camera()]
+            .iter()
+            .copied()
+            .chain(self.players.iter().map(|p| p.camera()))
+            .find(|&camera| scene.graph[camera].as_camera().is_enabled())
+    }
+
+    // Players in view of the active camera and not behind a wall, with where their tag goes on
+    // screen and how faded it is
+    #[cfg(not(feature = "server"))]
+    fn visible_nametags(
+        &self,
+        scene: &Scene,
+        screen_size: Vector2<f32>,
+    ) -> Vec<(u32, Vector2<f32>, f32)> {
+        let view = match self.active_camera(scene) {
+            Some(view) => view,
+            None => return Vec::new(),
+        };
+        let camera = scene.graph[view].as_camera();
+        let eye = camera.global_position();
+
+        // The third person camera looks over the local player's shoulder
+        let is_viewer = |player: &Player| {
+            player.camera() == view
+                || (view == self.third_person.camera() && player.is_current_player())
+        };
+        let viewer = self
+            .players
+            .iter()
+            .find(|p| is_viewer(p))
+            .map_or(Handle::NONE, |p| p.collider);
+
+        let mut tags = Vec::new();
+        for player in self.players.iter() {
+            // Don't label whoever we're looking through
+            if is_viewer(player) {
+                continue;
+            }
+
+            let head = player.get_position(scene) + Vector3::new(0.0, NAMETAG_OFFSET, 0.0);
+            let offset = head - eye;
+            let distance = offset.norm();
+            if distance >= NAMETAG_FADE_END || distance <= f32::EPSILON {
+                continue;
+            }
+
+            let position = match camera.project(head, screen_size) {
+                Some(position) => position,
+                None => continue, // Behind the camera
+            };
+
+            // First thing hit on the way to the head has to be the player itself, ignoring the
+            // player we're looking through
+            let occluded = crate::collision::cast_ray(
+                &scene.graph,
+                eye,
+                offset,
+                distance,
+                crate::collision::SOLID_GROUPS,
+                viewer,
+            )
+            .map_or(false, |i| i.collider != player.collider);
+            if occluded {
+                continue;
+            }
+
+            let opacity = 1.0
+                - ((distance - NAMETAG_FADE_START) / (NAMETAG_FADE_END - NAMETAG_FADE_START))
+                    .clamp(0.0, 1.0);
+            tags.push((player.index, position, opacity));
+        }
+
+        tags
+    }
+
     pub fn remove_player(&mut self, engine: &mut GameEngine, index: u32) {
         let scene = &mut engine.scenes[self.scene];
         if let Some(player) = self.get_player_by_index(index) {
             player.clean_up(scene);
         }
 
-        self.players.retain(|p| p.index != index)
+        self.players.retain(|p| p.index != index);
+        self.pending_spawns.retain(|s| s.index != index);
+        #[cfg(not(feature = "server"))]
+        self.server_positions.remove(&index);
     }
 
     pub fn clean_up(&mut self, engine: &mut GameEngine) {
@@ -121,23 +590,30 @@ impl Level {
         }
 
         self.players.clear();
+        self.projectiles.clear();
         engine.scenes.remove(self.scene);
     }
 
     pub fn update(
         &mut self,
         engine: &mut GameEngine,
-        dt: f32,
+        rates: TickRates,
         network_manager: &mut NetworkManager,
         elapsed_time: f32,
         game_event_sender: &Sender<GameEvent>,
-        interface: &Interface,
-    ) {
+        interface: &mut Interface,
+    ) -> Result<(), GameError> {
+        let dt = rates.timestep();
+        self.spawn_pending_players(engine, network_manager)?;
+
         while let Ok(action) = self.receiver.try_recv() {
-            // if let PlayerEvent::UpdateState { .. } = action {
-            // } else {
-            //     println!("player event received: {:?}", action);
-            // };
+            trace!("player event received: {:?}", action);
+            #[cfg(feature = "server")]
+            self.metrics.player_event();
+
+            if let Some(recorder) = &self.recorder {
+                recorder.record(DemoEvent::Player(action));
+            }
 
             match action {
                 PlayerEvent::ShootWeapon {
@@ -147,6 +623,9 @@ impl Level {
                     pitch,
                 } => {
                     if let Some(player) = self.get_player_by_index(index) {
+                        if active {
+                            player.pull_trigger();
+                        }
                         player.controller.shoot = active;
 
                         if network_manager
@@ -235,30 +714,70 @@ impl Level {
                         }
                     }
                 }
+                PlayerEvent::MoveAnalog {
+                    index,
+                    forward,
+                    right,
+                    yaw,
+                    pitch,
+                } => {
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.controller.move_axis = Vector2::new(right, forward);
+
+                        if network_manager
+                            .player_index
+                            .and_then(|id| if id == index { Some(id) } else { None })
+                            .is_none()
+                        {
+                            player.controller.yaw = yaw;
+                            player.controller.pitch = pitch;
+                        }
+                    }
+                }
                 PlayerEvent::Jump { index } => {
                     if let Some(player) = self.get_player_by_index(index) {
                         player.controller.jump = true;
                     }
                 }
+                PlayerEvent::SwitchWeapon { index, slot } => {
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.switch_weapon(slot as usize);
+                    }
+                }
                 PlayerEvent::Reload { index } => {
                     if let Some(player) = self.get_player_by_index(index) {
-                        // TODO: Reload
+                        player.reload();
                     }
                 }
-                PlayerEvent::Fly {
+                PlayerEvent::Crouch { index, active } => {
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.controller.crouch = active;
+                    }
+                }
+                PlayerEvent::Sprint {
                     index,
                     active,
-                    fuel,
+                    stamina,
                 } => {
                     if let Some(player) = self.get_player_by_index(index) {
-                        player.controller.fly = active;
+                        player.controller.sprint = active;
 
                         #[cfg(not(feature = "server"))]
                         {
-                            player.flight_fuel = fuel;
+                            player.stamina = stamina;
                         }
                     }
                 }
+                PlayerEvent::Fly { index, active } => {
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.controller.fly = active;
+                    }
+                }
+                PlayerEvent::Aim { index, active } => {
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.controller.aim = active;
+                    }
+                }
                 PlayerEvent::LookAround {
                     index,
                     yaw_delta,
@@ -279,140 +798,488 @@ impl Level {
                     pitch,
                     shoot,
                     fuel,
+                    weapon,
+                    ammo,
                 } => {
+                    #[cfg(not(feature = "server"))]
+                    self.server_positions.insert(index, position);
                     let scene = &mut engine.scenes[self.scene];
                     if let Some(player) = self.get_player_by_index(index) {
+                        // Server owns ammo count and fuel
+                        player.set_ammo(weapon as usize, ammo);
+                        player.flight_fuel = fuel;
+
                         let new_state = PlayerState {
                             timestamp: timestamp,
-                            position: Vector3::new(position.x, position.y, position.z),
-                            velocity: Vector3::new(velocity.x, velocity.y, velocity.z),
+                            position: position,
+                            velocity: velocity,
                             yaw: yaw,
                             pitch: pitch,
                             shoot: shoot,
                             fuel: fuel,
                         };
 
-                        let length = player.controller.new_states.len();
-                        let buffer_length = 1;
-                        if length >= buffer_length {
-                            player.controller.new_states.remove(0);
+                        // Only the latest state matters, start smoothing towards it from scratch
+                        if player.controller.new_states.is_full() {
                             player.controller.smoothing_speed = 0.0;
                         }
-
-                        player.controller.new_states.push(new_state);
+                        player.controller.new_states.push(timestamp, new_state);
                     }
                 }
                 PlayerEvent::DestroyBlock { index } => {
+                    #[cfg(feature = "server")]
                     self.destroy_block(engine, index);
+
+                    // Predicted blocks already had their effect
+                    #[cfg(not(feature = "server"))]
+                    {
+                        // Only the local player's shots are predicted
+                        let predicted = self.confirm_prediction(index);
+                        if predicted {
+                            self.local_stats.blocks_destroyed += 1;
+                        }
+                        if let Some(position) = self.destroy_block(engine, index) {
+                            if !predicted {
+                                self.block_effects
+                                    .block_destroyed(&mut engine.scenes[self.scene], position);
+                            }
+                        }
+                    }
                 }
-                #[cfg(feature = "server")]
-                PlayerEvent::KillPlayerFromIntersection { collider } => {
-                    // If player was killed then send kill and respawn events
-                    if let Some(player) = self.get_player_by_collider(collider) {
-                        let kill_event = PlayerEvent::KillPlayer {
-                            index: player.index,
-                        };
-                        let kill_message = NetworkMessage::PlayerEvent {
-                            index: player.index,
-                            event: kill_event,
+                PlayerEvent::HitBlock { index, source } => {
+                    let graph = &engine.scenes[self.scene].graph;
+                    let handle = graph.handle_from_index(index);
+                    if handle.is_some() && graph.is_valid_handle(handle) {
+                        let health = self.block_health.of(graph[handle].tag());
+                        let hits = self.damaged_blocks.entry(index).or_insert(0);
+                        *hits += 1;
+
+                        if *hits >= health {
+                            self.break_block(index, Some(source), network_manager);
+                        } else {
+                            let stage = crack_stage(*hits, health);
+                            let hits_left = health - *hits;
+                            self.crack_block(index, stage, hits_left, network_manager);
+                        }
+                    }
+                }
+                PlayerEvent::SpawnFallingBlock { index } => {
+                    self.drop_block(engine, index);
+                }
+                #[cfg(not(feature = "server"))]
+                PlayerEvent::CrackBlock {
+                    index,
+                    stage,
+                    hits_left,
+                } => {
+                    let graph = &mut engine.scenes[self.scene].graph;
+                    let handle = graph.handle_from_index(index);
+                    self.block_effects.block_cracked(graph, handle, stage);
+
+                    // A predicted block may still be cracked by an earlier shot, the shot that
+                    // breaks it could be on its way
+                    if !self.predicted_blocks.iter().any(|(i, _)| *i == index) {
+                        self.block_hits_left.insert(index, hits_left);
+                    }
+                }
+                PlayerEvent::LaunchProjectile {
+                    owner,
+                    kind,
+                    position,
+                    velocity,
+                    damage,
+                    radius,
+                    knockback,
+                } => {
+                    self.next_projectile_id += 1;
+                    let id = self.next_projectile_id;
+
+                    self.spawn_projectile(
+                        engine,
+                        id,
+                        owner,
+                        ProjectileSpawn {
+                            kind,
+                            position,
+                            velocity,
+                            damage,
+                            radius,
+                            knockback,
+                        },
+                    );
+
+                    let event = PlayerEvent::SpawnProjectile {
+                        id,
+                        owner,
+                        kind,
+                        position,
+                        velocity,
+                    };
+                    network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent {
+                        index: owner,
+                        event,
+                    });
+                }
+                #[cfg(not(feature = "server"))]
+                PlayerEvent::SpawnProjectile {
+                    id,
+                    owner,
+                    kind,
+                    position,
+                    velocity,
+                } => {
+                    // Damage is applied by the server so clients don't need it
+                    self.spawn_projectile(
+                        engine,
+                        id,
+                        owner,
+                        ProjectileSpawn {
+                            kind,
+                            position,
+                            velocity,
+                            damage: 0.0,
+                            radius: 0.0,
+                            knockback: 0.0,
+                        },
+                    );
+                }
+                PlayerEvent::ThrowGrenade { index } => {
+                    let authoritative = self.authoritative();
+                    let scene = &engine.scenes[self.scene];
+                    if let Some(player) = self.get_player_by_index(index) {
+                        let (origin, direction) = player.get_aim(scene);
+
+                        // Clients only keep their grenade count in sync, the server throws it
+                        // outside practice
+                        let thrown = player.throw_grenade();
+
+                        if thrown && authoritative {
+                            self.queue_event(PlayerEvent::LaunchProjectile {
+                                owner: index,
+                                kind: projectile::ProjectileKind::Grenade,
+                                position: origin + direction * 0.5,
+                                // Lob it slightly upwards so it arcs
+                                velocity: (direction + Vector3::new(0.0, 0.3, 0.0))
+                                    * projectile::GRENADE_SPEED,
+                                damage: 0.0,
+                                radius: projectile::GRENADE_RADIUS,
+                                knockback: projectile::GRENADE_KNOCKBACK,
+                            });
+                        }
+                    }
+                }
+                PlayerEvent::ApplyImpulse { index, impulse } => {
+                    let scene = &mut engine.scenes[self.scene];
+                    if let Some(player) = self.get_player_by_index(index) {
+                        player.apply_impulse(scene, impulse);
+                    }
+                }
+                PlayerEvent::DetonateProjectile { id, position } => {
+                    if let Some(i) = self.projectiles.iter().position(|p| p.id == id) {
+                        let projectile = self.projectiles.remove(i);
+                        projectile.clean_up(&mut engine.scenes[self.scene]);
+
+                        if self.authoritative() {
+                            self.explode(engine, position, &projectile, network_manager);
+                        }
+                    }
+                }
+                PlayerEvent::DamagePlayerFromIntersection {
+                    collider,
+                    amount,
+                    source,
+                    impulse,
+                    position,
+                    headshot_multiplier,
+                } => {
+                    let scene = &engine.scenes[self.scene];
+                    let target = self
+                        .get_player_by_collider(collider)
+                        .map(|p| (p.index, p.is_headshot(scene, position)));
+                    if let Some((index, headshot)) = target {
+                        #[cfg(feature = "server")]
+                        if source != index {
+                            self.round_stats_mut(source).shots_hit += 1;
+                        }
+                        let damage_event = PlayerEvent::Damage {
+                            index,
+                            amount: if headshot {
+                                amount * headshot_multiplier
+                            } else {
+                                amount
+                            },
+                            source,
+                            headshot,
                         };
+                        network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent {
+                            index,
+                            event: damage_event,
+                        });
+                        self.queue_event(damage_event);
 
-                        network_manager.send_to_all_reliably(&kill_message);
-                        self.queue_event(kill_event);
-
-                        if self.players.len() < 3 {
-                            let event = GameEvent::LoadLevel {
-                                level: self.name.clone(),
-                                state: LevelState {
-                                    destroyed_blocks: Vec::new(),
-                                },
-                            };
-                            game_event_sender.send(event).unwrap();
+                        if impulse != Vector3::zeros() {
+                            let impulse_event = PlayerEvent::ApplyImpulse { index, impulse };
+                            network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent {
+                                index,
+                                event: impulse_event,
+                            });
+                            self.queue_event(impulse_event);
                         }
                     }
                 }
-                PlayerEvent::KillPlayer { index } => {
+                PlayerEvent::Damage {
+                    index,
+                    amount,
+                    source,
+                    headshot,
+                } => {
+                    let authoritative = self.authoritative();
+                    if let Some(player) = self.get_player_by_index(index) {
+                        #[allow(unused_variables)]
+                        let (before, before_health) = (player.health + player.armor, player.health);
+                        player.take_damage(amount);
+
+                        if authoritative {
+                            // More damage in the tick before the queued kill runs doesn't kill again
+                            let killed = before_health > 0.0 && player.health <= 0.0;
+                            #[cfg(feature = "server")]
+                            {
+                                let dealt = before - (player.health + player.armor);
+                                self.round_stats_mut(index).damage_taken += dealt;
+                                if source != index {
+                                    self.round_stats_mut(source).damage_dealt += dealt;
+                                }
+                            }
+
+                            // Let the shooter know their shot landed
+                            if source != index {
+                                let event = PlayerEvent::HitConfirmed {
+                                    index: source,
+                                    target: index,
+                                    killed,
+                                    headshot,
+                                };
+                                #[cfg(feature = "server")]
+                                if let Some(address) =
+                                    network_manager.get_address_for_player(source)
+                                {
+                                    network_manager.send_to_address_reliably(
+                                        address,
+                                        &NetworkMessage::PlayerEvent {
+                                            index: source,
+                                            event,
+                                        },
+                                    );
+                                }
+                                // Bots in practice don't need telling
+                                #[cfg(not(feature = "server"))]
+                                if network_manager.player_index == Some(source) {
+                                    self.queue_event(event);
+                                }
+                            }
+
+                            if killed {
+                                self.eliminate_player(
+                                    index,
+                                    Some(source),
+                                    None,
+                                    network_manager,
+                                    game_event_sender,
+                                );
+                            }
+                        }
+
+                        // Point towards whoever is shooting the local player
+                        #[cfg(not(feature = "server"))]
+                        if network_manager.player_index == Some(index) && source != index {
+                            interface
+                                .damage_indicator
+                                .show(&engine.user_interface, source);
+                        }
+                    }
+                }
+                PlayerEvent::PickupItem { index, item } => {
+                    let scene = &mut engine.scenes[self.scene];
+                    self.pickups.take(&mut scene.graph, item);
+
+                    if let Some(kind) = self.pickups.kind(item) {
+                        if let Some(player) = self.get_player_by_index(index) {
+                            player.pick_up(kind);
+                        }
+                    }
+                }
+                #[cfg(not(feature = "server"))]
+                PlayerEvent::HitConfirmed {
+                    killed, headshot, ..
+                } => {
+                    self.local_stats.hits += 1;
+                    interface.hit_marker.show(&engine.user_interface, killed);
+
+                    let scene = &mut engine.scenes[self.scene];
+                    if let Some(camera) = self.active_camera(scene) {
+                        let position = scene.graph[camera].global_position();
+                        self.hit_sounds.play(scene, position, headshot);
+                    }
+                }
+                PlayerEvent::KillPlayer {
+                    index,
+                    killer,
+                    zone,
+                } => {
+                    let message = zone.map_or_else(
+                        || format!("Player {} has been eliminated.\n", index),
+                        |zone| zone.death_message(index),
+                    );
                     engine.user_interface.send_message(TextBoxMessage::text(
                         interface.textbox,
                         MessageDirection::ToWidget,
-                        format!("Player {} has been eliminated.\n", index),
+                        message,
                     ));
-                    self.remove_player(engine, index);
-                    // If current player was killed then spectate another player
-                    if let Some(player_index) = network_manager.player_index {
-                        if player_index == index {
-                            let scene = &mut engine.scenes[self.scene];
-                            if let Some(player_to_spectate) = self.players.first() {
-                                player_to_spectate.set_camera(scene, true);
-                            }
+                    // Falling off on your own doesn't count as a kill
+                    #[cfg(not(feature = "server"))]
+                    if killer.is_some()
+                        && killer == network_manager.player_index
+                        && killer != Some(index)
+                    {
+                        self.local_stats.kills += 1;
+                    }
+                    // If current player was killed then start spectating where they died, after
+                    // seeing how it happened from the killer's side
+                    #[cfg(not(feature = "server"))]
+                    if network_manager.player_index == Some(index) {
+                        self.local_stats.deaths += 1;
+                        let scene = &mut engine.scenes[self.scene];
+                        if let Some(player) = self.players.iter().find(|p| p.index == index) {
+                            let (position, _) = player.get_aim(scene);
+                            self.spectator.start(scene, position);
+
+                            self.killcam = killer
+                                .filter(|&killer| killer != index)
+                                .and_then(|killer| self.view_history.replay(killer))
+                                .map(|killcam| (killcam, position));
                         }
                     }
+
+                    self.remove_player(engine, index);
                 }
                 PlayerEvent::SpawnPlayer {
                     index,
                     state,
                     current_player,
+                    skin,
                 } => {
-                    fyrox::core::futures::executor::block_on(self.spawn_player(
+                    self.spawn_player(
                         engine,
                         index,
                         PlayerState {
-                            position: Vector3::new(
-                                state.position.x,
-                                state.position.y,
-                                state.position.z,
-                            ),
-                            velocity: Vector3::new(
-                                state.velocity.x,
-                                state.velocity.y,
-                                state.velocity.z,
-                            ),
+                            position: state.position,
+                            velocity: state.velocity,
                             yaw: state.yaw,
                             pitch: state.pitch,
                             shoot: state.shoot,
                             ..Default::default()
                         },
                         current_player,
+                        skin,
                         network_manager,
-                    ));
+                    )?;
                 }
                 _ => (),
             }
         }
 
-        for player in self.players.iter_mut() {
-            let scene = &mut engine.scenes[self.scene];
-            #[cfg(feature = "server")]
-            if elapsed_time % (SYNC_FREQUENCY as f32 * dt) < dt {
-                let position = player.get_position(&scene);
-                let velocity = player.get_velocity(&scene);
-                let state_message = NetworkMessage::PlayerEvent {
-                    index: player.index,
-                    event: PlayerEvent::UpdateState {
-                        timestamp: elapsed_time,
-                        index: player.index,
-                        position: SerializableVector {
-                            x: position.x,
-                            y: position.y,
-                            z: position.z,
-                        },
-                        velocity: SerializableVector {
-                            x: velocity.x,
-                            y: velocity.y,
-                            z: velocity.z,
-                        },
-                        yaw: player.get_yaw(),
-                        pitch: player.get_pitch(),
-                        shoot: player.controller.shoot,
-                        fuel: player.flight_fuel,
-                    },
-                };
+        #[cfg(not(feature = "server"))]
+        {
+            interface.hit_marker.update(&engine.user_interface, dt);
+
+            let scene = &engine.scenes[self.scene];
+            let local_player = network_manager
+                .player_index
+                .and_then(|index| self.players.iter().find(|p| p.index == index));
+
+            let spread = local_player.map_or(0.0, |player| player.spread(scene));
+            interface
+                .crosshair
+                .update(&engine.user_interface, dt, spread);
+            interface
+                .damage_indicator
+                .update(&engine.user_interface, dt, |source| {
+                    let player = local_player?;
+                    let shooter = self.players.iter().find(|p| p.index == source)?;
+                    let offset = shooter.get_position(scene) - player.get_position(scene);
+
+                    // Players look along +Z rotated by yaw around the Y axis
+                    Some(offset.x.atan2(offset.z) - player.get_yaw().to_radians())
+                });
+
+            let (window_width, window_height) = engine.renderer.get_frame_size();
+            let nametags = self.visible_nametags(
+                scene,
+                Vector2::new(window_width as f32, window_height as f32),
+            );
+            interface
+                .nametags
+                .update(&mut engine.user_interface, &nametags);
+        }
+
+        let scene = &mut engine.scenes[self.scene];
+
+        #[cfg(not(feature = "server"))]
+        {
+            self.view_history.record(scene, &self.players, dt);
 
-                network_manager.send_to_all_unreliably(&state_message, 0);
+            // Killcam moves the spectator camera until it's over, then free flying carries on
+            // from where the local player died
+            if let Some((killcam, position)) = &mut self.killcam {
+                match killcam.update(dt) {
+                    Some(view) => self.spectator.set_view(view.position, view.yaw, view.pitch),
+                    None => {
+                        self.spectator.start(scene, *position);
+                        self.killcam = None;
+                    }
+                }
+            }
+
+            self.spectator.update(scene, &mut self.players, dt);
+        }
+
+        let authoritative = self.authoritative();
+        for projectile in self.projectiles.iter_mut() {
+            // Clients wait for the server to detonate projectiles, except in practice
+            if let Some(impact) = projectile.update(scene, dt) {
+                if authoritative {
+                    let event = PlayerEvent::DetonateProjectile {
+                        id: projectile.id,
+                        position: impact,
+                    };
+                    network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent {
+                        index: projectile.owner,
+                        event,
+                    });
+                    self.sender.send(event).unwrap();
+                }
             }
+        }
+
+        #[cfg(feature = "server")]
+        if elapsed_time % (rates.sync_interval as f32 * dt) < dt {
+            let scene = &engine.scenes[self.scene];
+            let players = self
+                .players
+                .iter()
+                .map(|player| PlayerSnapshot::from_player(player, scene))
+                .collect();
+
+            network_manager.send_snapshot(elapsed_time, players);
+        }
+
+        #[cfg(not(feature = "server"))]
+        let mut block_hits = Vec::new();
+        for player in self.players.iter_mut() {
+            let scene = &mut engine.scenes[self.scene];
 
             let previous_state = PlayerState {
-                timestamp: 0.0,
+                timestamp: elapsed_time,
                 position: player.get_position(scene),
                 velocity: player.get_velocity(scene),
                 yaw: player.get_yaw(),
@@ -421,13 +1288,12 @@ impl Level {
                 fuel: player.flight_fuel,
             };
 
-            let length = player.controller.previous_states.len();
-            let buffer_length = 3;
-
-            if length >= buffer_length {
-                player.controller.previous_states.remove(0);
-            }
-            player.controller.previous_states.push(previous_state);
+            // Enough local states to cover the time between two snapshots
+            let history = &mut player.controller.previous_states;
+            history.set_capacity(rates.sync_interval as usize);
+            history.push(elapsed_time, previous_state);
+            #[cfg(not(feature = "server"))]
+            player.render_from = player.get_position(scene);
 
             player.update(
                 dt,
@@ -436,76 +1302,401 @@ impl Level {
                 engine.resource_manager.clone(),
                 network_manager,
                 &self.sender,
+                authoritative,
                 interface,
             );
+
+            #[cfg(not(feature = "server"))]
+            {
+                for impact in player.impacts.drain(..) {
+                    self.impact_effects.impact(
+                        &mut engine.scenes[self.scene],
+                        impact.position,
+                        impact.normal,
+                        impact.decal,
+                    );
+                }
+                block_hits.append(&mut player.block_hits);
+                self.local_stats.shots += std::mem::take(&mut player.shots_fired);
+            }
+        }
+
+        #[cfg(not(feature = "server"))]
+        {
+            for block in block_hits {
+                self.predict_block_hit(engine, block);
+            }
+            self.expire_predictions(engine, dt);
+            self.impact_effects
+                .update(&mut engine.scenes[self.scene], dt);
+            self.draw_debug(
+                engine,
+                interface.debug_overlay.is_visible(),
+                network_manager.player_index,
+                dt,
+            );
+        }
+
+        #[cfg(feature = "server")]
+        {
+            self.count_shots();
+            self.award_pickups(engine, network_manager);
+            self.launch_players(engine, network_manager, dt);
+            self.collapse_blocks(engine, network_manager, dt);
+            self.kill_players_in_zones(engine, network_manager, game_event_sender);
         }
+        self.pickups
+            .update(&mut engine.scenes[self.scene].graph, dt);
 
-        // let scene = &mut engine.scenes[self.scene];
-        // #[cfg(not(feature = "server"))]
-        // for (x, blocks_x) in self.blocks.iter().enumerate() {
-        //     for (y, blocks_y) in blocks_x.iter().enumerate() {
-        //         for (z, &handle) in blocks_y.iter().enumerate() {
-        //             if self.blocks[x][y][z].is_some() {
-        //                 let hidden_pos = self.get_hidden_block_position(x, y, z);
-        //                 if self.blocks[x - 1][y][z].is_some()
-        //                     && self.blocks[x + 1][y][z].is_some()
-        //                     && self.blocks[x][y - 1][z].is_some()
-        //                     && self.blocks[x][y + 1][z].is_some()
-        //                     && self.blocks[x][y][z - 1].is_some()
-        //                     && self.blocks[x][y][z + 1].is_some()
-        //                     && hidden_pos.is_none()
-        //                 {
-        //                     self.hidden_blocks
-        //                         .push(scene.graph.take_reserve_sub_graph(handle));
-        //                 } else if let Some(pos) = hidden_pos {
-        //                     scene
-        //                         .graph
-        //                         .put_sub_graph_back(self.hidden_blocks.remove(pos));
-        //                 }
-        //             }
-        //         }
-        //     }
-        // }
-    }
-
-    // fn get_hidden_block_position(&self, x: usize, y: usize, z: usize) -> Option<usize> {
-    //     self.hidden_blocks.iter().position(|g| {
-    //         (g.root.1.global_position().x.round() + 50.0) as usize == x
-    //             && (g.root.1.global_position().y.round() + 50.0) as usize == y
-    //             && (g.root.1.global_position().z.round() + 50.0) as usize == z
-    //     })
-    // }
-
-    pub async fn spawn_player(
+        Ok(())
+    }
+
+    // Players standing on jump pads get sent flying, the same way explosions push them
+    #[cfg(feature = "server")]
+    fn launch_players(
         &mut self,
         engine: &mut GameEngine,
-        index: u32,
-        state: PlayerState,
-        current_player: bool,
         network_manager: &mut NetworkManager,
+        dt: f32,
+    ) {
+        self.jump_pads.update(dt);
+
+        let scene = &engine.scenes[self.scene];
+        let mut launches = Vec::new();
+        for player in self.players.iter() {
+            let position = player.get_position(scene);
+            if let Some(impulse) = self.jump_pads.launch(&scene.graph, player.index, position) {
+                launches.push((player.index, impulse));
+            }
+        }
+
+        for (index, impulse) in launches {
+            let event = PlayerEvent::ApplyImpulse { index, impulse };
+            network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent { index, event });
+            self.queue_event(event);
+        }
+    }
+
+    // Blocks that lost their support start falling, ones that fell out of the level are destroyed
+    #[cfg(feature = "server")]
+    fn collapse_blocks(
+        &mut self,
+        engine: &mut GameEngine,
+        network_manager: &mut NetworkManager,
+        dt: f32,
+    ) {
+        for block in self.falling_blocks.update(&self.blocks, dt) {
+            let index = block.index();
+            let event = PlayerEvent::SpawnFallingBlock { index };
+            network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent { index, event });
+            self.queue_event(event);
+        }
+
+        let graph = &engine.scenes[self.scene].graph;
+        for index in self.falling_blocks.fallen(graph) {
+            self.break_block(index, None, network_manager);
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn kill_players_in_zones(
+        &mut self,
+        engine: &GameEngine,
+        network_manager: &mut NetworkManager,
+        game_event_sender: &Sender<GameEvent>,
     ) {
+        let scene = &engine.scenes[self.scene];
+        let killed: Vec<(u32, ZoneKind)> = self
+            .players
+            .iter()
+            .filter_map(|player| {
+                self.kill_zones
+                    .zone_at(player.get_position(scene))
+                    .map(|zone| (player.index, zone))
+            })
+            .collect();
+
+        for (index, zone) in killed {
+            self.eliminate_player(index, None, Some(zone), network_manager, game_event_sender);
+        }
+    }
+
+    // Hands out items players are touching, an item only goes to one of them
+    #[cfg(feature = "server")]
+    fn award_pickups(&mut self, engine: &mut GameEngine, network_manager: &mut NetworkManager) {
         let scene = &mut engine.scenes[self.scene];
 
-        if self.get_player_by_index(index).is_none() {
-            if current_player {
-                network_manager.player_index = Some(index);
+        for player in self.players.iter() {
+            let position = player.get_position(scene);
+            let item = match self
+                .pickups
+                .item_at(position, |kind| player.wants_item(kind))
+            {
+                Some(item) => item,
+                None => continue,
+            };
 
-                // Disable any spectator cams
-                for existing_player in self.players.iter() {
-                    existing_player.set_camera(scene, false);
-                }
+            self.pickups.take(&mut scene.graph, item);
+
+            let event = PlayerEvent::PickupItem {
+                index: player.index,
+                item,
+            };
+            network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent {
+                index: player.index,
+                event,
+            });
+            self.queue_event(event);
+        }
+    }
+
+    pub fn spawn_player(
+        &mut self,
+        engine: &mut GameEngine,
+        index: u32,
+        state: PlayerState,
+        current_player: bool,
+        skin: u32,
+        network_manager: &mut NetworkManager,
+    ) -> Result<(), GameError> {
+        let resources = match &self.player_resources {
+            Some(resources) => resources,
+            None => {
+                // Only the latest spawn for a player matters
+                self.pending_spawns.retain(|s| s.index != index);
+                self.pending_spawns.push(PendingSpawn {
+                    index,
+                    state,
+                    current_player,
+                    skin,
+                });
+                return Ok(());
             }
+        };
 
+        let scene = &mut engine.scenes[self.scene];
+
+        if self.players.iter().all(|p| p.index != index) {
+            // Nothing changes if the player can't be built
             let player = Player::new(
                 scene,
                 state,
-                engine.resource_manager.clone(),
+                resources,
                 current_player,
                 index,
-            )
-            .await;
+                skin,
+                self.fov,
+            )?;
+
+            if current_player {
+                network_manager.player_index = Some(index);
+
+                #[cfg(not(feature = "server"))]
+                {
+                    self.killcam = None;
+                    self.spectator.stop(scene, &mut self.players);
+                }
+            }
 
             self.players.push(player);
+
+            #[cfg(not(feature = "server"))]
+            if let (Some(player), Some(&color)) =
+                (self.players.last_mut(), self.player_colors.get(&index))
+            {
+                player.set_color(scene, color);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Colors come with the scores, players that spawn later are tinted when they do
+    #[cfg(not(feature = "server"))]
+    pub fn set_player_colors(&mut self, engine: &mut GameEngine, scores: &[PlayerScore]) {
+        self.player_colors = scores
+            .iter()
+            .map(|score| (score.index, player_color(scores, score.index)))
+            .collect();
+
+        let scene = &mut engine.scenes[self.scene];
+        for player in self.players.iter_mut() {
+            if let Some(&color) = self.player_colors.get(&player.index) {
+                player.set_color(scene, color);
+            }
+        }
+    }
+
+    // Picks up the player resources once they've loaded and spawns everyone waiting on them
+    fn spawn_pending_players(
+        &mut self,
+        engine: &mut GameEngine,
+        network_manager: &mut NetworkManager,
+    ) -> Result<(), GameError> {
+        if let Some(receiver) = &self.resource_receiver {
+            let resources = match receiver.try_recv() {
+                Ok(resources) => resources,
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => Err(GameError::Channel),
+            };
+            self.resource_receiver = None;
+
+            if resources.is_err() {
+                // Nobody can spawn on this level
+                self.pending_spawns.clear();
+            }
+            self.player_resources = Some(resources?);
+        }
+
+        for spawn in std::mem::take(&mut self.pending_spawns) {
+            self.spawn_player(
+                engine,
+                spawn.index,
+                spawn.state,
+                spawn.current_player,
+                spawn.skin,
+                network_manager,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn spawn_projectile(
+        &mut self,
+        engine: &mut GameEngine,
+        id: u32,
+        owner: u32,
+        spawn: ProjectileSpawn,
+    ) {
+        let owner_collider = self
+            .players
+            .iter()
+            .find(|p| p.index == owner)
+            .map_or(Handle::NONE, |p| p.collider);
+
+        let projectile = Projectile::new(
+            &mut engine.scenes[self.scene],
+            id,
+            owner,
+            owner_collider,
+            spawn,
+        );
+
+        self.projectiles.push(projectile);
+    }
+
+    // Destroy blocks, damage and push back players within the projectile's radius
+    fn explode(
+        &mut self,
+        engine: &mut GameEngine,
+        center: Vector3<f32>,
+        projectile: &Projectile,
+        network_manager: &mut NetworkManager,
+    ) {
+        let scene = &engine.scenes[self.scene];
+
+        let blocks: Vec<u32> = self
+            .blocks
+            .within_radius(center, projectile.radius)
+            .into_iter()
+            .map(|(_, handle)| handle.index())
+            .collect();
+
+        for block in blocks {
+            self.break_block(block, Some(projectile.owner), network_manager);
+        }
+
+        let knockback = projectile.knockback;
+        let mut events = Vec::new();
+
+        for player in self.players.iter() {
+            let offset = player.get_position(scene) - center;
+            let distance = offset.norm();
+            if distance > projectile.radius {
+                continue;
+            }
+
+            // Damage and knockback fall off linearly towards the edge of the explosion
+            let falloff = 1.0 - distance / projectile.radius;
+
+            if projectile.damage > 0.0 {
+                events.push(PlayerEvent::Damage {
+                    index: player.index,
+                    amount: projectile.damage * falloff,
+                    source: projectile.owner,
+                    headshot: false,
+                });
+            }
+
+            if knockback > 0.0 {
+                // Players standing right on top of the explosion get pushed straight up
+                let direction = offset
+                    .try_normalize(f32::EPSILON)
+                    .unwrap_or_else(Vector3::y);
+                events.push(PlayerEvent::ApplyImpulse {
+                    index: player.index,
+                    impulse: direction * knockback * falloff,
+                });
+            }
+        }
+
+        #[cfg(feature = "server")]
+        {
+            let hit_someone = events.iter().any(|event| {
+                matches!(event, PlayerEvent::Damage { index, .. } if *index != projectile.owner)
+            });
+            if hit_someone {
+                self.round_stats_mut(projectile.owner).shots_hit += 1;
+            }
+        }
+
+        for event in events {
+            let index = match event {
+                PlayerEvent::Damage { index, .. } | PlayerEvent::ApplyImpulse { index, .. } => {
+                    index
+                }
+                _ => continue,
+            };
+            network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent { index, event });
+            self.queue_event(event);
+        }
+    }
+
+    // Send kill events for player and reset the level if there aren't enough players left. Practice
+    // has no rounds, the game puts players back instead.
+    fn eliminate_player(
+        &mut self,
+        index: u32,
+        killer: Option<u32>,
+        zone: Option<ZoneKind>,
+        network_manager: &mut NetworkManager,
+        game_event_sender: &Sender<GameEvent>,
+    ) {
+        game_event_sender
+            .send(GameEvent::PlayerKilled { index, killer })
+            .unwrap();
+
+        let kill_event = PlayerEvent::KillPlayer {
+            index,
+            killer,
+            zone,
+        };
+        let kill_message = NetworkMessage::PlayerEvent {
+            index,
+            event: kill_event,
+        };
+
+        network_manager.send_to_all_reliably(&kill_message);
+        self.queue_event(kill_event);
+
+        #[cfg(feature = "server")]
+        if self.players.len() < 3 {
+            // Last player standing wins the round
+            let winner = self.players.iter().map(|p| p.index).find(|&i| i != index);
+            game_event_sender
+                .send(GameEvent::RoundEnded { winner })
+                .unwrap();
         }
     }
 
@@ -514,24 +1705,343 @@ impl Level {
         for i in state.destroyed_blocks {
             self.destroy_block(engine, i);
         }
+
+        #[cfg(not(feature = "server"))]
+        for (index, stage) in state.cracked_blocks {
+            let graph = &mut engine.scenes[self.scene].graph;
+            let handle = graph.handle_from_index(index);
+            self.block_effects.block_cracked(graph, handle, stage);
+        }
+    }
+
+    // Tells everyone to destroy a block, including this level. Counts for whoever broke it, if
+    // it wasn't the level itself.
+    #[allow(unused_variables)]
+    fn break_block(
+        &mut self,
+        index: u32,
+        breaker: Option<u32>,
+        network_manager: &mut NetworkManager,
+    ) {
+        #[cfg(feature = "server")]
+        if let Some(breaker) = breaker {
+            self.round_stats_mut(breaker).blocks_destroyed += 1;
+        }
+        let event = PlayerEvent::DestroyBlock { index };
+        network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent { index, event });
+        self.queue_event(event);
+    }
+
+    #[cfg(feature = "server")]
+    fn round_stats_mut(&mut self, index: u32) -> &mut PlayerMatchStats {
+        self.round_stats
+            .entry(index)
+            .or_insert_with(|| PlayerMatchStats {
+                index,
+                ..Default::default()
+            })
+    }
+
+    #[cfg(feature = "server")]
+    fn count_shots(&mut self) {
+        let shots: Vec<(u32, u32)> = self
+            .players
+            .iter_mut()
+            .map(|player| (player.index, std::mem::take(&mut player.shots_fired)))
+            .filter(|&(_, shots)| shots > 0)
+            .collect();
+        for (index, shots) in shots {
+            self.round_stats_mut(index).shots_fired += shots;
+        }
+    }
+
+    // Without kills and deaths, players who haven't done anything yet get zeros
+    #[cfg(feature = "server")]
+    pub fn round_stats(&self, index: u32) -> PlayerMatchStats {
+        self.round_stats
+            .get(&index)
+            .cloned()
+            .unwrap_or_else(|| PlayerMatchStats {
+                index,
+                ..Default::default()
+            })
+    }
+
+    // Elimination mode closes the arena in a ring at a time
+    #[cfg(feature = "server")]
+    pub fn shrink_floor(&mut self, network_manager: &mut NetworkManager) {
+        for block in self.blocks.outer_ring() {
+            self.break_block(block.index(), None, network_manager);
+        }
+    }
+
+    // Clients only need to know how cracked the block looks, joining clients get it from the state
+    fn crack_block(
+        &mut self,
+        index: u32,
+        stage: u32,
+        hits_left: u32,
+        network_manager: &mut NetworkManager,
+    ) {
+        match self
+            .state
+            .cracked_blocks
+            .iter_mut()
+            .find(|(i, _)| *i == index)
+        {
+            Some(cracked) => cracked.1 = stage,
+            None => self.state.cracked_blocks.push((index, stage)),
+        }
+
+        let event = PlayerEvent::CrackBlock {
+            index,
+            stage,
+            hits_left,
+        };
+        network_manager.send_to_all_reliably(&NetworkMessage::PlayerEvent { index, event });
+        // Practice has no server to send it back
+        #[cfg(not(feature = "server"))]
+        self.queue_event(event);
+    }
+
+    // Whether this level decides hits and damage itself rather than waiting to be told
+    #[cfg(feature = "server")]
+    fn authoritative(&self) -> bool {
+        true
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn authoritative(&self) -> bool {
+        self.practice
+    }
+
+    // Counts a hit by the local player on a block and hides the block straight away if the hit
+    // should break it, rather than waiting a round trip for the server to say so
+    #[cfg(not(feature = "server"))]
+    fn predict_block_hit(&mut self, engine: &mut GameEngine, handle: Handle<Node>) {
+        let scene = &mut engine.scenes[self.scene];
+        if !scene.graph.is_valid_handle(handle) {
+            return;
+        }
+
+        let index = handle.index();
+        if self.predicted_blocks.iter().any(|(i, _)| *i == index) {
+            return;
+        }
+
+        let health = self.block_health.of(scene.graph[handle].tag());
+        let hits_left = self.block_hits_left.entry(index).or_insert(health);
+        *hits_left = hits_left.saturating_sub(1);
+        if *hits_left > 0 {
+            return;
+        }
+
+        let position = scene.graph[handle].global_position();
+        let coord = BlockStore::coord_for_position(position);
+        if self.blocks.get(coord) != handle {
+            return;
+        }
+
+        self.hidden_blocks
+            .predict_removed(coord, handle, &self.blocks, &mut scene.graph);
+        self.block_effects.block_destroyed(scene, position);
+        self.predicted_blocks.push((index, PREDICTION_TIMEOUT));
     }
 
-    pub fn destroy_block(&mut self, engine: &mut GameEngine, index: u32) {
+    // Colliders, recent shots and a line from each remote player to where the server last put them,
+    // redrawn every tick while the debug overlay is up
+    #[cfg(not(feature = "server"))]
+    fn draw_debug(
+        &mut self,
+        engine: &mut GameEngine,
+        enabled: bool,
+        player_index: Option<u32>,
+        dt: f32,
+    ) {
+        let scene = &mut engine.scenes[self.scene];
+        scene.drawing_context.clear_lines();
+
+        for player in self.players.iter_mut() {
+            for (begin, end) in player.shot_rays.drain(..) {
+                if enabled {
+                    self.debug_shots.push((begin, end, DEBUG_SHOT_TIME));
+                }
+            }
+        }
+        if !enabled {
+            self.debug_shots.clear();
+            return;
+        }
+
+        scene.graph.physics.draw(&mut scene.drawing_context);
+
+        for shot in self.debug_shots.iter_mut() {
+            shot.2 -= dt;
+        }
+        self.debug_shots
+            .retain(|&(_, _, time_left)| time_left > 0.0);
+        for &(begin, end, _) in self.debug_shots.iter() {
+            scene.drawing_context.add_line(Line {
+                begin,
+                end,
+                color: Color::opaque(255, 220, 0),
+            });
+        }
+
+        for player in self.players.iter() {
+            if Some(player.index) == player_index {
+                continue;
+            }
+            let server_position = match self.server_positions.get(&player.index) {
+                Some(&position) => position,
+                None => continue,
+            };
+
+            let color = Color::opaque(255, 40, 40);
+            scene.drawing_context.add_line(Line {
+                begin: player.get_position(scene),
+                end: server_position,
+                color,
+            });
+            for axis in [Vector3::x(), Vector3::y(), Vector3::z()] {
+                let offset = axis * DEBUG_MARKER_SIZE / 2.0;
+                scene.drawing_context.add_line(Line {
+                    begin: server_position - offset,
+                    end: server_position + offset,
+                    color,
+                });
+            }
+        }
+    }
+
+    // States buffered for each remote player and how far they're drawn from the server position
+    #[cfg(not(feature = "server"))]
+    pub fn debug_summary(&self, engine: &GameEngine, player_index: Option<u32>) -> String {
+        let scene = &engine.scenes[self.scene];
+        let mut summary = String::new();
+        for player in self.players.iter() {
+            if Some(player.index) == player_index {
+                continue;
+            }
+
+            let offset = self
+                .server_positions
+                .get(&player.index)
+                .map_or(0.0, |position| {
+                    (position - player.get_position(scene)).norm()
+                });
+            summary += &format!(
+                "\nplayer {}: {} states buffered, {:.2} m from server",
+                player.index,
+                player.controller.new_states.len(),
+                offset
+            );
+        }
+        summary
+    }
+
+    // Returns whether the block had been predicted
+    #[cfg(not(feature = "server"))]
+    fn confirm_prediction(&mut self, index: u32) -> bool {
+        self.block_hits_left.remove(&index);
+
+        let count = self.predicted_blocks.len();
+        self.predicted_blocks.retain(|(i, _)| *i != index);
+        self.predicted_blocks.len() != count
+    }
+
+    // Blocks the server never destroyed come back. The hit count is forgotten too, the next
+    // CrackBlock sets it straight.
+    #[cfg(not(feature = "server"))]
+    fn expire_predictions(&mut self, engine: &mut GameEngine, dt: f32) {
+        let graph = &mut engine.scenes[self.scene].graph;
+
+        for (_, time_left) in self.predicted_blocks.iter_mut() {
+            *time_left -= dt;
+        }
+        let expired: Vec<u32> = self
+            .predicted_blocks
+            .iter()
+            .filter(|(_, time_left)| *time_left <= 0.0)
+            .map(|(index, _)| *index)
+            .collect();
+        self.predicted_blocks
+            .retain(|(_, time_left)| *time_left > 0.0);
+
+        for index in expired {
+            self.block_hits_left.remove(&index);
+            let handle = graph.handle_from_index(index);
+            if handle.is_some() && graph.is_valid_handle(handle) {
+                self.hidden_blocks.restore(handle, graph);
+            }
+        }
+    }
+
+    // Returns where the block was if it was still there
+    pub fn destroy_block(&mut self, engine: &mut GameEngine, index: u32) -> Option<Vector3<f32>> {
         let scene = &mut engine.scenes[self.scene];
 
         let handle = scene.graph.handle_from_index(index);
 
         if handle.is_some() && scene.graph.is_valid_handle(handle) {
-            let node = &scene.graph[handle];
-            // self.blocks[(node.global_position().x.round() + 50.0) as usize]
-            //     [(node.global_position().y.round() + 50.0) as usize]
-            //     [(node.global_position().z.round() + 50.0) as usize] = Handle::<Node>::NONE;
-
+            let position = scene.graph[handle].global_position();
+            self.take_from_store(&mut scene.graph, handle, position);
             scene.remove_node(handle);
 
             #[cfg(feature = "server")]
+            self.forget_block(index);
+
+            Some(position)
+        } else {
+            None
+        }
+    }
+
+    // Lets go of a block that lost its support, it's destroyed once it falls out of the level
+    fn drop_block(&mut self, engine: &mut GameEngine, index: u32) {
+        let graph = &mut engine.scenes[self.scene].graph;
+
+        let handle = graph.handle_from_index(index);
+        if handle.is_none() || !graph.is_valid_handle(handle) || !graph[handle].is_rigid_body() {
+            return;
+        }
+
+        let position = graph[handle].global_position();
+        self.take_from_store(graph, handle, position);
+        graph[handle]
+            .as_rigid_body_mut()
+            .set_body_type(RigidBodyType::Dynamic);
+
+        // Joining clients don't need to see it fall
+        #[cfg(feature = "server")]
+        {
+            self.forget_block(index);
+            self.falling_blocks.start_falling(index);
+        }
+    }
+
+    // The block is no longer part of the grid, whatever it was covering comes out
+    fn take_from_store(&mut self, graph: &mut Graph, handle: Handle<Node>, position: Vector3<f32>) {
+        let coord = BlockStore::coord_for_position(position);
+
+        if self.blocks.get(coord) == handle {
+            self.blocks.remove(coord);
+
+            self.hidden_blocks
+                .block_removed(coord, handle, &self.blocks, graph);
+            self.pickups.uncover(graph, coord);
+            #[cfg(feature = "server")]
+            self.falling_blocks.support_removed(coord);
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn forget_block(&mut self, index: u32) {
+        if !self.state.destroyed_blocks.contains(&index) {
             self.state.destroyed_blocks.push(index);
         }
+        self.state.cracked_blocks.retain(|(i, _)| *i != index);
+        self.damaged_blocks.remove(&index);
     }
 
     pub fn players(&self) -> &Vec<Player> {
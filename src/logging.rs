@@ -0,0 +1,55 @@
+// Diagnostics go through tracing. Everything is written to stdout with a timestamp, and the
+// dedicated server can also write JSON lines to a file for whatever collects its logs. Which
+// messages show up comes from RUST_LOG if it's set, otherwise from the log_filter setting. Both
+// take the usual filter syntax, like "info,breakfloor::network_manager=debug".
+
+#[cfg(feature = "server")]
+use std::{fs::OpenOptions, sync::Mutex};
+
+use tracing::warn;
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+use crate::Settings;
+
+pub const DEFAULT_FILTER: &str = "info";
+
+// Call once, before anything is logged. Messages from before this are lost.
+pub fn init(settings: &Settings) {
+    let directives = std::env::var("RUST_LOG").unwrap_or_else(|_| settings.log_filter.clone());
+    let (filter, bad_filter) = match EnvFilter::try_new(&directives) {
+        Ok(filter) => (filter, None),
+        Err(e) => (EnvFilter::new(DEFAULT_FILTER), Some(e)),
+    };
+
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer());
+
+    #[cfg(feature = "server")]
+    let mut file_error = None;
+    #[cfg(feature = "server")]
+    let registry = {
+        let file = settings.log_file.as_ref().and_then(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| file_error = Some((path.clone(), e)))
+                .ok()
+        });
+        registry.with(file.map(|file| fmt::layer().json().with_writer(Mutex::new(file))))
+    };
+
+    registry.init();
+
+    if let Some(e) = bad_filter {
+        warn!(
+            "invalid log filter {:?}, using {}: {}",
+            directives, DEFAULT_FILTER, e
+        );
+    }
+    #[cfg(feature = "server")]
+    if let Some((path, e)) = file_error {
+        warn!("failed to open log file {}: {}", path, e);
+    }
+}
@@ -1,18 +1,118 @@
-#![cfg_attr(not(feature = "console"), windows_subsystem = "windows")]
+// Hide the console window on Windows unless it's wanted for debugging
+#![cfg_attr(
+    all(target_os = "windows", not(feature = "console")),
+    windows_subsystem = "windows"
+)]
 pub mod animation;
+#[cfg(feature = "server")]
+pub mod bans;
+pub mod blocks;
+pub mod bot;
+pub mod chat;
+#[cfg(not(feature = "server"))]
+pub mod client_console;
+#[cfg(not(feature = "server"))]
+pub mod client_state;
+pub mod codec;
+pub mod collision;
+pub mod commands;
+pub mod compression;
+#[cfg(feature = "server")]
+pub mod console;
+pub mod demo;
+#[cfg(not(feature = "server"))]
+pub mod effects;
+pub mod error;
+#[cfg(feature = "server")]
+pub mod falling_blocks;
+#[cfg(not(feature = "server"))]
+pub mod footsteps;
+pub mod framing;
 pub mod game;
+#[cfg(not(feature = "server"))]
+pub mod gamepad;
+#[cfg(feature = "server")]
+pub mod headless;
+pub mod history;
+pub mod hud;
+pub mod input;
+#[cfg(not(feature = "server"))]
+pub mod input_queue;
+#[cfg(not(feature = "server"))]
+pub mod jetpack;
+#[cfg(feature = "server")]
+pub mod jump_pads;
+pub mod kill_zones;
+#[cfg(not(feature = "server"))]
+pub mod killcam;
 pub mod level;
+pub mod logging;
+pub mod match_state;
+pub mod menu;
+#[cfg(feature = "server")]
+pub mod metrics;
+pub mod net_stats;
 pub mod network_manager;
+pub mod pickups;
 pub mod player;
 pub mod player_event;
+#[cfg(not(feature = "server"))]
+pub mod player_stats;
+pub mod projectile;
+#[cfg(feature = "server")]
+pub mod relevancy;
+pub mod rotation;
+#[cfg(not(feature = "server"))]
+pub mod screenshot;
+pub mod secure;
+#[cfg(feature = "server")]
+pub mod send_budget;
+pub mod server_list;
+pub mod skins;
+pub mod snapshot;
+pub mod spectator;
+#[cfg(not(feature = "server"))]
+pub mod third_person;
+#[cfg(feature = "server")]
+pub mod validation;
+#[cfg(not(feature = "server"))]
+pub mod view_model;
+pub mod weapon;
 
+#[cfg(not(feature = "server"))]
+use crate::client_console::{ClientConsole, ConsoleCommand, AUTOEXEC_PATH};
+#[cfg(not(feature = "server"))]
+use crate::demo::DemoPlayer;
+#[cfg(not(feature = "server"))]
+use crate::gamepad::{Gamepad, GamepadAction};
+#[cfg(not(feature = "server"))]
+use crate::screenshot::Screenshots;
+#[cfg(not(feature = "server"))]
+use crate::server_list::{ServerList, ServerListEvent};
 use crate::{
+    chat::Chat,
     game::Game,
+    hud::{
+        move_widget, Anchor, Crosshair, CrosshairSettings, DamageIndicator, DebugOverlay,
+        HitMarker, MapVoteScreen, MatchStatus, Nametags, PerfHud, ResultsScreen, Scoreboard,
+        StatusBars,
+    },
+    input::{Action, Binding, GamepadSettings, KeyBindings},
     level::Level,
-    network_manager::{NetworkManager, NetworkMessage},
+    menu::{
+        BrowserAction, ControlsScreen, LoadingAction, LoadingScreen, Menu, MenuAction,
+        ServerBrowser, SettingsAction, SettingsScreen, SkinAction, SkinScreen, StatsScreen,
+        WarningDialog, FOV_RANGE, SENSITIVITY_RANGE,
+    },
+    network_manager::{NetworkManager, NetworkMessage, SERVER_ADDRESS},
     player::Player,
     player_event::PlayerEvent,
+    weapon::WEAPONS,
 };
+#[cfg(not(feature = "server"))]
+use crate::{input::InputEvent, input_queue::InputQueue};
+#[cfg(feature = "server")]
+use crate::{relevancy::Relevancy, server_list::ServerAnnouncer};
 use crossbeam_channel::{Receiver, Sender};
 use fyrox::{
     core::{
@@ -28,13 +128,12 @@ use fyrox::{
     event_loop::{ControlFlow, EventLoop},
     gui::{
         grid::GridBuilder,
-        image::ImageBuilder,
         message::MessageDirection,
         scroll_bar::ScrollBarBuilder,
         text::{TextBuilder, TextMessage},
         text_box::TextBoxBuilder,
         widget::WidgetBuilder,
-        HorizontalAlignment, UiNode, VerticalAlignment,
+        UiNode, UserInterface,
     },
     scene::{
         base::BaseBuilder,
@@ -45,19 +144,20 @@ use fyrox::{
         transform::TransformBuilder,
     },
     utils::{
-        into_gui_texture,
         log::{Log, MessageKind},
+        translate_event,
     },
     window::{Fullscreen, WindowBuilder},
 };
 use laminar::{Config, ErrorKind, Packet, Socket, SocketEvent};
 use player::PlayerState;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fmt,
     net::{SocketAddr, ToSocketAddrs},
-    os::windows::process,
     path::Path,
+    process::{Child, Command},
     sync::{
         mpsc::{self},
         Arc, RwLock,
@@ -65,64 +165,502 @@ use std::{
     thread,
     time::{self, Duration, Instant},
 };
+use tracing::{error, warn};
 
 // Create our own engine type aliases. These specializations are needed, because the engine
 // provides a way to extend UI with custom nodes and messages.
+#[cfg(not(feature = "server"))]
 type GameEngine = Engine;
+#[cfg(feature = "server")]
+type GameEngine = headless::HeadlessEngine;
 
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(default)]
 pub struct Settings {
-    look_sensitivity: f32,
+    version: u32,              // Which SETTINGS_VERSION the file was saved with
+    look_sensitivity: f32,     // Left and right
+    vertical_sensitivity: f32, // Up and down, as a multiple of look_sensitivity
+    invert_mouse_y: bool,
+    aim_sensitivity: f32, // Multiplies both sensitivities while aiming down sights
+    raw_mouse: bool, // Movement straight from the device, otherwise the cursor's with OS acceleration
     vsync: bool,
     fullscreen: bool,
+    fov: f32,       // Degrees
+    fov_kick: bool, // Widen the view while sprinting or flying
+    // Volumes are 0 to 1, effects and music are scaled by master
+    master_volume: f32,
+    effects_volume: f32,
+    music_volume: f32,
+    server_address: String,
+    server_port: u16,
+    client_port: u16, // 0 lets the OS pick a free port
+    compress_messages: bool,
+    server_name: String,
+    player_name: String,
+    player_color: [u8; 3],     // Tints the model and nametag others see
+    skin: u32,                 // Id from the skins manifest
+    practice_map: String,      // Loaded by the practice button
+    practice_bots: u32,        // Computer players to practice against, 0 for none
+    master_server_url: String, // Empty to not announce the server or browse servers
+    log_filter: String,        // Which messages get logged, RUST_LOG takes precedence
+    #[cfg(feature = "server")]
+    log_file: Option<String>, // Also log there as JSON lines
+    #[serde(skip)]
+    record_demo: Option<String>, // Only set from the command line
+    #[serde(skip)]
+    play_demo: Option<String>,
+    // Fields the command line changed, with the value from the file and the one given. The file's
+    // value is what gets saved so arguments only last for this run.
+    #[serde(skip)]
+    from_args: HashMap<String, (serde_json::Value, serde_json::Value)>,
+    bindings: KeyBindings,
+    gamepad: GamepadSettings,
+    crosshair: CrosshairSettings,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Self {
+            version: SETTINGS_VERSION,
             look_sensitivity: 0.5,
+            vertical_sensitivity: 1.0,
+            invert_mouse_y: false,
+            aim_sensitivity: 0.7,
+            raw_mouse: true,
             vsync: false,
             fullscreen: false,
+            fov: player::DEFAULT_FOV,
+            fov_kick: true,
+            master_volume: 1.0,
+            effects_volume: 1.0,
+            music_volume: 0.7,
+            server_address: SERVER_ADDRESS.to_string(),
+            server_port: 12351,
+            client_port: 0,
+            compress_messages: true,
+            server_name: String::from("Breakfloor server"),
+            player_name: String::from("Player"),
+            player_color: menu::PLAYER_COLORS[0],
+            skin: skins::DEFAULT_SKIN,
+            practice_map: String::from("block_test"),
+            practice_bots: 0,
+            master_server_url: server_list::MASTER_SERVER_URL.to_string(),
+            log_filter: logging::DEFAULT_FILTER.to_string(),
+            #[cfg(feature = "server")]
+            log_file: None,
+            record_demo: None,
+            play_demo: None,
+            from_args: HashMap::new(),
+            bindings: KeyBindings::default(),
+            gamepad: GamepadSettings::default(),
+            crosshair: CrosshairSettings::default(),
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+impl Settings {
+    // Mouse movement to yaw and pitch deltas in degrees
+    fn mouse_look(&self, x: f32, y: f32, aiming: bool) -> (f32, f32) {
+        let invert = if self.invert_mouse_y { -1.0 } else { 1.0 };
+        let scale = if aiming { self.aim_sensitivity } else { 1.0 };
+        (
+            self.look_sensitivity * scale * x,
+            self.look_sensitivity * self.vertical_sensitivity * scale * invert * y,
+        )
+    }
+}
+
+// Command line arguments override values from settings.json
+fn apply_args<I: Iterator<Item = String>>(
+    settings: &mut Settings,
+    mut args: I,
+) -> Result<(), String> {
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+
+        match arg.as_str() {
+            "--server-address" => settings.server_address = value()?,
+            "--server-port" => {
+                settings.server_port = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid server port: {}", e))?
+            }
+            "--client-port" => {
+                settings.client_port = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid client port: {}", e))?
+            }
+            "--server-name" => settings.server_name = value()?,
+            "--name" => settings.player_name = value()?,
+            "--master-server" => settings.master_server_url = value()?,
+            "--record-demo" => settings.record_demo = Some(value()?),
+            "--play-demo" => settings.play_demo = Some(value()?),
+            _ => return Err(format!("Unknown argument {}", arg)),
         }
     }
+
+    Ok(())
 }
 
-fn read_settings_from_file<P: AsRef<Path>>(path: P) -> Result<Settings, Box<dyn Error>> {
-    // Open the file in read-only mode with buffer.
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+const SETTINGS_PATH: &str = "settings.json";
+// An unreadable settings file is copied here before defaults are written over it
+const SETTINGS_BACKUP_PATH: &str = "settings.json.bak";
+// Bump when a setting is renamed or changes meaning, and add a step to migrate_settings
+const SETTINGS_VERSION: u32 = 1;
 
-    // Read the JSON contents of the file as an instance of `User`.
-    let u = serde_json::from_reader(reader)?;
+// Brings settings saved by older versions up to SETTINGS_VERSION and returns the version they were
+// saved with. Files from before there was a version count as 0.
+fn migrate_settings(value: &mut serde_json::Value) -> Result<u32, Box<dyn Error>> {
+    let settings = value.as_object_mut().ok_or("expected an object")?;
+    let version = match settings.get("version") {
+        Some(version) => version.as_u64().ok_or("version isn't a number")? as u32,
+        None => 0,
+    };
+
+    // 1: volume was split into master, effects and music volumes
+    if version < 1 {
+        if let Some(volume) = settings.remove("volume") {
+            settings.entry("master_volume").or_insert(volume);
+        }
+    }
 
-    // Return the `User`.
-    Ok(u)
+    if version < SETTINGS_VERSION {
+        settings.insert("version".to_string(), SETTINGS_VERSION.into());
+    }
+    Ok(version)
 }
 
-fn main() {
-    Log::set_verbosity(MessageKind::Warning);
+// Also returns whether the file should be written back, which it should if it was migrated or is
+// missing settings that were added since it was saved
+fn read_settings_from_file<P: AsRef<Path>>(path: P) -> Result<(Settings, bool), Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut value: serde_json::Value = serde_json::from_reader(reader)?;
+    let version = migrate_settings(&mut value)?;
+    let settings: Settings = serde_json::from_value(value.clone())?;
+
+    // Saved by a newer version, writing it back would lose whatever that version added
+    if version > SETTINGS_VERSION {
+        return Ok((settings, false));
+    }
+
+    let missing_fields = serde_json::to_value(&settings)?
+        .as_object()
+        .map_or(false, |fields| {
+            fields.keys().any(|key| value.get(key).is_none())
+        });
+    Ok((settings, version < SETTINGS_VERSION || missing_fields))
+}
+
+fn write_settings_to_file<P: AsRef<Path>>(
+    path: P,
+    settings: &Settings,
+) -> Result<(), Box<dyn Error>> {
+    let mut value = serde_json::to_value(settings)?;
+    // Unless it was changed in the game since, which is worth keeping
+    for (key, (on_file, given)) in &settings.from_args {
+        if let Some(current) = value.get_mut(key) {
+            if current == given {
+                *current = on_file.clone();
+            }
+        }
+    }
+
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, &value)?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "server"))]
+fn leave_game(
+    engine: &mut GameEngine,
+    game: &mut Game,
+    network_manager: &mut NetworkManager,
+    interface: &mut Interface,
+    menu: &Menu,
+    hosted_server: &mut Option<Child>,
+) {
+    network_manager.disconnect();
+    game.leave(engine, interface);
+    menu.set_status(&engine.user_interface, "");
+
+    // Nobody else can be playing on a server started from the menu once we've left
+    if let Some(mut server) = hosted_server.take() {
+        let _ = server.kill();
+    }
 
-    const SERVER: bool = cfg!(feature = "server");
-    // Our game logic will be updated at 60 Hz rate.
-    const TIMESTEP: f32 = 1.0 / 60.0;
+    menu.set_connected(&engine.user_interface, false);
+}
+
+// Says how far along joining is until there's a level to look at
+#[cfg(not(feature = "server"))]
+fn update_loading_screen(
+    loading_screen: &mut LoadingScreen,
+    engine: &GameEngine,
+    game: &Game,
+    network_manager: &NetworkManager,
+) {
+    // Error stays up until retry or back is clicked
+    if loading_screen.has_failed() {
+        return;
+    }
 
-    let settings: Settings = read_settings_from_file("settings.json").unwrap_or_default();
-    let fullscreen = if settings.fullscreen {
+    let ui = &engine.user_interface;
+    if network_manager.is_connected() && !network_manager.is_accepted() {
+        loading_screen.show_progress(ui, "Connecting...", None);
+    } else if game.is_loading() {
+        let progress = engine.resource_manager.state().loading_progress() as f32 / 100.0;
+        loading_screen.show_progress(ui, "Loading level...", Some(progress));
+    } else if network_manager.is_accepted() && game.level.is_none() {
+        loading_screen.show_progress(ui, "Downloading level state...", None);
+    } else {
+        loading_screen.hide(ui);
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn fullscreen_mode(enabled: bool) -> Option<Fullscreen> {
+    if enabled {
         Some(Fullscreen::Borderless(None))
     } else {
         None
+    }
+}
+
+// Settings are saved when leaving the screen rather than on every slider step
+#[cfg(not(feature = "server"))]
+fn close_settings(
+    settings_screen: &mut SettingsScreen,
+    menu: &mut Menu,
+    engine: &GameEngine,
+    game: &Game,
+) {
+    settings_screen.set_visible(&engine.user_interface, false);
+    menu.set_visible(&engine.user_interface, true);
+
+    if let Err(e) = write_settings_to_file(SETTINGS_PATH, &game.settings) {
+        warn!("failed to save settings: {}", e);
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn close_skins(skin_screen: &mut SkinScreen, menu: &mut Menu, engine: &GameEngine, game: &Game) {
+    skin_screen.set_visible(&engine.user_interface, false);
+    menu.set_visible(&engine.user_interface, true);
+
+    if let Err(e) = write_settings_to_file(SETTINGS_PATH, &game.settings) {
+        warn!("failed to save settings: {}", e);
+    }
+}
+
+// Name typed in the menu is saved so it's filled in next time
+#[cfg(not(feature = "server"))]
+fn remember_player_name(game: &mut Game, menu: &Menu) -> String {
+    let name = menu.player_name().trim().to_string();
+    if name != game.settings.player_name {
+        game.settings.player_name = name.clone();
+        if let Err(e) = write_settings_to_file(SETTINGS_PATH, &game.settings) {
+            warn!("failed to save settings: {}", e);
+        }
+    }
+    name
+}
+
+// Name of the server build that is started when hosting from the menu
+const SERVER_EXECUTABLE: &str = "breakfloor-server";
+
+#[cfg(not(feature = "server"))]
+fn host_server(port: u16) -> std::io::Result<Child> {
+    let path = std::env::current_exe()?
+        .with_file_name([SERVER_EXECUTABLE, std::env::consts::EXE_SUFFIX].concat());
+
+    Command::new(path)
+        .args(["--server-port", &port.to_string()])
+        .spawn()
+}
+
+// Returns false if the cursor couldn't be grabbed. Some platforms (e.g. Wayland compositors)
+// don't support it, the cursor is kept in the window with center_cursor instead.
+#[cfg(not(feature = "server"))]
+fn set_cursor_captured(engine: &GameEngine, captured: bool) -> bool {
+    let window = engine.get_window();
+    window.set_cursor_visible(!captured);
+
+    match window.set_cursor_grab(captured) {
+        Ok(_) => true,
+        Err(e) => {
+            if captured {
+                warn!("failed to grab cursor, falling back to centering: {}", e);
+            }
+            false
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn center_cursor(engine: &GameEngine) {
+    let window = engine.get_window();
+    let size = window.inner_size();
+    let _ = window.set_cursor_position(fyrox::dpi::PhysicalPosition::new(
+        size.width / 2,
+        size.height / 2,
+    ));
+}
+
+// Also returns a warning for the player if the settings file couldn't be read and defaults are
+// being used instead
+fn load_settings() -> (Settings, Option<String>) {
+    let (mut settings, save, invalid) = if !Path::new(SETTINGS_PATH).exists() {
+        // First start, the defaults are written out so there's a file to edit
+        (Settings::default(), true, None)
+    } else {
+        match read_settings_from_file(SETTINGS_PATH) {
+            Ok((settings, outdated)) => (settings, outdated, None),
+            Err(e) => (Settings::default(), false, Some(e)),
+        }
     };
 
+    // Whatever was in an unreadable file is kept so it can be fixed by hand, it's only replaced
+    // once it's safely copied
+    let backup = invalid
+        .as_ref()
+        .map(|_| fs::copy(SETTINGS_PATH, SETTINGS_BACKUP_PATH));
+    // Saved before the command line is applied, those only last for this run
+    let saved = (save || matches!(backup, Some(Ok(_))))
+        .then(|| write_settings_to_file(SETTINGS_PATH, &settings));
+
+    // What the command line changed is remembered so later saves don't keep it
+    let on_file = serde_json::to_value(&settings);
+    let args = apply_args(&mut settings, std::env::args().skip(1));
+    if let (Ok(serde_json::Value::Object(on_file)), Ok(serde_json::Value::Object(given))) =
+        (on_file, serde_json::to_value(&settings))
+    {
+        settings.from_args = given
+            .into_iter()
+            .filter_map(|(key, given)| {
+                let on_file = on_file.get(&key)?.clone();
+                (on_file != given).then(|| (key, (on_file, given)))
+            })
+            .collect();
+    }
+    logging::init(&settings);
+    if let Err(e) = args {
+        warn!("{}", e);
+    }
+    if let Some(Err(e)) = saved {
+        warn!("failed to save settings: {}", e);
+    }
+
+    let warning = invalid.map(|e| {
+        warn!("failed to read {}, using defaults: {}", SETTINGS_PATH, e);
+        let kept = match backup {
+            Some(Ok(_)) => format!("The old file was kept as {}.", SETTINGS_BACKUP_PATH),
+            Some(Err(e)) => {
+                warn!("failed to back up {}: {}", SETTINGS_PATH, e);
+                "Changing any setting will overwrite it.".to_string()
+            }
+            None => String::new(),
+        };
+        format!(
+            "{} couldn't be read so the default settings are being used. {}\n\n{}",
+            SETTINGS_PATH, kept, e
+        )
+    });
+
+    (settings, warning)
+}
+
+// Dedicated server runs without a window or renderer so it works on machines without a display
+#[cfg(feature = "server")]
+fn main() {
+    Log::set_verbosity(MessageKind::Warning);
+
+    // Already logged, there's nobody to show a dialog to
+    let (settings, _) = load_settings();
+
+    let mut engine = GameEngine::new();
+    let frame_size = engine.frame_size();
+    let mut interface = create_ui(&mut engine, frame_size, &settings.crosshair);
+
+    // Nothing to do without a socket, say why instead of panicking
+    let mut network_manager = match NetworkManager::new(&settings) {
+        Ok(network_manager) => network_manager,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut announcer = ServerAnnouncer::new(
+        &settings.master_server_url,
+        &settings.server_name,
+        settings.server_port,
+    );
+    let mut game = fyrox::core::futures::executor::block_on(Game::new(&mut engine, settings));
+    console::spawn(game.event_sender.clone());
+    console::catch_interrupt(game.event_sender.clone());
+    network_manager.set_relevancy(Relevancy::new(game.relevancy_radius));
+    network_manager.set_send_budget(game.send_budget);
+    network_manager.set_join_rules(
+        game.max_players,
+        game.password.clone(),
+        game.observer_password.clone(),
+    );
+    network_manager.set_idle_kick_time(game.idle_kick_time);
+    network_manager.set_metrics(game.metrics.clone());
+
+    let clock = time::Instant::now();
+    let mut elapsed_time = 0.0;
+    // Game logic runs at the tick rate from the server config
+    let timestep = game.rates.timestep();
+
+    loop {
+        network_manager.handle_events(&mut engine, &mut game);
+
+        let mut dt = clock.elapsed().as_secs_f32() - elapsed_time;
+        while dt >= timestep {
+            dt -= timestep;
+            elapsed_time += timestep;
+            let tick_started = Instant::now();
+
+            game.update(
+                &mut engine,
+                timestep,
+                &mut network_manager,
+                elapsed_time,
+                &mut interface,
+            );
+
+            if let Some(level) = &game.level {
+                announcer.update(timestep, &level.name, network_manager.player_count() as u32);
+            }
+
+            engine.update(timestep);
+            game.metrics.record_tick(tick_started.elapsed());
+        }
+
+        // Everything queued this tick goes out together
+        network_manager.flush();
+
+        // Nothing else to do until the next tick
+        thread::sleep(Duration::from_secs_f32(timestep - dt));
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn main() {
+    Log::set_verbosity(MessageKind::Warning);
+
+    let (settings, settings_warning) = load_settings();
+
     // Configure main window first.
     let window_builder = WindowBuilder::new()
-        .with_visible(!SERVER)
         .with_title("Breakfloor")
-        .with_fullscreen(fullscreen);
+        .with_fullscreen(fullscreen_mode(settings.fullscreen));
 
     // Create event loop that will be used to "listen" events from the OS.
     let event_loop = EventLoop::new();
@@ -138,338 +676,1383 @@ fn main() {
         })
         .unwrap();
 
-    let mut interface = create_ui(&mut engine);
+    let frame_size = engine.renderer.get_frame_size();
+    let mut interface = create_ui(&mut engine, frame_size, &settings.crosshair);
 
-    #[cfg(not(feature = "server"))]
-    {
-        let window = engine.get_window();
-        window.set_cursor_visible(false);
-        let _ = window.set_cursor_grab(true);
+    // Built before the menu so the menu can be opened on top of it
+    let mut loading_screen = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        LoadingScreen::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+        )
+    };
+    // Menu is shown at startup, cursor is captured once a game is joined
+    let mut menu = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        Menu::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+            &settings.server_address,
+            &settings.player_name,
+        )
+    };
+    let mut controls = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        ControlsScreen::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+            &settings.bindings,
+        )
+    };
+    let mut settings_screen = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        SettingsScreen::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+            &settings,
+        )
+    };
+    let mut server_browser = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        ServerBrowser::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+        )
+    };
+    let mut skin_screen = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        SkinScreen::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+            skins::load_manifest(),
+            settings.skin,
+        )
+    };
+    let mut stats_screen = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        StatsScreen::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+        )
+    };
+    let mut warning_dialog = {
+        let (window_width, window_height) = engine.renderer.get_frame_size();
+        WarningDialog::new(
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+            window_height as f32,
+        )
+    };
+    if let Some(warning) = &settings_warning {
+        warning_dialog.show(&engine.user_interface, warning);
     }
+    let mut console = {
+        let (window_width, _) = engine.renderer.get_frame_size();
+        ClientConsole::new(&mut engine.user_interface.build_ctx(), window_width as f32)
+    };
+    console.queue_script(&engine.user_interface, AUTOEXEC_PATH);
+    let mut screenshots = Screenshots::new(&mut engine);
+    let server_list = ServerList::new(&settings.master_server_url);
+    let mut hosted_server: Option<Child> = None;
+    // Repeated by the retry button if joining fails
+    let mut last_join: Option<MenuAction> = None;
 
     // Run the event loop of the main window. which will respond to OS and window events and update
     // engine's state accordingly. Engine lets you to decide which event should be handled,
     // this is minimal working example if how it should be.
     let clock = time::Instant::now();
     let mut elapsed_time = 0.0;
-    let mut focused = true;
-    let mut cursor_in_window = true;
+    // Set when the cursor is captured but the platform couldn't grab it
+    let mut keep_cursor_centered = false;
+    let mut input_was_enabled = false;
 
-    let mut network_manager = NetworkManager::new();
+    // The client falls back to any free port so this only fails if networking isn't available at all
+    let mut network_manager = match NetworkManager::new(&settings) {
+        Ok(network_manager) => network_manager,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut gamepad = Gamepad::new(&settings.gamepad);
+    let mut input_queue = InputQueue::default();
+    let host_port = settings.server_port;
     let mut game = fyrox::core::futures::executor::block_on(Game::new(&mut engine, settings));
 
+    // Watching a demo instead of joining a server
+    let mut demo = game.settings.play_demo.clone().and_then(|path| {
+        let (window_width, _) = engine.renderer.get_frame_size();
+        DemoPlayer::load(
+            &path,
+            &mut engine.user_interface.build_ctx(),
+            window_width as f32,
+        )
+        .map_err(|e| menu.set_status(&engine.user_interface, &e))
+        .ok()
+    });
+    if demo.is_some() {
+        menu.set_visible(&engine.user_interface, false);
+        menu.set_connected(&engine.user_interface, true);
+    }
+    interface.set_hud_visible(&engine.user_interface, game.client_state().shows_hud());
+
     event_loop.run(move |event, _, control_flow| {
         network_manager.handle_events(&mut engine, &mut game);
 
-        #[cfg(not(feature = "server"))]
-        if focused && cursor_in_window {
-            process_input_event(&event, &mut game, &mut network_manager, &mut engine);
+        // Anything up that needs the cursor pauses the game
+        let menu_open = menu.is_visible()
+            || controls.is_visible()
+            || settings_screen.is_visible()
+            || server_browser.is_visible()
+            || skin_screen.is_visible()
+            || stats_screen.is_visible()
+            || warning_dialog.is_visible()
+            || console.is_open()
+            || interface.map_vote.is_visible();
+        if let Some(state) = game.update_client_state(&network_manager, menu_open, demo.is_some()) {
+            interface.set_hud_visible(&engine.user_interface, state.shows_hud());
+        }
+        if let Some(captured) = game.update_cursor_capture() {
+            keep_cursor_centered = !set_cursor_captured(&engine, captured) && captured;
+            // Accelerated input measures from the middle, starting elsewhere would jerk the view
+            if captured {
+                center_cursor(&engine);
+            }
+        }
+
+        // Chat and the results screen sit on top of the game without pausing it
+        let input_enabled =
+            game.takes_input() && !interface.chat.is_open() && !interface.results.is_visible();
+
+        // Keys let go of after input stops reaching the game would stay down, alt-tabbing away
+        // mid-stride shouldn't leave the player running
+        if input_was_enabled && !input_enabled {
+            for &action in Action::HELD.iter() {
+                if let Some(binding) = game.settings.bindings.binding(action) {
+                    input_queue.push(
+                        clock.elapsed().as_secs_f32(),
+                        InputEvent::Button(binding, false),
+                    );
+                }
+            }
+        }
+        input_was_enabled = input_enabled;
+
+        if let (Some(demo), true) = (&mut demo, input_enabled) {
+            demo.process_input(&event, &mut engine, &mut game, &mut interface);
+        }
+
+        if input_enabled {
+            // Applied by the simulation step it arrived during
+            if let Some(input_event) = InputEvent::from_event(&event, game.settings.raw_mouse) {
+                input_queue.push(clock.elapsed().as_secs_f32(), input_event);
+            }
+
+            // Accelerated input goes by where the OS moved the cursor to, it's put back in the
+            // middle after every move so it never hits the edge
+            if !game.settings.raw_mouse {
+                if let Event::WindowEvent {
+                    event: WindowEvent::CursorMoved { position, .. },
+                    ..
+                } = event
+                {
+                    let size = engine.get_window().inner_size();
+                    let x = position.x - (size.width / 2) as f64;
+                    let y = position.y - (size.height / 2) as f64;
+                    // Moving it back reports a move to the middle too
+                    if x != 0.0 || y != 0.0 {
+                        input_queue.push(
+                            clock.elapsed().as_secs_f32(),
+                            InputEvent::MouseMotion(x as f32, y as f32),
+                        );
+                        center_cursor(&engine);
+                    }
+                }
+            }
+
+            if keep_cursor_centered && game.settings.raw_mouse {
+                if let Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { .. },
+                    ..
+                } = event
+                {
+                    center_cursor(&engine);
+                }
+            }
         }
 
         match event {
             Event::MainEventsCleared => {
+                // Typed commands and the autoexec script run the same way
+                while let Some(command) = console.next_command(&engine.user_interface) {
+                    match command {
+                        // Whatever's being played is left first
+                        ConsoleCommand::Connect(address) => {
+                            leave_game(
+                                &mut engine,
+                                &mut game,
+                                &mut network_manager,
+                                &mut interface,
+                                &menu,
+                                &mut hosted_server,
+                            );
+                            if let Some(demo) = demo.take() {
+                                demo.clean_up(&engine.user_interface);
+                            }
+
+                            last_join = Some(MenuAction::Connect(address.clone()));
+                            let name = remember_player_name(&mut game, &menu);
+                            match network_manager.connect(
+                                &address,
+                                &name,
+                                game.settings.player_color,
+                                game.settings.skin,
+                                menu.password(),
+                                false,
+                            ) {
+                                Ok(_) => {
+                                    console.close(&engine.user_interface);
+                                    menu.set_visible(&engine.user_interface, false);
+                                    menu.set_connected(&engine.user_interface, true);
+                                }
+                                Err(e) => console.print(&engine.user_interface, &e.to_string()),
+                            }
+                        }
+                        ConsoleCommand::Disconnect => {
+                            leave_game(
+                                &mut engine,
+                                &mut game,
+                                &mut network_manager,
+                                &mut interface,
+                                &menu,
+                                &mut hosted_server,
+                            );
+                            if let Some(demo) = demo.take() {
+                                demo.clean_up(&engine.user_interface);
+                            }
+                            menu.set_visible(&engine.user_interface, true);
+                        }
+                        // Kept to what the settings screen allows and saved like a change made there
+                        ConsoleCommand::Sensitivity(sensitivity) => {
+                            game.settings.look_sensitivity = sensitivity
+                                .clamp(*SENSITIVITY_RANGE.start(), *SENSITIVITY_RANGE.end());
+                            console.print(
+                                &engine.user_interface,
+                                &format!("sensitivity is {}", game.settings.look_sensitivity),
+                            );
+                            settings_screen.sync(&engine.user_interface, &game.settings);
+                            if let Err(e) = write_settings_to_file(SETTINGS_PATH, &game.settings) {
+                                warn!("failed to save settings: {}", e);
+                            }
+                        }
+                        ConsoleCommand::Fov(fov) => {
+                            game.settings.fov = fov.clamp(*FOV_RANGE.start(), *FOV_RANGE.end());
+                            console.print(
+                                &engine.user_interface,
+                                &format!("fov is {}", game.settings.fov),
+                            );
+                            settings_screen.sync(&engine.user_interface, &game.settings);
+                            if let Err(e) = write_settings_to_file(SETTINGS_PATH, &game.settings) {
+                                warn!("failed to save settings: {}", e);
+                            }
+                        }
+                        ConsoleCommand::NetGraph(visible) => interface
+                            .debug_overlay
+                            .set_visible(&engine.user_interface, visible),
+                    }
+                }
+
                 // This main game loop - it has fixed time step which means that game
                 // code will run at fixed speed even if renderer can't give you desired
-                // 60 fps.
+                // 60 fps. Tick rate is the server's once connected.
+                let timestep = game.rates.timestep();
                 let mut dt = clock.elapsed().as_secs_f32() - elapsed_time;
-                while dt >= TIMESTEP {
-                    dt -= TIMESTEP;
-                    elapsed_time += TIMESTEP;
+                // Shots are fired from the camera so it has to be back where the simulation put it
+                if dt >= timestep {
+                    if let Some(level) = &mut game.level {
+                        level.set_render_alpha(&mut engine, 1.0);
+                    }
+                }
+                while dt >= timestep {
+                    dt -= timestep;
+                    elapsed_time += timestep;
 
                     let fps = engine.renderer.get_statistics().frames_per_second;
 
-                    #[cfg(not(feature = "server"))]
                     engine.user_interface.send_message(TextMessage::text(
                         interface.fps,
                         MessageDirection::ToWidget,
                         format!("FPS: {}", fps),
                     ));
 
+                    if interface.debug_overlay.is_visible() {
+                        let mut details = network_manager.queue_depths();
+                        if let Some(level) = &game.level {
+                            details += &level.debug_summary(&engine, network_manager.player_index);
+                        }
+                        interface.debug_overlay.update(
+                            &engine.user_interface,
+                            network_manager.server_stats(),
+                            &details,
+                        );
+                    }
+                    if interface.perf_hud.is_visible() {
+                        interface.perf_hud.update(&engine.user_interface);
+                    }
+
+                    network_manager.update(timestep, &mut game);
+
+                    process_gamepad_input(
+                        &mut gamepad,
+                        input_enabled,
+                        &mut game,
+                        &mut network_manager,
+                        &mut engine,
+                        timestep,
+                    );
+
+                    if let Some(demo) = &mut demo {
+                        demo.update(timestep, &engine, &mut game);
+                    }
+
+                    while let Some(input_event) = input_queue.pop_until(elapsed_time) {
+                        process_input_event(
+                            &input_event,
+                            &mut game,
+                            &mut network_manager,
+                            &mut engine,
+                        );
+                    }
+
                     // Run our game's logic.
+                    let update_started = Instant::now();
                     game.update(
                         &mut engine,
-                        TIMESTEP,
+                        timestep,
                         &mut network_manager,
                         elapsed_time,
-                        &interface,
+                        &mut interface,
                     );
+                    let update_time = update_started.elapsed();
+                    update_loading_screen(&mut loading_screen, &engine, &game, &network_manager);
+
+                    while let Some(event) = server_list.poll() {
+                        match event {
+                            ServerListEvent::Servers(servers) => {
+                                server_browser.set_servers(&mut engine.user_interface, servers)
+                            }
+                            ServerListEvent::Ping { address, ping } => server_browser.set_ping(
+                                &engine.user_interface,
+                                &address,
+                                ping.as_millis() as u32,
+                            ),
+                            ServerListEvent::Failed(e) => {
+                                server_browser.set_status(&engine.user_interface, &e)
+                            }
+                        }
+                    }
 
                     while let Some(ui_message) = engine.user_interface.poll_message() {
-                        // match ui_message.data() {
-                        //     _ => (),
-                        // }
+                        interface.chat.handle_ui_message(&ui_message);
+                        console.handle_ui_message(&ui_message);
+
+                        if let Some(choice) = interface.map_vote.handle_ui_message(&ui_message) {
+                            if let Some(index) = network_manager.player_index {
+                                network_manager.send_to_server_reliably(&NetworkMessage::MapVote {
+                                    index,
+                                    choice,
+                                });
+                            }
+                        }
+
+                        if controls.handle_ui_message(&engine.user_interface, &ui_message) {
+                            controls.set_visible(
+                                &engine.user_interface,
+                                &game.settings.bindings,
+                                false,
+                            );
+                            menu.set_visible(&engine.user_interface, true);
+                        }
+
+                        match settings_screen.handle_ui_message(
+                            &engine.user_interface,
+                            &ui_message,
+                            &mut game.settings,
+                        ) {
+                            Some(SettingsAction::Fullscreen(enabled)) => {
+                                engine.get_window().set_fullscreen(fullscreen_mode(enabled));
+                            }
+                            Some(SettingsAction::Back) => {
+                                close_settings(&mut settings_screen, &mut menu, &engine, &game);
+                            }
+                            None => (),
+                        }
+
+                        match server_browser.handle_ui_message(&ui_message) {
+                            Some(BrowserAction::Join(address)) => {
+                                last_join = Some(MenuAction::Connect(address.clone()));
+                                let name = remember_player_name(&mut game, &menu);
+                                match network_manager.connect(
+                                    &address,
+                                    &name,
+                                    game.settings.player_color,
+                                    game.settings.skin,
+                                    menu.password(),
+                                    false,
+                                ) {
+                                    Ok(_) => {
+                                        server_browser.set_visible(&engine.user_interface, false);
+                                        menu.set_connected(&engine.user_interface, true);
+                                    }
+                                    Err(e) => server_browser
+                                        .set_status(&engine.user_interface, &e.to_string()),
+                                }
+                            }
+                            Some(BrowserAction::Refresh) => {
+                                server_browser.set_status(&engine.user_interface, "Refreshing...");
+                                server_list.refresh();
+                            }
+                            Some(BrowserAction::Back) => {
+                                server_browser.set_visible(&engine.user_interface, false);
+                                menu.set_visible(&engine.user_interface, true);
+                            }
+                            None => (),
+                        }
+
+                        if let Some(SkinAction::Back) = skin_screen.handle_ui_message(
+                            &engine.user_interface,
+                            &ui_message,
+                            &mut game.settings,
+                        ) {
+                            close_skins(&mut skin_screen, &mut menu, &engine, &game);
+                        }
+
+                        if warning_dialog.handle_ui_message(&ui_message) {
+                            warning_dialog.set_visible(&engine.user_interface, false);
+                        }
+
+                        if stats_screen.handle_ui_message(&ui_message) {
+                            stats_screen.set_visible(&engine.user_interface, false);
+                            menu.set_visible(&engine.user_interface, true);
+                        }
+
+                        let action = match loading_screen.handle_ui_message(&ui_message) {
+                            // Start over from the menu, which shows the error if it fails right away
+                            Some(LoadingAction::Retry) => {
+                                loading_screen.hide(&engine.user_interface);
+                                menu.set_visible(&engine.user_interface, true);
+                                last_join.clone()
+                            }
+                            Some(LoadingAction::Back) => {
+                                loading_screen.hide(&engine.user_interface);
+                                menu.set_visible(&engine.user_interface, true);
+                                None
+                            }
+                            None => menu.handle_ui_message(&ui_message),
+                        };
+
+                        let observe = matches!(action, Some(MenuAction::Observe(_)));
+                        match action {
+                            Some(MenuAction::Connect(address))
+                            | Some(MenuAction::Observe(address)) => {
+                                last_join = Some(if observe {
+                                    MenuAction::Observe(address.clone())
+                                } else {
+                                    MenuAction::Connect(address.clone())
+                                });
+                                let name = remember_player_name(&mut game, &menu);
+                                match network_manager.connect(
+                                    &address,
+                                    &name,
+                                    game.settings.player_color,
+                                    game.settings.skin,
+                                    menu.password(),
+                                    observe,
+                                ) {
+                                    Ok(_) => {
+                                        menu.set_visible(&engine.user_interface, false);
+                                        menu.set_connected(&engine.user_interface, true);
+                                    }
+                                    Err(e) => {
+                                        menu.set_status(&engine.user_interface, &e.to_string())
+                                    }
+                                }
+                            }
+                            Some(MenuAction::Host) => match host_server(host_port) {
+                                Ok(server) => {
+                                    hosted_server = Some(server);
+                                    last_join = Some(MenuAction::Host);
+                                    // Server will drop the first packets while it starts, connection is retried by laminar
+                                    let address = format!("127.0.0.1:{}", host_port);
+                                    let name = remember_player_name(&mut game, &menu);
+                                    match network_manager.connect(
+                                        &address,
+                                        &name,
+                                        game.settings.player_color,
+                                        game.settings.skin,
+                                        menu.password(),
+                                        false,
+                                    ) {
+                                        Ok(_) => {
+                                            menu.set_visible(&engine.user_interface, false);
+                                            menu.set_connected(&engine.user_interface, true);
+                                        }
+                                        Err(e) => {
+                                            menu.set_status(&engine.user_interface, &e.to_string())
+                                        }
+                                    }
+                                }
+                                Err(e) => {
+                                    error!("failed to start server: {}", e);
+                                    menu.set_status(
+                                        &engine.user_interface,
+                                        &format!("Couldn't start {}: {}", SERVER_EXECUTABLE, e),
+                                    );
+                                }
+                            },
+                            Some(MenuAction::Practice) => {
+                                last_join = Some(MenuAction::Practice);
+                                let name = remember_player_name(&mut game, &menu);
+                                match game.start_practice(&name) {
+                                    Ok(_) => {
+                                        menu.set_visible(&engine.user_interface, false);
+                                        menu.set_connected(&engine.user_interface, true);
+                                    }
+                                    Err(e) => menu.set_status(&engine.user_interface, &e),
+                                }
+                            }
+                            Some(MenuAction::Browse) => {
+                                menu.set_visible(&engine.user_interface, false);
+                                server_browser.set_visible(&engine.user_interface, true);
+                                server_browser.set_status(&engine.user_interface, "Refreshing...");
+                                server_list.refresh();
+                            }
+                            Some(MenuAction::Skins) => {
+                                menu.set_visible(&engine.user_interface, false);
+                                skin_screen.set_visible(&engine.user_interface, true);
+                            }
+                            Some(MenuAction::Stats) => {
+                                menu.set_visible(&engine.user_interface, false);
+                                let profile = menu.player_name().trim();
+                                stats_screen.show(
+                                    &engine.user_interface,
+                                    profile,
+                                    &game.player_stats(profile),
+                                );
+                            }
+                            Some(MenuAction::Controls) => {
+                                menu.set_visible(&engine.user_interface, false);
+                                controls.set_visible(
+                                    &engine.user_interface,
+                                    &game.settings.bindings,
+                                    true,
+                                );
+                            }
+                            Some(MenuAction::Settings) => {
+                                menu.set_visible(&engine.user_interface, false);
+                                settings_screen.set_visible(&engine.user_interface, true);
+                            }
+                            Some(MenuAction::Disconnect) => {
+                                leave_game(
+                                    &mut engine,
+                                    &mut game,
+                                    &mut network_manager,
+                                    &mut interface,
+                                    &menu,
+                                    &mut hosted_server,
+                                );
+                                if let Some(demo) = demo.take() {
+                                    demo.clean_up(&engine.user_interface);
+                                }
+                            }
+                            Some(MenuAction::Quit) => {
+                                if let Some(server) = &mut hosted_server {
+                                    let _ = server.kill();
+                                }
+                                *control_flow = ControlFlow::Exit;
+                            }
+                            None => (),
+                        }
                     }
 
                     // Update engine each frame.
-                    engine.update(TIMESTEP);
+                    let physics_started = Instant::now();
+                    engine.update(timestep);
+                    interface
+                        .perf_hud
+                        .record_tick(update_time, physics_started.elapsed());
                 }
 
+                // What's left over is how far the frame is towards the next step, players are
+                // drawn that far along so movement stays smooth when frames and ticks don't line up
+                if let Some(level) = &mut game.level {
+                    level.set_render_alpha(&mut engine, dt / timestep);
+                }
+
+                // Everything the ticks queued goes out together
+                network_manager.flush();
+
                 // Rendering must be explicitly requested and handled after RedrawRequested event is received.
                 engine.get_window().request_redraw();
             }
-            #[cfg(not(feature = "server"))]
             Event::RedrawRequested(_) => {
                 // Render at max speed - it is not tied to the game code.
+                let render_started = Instant::now();
                 engine.render().unwrap();
+                interface.perf_hud.record_frame(render_started.elapsed());
             }
-            #[cfg(not(feature = "server"))]
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                WindowEvent::KeyboardInput { input, .. } => {
-                    if focused && cursor_in_window {
-                        // Exit game by hitting Escape.
-                        if let Some(VirtualKeyCode::Escape) = input.virtual_keycode {
-                            *control_flow = ControlFlow::Exit
+            Event::WindowEvent { event, .. } => {
+                // The next key or mouse button is bound instead of being passed on. Bound on release
+                // so the UI doesn't see half of the click.
+                if controls.is_waiting() {
+                    if let Some((binding, state)) = input::get_binding(&event) {
+                        if state == ElementState::Released {
+                            if binding == Binding::Key(VirtualKeyCode::Escape) {
+                                controls.cancel(&engine.user_interface, &game.settings.bindings);
+                            } else if controls.bind(
+                                &engine.user_interface,
+                                &mut game.settings.bindings,
+                                binding,
+                            ) {
+                                if let Err(e) =
+                                    write_settings_to_file(SETTINGS_PATH, &game.settings)
+                                {
+                                    warn!("failed to save settings: {}", e);
+                                }
+                            }
                         }
+                        return;
                     }
                 }
-                WindowEvent::Resized(size) => {
-                    // It is very important to handle Resized event from window, because
-                    // renderer knows nothing about window size - it must be notified
-                    // directly when window size has changed.
-                    engine.set_frame_size(size.into());
-                    // interface = create_ui(&mut engine);
-                }
-                WindowEvent::Focused(focus) => {
-                    focused = focus;
+
+                // Let the UI handle input for menus
+                if let Some(os_event) = translate_event(&event) {
+                    engine.user_interface.process_os_event(&os_event);
                 }
-                WindowEvent::CursorEntered { device_id } => {
-                    cursor_in_window = true;
+
+                let bound = input::get_binding(&event).and_then(|(binding, state)| {
+                    game.settings
+                        .bindings
+                        .action(binding)
+                        .map(|action| (action, state))
+                });
+                let bound_action = bound.map(|(action, _)| action);
+
+                // Show scoreboard while the scoreboard key is held.
+                if let Some((Action::Scoreboard, state)) = bound {
+                    interface.scoreboard.set_visible(
+                        &engine.user_interface,
+                        state == ElementState::Pressed
+                            && game.client_state().takes_input()
+                            && !interface.chat.is_open(),
+                    );
                 }
-                WindowEvent::CursorLeft { device_id } => {
-                    cursor_in_window = false;
+
+                match event {
+                    WindowEvent::CloseRequested => {
+                        if let Some(server) = &mut hosted_server {
+                            let _ = server.kill();
+                        }
+                        *control_flow = ControlFlow::Exit
+                    }
+                    WindowEvent::KeyboardInput { input, .. } => {
+                        if game.is_focused() && input.state == ElementState::Pressed {
+                            match input.virtual_keycode {
+                                // The console takes every key while it's open
+                                Some(VirtualKeyCode::Return) if console.is_open() => {
+                                    console.submit(&engine.user_interface);
+                                }
+                                Some(VirtualKeyCode::Escape) if console.is_open() => {
+                                    console.close(&engine.user_interface);
+                                }
+                                _ if bound_action == Some(Action::Console)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    console.toggle(&engine.user_interface);
+                                }
+                                _ if console.is_open() => (),
+                                // Escape closes chat if it's open
+                                Some(VirtualKeyCode::Escape) if interface.chat.is_open() => {
+                                    interface.chat.close(&engine.user_interface);
+                                }
+                                // dismisses a warning, which is on top of any screen
+                                Some(VirtualKeyCode::Escape) if warning_dialog.is_visible() => {
+                                    warning_dialog.set_visible(&engine.user_interface, false);
+                                }
+                                // and goes back to the menu from the controls screen
+                                Some(VirtualKeyCode::Escape) if controls.is_visible() => {
+                                    controls.set_visible(
+                                        &engine.user_interface,
+                                        &game.settings.bindings,
+                                        false,
+                                    );
+                                    menu.set_visible(&engine.user_interface, true);
+                                }
+                                Some(VirtualKeyCode::Escape) if server_browser.is_visible() => {
+                                    server_browser.set_visible(&engine.user_interface, false);
+                                    menu.set_visible(&engine.user_interface, true);
+                                }
+                                Some(VirtualKeyCode::Escape) if settings_screen.is_visible() => {
+                                    close_settings(&mut settings_screen, &mut menu, &engine, &game);
+                                }
+                                Some(VirtualKeyCode::Escape) if skin_screen.is_visible() => {
+                                    close_skins(&mut skin_screen, &mut menu, &engine, &game);
+                                }
+                                Some(VirtualKeyCode::Escape) if stats_screen.is_visible() => {
+                                    stats_screen.set_visible(&engine.user_interface, false);
+                                    menu.set_visible(&engine.user_interface, true);
+                                }
+                                // Toggle menu by hitting Escape, it can only be closed while connected.
+                                Some(VirtualKeyCode::Escape) => {
+                                    let in_game = network_manager.is_connected()
+                                        || demo.is_some()
+                                        || game.is_practice();
+                                    let visible = !menu.is_visible() || !in_game;
+                                    menu.set_visible(&engine.user_interface, visible);
+                                }
+                                // Enter sends the chat message
+                                Some(VirtualKeyCode::Return) if interface.chat.is_open() => {
+                                    if let Some(text) =
+                                        interface.chat.submit(&engine.user_interface)
+                                    {
+                                        if let Some(index) = network_manager.player_index {
+                                            network_manager.send_to_server_reliably(
+                                                &NetworkMessage::Chat { index, text },
+                                            );
+                                        }
+                                    }
+                                }
+                                // Chat key opens chat input
+                                _ if bound_action == Some(Action::Chat)
+                                    && game.client_state().takes_input()
+                                    && !interface.chat.is_open() =>
+                                {
+                                    interface.chat.open(&engine.user_interface);
+                                }
+                                _ if bound_action == Some(Action::NetStats)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    interface.debug_overlay.toggle(&engine.user_interface);
+                                }
+                                _ if bound_action == Some(Action::PerfHud)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    interface.perf_hud.toggle(&engine.user_interface);
+                                }
+                                _ if bound_action == Some(Action::Screenshot)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    screenshots.request();
+                                }
+                                _ if bound_action == Some(Action::FreeCursor)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    game.toggle_cursor();
+                                }
+                                _ if bound_action == Some(Action::ThirdPerson)
+                                    && !interface.chat.is_open() =>
+                                {
+                                    if let Some(level) = &mut game.level {
+                                        level.toggle_third_person();
+                                    }
+                                }
+                                _ => (),
+                            }
+                        }
+                    }
+                    WindowEvent::Resized(size) => {
+                        // It is very important to handle Resized event from window, because
+                        // renderer knows nothing about window size - it must be notified
+                        // directly when window size has changed.
+                        engine.set_frame_size(size.into());
+                        interface.resize(
+                            &engine.user_interface,
+                            size.width as f32,
+                            size.height as f32,
+                        );
+                        loading_screen.resize(
+                            &engine.user_interface,
+                            size.width as f32,
+                            size.height as f32,
+                        );
+                        console.resize(&engine.user_interface, size.width as f32);
+                        if let Some(demo) = &demo {
+                            demo.resize(&engine.user_interface, size.width as f32);
+                        }
+                    }
+                    WindowEvent::Focused(focus) => {
+                        game.set_window_focused(focus);
+                    }
+                    WindowEvent::CursorEntered { device_id } => {
+                        game.set_cursor_in_window(true);
+                    }
+                    WindowEvent::CursorLeft { device_id } => {
+                        game.set_cursor_in_window(false);
+                    }
+                    _ => (),
                 }
-                _ => (),
-            },
+            }
+            // However the window was closed, stats from the game being left shouldn't be lost
+            Event::LoopDestroyed => game.save_stats(),
             _ => *control_flow = ControlFlow::Poll,
         }
 
-        #[cfg(not(feature = "server"))]
+        // Lost the server, go back to the menu. Failing to get into a game in the first place
+        // leaves the loading screen up with a retry button instead.
         if !game.active {
-            *control_flow = ControlFlow::Exit
+            leave_game(
+                &mut engine,
+                &mut game,
+                &mut network_manager,
+                &mut interface,
+                &menu,
+                &mut hosted_server,
+            );
+            let reason = game.disconnect_reason.take();
+            if loading_screen.is_visible() {
+                loading_screen.show_error(
+                    &engine.user_interface,
+                    reason.as_deref().unwrap_or("Failed to join the game"),
+                );
+            } else {
+                menu.set_visible(&engine.user_interface, true);
+                if let Some(reason) = reason {
+                    menu.set_status(&engine.user_interface, &reason);
+                }
+            }
         }
     });
 }
 
 #[cfg(not(feature = "server"))]
 fn process_input_event(
-    event: &Event<()>,
+    event: &InputEvent,
     game: &mut Game,
     network_manager: &mut NetworkManager,
     engine: &mut Engine,
 ) {
+    // Sensitivity is applied here for both the player and the spectator camera
+    let aiming = match (network_manager.player_index, &mut game.level) {
+        (Some(player_index), Some(level)) => level
+            .get_player_by_index(player_index)
+            .map_or(false, |player| player.is_aiming()),
+        _ => false,
+    };
+    let event = &match *event {
+        InputEvent::MouseMotion(x, y) => {
+            let (yaw, pitch) = game.settings.mouse_look(x, y, aiming);
+            InputEvent::MouseMotion(yaw, pitch)
+        }
+        event => event,
+    };
+
+    // Input only moves the spectator camera while the local player isn't alive
+    if let Some(level) = &mut game.level {
+        if level.is_spectating() {
+            level.process_spectator_input(event, engine, &game.settings.bindings);
+            return;
+        }
+    }
+
     if let (Some(player_index), Some(level)) = (network_manager.player_index, &mut game.level) {
-        match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::KeyboardInput { input, .. } => {
-                    if let Some(key_code) = input.virtual_keycode {
-                        match key_code {
-                            VirtualKeyCode::W => {
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    let action = PlayerEvent::MoveForward {
-                                        index: player_index,
-                                        active: input.state == ElementState::Pressed,
-                                        yaw: player.get_yaw(),
-                                        pitch: player.get_pitch(),
-                                    };
-                                    let message = NetworkMessage::PlayerEvent {
-                                        index: player_index,
-                                        event: action,
-                                    };
-
-                                    // TODO: Should active = false be reliable since it's only sent once?
-                                    network_manager.send_to_server_unreliably(&message, 0);
-                                    level.queue_event(action);
-                                }
-                            }
-                            VirtualKeyCode::S => {
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    let action = PlayerEvent::MoveBackward {
-                                        index: player_index,
-                                        active: input.state == ElementState::Pressed,
-                                        yaw: player.get_yaw(),
-                                        pitch: player.get_pitch(),
-                                    };
-
-                                    let message = NetworkMessage::PlayerEvent {
-                                        index: player_index,
-                                        event: action,
-                                    };
-
-                                    network_manager.send_to_server_unreliably(&message, 0);
-                                    level.queue_event(action);
-                                }
-                            }
-                            VirtualKeyCode::A => {
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    let action = PlayerEvent::MoveLeft {
-                                        index: player_index,
-                                        active: input.state == ElementState::Pressed,
-                                        yaw: player.get_yaw(),
-                                        pitch: player.get_pitch(),
-                                    };
-                                    let message = NetworkMessage::PlayerEvent {
-                                        index: player_index,
-                                        event: action,
-                                    };
-
-                                    network_manager.send_to_server_unreliably(&message, 0);
-                                    level.queue_event(action);
-                                }
-                            }
-                            VirtualKeyCode::D => {
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    let action = PlayerEvent::MoveRight {
-                                        index: player_index,
-                                        active: input.state == ElementState::Pressed,
-                                        yaw: player.get_yaw(),
-                                        pitch: player.get_pitch(),
-                                    };
-                                    let message = NetworkMessage::PlayerEvent {
-                                        index: player_index,
-                                        event: action,
-                                    };
-
-                                    network_manager.send_to_server_unreliably(&message, 0);
-                                    level.queue_event(action);
-                                }
-                            }
-                            VirtualKeyCode::Space => {
-                                let scene = &mut engine.scenes[level.scene];
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    if player.has_ground_contact(scene) {
-                                        let action = PlayerEvent::Jump {
-                                            index: player_index,
-                                        };
-                                        let message = NetworkMessage::PlayerEvent {
-                                            index: player_index,
-                                            event: action,
-                                        };
-
-                                        network_manager.send_to_server_unreliably(&message, 0);
-                                        // level.queue_event(action);
-                                    }
-                                }
-                            }
-                            VirtualKeyCode::LShift => {
-                                let scene = &mut engine.scenes[level.scene];
-                                if let Some(player) = level.get_player_by_index(player_index) {
-                                    let action = PlayerEvent::Fly {
-                                        index: player_index,
-                                        active: input.state == ElementState::Pressed,
-                                        fuel: player.flight_fuel,
-                                    };
-                                    let message = NetworkMessage::PlayerEvent {
-                                        index: player_index,
-                                        event: action,
-                                    };
-
-                                    network_manager.send_to_server_unreliably(&message, 0);
-                                    level.queue_event(action);
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
+        match *event {
+            InputEvent::Button(binding, pressed) => {
+                if let Some(action) = game.settings.bindings.action(binding) {
+                    process_action(
+                        action,
+                        pressed,
+                        player_index,
+                        level,
+                        network_manager,
+                        engine,
+                    );
                 }
-                &WindowEvent::MouseInput { button, state, .. } => {
-                    if button == MouseButton::Left {
-                        if let Some(player) = level.get_player_by_index(player_index) {
-                            let message = NetworkMessage::PlayerEvent {
-                                index: player_index,
-                                event: PlayerEvent::ShootWeapon {
-                                    index: player_index,
-                                    active: state == ElementState::Pressed,
-                                    yaw: player.get_yaw(),
-                                    pitch: player.get_pitch(),
-                                },
-                            };
-
-                            network_manager.send_to_server_reliably(&message);
-                        }
-                    }
+            }
+            InputEvent::Scroll(scroll) => {
+                if scroll != 0.0 {
+                    cycle_weapon(scroll > 0.0, player_index, level, network_manager);
                 }
-                _ => {}
-            },
-            Event::DeviceEvent { event, .. } => {
-                if let DeviceEvent::MouseMotion { delta } = event {
-                    let mouse_sens = game.settings.look_sensitivity;
+            }
+            InputEvent::MouseMotion(yaw, pitch) => {
+                look_around(yaw, pitch, player_index, level, network_manager);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn process_gamepad_input(
+    gamepad: &mut Gamepad,
+    input_enabled: bool,
+    game: &mut Game,
+    network_manager: &mut NetworkManager,
+    engine: &mut Engine,
+    dt: f32,
+) {
+    let actions = gamepad.poll();
 
-                    let action = PlayerEvent::LookAround {
+    if let (Some(player_index), Some(level)) = (network_manager.player_index, &mut game.level) {
+        if !input_enabled || level.is_spectating() {
+            if let Some(movement) = gamepad.release() {
+                move_analog(movement, player_index, level, network_manager);
+            }
+            return;
+        }
+
+        for action in actions {
+            match action {
+                GamepadAction::Action(action, pressed) => process_action(
+                    action,
+                    pressed,
+                    player_index,
+                    level,
+                    network_manager,
+                    engine,
+                ),
+                GamepadAction::NextWeapon => {
+                    cycle_weapon(true, player_index, level, network_manager)
+                }
+            }
+        }
+
+        if let Some(movement) = gamepad.movement_changed(&game.settings.gamepad) {
+            move_analog(movement, player_index, level, network_manager);
+        }
+
+        let look = gamepad.look(&game.settings.gamepad, dt);
+        if look != Vector2::default() {
+            look_around(look.x, look.y, player_index, level, network_manager);
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn move_analog(
+    movement: Vector2<f32>,
+    player_index: u32,
+    level: &mut Level,
+    network_manager: &mut NetworkManager,
+) {
+    if let Some(player) = level.get_player_by_index(player_index) {
+        let action = PlayerEvent::MoveAnalog {
+            index: player_index,
+            forward: movement.y,
+            right: movement.x,
+            yaw: player.get_yaw(),
+            pitch: player.get_pitch(),
+        };
+        let message = NetworkMessage::PlayerEvent {
+            index: player_index,
+            event: action,
+        };
+
+        // Stopping is only sent once so it must arrive
+        if movement == Vector2::default() {
+            network_manager.send_to_server_reliably(&message);
+        } else {
+            network_manager.send_to_server_unreliably(&message, 0);
+        }
+        level.queue_event(action);
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn look_around(
+    yaw_delta: f32,
+    pitch_delta: f32,
+    player_index: u32,
+    level: &mut Level,
+    network_manager: &mut NetworkManager,
+) {
+    let action = PlayerEvent::LookAround {
+        index: player_index,
+        yaw_delta,
+        pitch_delta,
+    };
+
+    let message = NetworkMessage::PlayerEvent {
+        index: player_index,
+        event: action,
+    };
+
+    network_manager.send_to_server_unreliably(&message, 0);
+    level.queue_event(action);
+}
+
+// Chat, Scoreboard and the other interface keys are handled with the menu keys since they work
+// without a player
+#[cfg(not(feature = "server"))]
+fn process_action(
+    action: Action,
+    pressed: bool,
+    player_index: u32,
+    level: &mut Level,
+    network_manager: &mut NetworkManager,
+    engine: &mut Engine,
+) {
+    match action {
+        Action::MoveForward => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::MoveForward {
+                    index: player_index,
+                    active: pressed,
+                    yaw: player.get_yaw(),
+                    pitch: player.get_pitch(),
+                };
+                let message = NetworkMessage::PlayerEvent {
+                    index: player_index,
+                    event: action,
+                };
+
+                // TODO: Should active = false be reliable since it's only sent once?
+                network_manager.send_to_server_unreliably(&message, 0);
+                level.queue_event(action);
+            }
+        }
+        Action::MoveBackward => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::MoveBackward {
+                    index: player_index,
+                    active: pressed,
+                    yaw: player.get_yaw(),
+                    pitch: player.get_pitch(),
+                };
+
+                let message = NetworkMessage::PlayerEvent {
+                    index: player_index,
+                    event: action,
+                };
+
+                network_manager.send_to_server_unreliably(&message, 0);
+                level.queue_event(action);
+            }
+        }
+        Action::MoveLeft => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::MoveLeft {
+                    index: player_index,
+                    active: pressed,
+                    yaw: player.get_yaw(),
+                    pitch: player.get_pitch(),
+                };
+                let message = NetworkMessage::PlayerEvent {
+                    index: player_index,
+                    event: action,
+                };
+
+                network_manager.send_to_server_unreliably(&message, 0);
+                level.queue_event(action);
+            }
+        }
+        Action::MoveRight => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::MoveRight {
+                    index: player_index,
+                    active: pressed,
+                    yaw: player.get_yaw(),
+                    pitch: player.get_pitch(),
+                };
+                let message = NetworkMessage::PlayerEvent {
+                    index: player_index,
+                    event: action,
+                };
+
+                network_manager.send_to_server_unreliably(&message, 0);
+                level.queue_event(action);
+            }
+        }
+        Action::Jump => {
+            let scene = &mut engine.scenes[level.scene];
+            if let Some(player) = level.get_player_by_index(player_index) {
+                if player.has_ground_contact(scene) {
+                    let action = PlayerEvent::Jump {
                         index: player_index,
-                        yaw_delta: mouse_sens * delta.0 as f32,
-                        pitch_delta: mouse_sens * delta.1 as f32,
                     };
-
                     let message = NetworkMessage::PlayerEvent {
                         index: player_index,
                         event: action,
                     };
 
                     network_manager.send_to_server_unreliably(&message, 0);
+                    // level.queue_event(action);
+                }
+            }
+        }
+        Action::Weapon1 | Action::Weapon2 => {
+            if pressed {
+                let slot = if action == Action::Weapon1 { 0 } else { 1 };
+                switch_weapon(slot, player_index, level, network_manager);
+            }
+        }
+        Action::Reload => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                if pressed && player.can_reload() {
+                    let action = PlayerEvent::Reload {
+                        index: player_index,
+                    };
+                    let message = NetworkMessage::PlayerEvent {
+                        index: player_index,
+                        event: action,
+                    };
+
+                    network_manager.send_to_server_reliably(&message);
                     level.queue_event(action);
                 }
             }
-            _ => (),
         }
+        Action::ThrowGrenade => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                if pressed && player.can_throw_grenade() {
+                    let action = PlayerEvent::ThrowGrenade {
+                        index: player_index,
+                    };
+                    let message = NetworkMessage::PlayerEvent {
+                        index: player_index,
+                        event: action,
+                    };
+
+                    network_manager.send_to_server_reliably(&message);
+                    level.queue_event(action);
+                }
+            }
+        }
+        Action::Crouch => {
+            let action = PlayerEvent::Crouch {
+                index: player_index,
+                active: pressed,
+            };
+            let message = NetworkMessage::PlayerEvent {
+                index: player_index,
+                event: action,
+            };
+
+            network_manager.send_to_server_unreliably(&message, 0);
+            level.queue_event(action);
+        }
+        Action::Sprint => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::Sprint {
+                    index: player_index,
+                    active: pressed,
+                    stamina: player.stamina,
+                };
+                let message = NetworkMessage::PlayerEvent {
+                    index: player_index,
+                    event: action,
+                };
+
+                network_manager.send_to_server_unreliably(&message, 0);
+                level.queue_event(action);
+            }
+        }
+        Action::Fly => {
+            let action = PlayerEvent::Fly {
+                index: player_index,
+                active: pressed,
+            };
+            let message = NetworkMessage::PlayerEvent {
+                index: player_index,
+                event: action,
+            };
+
+            // A lost release would leave the server flying until fuel runs out
+            network_manager.send_to_server_reliably(&message);
+            level.queue_event(action);
+        }
+        Action::Aim => {
+            let action = PlayerEvent::Aim {
+                index: player_index,
+                active: pressed,
+            };
+            let message = NetworkMessage::PlayerEvent {
+                index: player_index,
+                event: action,
+            };
+
+            // Others would see the player stuck aiming if the release got lost
+            network_manager.send_to_server_reliably(&message);
+            level.queue_event(action);
+        }
+        Action::Shoot => {
+            if let Some(player) = level.get_player_by_index(player_index) {
+                let action = PlayerEvent::ShootWeapon {
+                    index: player_index,
+                    active: pressed,
+                    yaw: player.get_yaw(),
+                    pitch: player.get_pitch(),
+                };
+
+                // Shots wait for the server to confirm them, practice has no server to ask
+                if network_manager.is_connected() {
+                    network_manager.send_to_server_reliably(&NetworkMessage::PlayerEvent {
+                        index: player_index,
+                        event: action,
+                    });
+                } else {
+                    level.queue_event(action);
+                }
+            }
+        }
+        Action::Chat
+        | Action::Scoreboard
+        | Action::NetStats
+        | Action::PerfHud
+        | Action::Screenshot
+        | Action::FreeCursor
+        | Action::ThirdPerson
+        | Action::Console => (),
     }
 }
 
+#[cfg(not(feature = "server"))]
+fn cycle_weapon(
+    forward: bool,
+    player_index: u32,
+    level: &mut Level,
+    network_manager: &mut NetworkManager,
+) {
+    if let Some(player) = level.get_player_by_index(player_index) {
+        let current = player.current_weapon_slot();
+        let slot = if forward {
+            (current + 1) % WEAPONS.len()
+        } else {
+            (current + WEAPONS.len() - 1) % WEAPONS.len()
+        };
+
+        if slot != current {
+            switch_weapon(slot as u32, player_index, level, network_manager);
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn switch_weapon(
+    slot: u32,
+    player_index: u32,
+    level: &mut Level,
+    network_manager: &mut NetworkManager,
+) {
+    let action = PlayerEvent::SwitchWeapon {
+        index: player_index,
+        slot,
+    };
+    let message = NetworkMessage::PlayerEvent {
+        index: player_index,
+        event: action,
+    };
+
+    network_manager.send_to_server_reliably(&message);
+    level.queue_event(action);
+}
+
 pub struct Interface {
     fps: Handle<UiNode>,
-    fuel: Handle<UiNode>,
+    status: StatusBars,
     textbox: Handle<UiNode>,
-    crosshair: Handle<UiNode>,
+    crosshair: Crosshair,
+    scoreboard: Scoreboard,
+    chat: Chat,
+    results: ResultsScreen,
+    map_vote: MapVoteScreen,
+    match_status: MatchStatus,
+    hit_marker: HitMarker,
+    damage_indicator: DamageIndicator,
+    nametags: Nametags,
+    debug_overlay: DebugOverlay,
+    perf_hud: PerfHud,
+}
+
+impl Interface {
+    // Everything is placed with absolute positions, so move it all when the window size changes
+    fn resize(&mut self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        move_widget(ui, self.textbox, kill_feed_position(window_height));
+        self.status.resize(ui, window_width, window_height);
+        self.crosshair.resize(ui, window_width, window_height);
+        self.scoreboard.resize(ui, window_width, window_height);
+        self.chat.resize(ui, window_height);
+        self.results.resize(ui, window_width);
+        self.perf_hud.resize(ui, window_width);
+        self.map_vote.resize(ui, window_width);
+        self.match_status.resize(ui, window_width);
+        self.hit_marker.resize(ui, window_width, window_height);
+        self.damage_indicator.resize(window_width, window_height);
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn set_hud_visible(&self, ui: &UserInterface, visible: bool) {
+        self.status.set_visible(ui, visible);
+        self.crosshair.set_visible(ui, visible);
+    }
+}
+
+fn kill_feed_position(window_height: f32) -> Vector2<f32> {
+    Anchor::BottomLeft.position(
+        0.0,
+        window_height,
+        Vector2::new(500.0, 250.0),
+        Vector2::new(0.0, 0.0),
+    )
 }
 
-fn create_ui(engine: &mut GameEngine) -> Interface {
-    let window_width = engine.renderer.get_frame_size().0 as f32;
-    let window_height = engine.renderer.get_frame_size().1 as f32;
+fn create_ui(
+    engine: &mut GameEngine,
+    frame_size: (u32, u32),
+    crosshair_settings: &CrosshairSettings,
+) -> Interface {
+    let window_width = frame_size.0 as f32;
+    let window_height = frame_size.1 as f32;
 
     let ctx = &mut engine.user_interface.build_ctx();
 
     // First of all create debug text that will show title of example and current FPS.
     let fps = TextBuilder::new(WidgetBuilder::new()).build(ctx);
-    let fuel = TextBuilder::new(
-        WidgetBuilder::new()
-            .with_width(90.0)
-            .with_desired_position(Vector2::new(window_width - 100.0, window_height - 25.0)),
-    )
-    .with_horizontal_text_alignment(HorizontalAlignment::Right)
-    .build(ctx);
+    let status = StatusBars::new(ctx, window_width, window_height);
 
     let textbox = TextBoxBuilder::new(
         WidgetBuilder::new()
             .with_opacity(Some(0.5))
             .with_height(250.0)
             .with_width(500.0)
-            .with_desired_position(Vector2::new(0.0, window_height - 250.0)),
+            .with_desired_position(kill_feed_position(window_height)),
     )
     .with_multiline(true)
     .with_editable(false)
     .build(ctx);
 
-    let crosshair = ImageBuilder::new(
-        WidgetBuilder::new()
-            .with_opacity(Some(0.35))
-            .with_desired_position(Vector2::new(
-                window_width / 2.0 - 32.0,
-                window_height / 2.0 - 32.0,
-            ))
-            .with_width(64.0)
-            .with_height(64.0),
-    )
-    .with_texture(into_gui_texture(
-        engine
-            .resource_manager
-            .request_texture("data/textures/crosshair.png"),
-    ))
-    .build(ctx);
+    let crosshair = Crosshair::new(ctx, window_width, window_height, crosshair_settings);
+
+    let scoreboard = Scoreboard::new(ctx, window_width, window_height);
+    let chat = Chat::new(ctx, window_height);
+    let results = ResultsScreen::new(ctx, window_width);
+    let map_vote = MapVoteScreen::new(ctx, window_width);
+    let match_status = MatchStatus::new(ctx, window_width);
+    let hit_marker = HitMarker::new(ctx, window_width, window_height);
+    let damage_indicator = DamageIndicator::new(ctx, window_width, window_height);
+    let debug_overlay = DebugOverlay::new(ctx);
+    let perf_hud = PerfHud::new(ctx, window_width);
 
     Interface {
         fps,
-        fuel,
+        status,
         textbox,
         crosshair,
+        scoreboard,
+        chat,
+        results,
+        map_vote,
+        match_status,
+        hit_marker,
+        damage_indicator,
+        nametags: Nametags::default(),
+        debug_overlay,
+        perf_hud,
     }
 }
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{game::PlayerScore, rotation::ServerConfig};
+
+// Warmup countdown doesn't start until this many players are connected
+pub const MIN_PLAYERS: usize = 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum MatchPhase {
+    Warmup,
+    Active,
+    RoundEnd { winner: Option<u32> },
+}
+
+impl Default for MatchPhase {
+    fn default() -> Self {
+        MatchPhase::Warmup
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum GameMode {
+    Standard,
+    Elimination, // The outer ring of the floor is taken away every so often during a round
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Standard
+    }
+}
+
+// What a player did in the round that just ended, compiled by the server for the results screen
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PlayerMatchStats {
+    pub index: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub blocks_destroyed: u32,
+    pub shots_fired: u32,
+    pub shots_hit: u32, // Shots that damaged someone else, a rocket hitting two players is one
+    pub damage_dealt: f32, // To other players, only what their health and armor could take
+    pub damage_taken: f32, // From anyone, including themselves
+}
+
+impl PlayerMatchStats {
+    // Percent of shots that hit, None before the first shot
+    pub fn accuracy(&self) -> Option<f32> {
+        accuracy(self.shots_fired, self.shots_hit)
+    }
+}
+
+pub fn accuracy(shots: u32, hits: u32) -> Option<f32> {
+    if shots == 0 {
+        return None;
+    }
+    Some(hits as f32 / shots as f32 * 100.0)
+}
+
+pub enum MatchTransition {
+    RoundStarted,                       // Warmup finished
+    RoundEnded { winner: Option<u32> }, // Results and map vote are shown
+    NextLevel,                          // Results finished, next map should be loaded
+}
+
+// Server side match flow: Warmup -> Active -> RoundEnd -> (Warmup or Active on the next map)
+pub struct MatchState {
+    phase: MatchPhase,
+    timer: f32, // Time left in the current phase, ignored while active without a time limit
+    warmup_time: f32,
+    score_limit: u32,
+    time_limit: f32,
+    round_end_time: f32,
+    shrink_interval: f32, // 0 if the floor doesn't shrink
+    shrink_timer: f32,
+}
+
+impl MatchState {
+    pub fn new(config: &ServerConfig) -> Self {
+        let shrink_interval = match config.mode {
+            GameMode::Standard => 0.0,
+            GameMode::Elimination => config.shrink_interval,
+        };
+
+        Self {
+            phase: MatchPhase::Warmup,
+            timer: config.warmup_time,
+            warmup_time: config.warmup_time,
+            score_limit: config.score_limit,
+            time_limit: config.time_limit,
+            round_end_time: config.round_end_time,
+            shrink_interval,
+            shrink_timer: shrink_interval,
+        }
+    }
+
+    pub fn phase(&self) -> MatchPhase {
+        self.phase
+    }
+
+    // 0 if the phase has no time limit
+    pub fn time_left(&self) -> f32 {
+        match self.phase {
+            MatchPhase::Active if self.time_limit <= 0.0 => 0.0,
+            _ => self.timer.max(0.0),
+        }
+    }
+
+    pub fn round_end_time(&self) -> f32 {
+        self.round_end_time
+    }
+
+    pub fn update(
+        &mut self,
+        dt: f32,
+        player_count: usize,
+        scores: &[PlayerScore],
+    ) -> Option<MatchTransition> {
+        match self.phase {
+            MatchPhase::Warmup => {
+                if player_count < MIN_PLAYERS {
+                    self.timer = self.warmup_time;
+                    return None;
+                }
+
+                self.timer -= dt;
+                if self.timer <= 0.0 {
+                    self.start_round();
+                    return Some(MatchTransition::RoundStarted);
+                }
+            }
+            MatchPhase::Active => {
+                if self.score_limit > 0 {
+                    if let Some(score) = scores.iter().find(|s| s.kills >= self.score_limit) {
+                        return self.end_round(Some(score.index));
+                    }
+                }
+
+                if self.time_limit > 0.0 {
+                    self.timer -= dt;
+                    if self.timer <= 0.0 {
+                        return self.end_round(leader(scores));
+                    }
+                }
+            }
+            MatchPhase::RoundEnd { .. } => {
+                self.timer -= dt;
+                if self.timer <= 0.0 {
+                    // Skip warmup if there are still enough players around
+                    if player_count >= MIN_PLAYERS {
+                        self.start_round();
+                    } else {
+                        self.phase = MatchPhase::Warmup;
+                        self.timer = self.warmup_time;
+                    }
+                    return Some(MatchTransition::NextLevel);
+                }
+            }
+        }
+
+        None
+    }
+
+    // True once every shrink interval while a round is going
+    pub fn shrink_due(&mut self, dt: f32) -> bool {
+        if self.phase != MatchPhase::Active || self.shrink_interval <= 0.0 {
+            return false;
+        }
+
+        self.shrink_timer -= dt;
+        if self.shrink_timer <= 0.0 {
+            self.shrink_timer += self.shrink_interval;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Only active rounds can be ended
+    pub fn end_round(&mut self, winner: Option<u32>) -> Option<MatchTransition> {
+        if self.phase != MatchPhase::Active {
+            return None;
+        }
+
+        self.phase = MatchPhase::RoundEnd { winner };
+        self.timer = self.round_end_time;
+        Some(MatchTransition::RoundEnded { winner })
+    }
+
+    fn start_round(&mut self) {
+        self.phase = MatchPhase::Active;
+        self.timer = self.time_limit;
+        self.shrink_timer = self.shrink_interval;
+    }
+}
+
+// Player with the most kills, None if nobody has a kill or there's a tie
+fn leader(scores: &[PlayerScore]) -> Option<u32> {
+    let best = scores
+        .iter()
+        .map(|s| s.kills)
+        .max()
+        .filter(|&kills| kills > 0)?;
+    let mut leaders = scores.iter().filter(|s| s.kills == best);
+
+    match (leaders.next(), leaders.next()) {
+        (Some(score), None) => Some(score.index),
+        _ => None,
+    }
+}
@@ -0,0 +1,1503 @@
+use std::ops::RangeInclusive;
+
+use fyrox::{
+    core::{algebra::Vector2, color::Color, pool::Handle},
+    gui::{
+        border::BorderBuilder,
+        brush::Brush,
+        button::{ButtonBuilder, ButtonMessage},
+        check_box::{CheckBoxBuilder, CheckBoxMessage},
+        grid::{Column, GridBuilder, Row},
+        message::{MessageDirection, UiMessage},
+        progress_bar::{ProgressBarBuilder, ProgressBarMessage},
+        scroll_bar::{ScrollBarBuilder, ScrollBarMessage},
+        stack_panel::StackPanelBuilder,
+        text::{TextBuilder, TextMessage},
+        text_box::TextBoxBuilder,
+        widget::{WidgetBuilder, WidgetMessage},
+        BuildContext, HorizontalAlignment, Orientation, Thickness, UiNode, UserInterface,
+        VerticalAlignment,
+    },
+};
+
+use crate::{
+    input::{Action, Binding, KeyBindings},
+    player_stats::PlayerStats,
+    server_list::ServerInfo,
+    skins::SkinInfo,
+    Settings,
+};
+
+const MENU_WIDTH: f32 = 300.0;
+const MENU_HEIGHT: f32 = 660.0;
+const CONTROLS_WIDTH: f32 = 400.0;
+const CONTROLS_HEIGHT: f32 = 940.0;
+const SETTINGS_WIDTH: f32 = 400.0;
+const SETTINGS_HEIGHT: f32 = 660.0;
+const BROWSER_WIDTH: f32 = 500.0;
+const BROWSER_HEIGHT: f32 = 400.0;
+const LOADING_WIDTH: f32 = 400.0;
+const SKINS_WIDTH: f32 = 300.0;
+const SKINS_HEIGHT: f32 = 400.0;
+const SWATCH_SIZE: f32 = 26.0;
+const STATS_WIDTH: f32 = 300.0;
+const STATS_HEIGHT: f32 = 300.0;
+const WARNING_WIDTH: f32 = 400.0;
+const WARNING_HEIGHT: f32 = 200.0;
+
+// What the settings sliders go between, the console keeps to them too
+pub const SENSITIVITY_RANGE: RangeInclusive<f32> = 0.05..=2.0;
+pub const FOV_RANGE: RangeInclusive<f32> = 60.0..=110.0;
+// More bots than this won't fit on the practice maps, practice keeps to it too
+pub const MAX_PRACTICE_BOTS: u32 = 7;
+
+// Colors players can pick from in the settings, different enough to tell apart at a distance
+pub const PLAYER_COLORS: [[u8; 3]; 8] = [
+    [230, 60, 50],
+    [240, 150, 40],
+    [240, 220, 60],
+    [90, 200, 80],
+    [60, 200, 210],
+    [60, 110, 230],
+    [160, 80, 220],
+    [235, 110, 180],
+];
+
+#[derive(Clone)]
+pub enum MenuAction {
+    Connect(String),
+    Observe(String), // Connect to watch without playing
+    Host,
+    Practice,
+    Browse,
+    Skins,
+    Stats,
+    Controls,
+    Settings,
+    Disconnect,
+    Quit,
+}
+
+pub struct Menu {
+    root: Handle<UiNode>,
+    status: Handle<UiNode>,
+    name_box: Handle<UiNode>,
+    address_box: Handle<UiNode>,
+    password_box: Handle<UiNode>,
+    connect: Handle<UiNode>,
+    observe: Handle<UiNode>,
+    host: Handle<UiNode>,
+    practice: Handle<UiNode>,
+    browse: Handle<UiNode>,
+    skins: Handle<UiNode>,
+    stats: Handle<UiNode>,
+    controls: Handle<UiNode>,
+    settings: Handle<UiNode>,
+    disconnect: Handle<UiNode>,
+    quit: Handle<UiNode>,
+    address: String,
+    player_name: String,
+    password: String, // Not saved with the settings, only sent to the server joined
+    visible: bool,
+}
+
+// Label to the left of a menu field, the field has to be built on column 1
+fn make_labeled(ctx: &mut BuildContext, name: &str, field: Handle<UiNode>) -> Handle<UiNode> {
+    let label = TextBuilder::new(
+        WidgetBuilder::new()
+            .with_width(70.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .with_text(name)
+    .with_vertical_text_alignment(VerticalAlignment::Center)
+    .build(ctx);
+
+    GridBuilder::new(WidgetBuilder::new().with_child(label).with_child(field))
+        .add_row(Row::auto())
+        .add_column(Column::auto())
+        .add_column(Column::stretch())
+        .build(ctx)
+}
+
+fn make_button(ctx: &mut BuildContext, text: &str) -> Handle<UiNode> {
+    ButtonBuilder::new(
+        WidgetBuilder::new()
+            .with_height(30.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .with_text(text)
+    .build(ctx)
+}
+
+impl Menu {
+    pub fn new(
+        ctx: &mut BuildContext,
+        window_width: f32,
+        window_height: f32,
+        address: &str,
+        player_name: &str,
+    ) -> Self {
+        let name_box = TextBoxBuilder::new(
+            WidgetBuilder::new()
+                .on_column(1)
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_text(player_name)
+        .build(ctx);
+
+        let address_box = TextBoxBuilder::new(
+            WidgetBuilder::new()
+                .on_column(1)
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_text(address)
+        .build(ctx);
+
+        // Left empty for servers without one
+        let password_box = TextBoxBuilder::new(
+            WidgetBuilder::new()
+                .on_column(1)
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .build(ctx);
+
+        let connect = make_button(ctx, "Connect");
+        let observe = make_button(ctx, "Observe");
+        let host = make_button(ctx, "Host");
+        let practice = make_button(ctx, "Practice");
+        let browse = make_button(ctx, "Server list");
+        let skins = make_button(ctx, "Character");
+        let stats = make_button(ctx, "Stats");
+        let controls = make_button(ctx, "Controls");
+        let settings = make_button(ctx, "Settings");
+        // Only shown while connected
+        let disconnect = ButtonBuilder::new(
+            WidgetBuilder::new()
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0))
+                .with_visibility(false),
+        )
+        .with_text("Disconnect")
+        .build(ctx);
+        let quit = make_button(ctx, "Quit");
+
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Breakfloor")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+        // Why the last connection ended
+        let status = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .with_wrap(true)
+            .build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(MENU_WIDTH)
+                .with_height(MENU_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - MENU_WIDTH) / 2.0,
+                    (window_height - MENU_HEIGHT) / 2.0,
+                ))
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children([
+                                title,
+                                status,
+                                make_labeled(ctx, "Name", name_box),
+                                make_labeled(ctx, "Server", address_box),
+                                make_labeled(ctx, "Password", password_box),
+                                connect,
+                                observe,
+                                host,
+                                practice,
+                                browse,
+                                skins,
+                                stats,
+                                controls,
+                                settings,
+                                disconnect,
+                                quit,
+                            ]),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            status,
+            name_box,
+            address_box,
+            password_box,
+            connect,
+            observe,
+            host,
+            practice,
+            browse,
+            skins,
+            stats,
+            controls,
+            settings,
+            disconnect,
+            quit,
+            address: address.to_string(),
+            player_name: player_name.to_string(),
+            password: String::new(),
+            visible: true,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn player_name(&self) -> &str {
+        &self.player_name
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+
+    pub fn set_status(&self, ui: &UserInterface, status: &str) {
+        set_text(ui, self.status, status);
+    }
+
+    pub fn set_connected(&self, ui: &UserInterface, connected: bool) {
+        ui.send_message(WidgetMessage::visibility(
+            self.disconnect,
+            MessageDirection::ToWidget,
+            connected,
+        ));
+    }
+
+    pub fn handle_ui_message(&mut self, message: &UiMessage) -> Option<MenuAction> {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.connect {
+                return Some(MenuAction::Connect(self.address.clone()));
+            } else if message.destination() == self.observe {
+                return Some(MenuAction::Observe(self.address.clone()));
+            } else if message.destination() == self.host {
+                return Some(MenuAction::Host);
+            } else if message.destination() == self.practice {
+                return Some(MenuAction::Practice);
+            } else if message.destination() == self.browse {
+                return Some(MenuAction::Browse);
+            } else if message.destination() == self.skins {
+                return Some(MenuAction::Skins);
+            } else if message.destination() == self.stats {
+                return Some(MenuAction::Stats);
+            } else if message.destination() == self.controls {
+                return Some(MenuAction::Controls);
+            } else if message.destination() == self.settings {
+                return Some(MenuAction::Settings);
+            } else if message.destination() == self.disconnect {
+                return Some(MenuAction::Disconnect);
+            } else if message.destination() == self.quit {
+                return Some(MenuAction::Quit);
+            }
+        } else if let Some(TextMessage::Text(text)) = message.data() {
+            // Keep track of the address, name and password as they're edited
+            if message.direction() == MessageDirection::FromWidget {
+                if message.destination() == self.address_box {
+                    self.address = text.clone();
+                } else if message.destination() == self.name_box {
+                    self.player_name = text.clone();
+                } else if message.destination() == self.password_box {
+                    self.password = text.clone();
+                }
+            }
+        }
+
+        None
+    }
+}
+
+struct ControlRow {
+    action: Action,
+    button: Handle<UiNode>,
+    text: Handle<UiNode>, // Binding name shown on the button
+}
+
+// Lists every action with its binding. Clicking a binding waits for the next key or mouse button,
+// which is bound to that action.
+pub struct ControlsScreen {
+    root: Handle<UiNode>,
+    rows: Vec<ControlRow>,
+    status: Handle<UiNode>,
+    back: Handle<UiNode>,
+    waiting: Option<Action>,
+    visible: bool,
+}
+
+impl ControlsScreen {
+    pub fn new(
+        ctx: &mut BuildContext,
+        window_width: f32,
+        window_height: f32,
+        bindings: &KeyBindings,
+    ) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Controls")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+
+        let mut children = vec![title];
+        let mut rows = Vec::new();
+
+        for action in Action::ALL {
+            let label = TextBuilder::new(
+                WidgetBuilder::new()
+                    .with_width(200.0)
+                    .with_margin(Thickness::uniform(4.0)),
+            )
+            .with_text(action.name())
+            .with_vertical_text_alignment(VerticalAlignment::Center)
+            .build(ctx);
+
+            let text = TextBuilder::new(WidgetBuilder::new())
+                .with_text(binding_name(bindings, action))
+                .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                .with_vertical_text_alignment(VerticalAlignment::Center)
+                .build(ctx);
+
+            let button = ButtonBuilder::new(
+                WidgetBuilder::new()
+                    .with_width(160.0)
+                    .with_height(30.0)
+                    .with_margin(Thickness::uniform(4.0)),
+            )
+            .with_content(text)
+            .build(ctx);
+
+            children.push(
+                StackPanelBuilder::new(WidgetBuilder::new().with_children([label, button]))
+                    .with_orientation(Orientation::Horizontal)
+                    .build(ctx),
+            );
+            rows.push(ControlRow {
+                action,
+                button,
+                text,
+            });
+        }
+
+        let status = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .build(ctx);
+        let back = make_button(ctx, "Back");
+        children.push(status);
+        children.push(back);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(CONTROLS_WIDTH)
+                .with_height(CONTROLS_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - CONTROLS_WIDTH) / 2.0,
+                    (window_height - CONTROLS_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children(children),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            rows,
+            status,
+            back,
+            waiting: None,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // Waiting for a key or mouse button to bind
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.is_some()
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, bindings: &KeyBindings, visible: bool) {
+        self.visible = visible;
+        self.cancel(ui, bindings);
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    // Returns true when the back button is clicked
+    pub fn handle_ui_message(&mut self, ui: &UserInterface, message: &UiMessage) -> bool {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.back {
+                return true;
+            }
+
+            if let Some(row) = self.rows.iter().find(|r| r.button == message.destination()) {
+                self.waiting = Some(row.action);
+                set_text(ui, row.text, "Press a key...");
+                set_text(ui, self.status, "Press Escape to cancel");
+            }
+        }
+
+        false
+    }
+
+    // Binds whatever was pressed to the action being waited on. Returns true if the bindings
+    // changed and should be saved.
+    pub fn bind(
+        &mut self,
+        ui: &UserInterface,
+        bindings: &mut KeyBindings,
+        binding: Binding,
+    ) -> bool {
+        let action = match self.waiting.take() {
+            Some(action) => action,
+            None => return false,
+        };
+
+        let status = if !binding.is_bindable() {
+            format!("{} can't be bound", binding.name())
+        } else if let Some(other) = bindings.bind(action, binding) {
+            // Bindings were swapped so nothing is left without one
+            format!(
+                "{} was used by {}, {} is now {}",
+                binding.name(),
+                other.name(),
+                other.name(),
+                binding_name(bindings, other)
+            )
+        } else {
+            String::new()
+        };
+
+        self.refresh(ui, bindings);
+        set_text(ui, self.status, &status);
+
+        binding.is_bindable()
+    }
+
+    pub fn cancel(&mut self, ui: &UserInterface, bindings: &KeyBindings) {
+        self.waiting = None;
+        self.refresh(ui, bindings);
+        set_text(ui, self.status, "");
+    }
+
+    fn refresh(&self, ui: &UserInterface, bindings: &KeyBindings) {
+        for row in self.rows.iter() {
+            set_text(ui, row.text, &binding_name(bindings, row.action));
+        }
+    }
+}
+
+fn binding_name(bindings: &KeyBindings, action: Action) -> String {
+    bindings
+        .binding(action)
+        .map_or_else(|| String::from("-"), |binding| binding.name())
+}
+
+fn set_text(ui: &UserInterface, text: Handle<UiNode>, value: &str) {
+    ui.send_message(TextMessage::text(
+        text,
+        MessageDirection::ToWidget,
+        value.to_string(),
+    ));
+}
+
+pub enum SettingsAction {
+    Fullscreen(bool),
+    Back,
+}
+
+// Sliders and toggles that change the settings as they're moved, main applies fullscreen and saves
+// the settings when going back
+pub struct SettingsScreen {
+    root: Handle<UiNode>,
+    sensitivity: Handle<UiNode>,
+    vertical_sensitivity: Handle<UiNode>,
+    invert_mouse_y: Handle<UiNode>,
+    raw_mouse: Handle<UiNode>,
+    aim_sensitivity: Handle<UiNode>,
+    fov: Handle<UiNode>,
+    fov_kick: Handle<UiNode>,
+    master_volume: Handle<UiNode>,
+    effects_volume: Handle<UiNode>,
+    music_volume: Handle<UiNode>,
+    fullscreen: Handle<UiNode>,
+    vsync: Handle<UiNode>,
+    practice_bots: Handle<UiNode>,
+    swatches: Vec<Swatch>,
+    back: Handle<UiNode>,
+    visible: bool,
+}
+
+struct Swatch {
+    button: Handle<UiNode>,
+    border: Handle<UiNode>, // Outlined when picked
+    color: [u8; 3],
+}
+
+fn swatch_outline(selected: bool) -> Brush {
+    if selected {
+        Brush::Solid(Color::WHITE)
+    } else {
+        Brush::Solid(Color::TRANSPARENT)
+    }
+}
+
+fn make_row(ctx: &mut BuildContext, name: &str, widget: Handle<UiNode>) -> Handle<UiNode> {
+    let label = TextBuilder::new(
+        WidgetBuilder::new()
+            .with_width(160.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .with_text(name)
+    .with_vertical_text_alignment(VerticalAlignment::Center)
+    .build(ctx);
+
+    StackPanelBuilder::new(WidgetBuilder::new().with_children([label, widget]))
+        .with_orientation(Orientation::Horizontal)
+        .build(ctx)
+}
+
+fn make_slider(
+    ctx: &mut BuildContext,
+    min: f32,
+    max: f32,
+    step: f32,
+    value: f32,
+) -> Handle<UiNode> {
+    ScrollBarBuilder::new(
+        WidgetBuilder::new()
+            .with_width(200.0)
+            .with_height(30.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .with_orientation(Orientation::Horizontal)
+    .with_min(min)
+    .with_max(max)
+    .with_step(step)
+    .with_value(value)
+    .show_value(true)
+    .with_value_precision(2)
+    .build(ctx)
+}
+
+fn make_check_box(ctx: &mut BuildContext, checked: bool) -> Handle<UiNode> {
+    CheckBoxBuilder::new(
+        WidgetBuilder::new()
+            .with_width(30.0)
+            .with_height(30.0)
+            .with_margin(Thickness::uniform(4.0)),
+    )
+    .checked(Some(checked))
+    .build(ctx)
+}
+
+impl SettingsScreen {
+    pub fn new(
+        ctx: &mut BuildContext,
+        window_width: f32,
+        window_height: f32,
+        settings: &Settings,
+    ) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Settings")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+
+        let sensitivity = make_slider(
+            ctx,
+            *SENSITIVITY_RANGE.start(),
+            *SENSITIVITY_RANGE.end(),
+            0.05,
+            settings.look_sensitivity,
+        );
+        let vertical_sensitivity = make_slider(ctx, 0.25, 2.0, 0.05, settings.vertical_sensitivity);
+        let invert_mouse_y = make_check_box(ctx, settings.invert_mouse_y);
+        let raw_mouse = make_check_box(ctx, settings.raw_mouse);
+        let aim_sensitivity = make_slider(ctx, 0.25, 1.5, 0.05, settings.aim_sensitivity);
+        let fov = make_slider(ctx, *FOV_RANGE.start(), *FOV_RANGE.end(), 1.0, settings.fov);
+        let fov_kick = make_check_box(ctx, settings.fov_kick);
+        let master_volume = make_slider(ctx, 0.0, 1.0, 0.05, settings.master_volume);
+        let effects_volume = make_slider(ctx, 0.0, 1.0, 0.05, settings.effects_volume);
+        let music_volume = make_slider(ctx, 0.0, 1.0, 0.05, settings.music_volume);
+        let fullscreen = make_check_box(ctx, settings.fullscreen);
+        let vsync = make_check_box(ctx, settings.vsync);
+        let practice_bots = make_slider(
+            ctx,
+            0.0,
+            MAX_PRACTICE_BOTS as f32,
+            1.0,
+            settings.practice_bots as f32,
+        );
+        let swatches: Vec<Swatch> = PLAYER_COLORS
+            .iter()
+            .map(|&color| {
+                let border = BorderBuilder::new(
+                    WidgetBuilder::new()
+                        .with_background(Brush::Solid(Color::opaque(color[0], color[1], color[2])))
+                        .with_foreground(swatch_outline(color == settings.player_color)),
+                )
+                .with_stroke_thickness(Thickness::uniform(2.0))
+                .build(ctx);
+                let button = ButtonBuilder::new(
+                    WidgetBuilder::new()
+                        .with_width(SWATCH_SIZE)
+                        .with_height(SWATCH_SIZE)
+                        .with_margin(Thickness::uniform(1.0)),
+                )
+                .with_content(border)
+                .build(ctx);
+
+                Swatch {
+                    button,
+                    border,
+                    color,
+                }
+            })
+            .collect();
+        let palette = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_margin(Thickness::uniform(3.0))
+                .with_children(swatches.iter().map(|swatch| swatch.button)),
+        )
+        .with_orientation(Orientation::Horizontal)
+        .build(ctx);
+        let back = make_button(ctx, "Back");
+
+        let children = [
+            title,
+            make_row(ctx, "Mouse sensitivity", sensitivity),
+            // Multiplies the sensitivity for looking up and down
+            make_row(ctx, "Vertical scale", vertical_sensitivity),
+            make_row(ctx, "Invert mouse Y", invert_mouse_y),
+            // Off uses the cursor's movement, with whatever acceleration the OS adds
+            make_row(ctx, "Raw mouse input", raw_mouse),
+            // Multiplies the sensitivity while aiming down sights
+            make_row(ctx, "Aiming scale", aim_sensitivity),
+            make_row(ctx, "Field of view", fov),
+            make_row(ctx, "FOV kick", fov_kick),
+            make_row(ctx, "Master volume", master_volume),
+            make_row(ctx, "Effects volume", effects_volume),
+            make_row(ctx, "Music volume", music_volume),
+            make_row(ctx, "Fullscreen", fullscreen),
+            // The renderer only picks vsync up when the window is created
+            make_row(ctx, "VSync (on restart)", vsync),
+            // Sent to the server when joining
+            make_row(ctx, "Color (next game)", palette),
+            make_row(ctx, "Practice bots", practice_bots),
+            back,
+        ];
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(SETTINGS_WIDTH)
+                .with_height(SETTINGS_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - SETTINGS_WIDTH) / 2.0,
+                    (window_height - SETTINGS_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children(children),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            sensitivity,
+            vertical_sensitivity,
+            invert_mouse_y,
+            raw_mouse,
+            aim_sensitivity,
+            fov,
+            fov_kick,
+            master_volume,
+            effects_volume,
+            music_volume,
+            fullscreen,
+            vsync,
+            practice_bots,
+            swatches,
+            back,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    // Moves the sliders to settings changed from somewhere else, like the console
+    pub fn sync(&self, ui: &UserInterface, settings: &Settings) {
+        ui.send_message(ScrollBarMessage::value(
+            self.sensitivity,
+            MessageDirection::ToWidget,
+            settings.look_sensitivity,
+        ));
+        ui.send_message(ScrollBarMessage::value(
+            self.fov,
+            MessageDirection::ToWidget,
+            settings.fov,
+        ));
+    }
+
+    // Values are written to the settings as soon as they change
+    pub fn handle_ui_message(
+        &self,
+        ui: &UserInterface,
+        message: &UiMessage,
+        settings: &mut Settings,
+    ) -> Option<SettingsAction> {
+        if message.direction() != MessageDirection::FromWidget {
+            return None;
+        }
+
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.back {
+                return Some(SettingsAction::Back);
+            } else if let Some(picked) = self
+                .swatches
+                .iter()
+                .find(|swatch| swatch.button == message.destination())
+            {
+                settings.player_color = picked.color;
+                for swatch in self.swatches.iter() {
+                    ui.send_message(WidgetMessage::foreground(
+                        swatch.border,
+                        MessageDirection::ToWidget,
+                        swatch_outline(swatch.button == picked.button),
+                    ));
+                }
+            }
+        } else if let Some(&ScrollBarMessage::Value(value)) = message.data() {
+            if message.destination() == self.sensitivity {
+                settings.look_sensitivity = value;
+            } else if message.destination() == self.vertical_sensitivity {
+                settings.vertical_sensitivity = value;
+            } else if message.destination() == self.aim_sensitivity {
+                settings.aim_sensitivity = value;
+            } else if message.destination() == self.fov {
+                settings.fov = value;
+            } else if message.destination() == self.master_volume {
+                settings.master_volume = value;
+            } else if message.destination() == self.effects_volume {
+                settings.effects_volume = value;
+            } else if message.destination() == self.music_volume {
+                settings.music_volume = value;
+            } else if message.destination() == self.practice_bots {
+                settings.practice_bots = value.round() as u32;
+            }
+        } else if let Some(&CheckBoxMessage::Check(Some(checked))) = message.data() {
+            if message.destination() == self.fullscreen {
+                settings.fullscreen = checked;
+                return Some(SettingsAction::Fullscreen(checked));
+            } else if message.destination() == self.fov_kick {
+                settings.fov_kick = checked;
+            } else if message.destination() == self.vsync {
+                settings.vsync = checked;
+            } else if message.destination() == self.invert_mouse_y {
+                settings.invert_mouse_y = checked;
+            } else if message.destination() == self.raw_mouse {
+                settings.raw_mouse = checked;
+            }
+        }
+
+        None
+    }
+}
+
+struct ServerRow {
+    button: Handle<UiNode>,
+    text: Handle<UiNode>,
+    info: ServerInfo,
+    ping: Option<u32>, // Milliseconds
+}
+
+impl ServerRow {
+    fn label(&self) -> String {
+        let ping = self
+            .ping
+            .map_or_else(|| String::from("-"), |ping| format!("{} ms", ping));
+        format!(
+            "{}  |  {}  |  {} players  |  {}",
+            self.info.name, self.info.map, self.info.players, ping
+        )
+    }
+}
+
+pub enum BrowserAction {
+    Join(String),
+    Refresh,
+    Back,
+}
+
+// Servers fetched from the master server, clicking one joins it
+pub struct ServerBrowser {
+    root: Handle<UiNode>,
+    list: Handle<UiNode>,
+    status: Handle<UiNode>,
+    refresh: Handle<UiNode>,
+    back: Handle<UiNode>,
+    rows: Vec<ServerRow>,
+    visible: bool,
+}
+
+impl ServerBrowser {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Servers")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+        let status = TextBuilder::new(
+            WidgetBuilder::new()
+                .with_height(30.0)
+                .with_margin(Thickness::uniform(4.0)),
+        )
+        .with_horizontal_text_alignment(HorizontalAlignment::Center)
+        .build(ctx);
+        let list = StackPanelBuilder::new(WidgetBuilder::new()).build(ctx);
+        let refresh = make_button(ctx, "Refresh");
+        let back = make_button(ctx, "Back");
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(BROWSER_WIDTH)
+                .with_height(BROWSER_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - BROWSER_WIDTH) / 2.0,
+                    (window_height - BROWSER_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children([title, list, status, refresh, back]),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            list,
+            status,
+            refresh,
+            back,
+            rows: Vec::new(),
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn set_status(&self, ui: &UserInterface, status: &str) {
+        set_text(ui, self.status, status);
+    }
+
+    pub fn set_servers(&mut self, ui: &mut UserInterface, servers: Vec<ServerInfo>) {
+        for row in self.rows.drain(..) {
+            ui.send_message(WidgetMessage::remove(
+                row.button,
+                MessageDirection::ToWidget,
+            ));
+        }
+
+        for info in servers {
+            let ctx = &mut ui.build_ctx();
+            let text = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::left(4.0)))
+                .with_vertical_text_alignment(VerticalAlignment::Center)
+                .build(ctx);
+            let button = ButtonBuilder::new(
+                WidgetBuilder::new()
+                    .with_height(30.0)
+                    .with_margin(Thickness::uniform(2.0)),
+            )
+            .with_content(text)
+            .build(ctx);
+
+            ui.send_message(WidgetMessage::link(
+                button,
+                MessageDirection::ToWidget,
+                self.list,
+            ));
+
+            let row = ServerRow {
+                button,
+                text,
+                info,
+                ping: None,
+            };
+            set_text(ui, row.text, &row.label());
+            self.rows.push(row);
+        }
+
+        let status = if self.rows.is_empty() {
+            "No servers found"
+        } else {
+            ""
+        };
+        set_text(ui, self.status, status);
+    }
+
+    pub fn set_ping(&mut self, ui: &UserInterface, address: &str, ping: u32) {
+        if let Some(row) = self.rows.iter_mut().find(|r| r.info.address == address) {
+            row.ping = Some(ping);
+            set_text(ui, row.text, &row.label());
+        }
+    }
+
+    pub fn handle_ui_message(&self, message: &UiMessage) -> Option<BrowserAction> {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.refresh {
+                return Some(BrowserAction::Refresh);
+            } else if message.destination() == self.back {
+                return Some(BrowserAction::Back);
+            } else if let Some(row) = self.rows.iter().find(|r| r.button == message.destination()) {
+                return Some(BrowserAction::Join(row.info.address.clone()));
+            }
+        }
+
+        None
+    }
+}
+
+struct SkinRow {
+    button: Handle<UiNode>,
+    text: Handle<UiNode>,
+    skin: SkinInfo,
+}
+
+pub enum SkinAction {
+    Back,
+}
+
+// Third person models from the skins manifest, the picked one is what other players see
+pub struct SkinScreen {
+    root: Handle<UiNode>,
+    back: Handle<UiNode>,
+    rows: Vec<SkinRow>,
+    visible: bool,
+}
+
+fn skin_label(skin: &SkinInfo, selected: bool) -> String {
+    if selected {
+        format!("> {} <", skin.name)
+    } else {
+        skin.name.clone()
+    }
+}
+
+impl SkinScreen {
+    pub fn new(
+        ctx: &mut BuildContext,
+        window_width: f32,
+        window_height: f32,
+        skins: Vec<SkinInfo>,
+        selected: u32,
+    ) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Character")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+
+        let mut children = vec![title];
+        let mut rows = Vec::new();
+
+        for skin in skins {
+            let text = TextBuilder::new(WidgetBuilder::new())
+                .with_text(skin_label(&skin, skin.id == selected))
+                .with_horizontal_text_alignment(HorizontalAlignment::Center)
+                .with_vertical_text_alignment(VerticalAlignment::Center)
+                .build(ctx);
+            let button = ButtonBuilder::new(
+                WidgetBuilder::new()
+                    .with_height(30.0)
+                    .with_margin(Thickness::uniform(4.0)),
+            )
+            .with_content(text)
+            .build(ctx);
+
+            children.push(button);
+            rows.push(SkinRow { button, text, skin });
+        }
+
+        let back = make_button(ctx, "Back");
+        children.push(back);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(SKINS_WIDTH)
+                .with_height(SKINS_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - SKINS_WIDTH) / 2.0,
+                    (window_height - SKINS_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children(children),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            back,
+            rows,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    // The pick is written to the settings right away, it takes effect the next time joining a game
+    pub fn handle_ui_message(
+        &self,
+        ui: &UserInterface,
+        message: &UiMessage,
+        settings: &mut Settings,
+    ) -> Option<SkinAction> {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.back {
+                return Some(SkinAction::Back);
+            } else if let Some(picked) =
+                self.rows.iter().find(|r| r.button == message.destination())
+            {
+                settings.skin = picked.skin.id;
+                for row in self.rows.iter() {
+                    set_text(
+                        ui,
+                        row.text,
+                        &skin_label(&row.skin, row.skin.id == picked.skin.id),
+                    );
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// Lifetime stats of the profile named in the menu
+pub struct StatsScreen {
+    root: Handle<UiNode>,
+    title: Handle<UiNode>,
+    text: Handle<UiNode>,
+    back: Handle<UiNode>,
+    visible: bool,
+}
+
+fn format_playtime(seconds: f32) -> String {
+    let minutes = (seconds / 60.0) as u32;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+impl StatsScreen {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+        let text =
+            TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0))).build(ctx);
+        let back = make_button(ctx, "Back");
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(STATS_WIDTH)
+                .with_height(STATS_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - STATS_WIDTH) / 2.0,
+                    (window_height - STATS_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 200)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children([title, text, back]),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            title,
+            text,
+            back,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn show(&mut self, ui: &UserInterface, profile: &str, stats: &PlayerStats) {
+        let accuracy = stats
+            .accuracy()
+            .map_or_else(|| "-".to_string(), |accuracy| format!("{:.1}%", accuracy));
+        set_text(ui, self.title, &format!("Stats for {}", profile));
+        set_text(
+            ui,
+            self.text,
+            &format!(
+                "Kills: {}\nDeaths: {}\nBlocks destroyed: {}\nShots: {}\nHits: {}\nAccuracy: {}\nPlaytime: {}",
+                stats.kills,
+                stats.deaths,
+                stats.blocks_destroyed,
+                stats.shots,
+                stats.hits,
+                accuracy,
+                format_playtime(stats.playtime),
+            ),
+        );
+        self.set_visible(ui, true);
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    // Returns true when the back button is clicked
+    pub fn handle_ui_message(&self, message: &UiMessage) -> bool {
+        matches!(message.data(), Some(ButtonMessage::Click)) && message.destination() == self.back
+    }
+}
+
+// Tells the player about something that went wrong outside of a game. Build it after the other
+// screens so it's drawn on top of them.
+pub struct WarningDialog {
+    root: Handle<UiNode>,
+    text: Handle<UiNode>,
+    ok: Handle<UiNode>,
+    visible: bool,
+}
+
+impl WarningDialog {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let title = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_text("Warning")
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .build(ctx);
+        let text = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_wrap(true)
+            .build(ctx);
+        let ok = make_button(ctx, "OK");
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(WARNING_WIDTH)
+                .with_height(WARNING_HEIGHT)
+                .with_desired_position(Vector2::new(
+                    (window_width - WARNING_WIDTH) / 2.0,
+                    (window_height - WARNING_HEIGHT) / 2.0,
+                ))
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(0, 0, 0, 230)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_margin(Thickness::uniform(10.0))
+                            .with_children([title, text, ok]),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            text,
+            ok,
+            visible: false,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn show(&mut self, ui: &UserInterface, text: &str) {
+        set_text(ui, self.text, text);
+        self.set_visible(ui, true);
+    }
+
+    pub fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    // Returns true when the OK button is clicked
+    pub fn handle_ui_message(&self, message: &UiMessage) -> bool {
+        matches!(message.data(), Some(ButtonMessage::Click)) && message.destination() == self.ok
+    }
+}
+
+pub enum LoadingAction {
+    Retry,
+    Back,
+}
+
+// Covers the window from joining until there's a level to look at, so there's something other
+// than a black screen while connecting and loading. Stays up with the error if joining fails.
+pub struct LoadingScreen {
+    root: Handle<UiNode>,
+    status: Handle<UiNode>,
+    progress: Handle<UiNode>,
+    buttons: Handle<UiNode>,
+    retry: Handle<UiNode>,
+    back: Handle<UiNode>,
+    visible: bool,
+    failed: bool,
+}
+
+impl LoadingScreen {
+    pub fn new(ctx: &mut BuildContext, window_width: f32, window_height: f32) -> Self {
+        let status = TextBuilder::new(WidgetBuilder::new().with_margin(Thickness::uniform(4.0)))
+            .with_horizontal_text_alignment(HorizontalAlignment::Center)
+            .with_wrap(true)
+            .build(ctx);
+        let progress = ProgressBarBuilder::new(
+            WidgetBuilder::new()
+                .with_height(20.0)
+                .with_margin(Thickness::uniform(4.0))
+                .with_visibility(false),
+        )
+        .build(ctx);
+        let retry = make_button(ctx, "Retry");
+        let back = make_button(ctx, "Back");
+        // Only shown when joining failed
+        let buttons = StackPanelBuilder::new(
+            WidgetBuilder::new()
+                .with_visibility(false)
+                .with_children([retry, back]),
+        )
+        .build(ctx);
+
+        let root = BorderBuilder::new(
+            WidgetBuilder::new()
+                .with_width(window_width)
+                .with_height(window_height)
+                .with_visibility(false)
+                .with_background(Brush::Solid(Color::from_rgba(10, 10, 10, 255)))
+                .with_child(
+                    StackPanelBuilder::new(
+                        WidgetBuilder::new()
+                            .with_width(LOADING_WIDTH)
+                            .with_horizontal_alignment(HorizontalAlignment::Center)
+                            .with_vertical_alignment(VerticalAlignment::Center)
+                            .with_children([status, progress, buttons]),
+                    )
+                    .build(ctx),
+                ),
+        )
+        .build(ctx);
+
+        Self {
+            root,
+            status,
+            progress,
+            buttons,
+            retry,
+            back,
+            visible: false,
+            failed: false,
+        }
+    }
+
+    pub fn resize(&self, ui: &UserInterface, window_width: f32, window_height: f32) {
+        ui.send_message(WidgetMessage::width(
+            self.root,
+            MessageDirection::ToWidget,
+            window_width,
+        ));
+        ui.send_message(WidgetMessage::height(
+            self.root,
+            MessageDirection::ToWidget,
+            window_height,
+        ));
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    // Waiting for the player to pick retry or back
+    pub fn has_failed(&self) -> bool {
+        self.failed
+    }
+
+    // Progress is 0 to 1, None when there's nothing to measure
+    pub fn show_progress(&mut self, ui: &UserInterface, status: &str, progress: Option<f32>) {
+        self.set_visible(ui, true);
+        self.failed = false;
+        set_text(ui, self.status, status);
+
+        ui.send_message(WidgetMessage::visibility(
+            self.progress,
+            MessageDirection::ToWidget,
+            progress.is_some(),
+        ));
+        if let Some(progress) = progress {
+            ui.send_message(ProgressBarMessage::progress(
+                self.progress,
+                MessageDirection::ToWidget,
+                progress,
+            ));
+        }
+        ui.send_message(WidgetMessage::visibility(
+            self.buttons,
+            MessageDirection::ToWidget,
+            false,
+        ));
+    }
+
+    pub fn show_error(&mut self, ui: &UserInterface, error: &str) {
+        self.set_visible(ui, true);
+        self.failed = true;
+        set_text(ui, self.status, error);
+
+        ui.send_message(WidgetMessage::visibility(
+            self.progress,
+            MessageDirection::ToWidget,
+            false,
+        ));
+        ui.send_message(WidgetMessage::visibility(
+            self.buttons,
+            MessageDirection::ToWidget,
+            true,
+        ));
+    }
+
+    pub fn hide(&mut self, ui: &UserInterface) {
+        self.set_visible(ui, false);
+        self.failed = false;
+    }
+
+    fn set_visible(&mut self, ui: &UserInterface, visible: bool) {
+        // Progress updates come every frame, only send visibility when it changes
+        if visible == self.visible {
+            return;
+        }
+        self.visible = visible;
+
+        ui.send_message(WidgetMessage::visibility(
+            self.root,
+            MessageDirection::ToWidget,
+            visible,
+        ));
+    }
+
+    pub fn handle_ui_message(&self, message: &UiMessage) -> Option<LoadingAction> {
+        if let Some(ButtonMessage::Click) = message.data() {
+            if message.destination() == self.retry {
+                return Some(LoadingAction::Retry);
+            } else if message.destination() == self.back {
+                return Some(LoadingAction::Back);
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,245 @@
+// Numbers for server operators to graph. Everything that does work keeps a handle and counts as it
+// goes, and if the server config sets a metrics port they're served over HTTP in Prometheus' text
+// format for a scraper to collect. Counters only go up, tick and level load times are summed so
+// averages can be worked out between scrapes.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::{info, warn};
+
+#[derive(Debug, Default)]
+struct Counters {
+    ticks: AtomicU64,
+    tick_micros: AtomicU64,
+    longest_tick_micros: AtomicU64, // Since the last scrape
+    players: AtomicU64,
+    bots: AtomicU64,
+    packets_received: AtomicU64,
+    packets_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    game_events: AtomicU64,
+    player_events: AtomicU64,
+    level_loads: AtomicU64,
+    level_load_micros: AtomicU64,
+    last_level_load_micros: AtomicU64,
+}
+
+// Cheap to clone, every clone counts into the same numbers
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+impl Metrics {
+    // Starts serving on the port unless it's 0
+    pub fn new(port: u16) -> Self {
+        let metrics = Self::default();
+        if port != 0 {
+            match TcpListener::bind(("0.0.0.0", port)) {
+                Ok(listener) => {
+                    info!("serving metrics on port {}", port);
+                    let served = metrics.clone();
+                    thread::spawn(move || served.serve(listener));
+                }
+                Err(e) => warn!("failed to bind metrics port {}: {}", port, e),
+            }
+        }
+        metrics
+    }
+
+    pub fn record_tick(&self, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        self.counters.ticks.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .tick_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.counters
+            .longest_tick_micros
+            .fetch_max(micros, Ordering::Relaxed);
+    }
+
+    pub fn set_players(&self, players: usize, bots: usize) {
+        self.counters
+            .players
+            .store(players as u64, Ordering::Relaxed);
+        self.counters.bots.store(bots as u64, Ordering::Relaxed);
+    }
+
+    pub fn packet_received(&self, bytes: usize) {
+        self.counters
+            .packets_received
+            .fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn packet_sent(&self, bytes: usize) {
+        self.counters.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn game_event(&self) {
+        self.counters.game_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn player_event(&self) {
+        self.counters.player_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_level_load(&self, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        self.counters.level_loads.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .level_load_micros
+            .fetch_add(micros, Ordering::Relaxed);
+        self.counters
+            .last_level_load_micros
+            .store(micros, Ordering::Relaxed);
+    }
+
+    // Scrapes are rare and small, one at a time is plenty
+    fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.answer(stream) {
+                        warn!("failed to answer metrics request: {}", e);
+                    }
+                }
+                Err(e) => warn!("metrics connection failed: {}", e),
+            }
+        }
+    }
+
+    // Every request gets the metrics, whatever path it asked for
+    fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request)?;
+
+        let body = self.render();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    fn render(&self) -> String {
+        let c = &self.counters;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let seconds = |micros: u64| micros as f64 / 1_000_000.0;
+
+        let mut body = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            body.push_str(&format!(
+                "# HELP breakfloor_{name} {help}\n# TYPE breakfloor_{name} {kind}\nbreakfloor_{name} {value}\n",
+                name = name,
+                kind = kind,
+                help = help,
+                value = value,
+            ));
+        };
+
+        metric(
+            "ticks_total",
+            "counter",
+            "Simulation steps run",
+            load(&c.ticks).to_string(),
+        );
+        metric(
+            "tick_seconds_total",
+            "counter",
+            "Time spent simulating",
+            seconds(load(&c.tick_micros)).to_string(),
+        );
+        metric(
+            "longest_tick_seconds",
+            "gauge",
+            "Slowest tick since the last scrape",
+            seconds(c.longest_tick_micros.swap(0, Ordering::Relaxed)).to_string(),
+        );
+        metric(
+            "players",
+            "gauge",
+            "Connected players",
+            load(&c.players).to_string(),
+        );
+        metric(
+            "bots",
+            "gauge",
+            "Bots in the level",
+            load(&c.bots).to_string(),
+        );
+        metric(
+            "packets_received_total",
+            "counter",
+            "Packets received",
+            load(&c.packets_received).to_string(),
+        );
+        metric(
+            "packets_sent_total",
+            "counter",
+            "Packets sent",
+            load(&c.packets_sent).to_string(),
+        );
+        metric(
+            "received_bytes_total",
+            "counter",
+            "Payload bytes received",
+            load(&c.bytes_received).to_string(),
+        );
+        metric(
+            "sent_bytes_total",
+            "counter",
+            "Payload bytes sent",
+            load(&c.bytes_sent).to_string(),
+        );
+        metric(
+            "game_events_total",
+            "counter",
+            "Game events processed",
+            load(&c.game_events).to_string(),
+        );
+        metric(
+            "player_events_total",
+            "counter",
+            "Player events processed",
+            load(&c.player_events).to_string(),
+        );
+        metric(
+            "level_loads_total",
+            "counter",
+            "Levels loaded",
+            load(&c.level_loads).to_string(),
+        );
+        metric(
+            "level_load_seconds_total",
+            "counter",
+            "Time spent loading levels",
+            seconds(load(&c.level_load_micros)).to_string(),
+        );
+        metric(
+            "last_level_load_seconds",
+            "gauge",
+            "How long the current level took to load",
+            seconds(load(&c.last_level_load_micros)).to_string(),
+        );
+
+        body
+    }
+}
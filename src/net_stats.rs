@@ -0,0 +1,127 @@
+// Connection quality, measured for the server connection on clients and for every client on the
+// server. Ping comes from Ping/Pong messages, loss from how many of those never came back.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+pub const PING_INTERVAL: Duration = Duration::from_millis(500);
+// Pings that take longer than this count as lost
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+const LOSS_SAMPLES: usize = 20;
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(1);
+const RTT_SMOOTHING: f32 = 0.2; // Weight of the newest sample
+
+#[derive(Debug)]
+pub struct NetStats {
+    rtt: Option<f32>, // Seconds
+    pending_pings: VecDeque<(u32, Instant)>,
+    ping_results: VecDeque<bool>, // Recent pings, true if answered
+    bytes_sent: usize,            // So far in the current window
+    bytes_received: usize,
+    window_start: Instant,
+    upload: f32, // Bytes per second over the last window
+    download: f32,
+}
+
+impl Default for NetStats {
+    fn default() -> Self {
+        Self {
+            rtt: None,
+            pending_pings: VecDeque::new(),
+            ping_results: VecDeque::new(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            window_start: Instant::now(),
+            upload: 0.0,
+            download: 0.0,
+        }
+    }
+}
+
+impl NetStats {
+    pub fn record_sent(&mut self, bytes: usize) {
+        self.bytes_sent += bytes;
+    }
+
+    pub fn record_received(&mut self, bytes: usize) {
+        self.bytes_received += bytes;
+    }
+
+    pub fn ping_sent(&mut self, sequence: u32) {
+        self.pending_pings.push_back((sequence, Instant::now()));
+    }
+
+    pub fn pong_received(&mut self, sequence: u32) {
+        let position = match self.pending_pings.iter().position(|&(s, _)| s == sequence) {
+            Some(position) => position,
+            None => return, // Already timed out
+        };
+        let (_, sent) = self.pending_pings.remove(position).unwrap();
+
+        let sample = sent.elapsed().as_secs_f32();
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt + (sample - rtt) * RTT_SMOOTHING,
+            None => sample,
+        });
+        self.push_result(true);
+    }
+
+    fn push_result(&mut self, answered: bool) {
+        self.ping_results.push_back(answered);
+        if self.ping_results.len() > LOSS_SAMPLES {
+            self.ping_results.pop_front();
+        }
+    }
+
+    // Expires old pings and rolls the bandwidth window over
+    pub fn update(&mut self) {
+        while let Some(&(_, sent)) = self.pending_pings.front() {
+            if sent.elapsed() < PING_TIMEOUT {
+                break;
+            }
+            self.pending_pings.pop_front();
+            self.push_result(false);
+        }
+
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= BANDWIDTH_WINDOW {
+            self.upload = self.bytes_sent as f32 / elapsed.as_secs_f32();
+            self.download = self.bytes_received as f32 / elapsed.as_secs_f32();
+            self.bytes_sent = 0;
+            self.bytes_received = 0;
+            self.window_start = Instant::now();
+        }
+    }
+
+    // Milliseconds, None until the first pong
+    pub fn ping(&self) -> Option<u32> {
+        self.rtt.map(|rtt| (rtt * 1000.0) as u32)
+    }
+
+    // Percentage of recent pings that were lost
+    pub fn loss(&self) -> f32 {
+        if self.ping_results.is_empty() {
+            return 0.0;
+        }
+
+        let lost = self
+            .ping_results
+            .iter()
+            .filter(|&&answered| !answered)
+            .count();
+        lost as f32 / self.ping_results.len() as f32 * 100.0
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "ping {}  loss {:.0}%  up {:.1} KB/s  down {:.1} KB/s",
+            self.ping()
+                .map_or(String::from("?"), |ping| format!("{} ms", ping)),
+            self.loss(),
+            self.upload / 1024.0,
+            self.download / 1024.0
+        )
+    }
+}
@@ -3,95 +3,669 @@ use crossbeam_channel::{Receiver, Sender};
 use laminar::{Config, ErrorKind, Packet, Socket, SocketEvent, VirtualConnection};
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
     convert::TryInto,
-    net::{SocketAddr, ToSocketAddrs},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
+use tracing::{debug, error, info, warn};
 
+#[cfg(feature = "server")]
 use crate::{
-    game::{Game, GameEvent},
+    bans::{Ban, BanList},
+    match_state::MatchPhase,
+    metrics::Metrics,
+    relevancy::Relevancy,
+    send_budget::SendBudget,
+    snapshot::{PlayerSnapshot, WorldSnapshot},
+    validation::InputValidator,
+};
+use crate::{
+    chat::{FloodGuard, MAX_CHAT_LENGTH},
+    compression::{compress, decompress, MAX_MESSAGE_SIZE},
+    error::GameError,
+    framing::{frame, unframe, MAX_FRAME_SIZE},
+    game::{Game, GameEvent, PlayerScore},
     level::LevelState,
-    player::Player,
-    player_event::{PlayerEvent, SerializablePlayerState, SerializableVector},
-    GameEngine,
+    net_stats::{NetStats, PING_INTERVAL},
+    player::{Player, SYNC_FREQUENCY},
+    player_event::{PlayerEvent, SerializablePlayerState},
+    secure::{self, PublicKeyBytes, Session, SessionToken},
+    skins,
+    snapshot::{SnapshotHistory, SnapshotPayload},
+    weapon, GameEngine, Settings,
+};
+
+pub const SERVER_ADDRESS: &str = "wtblife.ddns.net:12351";
+// Bump whenever anything sent over the network changes
+pub const PROTOCOL_VERSION: u32 = 35;
+pub const MAX_NAME_LENGTH: usize = 16;
+// Shown to players when versions don't match, set BREAKFLOOR_BUILD at compile time to include a commit hash
+pub const BUILD: &str = match option_env!("BREAKFLOOR_BUILD") {
+    Some(build) => build,
+    None => env!("CARGO_PKG_VERSION"),
 };
+// Delay before the first reconnect attempt, doubled after every failed attempt
+#[cfg(not(feature = "server"))]
+const RECONNECT_DELAY: f32 = 1.0;
+#[cfg(not(feature = "server"))]
+const MAX_RECONNECT_DELAY: f32 = 8.0;
+#[cfg(not(feature = "server"))]
+const MAX_RECONNECT_ATTEMPTS: u32 = 6;
+// Seconds to wait for the server to answer the handshake
+#[cfg(not(feature = "server"))]
+const CONNECT_TIMEOUT: f32 = 10.0;
+
+#[cfg(feature = "server")]
+const BANNED_REASON: &str = "You are banned from this server";
+
+const MIN_TICK_RATE: u32 = 10;
+pub const MAX_TICK_RATE: u32 = 240;
+#[cfg(feature = "server")]
+const STATS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+// Unreliable messages queued in a tick are framed into packets of about this many bytes, small
+// enough that laminar never has to split them. Reliable ones can be split so they go up to the
+// largest message size.
+const MAX_UNRELIABLE_BATCH: usize = 1024;
+
+// Simulation and snapshot rates. The server picks them and sends them to clients when they
+// connect, so prediction runs at the same speed on both ends.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TickRates {
+    pub tick_rate: u32,     // Simulation steps per second
+    pub sync_interval: u32, // Ticks between snapshots
+}
+
+impl Default for TickRates {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60,
+            sync_interval: SYNC_FREQUENCY,
+        }
+    }
+}
+
+impl TickRates {
+    // Snapshots can't go out more often than once a tick
+    pub fn new(tick_rate: u32, sync_rate: u32) -> Self {
+        let tick_rate = tick_rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE);
+        let sync_rate = sync_rate.clamp(1, tick_rate);
+
+        Self {
+            tick_rate,
+            sync_interval: (tick_rate as f32 / sync_rate as f32).round() as u32,
+        }
+    }
+
+    // Keeps rates from a misbehaving server in a range we can run at
+    pub fn clamped(self) -> Self {
+        let tick_rate = self.tick_rate.clamp(MIN_TICK_RATE, MAX_TICK_RATE);
+
+        Self {
+            tick_rate,
+            sync_interval: self.sync_interval.clamp(1, tick_rate),
+        }
+    }
+
+    pub fn timestep(&self) -> f32 {
+        1.0 / self.tick_rate as f32
+    }
+}
 
-const SERVER_ADDRESS: &str = "wtblife.ddns.net:12351";
+#[cfg(not(feature = "server"))]
+struct Reconnect {
+    attempt: u32,
+    timer: f32, // Until the next attempt
+}
 
 pub struct NetworkManager {
-    server_addr: SocketAddr,
+    server_addr: Option<SocketAddr>,
     net_sender: Sender<Packet>,
     net_receiver: Receiver<SocketEvent>,
+    polling: Arc<AtomicBool>, // Cleared to stop the socket thread
     connections: Vec<PlayerConnection>,
     highest_player_index: u32,
     pub player_index: Option<u32>, // TODO: Should this be in game module or here? It is here because it's easier
+    #[cfg(feature = "server")]
+    snapshot_sequence: u32,
+    // Time left in the match phase, kept up to date by the game and sent with every snapshot so
+    // client countdowns don't drift
+    #[cfg(feature = "server")]
+    pub match_clock: f32,
+    #[cfg(not(feature = "server"))]
+    snapshot_history: SnapshotHistory, // Snapshots received from the server
+    #[cfg(feature = "server")]
+    relevancy: Relevancy,
+    // Per peer, the server on clients and every player on the server. In a RefCell so sending
+    // doesn't need a mutable borrow.
+    stats: RefCell<HashMap<SocketAddr, NetStats>>,
+    // Encryption for each peer that finished the handshake
+    sessions: HashMap<SocketAddr, Session>,
+    #[cfg(not(feature = "server"))]
+    secret: crypto_box::SecretKey, // Half of the key exchange, new for every server joined
+    // Messages waiting for the end of the tick, already serialized
+    outbox: HashMap<(SocketAddr, Stream, Delivery), Outgoing>,
+    // Per player, spent as packets go out. Also in a RefCell for sending without a mutable borrow.
+    #[cfg(feature = "server")]
+    budgets: RefCell<HashMap<SocketAddr, SendBudget>>,
+    #[cfg(feature = "server")]
+    send_budget: u32, // Bytes per second each new player gets
+    #[cfg(feature = "server")]
+    max_players: u32, // 0 for no limit
+    #[cfg(feature = "server")]
+    password: Option<String>,
+    #[cfg(feature = "server")]
+    observer_password: Option<String>, // None turns observing off
+    #[cfg(feature = "server")]
+    bans: BanList,
+    #[cfg(feature = "server")]
+    idle_kick_time: f32, // 0 for never
+    #[cfg(feature = "server")]
+    metrics: Metrics,
+    ping_sequence: u32,
+    last_ping: Instant,
+    #[cfg(feature = "server")]
+    last_stats_log: Instant,
+    #[cfg(not(feature = "server"))]
+    reconnect: Option<Reconnect>, // Set while trying to get back to a server that timed out
+    #[cfg(not(feature = "server"))]
+    accepted: bool, // Server answered the handshake
+    #[cfg(not(feature = "server"))]
+    resuming: bool, // Moved to a new socket, until the server sends the level again
+    #[cfg(not(feature = "server"))]
+    connect_timer: f32, // Time since connecting, until accepted
+    #[cfg(not(feature = "server"))]
+    player_name: String, // Sent with Join once the server answers the handshake
+    #[cfg(not(feature = "server"))]
+    player_color: [u8; 3],
+    #[cfg(not(feature = "server"))]
+    player_skin: u32,
+    #[cfg(not(feature = "server"))]
+    player_password: String,
+    #[cfg(not(feature = "server"))]
+    observer: bool, // Joining to watch, the server never spawns a player for us
+    compress_messages: bool, // Large outgoing messages, incoming ones are always understood
 }
 
 impl NetworkManager {
-    pub fn new() -> Self {
-        let server_addr = SERVER_ADDRESS
-            .to_socket_addrs()
-            .expect("Failed to resolve server hostname")
-            .next()
-            .expect("Failed to resolve server hostname");
-
-        let config = Config {
-            heartbeat_interval: Some(Duration::from_millis(500)),
-            ..Default::default()
-        };
+    pub fn new(settings: &Settings) -> Result<Self, GameError> {
+        let config = socket_config();
 
-        let mut socket;
+        let socket;
 
         #[cfg(feature = "server")]
         {
-            socket = Socket::bind_with_config("0.0.0.0:12351", config).unwrap();
+            socket = Socket::bind_with_config(("0.0.0.0", settings.server_port), config).map_err(
+                |e| GameError::Bind {
+                    port: settings.server_port,
+                    reason: e.to_string(),
+                },
+            )?;
         }
         #[cfg(not(feature = "server"))]
         {
-            socket = Socket::bind_with_config("0.0.0.0:12352", config).unwrap();
+            // Fall back to an ephemeral port if the configured one is taken (e.g. a second client)
+            socket = Socket::bind_with_config(("0.0.0.0", settings.client_port), config.clone())
+                .or_else(|_| Socket::bind_with_config(("0.0.0.0", 0), config))
+                .map_err(|e| GameError::Bind {
+                    port: settings.client_port,
+                    reason: e.to_string(),
+                })?;
         }
 
-        let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
-
-        thread::spawn(move || socket.start_polling_with_duration(None));
+        let (sender, receiver, polling) = start_polling(socket);
 
-        #[cfg(not(feature = "server"))]
-        {
-            sender
-                .send(Packet::reliable_ordered(
-                    server_addr,
-                    serialize(&NetworkMessage::Connected).unwrap(),
-                    None,
-                ))
-                .unwrap();
-        }
-
-        Self {
-            server_addr,
+        Ok(Self {
+            server_addr: None,
             net_sender: sender,
             net_receiver: receiver,
+            polling,
             connections: Vec::new(),
             highest_player_index: 0,
             player_index: None,
+            #[cfg(feature = "server")]
+            snapshot_sequence: 0,
+            #[cfg(feature = "server")]
+            match_clock: 0.0,
+            #[cfg(not(feature = "server"))]
+            snapshot_history: SnapshotHistory::default(),
+            #[cfg(feature = "server")]
+            relevancy: Relevancy::new(0.0),
+            stats: RefCell::new(HashMap::new()),
+            sessions: HashMap::new(),
+            #[cfg(not(feature = "server"))]
+            secret: secure::generate_secret(),
+            outbox: HashMap::new(),
+            #[cfg(feature = "server")]
+            budgets: RefCell::new(HashMap::new()),
+            #[cfg(feature = "server")]
+            send_budget: 0,
+            #[cfg(feature = "server")]
+            max_players: 0,
+            #[cfg(feature = "server")]
+            password: None,
+            #[cfg(feature = "server")]
+            observer_password: None,
+            #[cfg(feature = "server")]
+            bans: BanList::load(),
+            #[cfg(feature = "server")]
+            idle_kick_time: 0.0,
+            #[cfg(feature = "server")]
+            metrics: Metrics::default(),
+            ping_sequence: 0,
+            last_ping: Instant::now(),
+            #[cfg(feature = "server")]
+            last_stats_log: Instant::now(),
+            #[cfg(not(feature = "server"))]
+            reconnect: None,
+            #[cfg(not(feature = "server"))]
+            accepted: false,
+            #[cfg(not(feature = "server"))]
+            resuming: false,
+            #[cfg(not(feature = "server"))]
+            connect_timer: 0.0,
+            #[cfg(not(feature = "server"))]
+            player_name: String::new(),
+            #[cfg(not(feature = "server"))]
+            player_color: [255, 255, 255],
+            #[cfg(not(feature = "server"))]
+            player_skin: skins::DEFAULT_SKIN,
+            #[cfg(not(feature = "server"))]
+            player_password: String::new(),
+            #[cfg(not(feature = "server"))]
+            observer: false,
+            compress_messages: settings.compress_messages,
+        })
+    }
+
+    // Resolve server address and send the first packet, the server responds once it accepts the connection
+    #[cfg(not(feature = "server"))]
+    pub fn connect(
+        &mut self,
+        address: &str,
+        player_name: &str,
+        player_color: [u8; 3],
+        player_skin: u32,
+        password: &str,
+        observer: bool,
+    ) -> Result<(), GameError> {
+        let server_addr = address
+            .to_socket_addrs()
+            .map_err(|e| GameError::Resolve {
+                address: address.to_string(),
+                reason: e.to_string(),
+            })?
+            .next()
+            .ok_or_else(|| GameError::Resolve {
+                address: address.to_string(),
+                reason: String::from("no addresses found"),
+            })?;
+
+        self.server_addr = Some(server_addr);
+        self.accepted = false;
+        self.connect_timer = 0.0;
+        self.player_name = player_name.to_string();
+        self.player_color = player_color;
+        self.player_skin = player_skin;
+        self.player_password = password.to_string();
+        self.observer = observer;
+        self.secret = secure::generate_secret();
+        self.sessions.clear();
+        self.stats.borrow_mut().clear();
+        self.stats
+            .borrow_mut()
+            .insert(server_addr, NetStats::default());
+        self.send_connect_request(server_addr);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn send_connect_request(&self, server_addr: SocketAddr) {
+        // Sent right away and unframed so servers with a different protocol can read it
+        self.send_plain(
+            server_addr,
+            &NetworkMessage::Handshake {
+                version: PROTOCOL_VERSION,
+                build: BUILD.to_string(),
+                public_key: secure::public_key(&self.secret),
+            },
+        );
+    }
+
+    #[cfg(feature = "server")]
+    fn reject_version(&mut self, address: SocketAddr, version: u32, build: &str) {
+        info!(%address, version, %build, "rejected, protocol mismatch");
+        self.send_to_address_reliably(
+            address,
+            &NetworkMessage::Rejected {
+                reason: format!(
+                    "Version mismatch: server is build {} (protocol {}), you have build {} (protocol {})",
+                    BUILD, PROTOCOL_VERSION, build, version
+                ),
+            },
+        );
+    }
+
+    // Skips the outbox and encryption, only for the handshake and the answer to it
+    fn send_plain(&self, address: SocketAddr, message: &NetworkMessage) {
+        self.send_packet(Packet::reliable_ordered(
+            address,
+            self.pack(serialize(message).unwrap()),
+            message.stream().id(),
+        ));
+    }
+
+    // Retries the connection with backoff after the server timed out. The server treats the
+    // client as a new player so it goes through the normal join, the level state and player list
+    // are sent again and the level is reloaded.
+    #[cfg(not(feature = "server"))]
+    pub fn update(&mut self, dt: f32, game: &mut Game) {
+        // Laminar keeps sending the handshake forever if nothing answers
+        if let (Some(server_addr), false) = (self.server_addr, self.accepted) {
+            self.connect_timer += dt;
+            if self.connect_timer >= CONNECT_TIMEOUT {
+                warn!(address = %server_addr, "timed out connecting");
+                self.server_addr = None;
+                game.disconnect_reason = Some(format!("Timed out connecting to {}", server_addr));
+                game.queue_event(GameEvent::Disconnected);
+                return;
+            }
+        }
+
+        let (reconnect, server_addr) = match (&mut self.reconnect, self.server_addr) {
+            (Some(reconnect), Some(server_addr)) => (reconnect, server_addr),
+            _ => return,
+        };
+
+        reconnect.timer -= dt;
+        if reconnect.timer > 0.0 {
+            return;
+        }
+
+        if reconnect.attempt >= MAX_RECONNECT_ATTEMPTS {
+            warn!(address = %server_addr, "failed to reconnect");
+            self.reconnect = None;
+            game.disconnect_reason = Some(String::from("Lost connection to the server"));
+            game.queue_event(GameEvent::Disconnected);
+            return;
+        }
+
+        reconnect.timer =
+            (RECONNECT_DELAY * 2f32.powi(reconnect.attempt as i32)).min(MAX_RECONNECT_DELAY);
+        reconnect.attempt += 1;
+        game.queue_event(GameEvent::Reconnecting {
+            attempt: reconnect.attempt,
+        });
+
+        self.send_connect_request(server_addr);
+    }
+
+    // Tells the server we're leaving and forgets everything about the session. Laminar keeps its
+    // side of the connection, the server treats the next Connected message as a new player.
+    #[cfg(not(feature = "server"))]
+    pub fn disconnect(&mut self) {
+        if self.server_addr.is_some() {
+            self.send_to_server_reliably(&NetworkMessage::Disconnected);
+            self.flush();
+        }
+        self.sessions.clear();
+
+        self.server_addr = None;
+        self.reconnect = None;
+        self.accepted = false;
+        self.stats.borrow_mut().clear();
+        self.reset_session();
+    }
+
+    // Index and snapshots belong to one session on the server
+    #[cfg(not(feature = "server"))]
+    fn reset_session(&mut self) {
+        self.player_index = None;
+        self.snapshot_history = SnapshotHistory::default();
+        self.resuming = false;
+    }
+
+    // Laminar keeps what it knows about a connection by address on both ends, so once the server
+    // has moved us to a new one the only way to start that over is a new socket. Anything still
+    // in flight on the old one is lost, the server sends the level and players again after Resume.
+    #[cfg(not(feature = "server"))]
+    fn resume(&mut self) -> Result<(), GameError> {
+        let socket = Socket::bind_with_config(("0.0.0.0", 0), socket_config()).map_err(|e| {
+            GameError::Bind {
+                port: 0,
+                reason: e.to_string(),
+            }
+        })?;
+
+        self.polling.store(false, Ordering::Relaxed);
+        let (sender, receiver, polling) = start_polling(socket);
+        self.net_sender = sender;
+        self.net_receiver = receiver;
+        self.polling = polling;
+
+        self.resuming = true;
+        self.snapshot_history = SnapshotHistory::default();
+        self.send_to_server_reliably(&NetworkMessage::Resume);
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.server_addr.is_some()
+    }
+
+    // Connected and let in by the server, as opposed to still waiting on the handshake
+    #[cfg(not(feature = "server"))]
+    pub fn is_accepted(&self) -> bool {
+        self.server_addr.is_some() && self.accepted
+    }
+
+    // Shown in the network overlay
+    #[cfg(not(feature = "server"))]
+    pub fn server_stats(&self) -> Option<String> {
+        let stats = self.stats.borrow();
+        self.server_addr
+            .and_then(|address| stats.get(&address))
+            .map(|stats| stats.summary())
+    }
+
+    // Packets waiting on either side of the socket thread, for the debug overlay
+    #[cfg(not(feature = "server"))]
+    pub fn queue_depths(&self) -> String {
+        format!(
+            "socket queue in {}  out {}",
+            self.net_receiver.len(),
+            self.net_sender.len()
+        )
+    }
+
+    fn pack(&self, bytes: Vec<u8>) -> Vec<u8> {
+        if self.compress_messages {
+            compress(bytes)
+        } else {
+            bytes
+        }
+    }
+
+    fn send_packet(&self, packet: Packet) {
+        if let Some(stats) = self.stats.borrow_mut().get_mut(&packet.addr()) {
+            stats.record_sent(packet.payload().len());
+        }
+        #[cfg(feature = "server")]
+        if let Some(budget) = self.budgets.borrow_mut().get_mut(&packet.addr()) {
+            budget.spend(packet.payload().len());
+        }
+        #[cfg(feature = "server")]
+        self.metrics.packet_sent(packet.payload().len());
+        // Only fails if the socket thread is gone, nothing gets through after that anyway
+        if self.net_sender.send(packet).is_err() {
+            error!("failed to send packet, socket closed");
+        }
+    }
+
+    // Pings every peer now and then and keeps the numbers up to date
+    fn update_stats(&mut self) {
+        if self.last_ping.elapsed() >= PING_INTERVAL {
+            self.last_ping = Instant::now();
+            self.ping_sequence += 1;
+
+            let addresses: Vec<SocketAddr> = self.stats.borrow().keys().copied().collect();
+            for address in addresses {
+                if let Some(stats) = self.stats.borrow_mut().get_mut(&address) {
+                    stats.ping_sent(self.ping_sequence);
+                }
+                self.send_to_address_unreliably(
+                    address,
+                    &NetworkMessage::Ping {
+                        sequence: self.ping_sequence,
+                    },
+                    0,
+                );
+            }
+        }
+
+        for stats in self.stats.borrow_mut().values_mut() {
+            stats.update();
+        }
+
+        #[cfg(feature = "server")]
+        if self.last_stats_log.elapsed() >= STATS_LOG_INTERVAL && !self.connections.is_empty() {
+            self.last_stats_log = Instant::now();
+
+            let stats = self.stats.borrow();
+            for connection in self.connections.iter() {
+                if let Some(stats) = stats.get(&connection.socket_addr) {
+                    info!(
+                        player = %connection.name,
+                        address = %connection.socket_addr,
+                        "{}",
+                        stats.summary()
+                    );
+                }
+                if let Some(budget) = self.budgets.borrow_mut().get_mut(&connection.socket_addr) {
+                    let skipped = budget.take_skipped();
+                    if skipped > 0 {
+                        info!(
+                            player = %connection.name,
+                            address = %connection.socket_addr,
+                            skipped,
+                            "over budget, skipped state updates"
+                        );
+                    }
+                }
+            }
         }
     }
 
     pub fn handle_events(&mut self, engine: &mut GameEngine, game: &mut Game) {
+        self.update_stats();
+
+        #[cfg(feature = "server")]
+        for connection in self.connections.iter_mut() {
+            connection.validator.start_tick();
+        }
+
         while let Ok(event) = self.net_receiver.try_recv() {
             match event {
                 // TODO: Maybe have this call handle_server_events and handle_client_events to make code easier to follow
                 SocketEvent::Packet(packet) => {
+                    if let Some(stats) = self.stats.borrow_mut().get_mut(&packet.addr()) {
+                        stats.record_received(packet.payload().len());
+                    }
+                    #[cfg(feature = "server")]
+                    self.metrics.packet_received(packet.payload().len());
+
+                    let (payload, sealed) = match self.open(packet.addr(), packet.payload()) {
+                        Some(opened) => opened,
+                        None => continue,
+                    };
+
+                    // Anything readable from the server means it has taken us back
+                    #[cfg(not(feature = "server"))]
+                    if self.reconnect.is_some() && Some(packet.addr()) == self.server_addr {
+                        info!(address = %packet.addr(), "reconnected");
+                        self.reconnect = None;
+                        self.reset_session();
+                    }
+
                     let bincode = DefaultOptions::new()
                         .with_fixint_encoding()
                         .allow_trailing_bytes()
-                        .with_limit(1024);
+                        .with_limit(MAX_MESSAGE_SIZE as u64);
 
-                    if let Ok(message) =
-                        &mut bincode.deserialize::<NetworkMessage>(packet.payload())
-                    {
+                    // Either end may compress, so anything compressed is unpacked regardless
+                    let payload = match decompress(&payload) {
+                        Some(payload) => payload,
+                        None => continue,
+                    };
+
+                    // Messages sent in the same tick arrive framed together
+                    let frames = match unframe(&payload) {
+                        Some(frames) => frames,
+                        None => continue,
+                    };
+                    let mut messages: Vec<NetworkMessage> = Vec::new();
+                    for bytes in frames {
+                        match bincode.deserialize(bytes) {
+                            Ok(message) => messages.push(message),
+                            // Handshakes from other builds may not decode in full, the version at
+                            // the start is enough to tell them why they can't join
+                            #[cfg(feature = "server")]
+                            Err(_) if !sealed => {
+                                if let Ok(VersionOnly::Handshake { version, build }) =
+                                    bincode.deserialize(bytes)
+                                {
+                                    if self.get_index_for_address(packet.addr()).is_none() {
+                                        self.reject_version(packet.addr(), version, &build);
+                                    }
+                                }
+                            }
+                            Err(_) => (),
+                        }
+                    }
+                    // Anyone can send plain packets, they're only good for the handshake
+                    if !sealed {
+                        messages.retain(|message| message.is_handshake());
+                    }
+
+                    for message in messages.iter_mut() {
                         match message {
+                            // Only peers get an answer so strangers can't use us to bounce traffic
+                            NetworkMessage::Ping { sequence } => {
+                                if self.stats.borrow().contains_key(&packet.addr()) {
+                                    self.send_to_address_unreliably(
+                                        packet.addr(),
+                                        &NetworkMessage::Pong {
+                                            sequence: *sequence,
+                                        },
+                                        0,
+                                    );
+                                }
+                            }
+                            NetworkMessage::Pong { sequence } => {
+                                if let Some(stats) = self.stats.borrow_mut().get_mut(&packet.addr())
+                                {
+                                    stats.pong_received(*sequence);
+                                }
+                            }
                             NetworkMessage::PlayerEvent { index, event } => {
+                                // Observers have nothing to control
+                                #[cfg(feature = "server")]
+                                if self.is_observer(packet.addr())
+                                    || !self
+                                        .validator(packet.addr())
+                                        .map_or(false, |validator| validator.check_event(event))
+                                {
+                                    continue;
+                                }
+
                                 if let Some(level) = &mut game.level {
                                     match event {
                                         PlayerEvent::ShootWeapon {
@@ -110,8 +684,17 @@ impl NetworkManager {
                                                 if let Some(player) =
                                                     level.get_player_by_index(net_index)
                                                 {
-                                                    // Validate shoot command
-                                                    if !*active || player.can_shoot() {
+                                                    // Validate shoot command, pulling the trigger faster than the weapon fires is dropped
+                                                    let fire_interval =
+                                                        player.weapon().definition().fire_interval;
+                                                    if !*active
+                                                        || (self.validator(packet.addr()).map_or(
+                                                            false,
+                                                            |validator| {
+                                                                validator.allow_shot(fire_interval)
+                                                            },
+                                                        ) && player.can_shoot())
+                                                    {
                                                         level.queue_event(*event);
                                                         self.send_to_all_reliably(message);
                                                     }
@@ -122,20 +705,14 @@ impl NetworkManager {
                                             level.queue_event(*event);
                                         }
                                         #[cfg(not(feature = "server"))]
-                                        PlayerEvent::DestroyBlock { index } => {
-                                            level.queue_event(*event);
-                                        }
-                                        #[cfg(not(feature = "server"))]
-                                        PlayerEvent::UpdateState {
-                                            timestamp,
-                                            index,
-                                            position,
-                                            velocity,
-                                            yaw,
-                                            pitch,
-                                            shoot,
-                                            fuel,
-                                        } => {
+                                        PlayerEvent::DestroyBlock { .. }
+                                        | PlayerEvent::CrackBlock { .. }
+                                        | PlayerEvent::SpawnFallingBlock { .. }
+                                        | PlayerEvent::SpawnProjectile { .. }
+                                        | PlayerEvent::DetonateProjectile { .. }
+                                        | PlayerEvent::ApplyImpulse { .. }
+                                        | PlayerEvent::HitConfirmed { .. }
+                                        | PlayerEvent::PickupItem { .. } => {
                                             level.queue_event(*event);
                                         }
                                         // Handles all client predicted events (move events, etc) and player spawn. TODO: Player spawn should be reliable
@@ -143,7 +720,8 @@ impl NetworkManager {
                                         | PlayerEvent::MoveBackward { index, .. }
                                         | PlayerEvent::MoveForward { index, .. }
                                         | PlayerEvent::MoveLeft { index, .. }
-                                        | PlayerEvent::MoveRight { index, .. } => {
+                                        | PlayerEvent::MoveRight { index, .. }
+                                        | PlayerEvent::MoveAnalog { index, .. } => {
                                             // If event isn't for active player then it hasn't been applied yet. This includes server.
                                             // TODO: This check probably isn't necessary
                                             // if self
@@ -186,19 +764,70 @@ impl NetworkManager {
                                             level.queue_event(*event);
                                         }
                                         PlayerEvent::Reload { index } => {
+                                            // Relay to other players so they see the reload animation
+                                            #[cfg(feature = "server")]
+                                            if let Some(net_index) =
+                                                self.get_index_for_address(packet.addr())
+                                            {
+                                                *index = net_index;
+                                                self.send_to_all_except_address_reliably(
+                                                    packet.addr(),
+                                                    message,
+                                                );
+                                            }
+
+                                            level.queue_event(*event);
+                                        }
+                                        // Other clients only need to see the grenade spawn
+                                        #[cfg(feature = "server")]
+                                        PlayerEvent::ThrowGrenade { index } => {
+                                            if let Some(net_index) =
+                                                self.get_index_for_address(packet.addr())
+                                            {
+                                                *index = net_index;
+                                                level.queue_event(*event);
+                                            }
+                                        }
+                                        PlayerEvent::SwitchWeapon { index, slot } => {
+                                            #[cfg(feature = "server")]
+                                            if let Some(net_index) =
+                                                self.get_index_for_address(packet.addr())
+                                            {
+                                                *index = net_index;
+
+                                                if weapon::is_valid_slot(*slot) {
+                                                    level.queue_event(*event);
+                                                    self.send_to_all_except_address_reliably(
+                                                        packet.addr(),
+                                                        message,
+                                                    );
+                                                }
+                                            }
+
+                                            #[cfg(not(feature = "server"))]
+                                            level.queue_event(*event);
+                                        }
+                                        PlayerEvent::Crouch { index, .. } => {
                                             #[cfg(feature = "server")]
                                             if let Some(net_index) =
                                                 self.get_index_for_address(packet.addr())
                                             {
                                                 *index = net_index;
+                                                level.queue_event(*event);
+                                                self.send_to_all_except_address_unreliably(
+                                                    packet.addr(),
+                                                    message,
+                                                    0,
+                                                );
                                             }
 
+                                            #[cfg(not(feature = "server"))]
                                             level.queue_event(*event);
                                         }
-                                        PlayerEvent::Fly {
+                                        PlayerEvent::Sprint {
                                             index,
                                             active,
-                                            fuel,
+                                            stamina,
                                         } => {
                                             #[cfg(feature = "server")]
                                             if let Some(net_index) =
@@ -208,10 +837,10 @@ impl NetworkManager {
                                                     level.get_player_by_index(net_index)
                                                 {
                                                     *index = net_index;
-                                                    *fuel = player.flight_fuel;
+                                                    *stamina = player.stamina;
 
-                                                    // Validate fly command
-                                                    if !*active || player.has_fuel() {
+                                                    // Validate sprint command
+                                                    if !*active || player.has_stamina() {
                                                         level.queue_event(*event);
                                                         self.send_to_all_except_address_unreliably(
                                                             packet.addr(),
@@ -225,15 +854,68 @@ impl NetworkManager {
                                             #[cfg(not(feature = "server"))]
                                             level.queue_event(*event);
                                         }
+                                        PlayerEvent::Fly { index, active } => {
+                                            #[cfg(feature = "server")]
+                                            if let Some(net_index) =
+                                                self.get_index_for_address(packet.addr())
+                                            {
+                                                if let Some(player) =
+                                                    level.get_player_by_index(net_index)
+                                                {
+                                                    *index = net_index;
+
+                                                    // Can't take off on an empty tank, the client
+                                                    // is told to stop so its prediction matches
+                                                    if !*active || player.has_fuel() {
+                                                        level.queue_event(*event);
+                                                        self.send_to_all_except_address_reliably(
+                                                            packet.addr(),
+                                                            message,
+                                                        );
+                                                    } else {
+                                                        let event = PlayerEvent::Fly {
+                                                            index: net_index,
+                                                            active: false,
+                                                        };
+                                                        self.send_to_address_reliably(
+                                                            packet.addr(),
+                                                            &NetworkMessage::PlayerEvent {
+                                                                index: net_index,
+                                                                event,
+                                                            },
+                                                        );
+                                                    }
+                                                }
+                                            }
+
+                                            #[cfg(not(feature = "server"))]
+                                            level.queue_event(*event);
+                                        }
+                                        PlayerEvent::Aim { index, .. } => {
+                                            #[cfg(feature = "server")]
+                                            if let Some(net_index) =
+                                                self.get_index_for_address(packet.addr())
+                                            {
+                                                *index = net_index;
+                                                level.queue_event(*event);
+                                                self.send_to_all_except_address_reliably(
+                                                    packet.addr(),
+                                                    message,
+                                                );
+                                            }
+
+                                            #[cfg(not(feature = "server"))]
+                                            level.queue_event(*event);
+                                        }
                                         #[cfg(not(feature = "server"))]
-                                        PlayerEvent::KillPlayer { index } => {
+                                        PlayerEvent::KillPlayer { .. } => {
                                             level.queue_event(*event);
                                         }
-                                        PlayerEvent::SpawnPlayer {
-                                            state,
-                                            index,
-                                            current_player,
-                                        } => {
+                                        #[cfg(not(feature = "server"))]
+                                        PlayerEvent::Damage { .. } => {
+                                            level.queue_event(*event);
+                                        }
+                                        PlayerEvent::SpawnPlayer { .. } => {
                                             level.queue_event(*event);
                                         }
                                         _ => (),
@@ -249,32 +931,35 @@ impl NetworkManager {
                                             if let Some(index) =
                                                 self.get_index_for_address(packet.addr())
                                             {
+                                                // Back after Resume, their player never left
+                                                let rejoining = self
+                                                    .connections
+                                                    .iter_mut()
+                                                    .find(|c| c.socket_addr == packet.addr())
+                                                    .map_or(false, |c| {
+                                                        std::mem::take(&mut c.rejoining)
+                                                    });
+
                                                 // Send events to spawn existing players for player that joined
                                                 for player in level.players().iter() {
                                                     let scene = &mut engine.scenes[level.scene];
-                                                    let position = player.get_position(scene);
-                                                    let velocity = player.get_velocity(scene);
                                                     let message = NetworkMessage::PlayerEvent {
                                                         index: player.index,
                                                         event: PlayerEvent::SpawnPlayer {
                                                             index: player.index,
                                                             state: SerializablePlayerState {
-                                                                position: SerializableVector {
-                                                                    x: position.x,
-                                                                    y: position.y,
-                                                                    z: position.z,
-                                                                },
-                                                                velocity: SerializableVector {
-                                                                    x: velocity.x,
-                                                                    y: velocity.y,
-                                                                    z: velocity.z,
-                                                                },
+                                                                position: player
+                                                                    .get_position(scene),
+                                                                velocity: player
+                                                                    .get_velocity(scene),
                                                                 yaw: player.get_yaw(),
                                                                 pitch: player.get_pitch(),
                                                                 shoot: player.controller.shoot,
                                                                 fuel: player.flight_fuel,
                                                             },
-                                                            current_player: false,
+                                                            current_player: rejoining
+                                                                && player.index == index,
+                                                            skin: player.skin,
                                                         },
                                                     };
 
@@ -284,208 +969,727 @@ impl NetworkManager {
                                                     );
                                                 }
 
-                                                // Send spawn player event to all other players
-                                                let position = SerializableVector {
-                                                    x: 5.0 * (-1.0f32).powi(index as i32),
-                                                    y: 3.0,
-                                                    z: 1.0,
-                                                };
-                                                let event = PlayerEvent::SpawnPlayer {
-                                                    index: index,
-                                                    state: SerializablePlayerState {
-                                                        position: position,
-                                                        ..Default::default()
-                                                    },
-                                                    current_player: false,
-                                                };
-                                                level.queue_event(event);
-                                                self.send_to_all_except_address_reliably(
-                                                    packet.addr(),
-                                                    &NetworkMessage::PlayerEvent {
+                                                // Observers get everything that's going on
+                                                // but never a player of their own
+                                                let observer = self.is_observer(packet.addr());
+                                                if rejoining {
+                                                    self.send_to_address_reliably(
+                                                        packet.addr(),
+                                                        &NetworkMessage::ScoreUpdate {
+                                                            scores: game.scores.clone(),
+                                                        },
+                                                    );
+                                                } else if !observer {
+                                                    // Send spawn player event to all other players
+                                                    let position = level.pick_spawn_point(
+                                                        &engine.scenes[level.scene],
+                                                        index,
+                                                    );
+                                                    let skin = self
+                                                        .connections
+                                                        .iter()
+                                                        .find(|c| c.socket_addr == packet.addr())
+                                                        .map_or(skins::DEFAULT_SKIN, |c| c.skin);
+                                                    let event = PlayerEvent::SpawnPlayer {
                                                         index: index,
-                                                        event: event,
-                                                    },
-                                                );
+                                                        state: SerializablePlayerState {
+                                                            position: position,
+                                                            ..Default::default()
+                                                        },
+                                                        current_player: false,
+                                                        skin,
+                                                    };
+                                                    level.queue_event(event);
+                                                    self.send_to_all_except_address_reliably(
+                                                        packet.addr(),
+                                                        &NetworkMessage::PlayerEvent {
+                                                            index: index,
+                                                            event: event,
+                                                        },
+                                                    );
 
-                                                // Send spawn player event to player (with current player true for setting camera)
-                                                let event = PlayerEvent::SpawnPlayer {
-                                                    index: index,
-                                                    state: SerializablePlayerState {
-                                                        position: position,
-                                                        ..Default::default()
-                                                    },
-                                                    current_player: true,
-                                                };
-                                                self.send_to_address_reliably(
-                                                    packet.addr(),
-                                                    &NetworkMessage::PlayerEvent {
+                                                    // Send spawn player event to player (with current player true for setting camera)
+                                                    let event = PlayerEvent::SpawnPlayer {
                                                         index: index,
-                                                        event: event,
-                                                    },
-                                                );
-
-                                                println!("player joined: {}", index);
-                                            }
+                                                        state: SerializablePlayerState {
+                                                            position: position,
+                                                            ..Default::default()
+                                                        },
+                                                        current_player: true,
+                                                        skin,
+                                                    };
+                                                    self.send_to_address_reliably(
+                                                        packet.addr(),
+                                                        &NetworkMessage::PlayerEvent {
+                                                            index: index,
+                                                            event: event,
+                                                        },
+                                                    );
+
+                                                    // Add an empty score entry so the player shows up on scoreboards
+                                                    game.scores
+                                                        .retain(|score| score.index != index);
+                                                    let connection = self
+                                                        .connections
+                                                        .iter()
+                                                        .find(|c| c.socket_addr == packet.addr());
+                                                    game.scores.push(PlayerScore {
+                                                        index,
+                                                        name: connection
+                                                            .map(|c| c.name.clone())
+                                                            .unwrap_or_default(),
+                                                        color: connection.map(|c| c.color),
+                                                        ..Default::default()
+                                                    });
+                                                    self.send_to_all_reliably(
+                                                        &NetworkMessage::ScoreUpdate {
+                                                            scores: game.scores.clone(),
+                                                        },
+                                                    );
+                                                }
+
+                                                // Let the player know what part of the match they joined in
+                                                self.send_to_address_reliably(
+                                                    packet.addr(),
+                                                    &NetworkMessage::GameEvent {
+                                                        event: GameEvent::MatchPhaseChanged {
+                                                            phase: game.match_state.phase(),
+                                                            time_left: game.match_state.time_left(),
+                                                        },
+                                                    },
+                                                );
+                                                if let (
+                                                    MatchPhase::RoundEnd { winner },
+                                                    Some(stats),
+                                                ) =
+                                                    (game.match_state.phase(), &game.match_summary)
+                                                {
+                                                    self.send_to_address_reliably(
+                                                        packet.addr(),
+                                                        &NetworkMessage::GameEvent {
+                                                            event: GameEvent::MatchSummary {
+                                                                winner,
+                                                                stats: stats.clone(),
+                                                            },
+                                                        },
+                                                    );
+                                                }
+
+                                                if rejoining {
+                                                    info!(index, address = %packet.addr(), "rejoined");
+                                                } else if observer {
+                                                    info!(index, address = %packet.addr(), "observer joined");
+                                                } else {
+                                                    info!(index, address = %packet.addr(), "player joined");
+                                                }
+                                            }
                                         }
                                     }
+                                    // The server's answer to Resume
+                                    #[cfg(not(feature = "server"))]
+                                    GameEvent::LoadLevel { .. } => self.resuming = false,
                                     _ => (),
                                 }
 
+                                // Console commands and match flow only come from the server itself
+                                #[cfg(feature = "server")]
+                                if !matches!(event, GameEvent::Joined) {
+                                    continue;
+                                }
+
                                 game.queue_event(event.clone());
                             }
                             #[cfg(feature = "server")]
-                            NetworkMessage::Connected => {
-                                // Respond to connected (first) packet so client can connect.
-                                self.net_sender
-                                    .send(Packet::reliable_ordered(
+                            NetworkMessage::Chat { index, text } => {
+                                if let Some(connection) = self
+                                    .connections
+                                    .iter_mut()
+                                    .find(|c| c.socket_addr == packet.addr())
+                                {
+                                    // Use index from connection so players can't speak for others
+                                    *index = connection.player_index;
+                                    *text = text.trim().chars().take(MAX_CHAT_LENGTH).collect();
+
+                                    if !text.is_empty() && connection.chat_flood_guard.allow() {
+                                        self.send_to_all_reliably(message);
+                                    }
+                                }
+                            }
+                            #[cfg(feature = "server")]
+                            NetworkMessage::MapVote { index, choice } => {
+                                if self.is_observer(packet.addr()) {
+                                    continue;
+                                }
+
+                                if let Some(net_index) = self.get_index_for_address(packet.addr()) {
+                                    *index = net_index;
+
+                                    if game.rotation.cast_vote(*index, *choice) {
+                                        self.send_to_all_reliably(&NetworkMessage::GameEvent {
+                                            event: GameEvent::MapVotesChanged {
+                                                votes: game.rotation.tally(),
+                                            },
+                                        });
+                                    }
+                                }
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::Chat { index, text } => {
+                                game.queue_event(GameEvent::Chat {
+                                    index: *index,
+                                    text: text.clone(),
+                                });
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::ScoreUpdate { scores } => {
+                                game.scores = scores.clone();
+                                game.queue_event(GameEvent::ScoresChanged);
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::Snapshot {
+                                payload,
+                                match_clock,
+                            } => {
+                                game.sync_match_clock(*match_clock);
+
+                                if let Some(snapshot) = payload.decode(&self.snapshot_history) {
+                                    self.send_to_server_unreliably(
+                                        &NetworkMessage::SnapshotAck {
+                                            sequence: snapshot.sequence,
+                                        },
+                                        0,
+                                    );
+
+                                    if let Some(level) = &mut game.level {
+                                        for player in snapshot.players.iter() {
+                                            level.queue_event(
+                                                player.to_update_event(snapshot.timestamp),
+                                            );
+                                        }
+                                    }
+
+                                    self.snapshot_history.push(snapshot);
+                                }
+                            }
+                            #[cfg(feature = "server")]
+                            NetworkMessage::SnapshotAck { sequence } => {
+                                if let Some(connection) = self
+                                    .connections
+                                    .iter_mut()
+                                    .find(|c| c.socket_addr == packet.addr())
+                                {
+                                    // Acks are unreliable so an older one can arrive late
+                                    if connection
+                                        .last_acked_snapshot
+                                        .map_or(true, |acked| *sequence > acked)
+                                    {
+                                        connection.last_acked_snapshot = Some(*sequence);
+                                    }
+                                }
+                            }
+                            // First packet from a client, answered with the keys for the session if their version checks out
+                            #[cfg(feature = "server")]
+                            NetworkMessage::Handshake {
+                                version,
+                                build,
+                                public_key,
+                            } => {
+                                // Players already in keep their session, anyone could send a
+                                // handshake from their address
+                                if self.get_index_for_address(packet.addr()).is_some() {
+                                    continue;
+                                }
+
+                                if *version == PROTOCOL_VERSION {
+                                    let secret = secure::generate_secret();
+                                    let token = secure::generate_token();
+                                    self.send_plain(
+                                        packet.addr(),
+                                        &NetworkMessage::Connected {
+                                            rates: game.rates,
+                                            public_key: secure::public_key(&secret),
+                                            token,
+                                        },
+                                    );
+                                    self.sessions.insert(
+                                        packet.addr(),
+                                        Session::new(&secret, *public_key, token),
+                                    );
+                                } else {
+                                    self.reject_version(packet.addr(), *version, build);
+                                }
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::Connected {
+                                rates,
+                                public_key,
+                                token,
+                            } => {
+                                if Some(packet.addr()) == self.server_addr {
+                                    self.sessions.insert(
+                                        packet.addr(),
+                                        Session::new(&self.secret, *public_key, *token),
+                                    );
+                                    self.accepted = true;
+                                    info!(
+                                        "server runs at {} ticks per second, snapshot every {} ticks",
+                                        rates.tick_rate, rates.sync_interval
+                                    );
+                                    game.rates = rates.clamped();
+                                    // Sealed, unlike the handshake, so the password isn't sent in the clear
+                                    self.send_to_server_reliably(&NetworkMessage::Join {
+                                        name: self.player_name.clone(),
+                                        color: self.player_color,
+                                        skin: self.player_skin,
+                                        password: self.player_password.clone(),
+                                        observer: self.observer,
+                                    });
+                                }
+                            }
+                            // Players are only added once the server has room for them and the
+                            // password checks out
+                            #[cfg(feature = "server")]
+                            NetworkMessage::Join {
+                                name,
+                                color,
+                                skin,
+                                password,
+                                observer,
+                            } => {
+                                if self.get_index_for_address(packet.addr()).is_some() {
+                                    continue;
+                                }
+
+                                match self.join_refusal(packet.addr(), password, *observer) {
+                                    Some(reason) => {
+                                        info!(address = %packet.addr(), %reason, "rejected");
+                                        self.send_to_address_reliably(
+                                            packet.addr(),
+                                            &NetworkMessage::Rejected { reason },
+                                        );
+                                    }
+                                    None => self.add_connection(
+                                        game,
+                                        packet.addr(),
+                                        name,
+                                        *color,
+                                        *skin,
+                                        *observer,
+                                    ),
+                                }
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::Rejected { reason } => {
+                                if Some(packet.addr()) == self.server_addr {
+                                    warn!("rejected by server: {}", reason);
+                                    self.reconnect = None;
+                                    game.disconnect_reason = Some(reason.clone());
+                                    game.queue_event(GameEvent::Disconnected);
+                                }
+                            }
+                            #[cfg(not(feature = "server"))]
+                            NetworkMessage::Moved => {
+                                if Some(packet.addr()) == self.server_addr && !self.resuming {
+                                    match self.resume() {
+                                        Ok(()) => info!("moved by the server, resuming"),
+                                        Err(e) => warn!("couldn't resume: {}", e),
+                                    }
+                                }
+                            }
+                            // A moved client on its fresh socket. It reloads the level and joins
+                            // again to pick up whatever was lost with the old one.
+                            #[cfg(feature = "server")]
+                            NetworkMessage::Resume => {
+                                let mut resumed = false;
+                                if let Some(connection) = self
+                                    .connections
+                                    .iter_mut()
+                                    .find(|c| c.socket_addr == packet.addr())
+                                {
+                                    connection.moved = false;
+                                    connection.rejoining = true;
+                                    // The client starts its snapshot history over
+                                    connection.last_acked_snapshot = None;
+                                    connection.snapshot_history = SnapshotHistory::default();
+                                    resumed = true;
+                                }
+
+                                if let (true, Some(level)) = (resumed, &game.level) {
+                                    info!(address = %packet.addr(), "resumed");
+                                    self.send_to_address_reliably(
                                         packet.addr(),
-                                        packet.payload().to_vec(),
-                                        None,
-                                    ))
-                                    .unwrap();
+                                        &NetworkMessage::GameEvent {
+                                            event: GameEvent::LoadLevel {
+                                                level: level.name.clone(),
+                                                state: level.state.clone(),
+                                            },
+                                        },
+                                    );
+                                }
+                            }
+                            // Client left on purpose, don't wait for the connection to time out
+                            #[cfg(feature = "server")]
+                            NetworkMessage::Disconnected => {
+                                self.remove_connection(engine, game, packet.addr());
+                                info!(address = %packet.addr(), "left");
                             }
                             _ => {}
                         }
                     }
+
+                    // Until they come back on a fresh socket
+                    #[cfg(feature = "server")]
+                    if self
+                        .connections
+                        .iter()
+                        .any(|c| c.socket_addr == packet.addr() && c.moved)
+                    {
+                        self.send_moved(packet.addr());
+                    }
                 }
                 SocketEvent::Connect(address) => {
+                    // Turned away before they get anywhere, Join checks again in case it arrives first
                     #[cfg(feature = "server")]
-                    if let Some(level) = &mut game.level {
-                        // Get the highest player index OR the last player index and add 1
-                        self.highest_player_index = *self
-                            .connections
-                            .iter()
-                            .map(|connection| connection.player_index)
-                            .max()
-                            .get_or_insert(self.highest_player_index)
-                            + 1;
-
-                        self.connections.push(PlayerConnection {
-                            socket_addr: address,
-                            player_index: self.highest_player_index,
-                        });
-
-                        let reset_level = level.players().len() < 2;
-                        let state = if reset_level {
-                            LevelState {
-                                destroyed_blocks: Vec::new(),
-                            }
-                        } else {
-                            level.state.clone()
-                        };
-
-                        // Send message to load level
-                        let event = GameEvent::LoadLevel {
-                            level: level.name.clone(),
-                            state: state.clone(),
-                        };
-
-                        if reset_level {
-                            // TODO: Fix issue with event not being cloneable
-                            // TODO: Fix issue with not being able to re-borrow game
-                            game.event_sender
-                                .send(GameEvent::LoadLevel {
-                                    level: level.name.clone(),
-                                    state: state.clone(),
-                                })
-                                .unwrap();
-                        } else {
-                            self.send_to_address_reliably(
-                                address,
-                                &NetworkMessage::GameEvent { event: event },
-                            );
-                        }
+                    if self.bans.is_banned(address.ip()) {
+                        info!(%address, "rejected, banned");
+                        self.kick_address(engine, game, address, BANNED_REASON);
+                        continue;
                     }
 
                     game.queue_event(GameEvent::Connected);
 
-                    println!("{} connected", address.to_string());
-                    println!("currently connected: {:?}", self.connections);
+                    info!(%address, "connected");
+                    debug!("currently connected: {:?}", self.connections);
                 }
                 SocketEvent::Disconnect(address) => {
                     #[cfg(feature = "server")]
-                    {
-                        if let Some(level) = &mut game.level {
-                            if let Some(index) = self.get_index_for_address(address) {
-                                let event = PlayerEvent::KillPlayer { index: index };
-                                level.remove_player(engine, index);
-                                self.send_to_all_except_address_reliably(
-                                    address,
-                                    &NetworkMessage::PlayerEvent {
-                                        index: index,
-                                        event: event,
-                                    },
-                                );
-                            }
-                        }
-                        self.connections
-                            .retain(|connection| connection.socket_addr != address);
-                    }
+                    self.remove_connection(engine, game, address);
 
+                    // Only the server going away matters, not a stale connection from before. Try to
+                    // get back in before giving up on the session.
                     #[cfg(not(feature = "server"))]
-                    game.queue_event(GameEvent::Disconnected);
+                    if self.server_addr == Some(address) && self.reconnect.is_none() {
+                        self.reconnect = Some(Reconnect {
+                            attempt: 0,
+                            timer: RECONNECT_DELAY,
+                        });
+                    }
 
-                    println!("{} disconnected", address.to_string());
-                    println!("currently connected: {:?}", self.connections);
+                    info!(%address, "disconnected");
+                    debug!("currently connected: {:?}", self.connections);
                 }
                 SocketEvent::Timeout(address) => {
-                    println!("{} timed out", address.to_string());
+                    info!(%address, "timed out");
                 }
             }
         }
+
+        #[cfg(feature = "server")]
+        {
+            self.kick_cheaters(engine, game);
+            self.kick_idle(engine, game);
+        }
     }
 
-    pub fn send_to_all_except_address_reliably(
+    #[cfg(feature = "server")]
+    fn validator(&mut self, address: SocketAddr) -> Option<&mut InputValidator> {
+        self.connections
+            .iter_mut()
+            .find(|connection| connection.socket_addr == address)
+            .map(|connection| &mut connection.validator)
+    }
+
+    // Kicked clients are told why the same way as a failed handshake
+    #[cfg(feature = "server")]
+    fn kick_cheaters(&mut self, engine: &mut GameEngine, game: &mut Game) {
+        let kicked: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .filter(|connection| connection.validator.should_kick())
+            .map(|connection| connection.socket_addr)
+            .collect();
+
+        for address in kicked {
+            warn!(%address, "kicked for invalid input");
+            self.kick_address(engine, game, address, "Kicked for sending invalid input");
+        }
+    }
+
+    // Removed the same way as any other player who leaves, so everyone sees them go
+    #[cfg(feature = "server")]
+    fn kick_idle(&mut self, engine: &mut GameEngine, game: &mut Game) {
+        if self.idle_kick_time <= 0.0 {
+            return;
+        }
+
+        let kicked: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .filter(|connection| {
+                !connection.observer && connection.validator.idle_time() >= self.idle_kick_time
+            })
+            .map(|connection| connection.socket_addr)
+            .collect();
+
+        for address in kicked {
+            info!(%address, "kicked for being idle");
+            self.kick_address(engine, game, address, "Kicked for being idle");
+        }
+    }
+
+    // Returns false if nobody has that index
+    #[cfg(feature = "server")]
+    pub fn kick(
+        &mut self,
+        engine: &mut GameEngine,
+        game: &mut Game,
+        index: u32,
+        reason: &str,
+    ) -> bool {
+        match self.get_address_for_player(index) {
+            Some(address) => {
+                info!(%address, "kicked");
+                self.kick_address(engine, game, address, reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Kicks everyone on the player's IP, returns false if nobody has that index
+    #[cfg(feature = "server")]
+    pub fn ban(&mut self, engine: &mut GameEngine, game: &mut Game, index: u32) -> bool {
+        let connection = match self
+            .connections
+            .iter()
+            .find(|connection| connection.player_index == index)
+        {
+            Some(connection) => connection,
+            None => return false,
+        };
+
+        let (address, name) = (connection.socket_addr.ip(), connection.name.clone());
+        self.ban_address(engine, game, address, &name);
+        true
+    }
+
+    #[cfg(feature = "server")]
+    pub fn ban_address(
+        &mut self,
+        engine: &mut GameEngine,
+        game: &mut Game,
+        address: IpAddr,
+        name: &str,
+    ) {
+        if self.bans.ban(address, name) {
+            info!(%address, "banned");
+        } else {
+            info!(%address, "already banned");
+        }
+
+        let banned: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .map(|connection| connection.socket_addr)
+            .filter(|socket_addr| socket_addr.ip() == address)
+            .collect();
+        for socket_addr in banned {
+            info!(address = %socket_addr, "kicked");
+            self.kick_address(engine, game, socket_addr, BANNED_REASON);
+        }
+    }
+
+    // Returns false if the address wasn't banned
+    #[cfg(feature = "server")]
+    pub fn unban(&mut self, address: IpAddr) -> bool {
+        self.bans.unban(address)
+    }
+
+    #[cfg(feature = "server")]
+    pub fn bans(&self) -> &[Ban] {
+        self.bans.bans()
+    }
+
+    #[cfg(feature = "server")]
+    fn kick_address(
         &mut self,
+        engine: &mut GameEngine,
+        game: &mut Game,
         address: SocketAddr,
-        message: &NetworkMessage,
+        reason: &str,
     ) {
-        // Send to all players except one it was sent from
-        for connection in self.connections.iter() {
-            if connection.socket_addr != address {
-                // TODO: Refactor this to use our send function?
-                self.net_sender
-                    .send(Packet::reliable_ordered(
-                        connection.socket_addr,
-                        serialize(message).unwrap(),
-                        self.get_connection_stream_id(connection),
-                    ))
+        self.send_to_address_reliably(
+            address,
+            &NetworkMessage::Rejected {
+                reason: reason.to_string(),
+            },
+        );
+        // Has to go out while there's still a session to seal it with, the client ignores plain
+        // packets once it's in
+        self.flush();
+        self.remove_connection(engine, game, address);
+    }
+
+    // Sends everyone back to their menu. Nothing gets resent once the process is gone, so extra
+    // unreliable copies go out in case the reliable one is lost.
+    #[cfg(feature = "server")]
+    pub fn shutdown(&mut self, reason: &str) {
+        let message = NetworkMessage::GameEvent {
+            event: GameEvent::ServerShutdown {
+                reason: reason.to_string(),
+            },
+        };
+        self.send_to_all_reliably(&message);
+        self.send_to_all_unreliably(&message, 2);
+        self.flush();
+        // Packets go out on the socket thread, give it a moment before the process ends
+        thread::sleep(Duration::from_millis(200));
+    }
+
+    // Indices are never reused so events for a player who left can't end up with someone else.
+    // Bots take theirs from here too, in practice as well.
+    pub fn next_player_index(&mut self) -> u32 {
+        self.highest_player_index += 1;
+        self.highest_player_index
+    }
+
+    // Clients can repeat the join (reconnecting, or over a connection laminar still considers
+    // open) so known addresses are ignored
+    #[cfg(feature = "server")]
+    fn add_connection(
+        &mut self,
+        game: &mut Game,
+        address: SocketAddr,
+        name: &str,
+        color: [u8; 3],
+        skin: u32,
+        observer: bool,
+    ) {
+        if self.get_index_for_address(address).is_some() {
+            return;
+        }
+
+        let name: String = name.trim().chars().take(MAX_NAME_LENGTH).collect();
+
+        if let Some(level) = &mut game.level {
+            let player_index = self.next_player_index();
+
+            self.connections.push(PlayerConnection {
+                socket_addr: address,
+                player_index,
+                // Players who didn't pick a name go by their index
+                name: if name.is_empty() {
+                    format!("Player {}", player_index)
+                } else {
+                    name
+                },
+                color,
+                skin,
+                observer,
+                last_acked_snapshot: None,
+                snapshot_history: SnapshotHistory::default(),
+                chat_flood_guard: FloodGuard::default(),
+                validator: InputValidator::default(),
+                moved: false,
+                rejoining: false,
+            });
+            self.stats.borrow_mut().insert(address, NetStats::default());
+            self.budgets
+                .borrow_mut()
+                .insert(address, SendBudget::new(self.send_budget));
+
+            // Observers watch whatever is going on, only players start a fresh round
+            let reset_level = !observer && level.players().len() < 2;
+            let state = if reset_level {
+                LevelState {
+                    destroyed_blocks: Vec::new(),
+                    cracked_blocks: Vec::new(),
+                }
+            } else {
+                level.state.clone()
+            };
+
+            // Send message to load level
+            let event = GameEvent::LoadLevel {
+                level: level.name.clone(),
+                state: state.clone(),
+            };
+
+            if reset_level {
+                // TODO: Fix issue with event not being cloneable
+                // TODO: Fix issue with not being able to re-borrow game
+                game.event_sender
+                    .send(GameEvent::LoadLevel {
+                        level: level.name.clone(),
+                        state: state.clone(),
+                    })
                     .unwrap();
+            } else {
+                self.send_to_address_reliably(address, &NetworkMessage::GameEvent { event: event });
             }
         }
     }
 
+    #[cfg(feature = "server")]
+    fn remove_connection(&mut self, engine: &mut GameEngine, game: &mut Game, address: SocketAddr) {
+        if let Some(level) = &mut game.level {
+            // Observers have no player to take out
+            if let Some(index) = self
+                .get_index_for_address(address)
+                .filter(|_| !self.is_observer(address))
+            {
+                let event = PlayerEvent::KillPlayer {
+                    index: index,
+                    killer: None,
+                    zone: None,
+                };
+                level.remove_player(engine, index);
+                self.send_to_all_except_address_reliably(
+                    address,
+                    &NetworkMessage::PlayerEvent {
+                        index: index,
+                        event: event,
+                    },
+                );
+            }
+        }
+        if let Some(index) = self.get_index_for_address(address) {
+            game.scores.retain(|score| score.index != index);
+            game.rotation.remove_voter(index);
+        }
+
+        self.connections
+            .retain(|connection| connection.socket_addr != address);
+        self.stats.borrow_mut().remove(&address);
+        self.budgets.borrow_mut().remove(&address);
+        self.sessions.remove(&address);
+        self.send_to_all_reliably(&NetworkMessage::ScoreUpdate {
+            scores: game.scores.clone(),
+        });
+    }
+
+    pub fn send_to_all_except_address_reliably(
+        &mut self,
+        address: SocketAddr,
+        message: &NetworkMessage,
+    ) {
+        // Send to all players except one it was sent from
+        let addresses = self.addresses_except(Some(address));
+        self.queue(&addresses, message, Delivery::Reliable, 0);
+    }
+
     fn send_to_all_except_address_unreliably(
         &mut self,
         address: SocketAddr,
         message: &NetworkMessage,
         redundancy: i32,
     ) {
-        // Send to all players except one it was sent from
-        for connection in self.connections.iter() {
-            if connection.socket_addr != address {
-                for _ in 0..=redundancy {
-                    // TODO: Refactor this to use our function?
-                    self.net_sender
-                        .send(Packet::unreliable_sequenced(
-                            connection.socket_addr,
-                            serialize(message).unwrap(),
-                            None,
-                        ))
-                        .unwrap();
-                }
-            }
-        }
+        let addresses = self.addresses_except(Some(address));
+        self.queue(&addresses, message, Delivery::Unreliable, redundancy);
     }
 
     pub fn send_to_address_reliably(&mut self, address: SocketAddr, message: &NetworkMessage) {
-        self.net_sender
-            .send(Packet::reliable_ordered(
-                address,
-                serialize(message).unwrap(),
-                self.get_address_stream_id(address),
-            ))
-            .unwrap();
+        self.queue(&[address], message, Delivery::Reliable, 0);
     }
 
     fn send_to_address_unreliably(
@@ -494,62 +1698,315 @@ impl NetworkManager {
         message: &NetworkMessage,
         redundancy: i32,
     ) {
-        for _ in 0..=redundancy {
-            self.net_sender
-                .send(Packet::unreliable_sequenced(
+        self.queue(&[address], message, Delivery::Unreliable, redundancy);
+    }
+
+    pub fn send_to_all_reliably(&mut self, message: &NetworkMessage) {
+        let addresses = self.addresses_except(None);
+        self.queue(&addresses, message, Delivery::Reliable, 0);
+    }
+
+    pub fn send_to_all_unreliably(&mut self, message: &NetworkMessage, redundancy: i32) {
+        let addresses = self.addresses_except(None);
+        self.queue(&addresses, message, Delivery::Unreliable, redundancy);
+    }
+
+    pub fn send_to_server_reliably(&mut self, message: &NetworkMessage) {
+        if let Some(server_addr) = self.server_addr {
+            self.queue(&[server_addr], message, Delivery::Reliable, 0);
+        }
+    }
+
+    pub fn send_to_server_unreliably(&mut self, message: &NetworkMessage, redundancy: i32) {
+        if let Some(server_addr) = self.server_addr {
+            self.queue(&[server_addr], message, Delivery::Unreliable, redundancy);
+        }
+    }
+
+    fn addresses_except(&self, address: Option<SocketAddr>) -> Vec<SocketAddr> {
+        self.connections
+            .iter()
+            .map(|connection| connection.socket_addr)
+            .filter(|&to| Some(to) != address)
+            .collect()
+    }
+
+    // Messages wait for flush, so everything sent to someone in a tick shares a few packets
+    fn queue(
+        &mut self,
+        addresses: &[SocketAddr],
+        message: &NetworkMessage,
+        delivery: Delivery,
+        redundancy: i32,
+    ) {
+        let bytes = serialize(message).unwrap();
+        for &address in addresses {
+            // Players over their budget miss some state updates, the next ones replace them anyway
+            #[cfg(feature = "server")]
+            if delivery == Delivery::Unreliable
+                && message.is_state_update()
+                && !self.allows_state(address)
+            {
+                continue;
+            }
+
+            self.queue_bytes(
+                address,
+                message.stream(),
+                delivery,
+                bytes.clone(),
+                redundancy,
+            );
+        }
+    }
+
+    fn queue_bytes(
+        &mut self,
+        address: SocketAddr,
+        stream: Stream,
+        delivery: Delivery,
+        bytes: Vec<u8>,
+        redundancy: i32,
+    ) {
+        let outgoing = self.outbox.entry((address, stream, delivery)).or_default();
+        outgoing.messages.push(bytes);
+        outgoing.redundancy = outgoing.redundancy.max(redundancy);
+    }
+
+    // Sends everything queued since the last flush, in as few packets as fit
+    pub fn flush(&mut self) {
+        for ((address, stream, delivery), outgoing) in std::mem::take(&mut self.outbox) {
+            for batch in outgoing.batches(delivery.max_batch_size()) {
+                let payload = self.seal(
                     address,
-                    serialize(message).unwrap(),
-                    None,
-                ))
-                .unwrap();
+                    self.pack(frame(batch)),
+                    delivery == Delivery::Reliable,
+                );
+                match delivery {
+                    Delivery::Reliable => {
+                        self.send_packet(Packet::reliable_ordered(address, payload, stream.id()))
+                    }
+                    Delivery::Unreliable => {
+                        for _ in 0..=outgoing.redundancy {
+                            self.send_packet(Packet::unreliable_sequenced(
+                                address,
+                                payload.clone(),
+                                stream.id(),
+                            ));
+                        }
+                    }
+                }
+            }
         }
     }
 
-    pub fn send_to_all_reliably(&mut self, message: &NetworkMessage) {
-        for connection in self.connections.iter() {
-            self.net_sender
-                .send(Packet::reliable_ordered(
-                    connection.socket_addr,
-                    serialize(message).unwrap(),
-                    self.get_connection_stream_id(connection),
-                ))
-                .unwrap();
+    // Tells a client it was moved to a new address. Laminar on their end still has the old
+    // connection's sequence numbers, so this goes without the ordering or sequencing they'd drop it
+    // for.
+    #[cfg(feature = "server")]
+    fn send_moved(&mut self, address: SocketAddr) {
+        let message = serialize(&NetworkMessage::Moved).unwrap();
+        let payload = self.seal(address, self.pack(frame(&[message])), false);
+        self.send_packet(Packet::unreliable(address, payload));
+    }
+
+    fn seal(&mut self, address: SocketAddr, payload: Vec<u8>, reliable: bool) -> Vec<u8> {
+        match self.sessions.get_mut(&address) {
+            Some(session) => session.seal(&payload, reliable),
+            None => payload,
         }
     }
 
-    pub fn send_to_all_unreliably(&mut self, message: &NetworkMessage, redundancy: i32) {
-        for connection in self.connections.iter() {
-            for _ in 0..=redundancy {
-                self.net_sender
-                    .send(Packet::unreliable_sequenced(
-                        connection.socket_addr,
-                        serialize(message).unwrap(),
-                        None,
-                    ))
-                    .unwrap();
+    // Opens a sealed packet, or lets a plain one through if there's no session with the sender yet.
+    // Also says whether it was sealed. None if the packet should be dropped.
+    fn open<'a>(&mut self, address: SocketAddr, packet: &'a [u8]) -> Option<(Cow<'a, [u8]>, bool)> {
+        if secure::is_sealed(packet) {
+            #[cfg(feature = "server")]
+            if !self.sessions.contains_key(&address) {
+                return self
+                    .rebind(address, packet)
+                    .map(|payload| (Cow::Owned(payload), true));
             }
+
+            let payload = self.sessions.get_mut(&address)?.open(packet)?;
+            return Some((Cow::Owned(payload), true));
+        }
+
+        // A client waiting on the server also takes a plain answer from a server that forgot it
+        #[cfg(not(feature = "server"))]
+        let waiting = !self.accepted || self.reconnect.is_some();
+        #[cfg(feature = "server")]
+        let waiting = false;
+
+        if waiting || !self.sessions.contains_key(&address) {
+            Some((Cow::Borrowed(packet), false))
+        } else {
+            None
         }
     }
 
-    pub fn send_to_server_reliably(&mut self, message: &NetworkMessage) {
-        self.net_sender
-            .send(Packet::reliable_ordered(
-                self.server_addr,
-                serialize(message).unwrap(),
-                self.get_address_stream_id(self.server_addr),
-            ))
-            .unwrap();
+    // A sealed packet from an address nobody has may be a player whose address changed. The token
+    // says who it should be from and only their key opens it, after that the player is moved over
+    // to the new address. Replayed packets are caught by the session's counter window, so copies
+    // of someone's packets sent from elsewhere can't steal their player. Laminar's state for the
+    // connection stays behind with the old address, so reliable traffic only gets going again once
+    // the client resumes on a fresh socket.
+    #[cfg(feature = "server")]
+    fn rebind(&mut self, address: SocketAddr, packet: &[u8]) -> Option<Vec<u8>> {
+        let token = secure::token_of(packet)?;
+        let old_address = self
+            .sessions
+            .iter()
+            .find(|(_, session)| session.token() == token)
+            .map(|(&old_address, _)| old_address)?;
+
+        let payload = self.sessions.get_mut(&old_address)?.open_newest(packet)?;
+        info!(from = %old_address, to = %address, "player moved");
+
+        if let Some(session) = self.sessions.remove(&old_address) {
+            self.sessions.insert(address, session);
+        }
+        if let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.socket_addr == old_address)
+        {
+            connection.socket_addr = address;
+            connection.moved = true;
+        }
+        let mut stats = self.stats.borrow_mut();
+        if let Some(old_stats) = stats.remove(&old_address) {
+            stats.insert(address, old_stats);
+        }
+        let mut budgets = self.budgets.borrow_mut();
+        if let Some(budget) = budgets.remove(&old_address) {
+            budgets.insert(address, budget);
+        }
+
+        Some(payload)
     }
 
-    pub fn send_to_server_unreliably(&mut self, message: &NetworkMessage, redundancy: i32) {
-        for _ in 0..=redundancy {
-            self.net_sender
-                .send(Packet::unreliable_sequenced(
-                    self.server_addr,
-                    serialize(message).unwrap(),
-                    None,
-                ))
-                .unwrap();
+    #[cfg(feature = "server")]
+    fn allows_state(&self, address: SocketAddr) -> bool {
+        self.budgets
+            .borrow_mut()
+            .get_mut(&address)
+            .map_or(true, |budget| budget.allows_state())
+    }
+
+    #[cfg(feature = "server")]
+    pub fn set_send_budget(&mut self, bytes_per_second: u32) {
+        self.send_budget = bytes_per_second;
+    }
+
+    #[cfg(feature = "server")]
+    pub fn set_idle_kick_time(&mut self, seconds: f32) {
+        self.idle_kick_time = seconds;
+    }
+
+    #[cfg(feature = "server")]
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+
+    #[cfg(feature = "server")]
+    pub fn set_join_rules(
+        &mut self,
+        max_players: u32,
+        password: Option<String>,
+        observer_password: Option<String>,
+    ) {
+        self.max_players = max_players;
+        self.password = password.filter(|password| !password.is_empty());
+        self.observer_password = observer_password.filter(|password| !password.is_empty());
+    }
+
+    // Why a player can't join right now, shown to them in the menu. Observers don't take a slot
+    // and need the observer password instead.
+    #[cfg(feature = "server")]
+    fn join_refusal(&self, address: SocketAddr, password: &str, observer: bool) -> Option<String> {
+        if self.bans.is_banned(address.ip()) {
+            return Some(String::from(BANNED_REASON));
+        }
+        if observer {
+            return match &self.observer_password {
+                None => Some(String::from("This server doesn't allow observers")),
+                Some(_) if password.is_empty() => Some(String::from(
+                    "Observing needs the observer password, enter it in the menu",
+                )),
+                Some(expected) if expected != password => Some(String::from("Wrong password")),
+                _ => None,
+            };
+        }
+        if self.max_players > 0 && self.player_count() >= self.max_players as usize {
+            return Some(format!(
+                "Server is full ({}/{} players)",
+                self.player_count(),
+                self.max_players
+            ));
+        }
+
+        match &self.password {
+            Some(_) if password.is_empty() => Some(String::from(
+                "This server needs a password, enter it in the menu",
+            )),
+            Some(expected) if expected != password => Some(String::from("Wrong password")),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "server")]
+    pub fn set_relevancy(&mut self, relevancy: Relevancy) {
+        self.relevancy = relevancy;
+    }
+
+    // Sends a snapshot of the players relevant to each connection, delta compressed against the
+    // last snapshot that connection acknowledged. Falls back to a full snapshot if nothing was
+    // acked yet or the acked snapshot is too old (lost packets). Players left out count as
+    // removed in the delta and are added back in full once they're relevant again.
+    #[cfg(feature = "server")]
+    pub fn send_snapshot(&mut self, timestamp: f32, players: Vec<PlayerSnapshot>) {
+        self.snapshot_sequence += 1;
+
+        let snapshot = WorldSnapshot {
+            sequence: self.snapshot_sequence,
+            timestamp,
+            players,
+        };
+
+        let mut snapshots = Vec::new();
+        for connection in self.connections.iter_mut() {
+            // Skipped snapshots never make it into the history so deltas stay against ones the
+            // client could have acked
+            if let Some(budget) = self.budgets.borrow_mut().get_mut(&connection.socket_addr) {
+                if !budget.allows_state() {
+                    continue;
+                }
+            }
+
+            let filtered = self.relevancy.filter(&snapshot, connection.player_index);
+
+            let payload = match connection
+                .last_acked_snapshot
+                .and_then(|sequence| connection.snapshot_history.get(sequence))
+            {
+                Some(base) => filtered.delta_from(base),
+                None => SnapshotPayload::Full(filtered.clone()),
+            };
+
+            snapshots.push((
+                connection.socket_addr,
+                serialize(&NetworkMessage::Snapshot {
+                    payload,
+                    match_clock: self.match_clock,
+                })
+                .unwrap(),
+            ));
+            connection.snapshot_history.push(filtered);
+        }
+
+        for (address, bytes) in snapshots {
+            self.queue_bytes(address, Stream::StateSync, Delivery::Unreliable, bytes, 0);
         }
     }
 
@@ -557,6 +2014,19 @@ impl NetworkManager {
 
     // pub fn send_to_player_unreliably(&mut self) {}
 
+    // Observers don't count
+    #[cfg(feature = "server")]
+    pub fn player_count(&self) -> usize {
+        self.connections.iter().filter(|c| !c.observer).count()
+    }
+
+    #[cfg(feature = "server")]
+    fn is_observer(&self, address: SocketAddr) -> bool {
+        self.connections
+            .iter()
+            .any(|c| c.socket_addr == address && c.observer)
+    }
+
     pub fn get_address_for_player(&self, index: u32) -> Option<SocketAddr> {
         self.connections
             .iter()
@@ -570,30 +2040,254 @@ impl NetworkManager {
             .find(|connection| connection.socket_addr == address)
             .and_then(|connection| Some(connection.player_index))
     }
+}
 
-    fn get_connection_stream_id(&self, connection: &PlayerConnection) -> Option<u8> {
-        Some(connection.player_index.to_le_bytes()[0])
+// Stops the socket thread, nothing is sent or received after this
+impl Drop for NetworkManager {
+    fn drop(&mut self) {
+        self.polling.store(false, Ordering::Relaxed);
     }
+}
 
-    fn get_address_stream_id(&self, address: SocketAddr) -> Option<u8> {
-        if address == self.server_addr {
-            return Some(0u8);
-        };
+// Laminar keeps packets in order per stream, and every connection has its own set of streams. Each
+// kind of message gets a stream of its own so a lost chat message or score update doesn't hold
+// up movement behind it while it's resent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stream {
+    Input,     // What players are doing with their controls
+    StateSync, // Snapshots, corrections and pings
+    Events,    // Things that happen in the game, and joining and leaving
+    Chat,
+}
 
-        self.get_index_for_address(address)
-            .and_then(|player_index| Some(player_index.to_le_bytes()[0]))
+impl Stream {
+    fn id(self) -> Option<u8> {
+        Some(match self {
+            Stream::Input => 0,
+            Stream::StateSync => 1,
+            Stream::Events => 2,
+            Stream::Chat => 3,
+        })
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum NetworkMessage {
-    Connected,
+    // Handshake and Rejected must stay the first two variants so builds with a different protocol
+    // can still read them
+    Handshake {
+        version: u32,
+        build: String,
+        public_key: PublicKeyBytes, // Last so older builds can still read the rest
+    },
+    Rejected {
+        reason: String,
+    },
+    Connected {
+        rates: TickRates,
+        public_key: PublicKeyBytes,
+        token: SessionToken, // Starts every packet after this, in both directions
+    },
+    // Client's answer to Connected, players are added once the server accepts it
+    Join {
+        name: String,
+        color: [u8; 3],
+        skin: u32,
+        password: String, // Empty if the player didn't enter one
+        observer: bool,   // Watching without a player, the password is the observer one
+    },
     Disconnected,
-    PlayerEvent { index: u32, event: PlayerEvent },
-    GameEvent { event: GameEvent },
+    PlayerEvent {
+        index: u32,
+        event: PlayerEvent,
+    },
+    GameEvent {
+        event: GameEvent,
+    },
+    Snapshot {
+        payload: SnapshotPayload,
+        match_clock: f32, // Seconds left in the match phase
+    },
+    ScoreUpdate {
+        scores: Vec<PlayerScore>,
+    },
+    Chat {
+        index: u32,
+        text: String,
+    },
+    SnapshotAck {
+        sequence: u32,
+    },
+    MapVote {
+        index: u32,
+        choice: u32,
+    }, // Index of the map in the rotation
+    Ping {
+        sequence: u32,
+    },
+    Pong {
+        sequence: u32,
+    },
+    // Server to a client it moved to a new address, answered with Resume from a fresh socket
+    Moved,
+    Resume,
+}
+
+// What every build's handshake starts with, the first variant of NetworkMessage with its first two
+// fields. Anything after them is left unread.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+enum VersionOnly {
+    Handshake { version: u32, build: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Delivery {
+    Reliable,   // Resent until acked, in order within the stream
+    Unreliable, // Newer packets make older ones on the stream obsolete
+}
+
+impl Delivery {
+    fn max_batch_size(self) -> usize {
+        match self {
+            Delivery::Reliable => MAX_MESSAGE_SIZE,
+            Delivery::Unreliable => MAX_UNRELIABLE_BATCH,
+        }
+    }
+}
+
+// Serialized messages for one peer, stream and delivery, waiting to be sent
+#[derive(Default)]
+struct Outgoing {
+    messages: Vec<Vec<u8>>,
+    redundancy: i32, // Extra copies of each packet, unreliable only
+}
+
+impl Outgoing {
+    // Splits the messages into runs that fit in a packet. A message bigger than that, or too big
+    // for a frame, goes alone.
+    fn batches(&self, max_size: usize) -> Vec<&[Vec<u8>]> {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        let mut size = 0;
+        for (i, message) in self.messages.iter().enumerate() {
+            let alone = message.len() > MAX_FRAME_SIZE;
+            if i > start && (alone || size + message.len() > max_size) {
+                batches.push(&self.messages[start..i]);
+                start = i;
+                size = 0;
+            }
+            size += message.len();
+            if alone {
+                batches.push(&self.messages[start..=i]);
+                start = i + 1;
+                size = 0;
+            }
+        }
+        if start < self.messages.len() {
+            batches.push(&self.messages[start..]);
+        }
+        batches
+    }
+}
+
+impl NetworkMessage {
+    pub fn stream(&self) -> Stream {
+        match self {
+            NetworkMessage::PlayerEvent { event, .. } => match event {
+                PlayerEvent::ShootWeapon { .. }
+                | PlayerEvent::MoveForward { .. }
+                | PlayerEvent::MoveBackward { .. }
+                | PlayerEvent::MoveLeft { .. }
+                | PlayerEvent::MoveRight { .. }
+                | PlayerEvent::MoveAnalog { .. }
+                | PlayerEvent::Jump { .. }
+                | PlayerEvent::Crouch { .. }
+                | PlayerEvent::Sprint { .. }
+                | PlayerEvent::Fly { .. }
+                | PlayerEvent::Aim { .. }
+                | PlayerEvent::LookAround { .. }
+                | PlayerEvent::Reload { .. }
+                | PlayerEvent::SwitchWeapon { .. }
+                | PlayerEvent::ThrowGrenade { .. } => Stream::Input,
+                PlayerEvent::UpdateState { .. } => Stream::StateSync,
+                _ => Stream::Events,
+            },
+            NetworkMessage::Snapshot { .. }
+            | NetworkMessage::SnapshotAck { .. }
+            | NetworkMessage::Ping { .. }
+            | NetworkMessage::Pong { .. } => Stream::StateSync,
+            NetworkMessage::Chat { .. } => Stream::Chat,
+            _ => Stream::Events,
+        }
+    }
+
+    // Sent in the clear, everything else has to be sealed
+    fn is_handshake(&self) -> bool {
+        matches!(
+            self,
+            NetworkMessage::Handshake { .. }
+                | NetworkMessage::Rejected { .. }
+                | NetworkMessage::Connected { .. }
+        )
+    }
+
+    #[cfg(feature = "server")]
+    fn is_state_update(&self) -> bool {
+        matches!(
+            self,
+            NetworkMessage::Snapshot { .. }
+                | NetworkMessage::PlayerEvent {
+                    event: PlayerEvent::UpdateState { .. },
+                    ..
+                }
+        )
+    }
 }
+
 #[derive(Debug)]
 struct PlayerConnection {
     socket_addr: SocketAddr,
     player_index: u32,
+    name: String,
+    color: [u8; 3],
+    skin: u32,
+    observer: bool, // Watching, no player is ever spawned for them
+    last_acked_snapshot: Option<u32>,
+    // Snapshots as they were sent to this player, which only has the players relevant to them
+    #[cfg(feature = "server")]
+    snapshot_history: SnapshotHistory,
+    chat_flood_guard: FloodGuard,
+    #[cfg(feature = "server")]
+    validator: InputValidator,
+    #[cfg(feature = "server")]
+    moved: bool, // Told to resume on a fresh socket until they do
+    #[cfg(feature = "server")]
+    rejoining: bool, // Resumed, their next Joined keeps the player they have
+}
+
+fn socket_config() -> Config {
+    Config {
+        heartbeat_interval: Some(Duration::from_millis(500)),
+        // Reliable packets are resent sealed as they were, the replay window has to cover them
+        max_packets_in_flight: secure::MAX_PACKETS_IN_FLIGHT,
+        ..Default::default()
+    }
+}
+
+// Laminar's own polling loop never returns, this one stops once the flag is cleared so a socket
+// can be replaced without leaving its thread spinning
+fn start_polling(mut socket: Socket) -> (Sender<Packet>, Receiver<SocketEvent>, Arc<AtomicBool>) {
+    let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
+    let polling = Arc::new(AtomicBool::new(true));
+
+    let running = polling.clone();
+    thread::spawn(move || {
+        while running.load(Ordering::Relaxed) {
+            socket.manual_poll(Instant::now());
+            thread::yield_now();
+        }
+    });
+
+    (sender, receiver, polling)
 }
@@ -0,0 +1,140 @@
+// Items placed in the level scene that players pick up by walking into them. The server decides who
+// got what and sends PickupItem, everyone hides the item until it comes back on its own timer.
+// Items placed inside a destructible block stay hidden until the block is shot away, so a level can
+// have fuel canisters and such that drop out of special blocks.
+
+use fyrox::{
+    core::{algebra::Vector3, pool::Handle},
+    scene::{graph::Graph, node::Node},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::blocks::BlockStore;
+
+// Nodes in the level scene named with one of these prefixes are items
+const ARMOR_PREFIX: &str = "PickupArmor";
+const FUEL_PREFIX: &str = "PickupFuel";
+const AMMO_PREFIX: &str = "PickupAmmo";
+// Distance from the player's center that counts as touching an item
+#[cfg(feature = "server")]
+const PICKUP_RADIUS: f32 = 0.6;
+const RESPAWN_TIME: f32 = 20.0; // Seconds
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ItemKind {
+    Armor,
+    Fuel, // Fills the jetpack
+    Ammo, // Every weapon and grenades
+}
+
+impl ItemKind {
+    fn from_name(name: &str) -> Option<Self> {
+        if name.starts_with(ARMOR_PREFIX) {
+            Some(ItemKind::Armor)
+        } else if name.starts_with(FUEL_PREFIX) {
+            Some(ItemKind::Fuel)
+        } else if name.starts_with(AMMO_PREFIX) {
+            Some(ItemKind::Ammo)
+        } else {
+            None
+        }
+    }
+}
+
+struct Item {
+    node: Handle<Node>,
+    kind: ItemKind,
+    position: Vector3<f32>,
+    respawn_timer: f32, // Zero while the item can be picked up
+    buried: bool,       // Inside a block that hasn't been destroyed yet
+}
+
+// Items are referred to by their index in here, which is the same everywhere since it comes from
+// the order of nodes in the level scene
+#[derive(Default)]
+pub struct Pickups {
+    items: Vec<Item>,
+}
+
+impl Pickups {
+    // Global positions need to be up to date
+    pub fn from_scene(graph: &mut Graph, blocks: &BlockStore) -> Self {
+        let items: Vec<Item> = graph
+            .pair_iter()
+            .filter_map(|(handle, node)| {
+                ItemKind::from_name(node.name()).map(|kind| {
+                    let position = node.global_position();
+                    let block = blocks.get(BlockStore::coord_for_position(position));
+                    Item {
+                        node: handle,
+                        kind,
+                        position,
+                        respawn_timer: 0.0,
+                        buried: block.is_some() && block != handle,
+                    }
+                })
+            })
+            .collect();
+
+        for item in items.iter().filter(|item| item.buried) {
+            graph[item.node].set_visibility(false);
+        }
+
+        Self { items }
+    }
+
+    pub fn kind(&self, item: u32) -> Option<ItemKind> {
+        self.items.get(item as usize).map(|item| item.kind)
+    }
+
+    // First item in reach of the position that's there to be picked up and wanted
+    #[cfg(feature = "server")]
+    pub fn item_at(&self, position: Vector3<f32>, wants: impl Fn(ItemKind) -> bool) -> Option<u32> {
+        self.items
+            .iter()
+            .position(|item| {
+                !item.buried
+                    && item.respawn_timer <= 0.0
+                    && (item.position - position).norm() <= PICKUP_RADIUS
+                    && wants(item.kind)
+            })
+            .map(|index| index as u32)
+    }
+
+    // Hides the item until it respawns. Taking an item that's already gone leaves its timer alone,
+    // the server takes items as soon as it hands them out and again when the event comes around.
+    pub fn take(&mut self, graph: &mut Graph, item: u32) {
+        if let Some(item) = self.items.get_mut(item as usize) {
+            if item.respawn_timer <= 0.0 {
+                item.respawn_timer = RESPAWN_TIME;
+            }
+            if graph.is_valid_handle(item.node) {
+                graph[item.node].set_visibility(false);
+            }
+        }
+    }
+
+    // Frees items inside a block that was just destroyed. Block destruction reaches everyone in the
+    // same order, joining clients replay it from the level state, so items come out everywhere.
+    pub fn uncover(&mut self, graph: &mut Graph, coord: Vector3<i32>) {
+        for item in self.items.iter_mut().filter(|item| item.buried) {
+            if BlockStore::coord_for_position(item.position) == coord {
+                item.buried = false;
+                if item.respawn_timer <= 0.0 && graph.is_valid_handle(item.node) {
+                    graph[item.node].set_visibility(true);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, graph: &mut Graph, dt: f32) {
+        for item in self.items.iter_mut() {
+            if item.respawn_timer > 0.0 {
+                item.respawn_timer -= dt;
+                if item.respawn_timer <= 0.0 && graph.is_valid_handle(item.node) {
+                    graph[item.node].set_visibility(true);
+                }
+            }
+        }
+    }
+}
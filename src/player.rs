@@ -2,7 +2,7 @@ use bincode::serialize;
 use fyrox::{
     animation::Animation,
     core::{
-        algebra::{Matrix3, Translation3, UnitQuaternion, Vector3},
+        algebra::{Matrix3, Translation3, UnitQuaternion, Vector2, Vector3},
         color::Color,
         color_gradient::{ColorGradient, GradientPoint},
         math::{ray::Ray, Vector3Ext},
@@ -10,17 +10,15 @@ use fyrox::{
     },
     engine::resource_manager::ResourceManager,
     event::ElementState,
-    gui::{message::MessageDirection, text::TextMessage},
     material::{Material, PropertyValue},
-    resource::texture::TextureWrapMode,
+    resource::{model::Model, texture::TextureWrapMode},
     scene::{
         base::BaseBuilder,
-        camera::{CameraBuilder, Exposure, SkyBox, SkyBoxBuilder},
-        collider::{ColliderBuilder, ColliderShape},
-        graph::{
-            physics::{CoefficientCombineRule, RayCastOptions},
-            Graph,
+        camera::{
+            CameraBuilder, Exposure, PerspectiveProjection, Projection, SkyBox, SkyBoxBuilder,
         },
+        collider::{ColliderBuilder, ColliderShape},
+        graph::{physics::CoefficientCombineRule, Graph},
         mesh::{
             surface::{SurfaceBuilder, SurfaceData},
             MeshBuilder, RenderPath,
@@ -38,21 +36,95 @@ use std::{
     path::{Path, PathBuf},
     sync::mpsc::{self, Sender},
 };
+use tracing::warn;
 
 use crate::{
-    animation::{PlayerAnimationMachine, PlayerAnimationMachineInput},
+    animation::{PlayerAnimationMachine, PlayerAnimationMachineInput, PlayerAnimations},
+    collision::{self, SurfaceKind},
+    error::GameError,
+    history::History,
     level::Level,
     network_manager::{self, NetworkManager, NetworkMessage},
+    pickups::ItemKind,
     player_event::PlayerEvent,
+    projectile, skins,
+    weapon::{self, FireMode, Weapon, SWITCH_TIME},
     GameEngine, Interface,
 };
+#[cfg(not(feature = "server"))]
+use crate::{
+    footsteps::{FootstepSounds, Footsteps},
+    jetpack::{Jetpack, JetpackResources},
+    view_model::{ViewModel, ViewModelInput},
+};
 
 const MOVEMENT_SPEED: f32 = 1.5;
+const SPRINT_MULTIPLIER: f32 = 1.6;
+const CROUCH_MULTIPLIER: f32 = 0.5;
+const AIM_MULTIPLIER: f32 = 0.7;
+const MAX_STAMINA: f32 = 100.0;
+const STAMINA_DRAIN: f32 = 30.0; // Per second while sprinting
+const STAMINA_REGEN: f32 = 15.0; // Per second while not sprinting
+const CAPSULE_RADIUS: f32 = 0.20;
+const CAPSULE_HALF_HEIGHT: f32 = 0.25;
+const CROUCH_HALF_HEIGHT: f32 = 0.10;
+const CAMERA_HEIGHT: f32 = 0.37;
+// Capsule keeps its bottom in place when crouching so the top (and camera) drops by the difference
+const CROUCH_DROP: f32 = 2.0 * (CAPSULE_HALF_HEIGHT - CROUCH_HALF_HEIGHT);
+const CROUCH_CAMERA_SPEED: f32 = 10.0;
+// Further than a step could move, the player was teleported or respawned so rendering snaps
+#[cfg(not(feature = "server"))]
+const MAX_RENDER_OFFSET: f32 = 2.0;
+// Hits on the capsule no lower than this below the eyes count as headshots
+const HEAD_DEPTH: f32 = 0.1;
+pub const DEFAULT_FOV: f32 = 75.0;
+// Degrees added to the field of view while sprinting or using the jetpack
+const SPRINT_FOV_KICK: f32 = 8.0;
+const FLY_FOV_KICK: f32 = 5.0;
+const FOV_KICK_SPEED: f32 = 6.0;
+// Share of the field of view left when fully zoomed in, and how quickly aiming zooms
+const AIM_FOV_SCALE: f32 = 0.6;
+const AIM_ZOOM_SPEED: f32 = 12.0;
+// Degrees the third person upper body leans into the gun while aiming
+const AIM_LEAN: f32 = 12.0;
 const GRAVITY_SCALE: f32 = 0.6;
-const JET_SPEED: f32 = 0.0155;
+const JET_THRUST: f32 = 0.93; // Impulse per second
 const JUMP_SCALAR: f32 = 0.32;
-const MAX_FUEL: u32 = 225;
+const MAX_FUEL: f32 = 225.0;
+// Per second, regen keeps going while flying
+const FUEL_BURN: f32 = 180.0;
+const FUEL_REGEN: f32 = 60.0;
+pub const MAX_HEALTH: f32 = 100.0;
+const MAX_ARMOR: f32 = 100.0;
+const ARMOR_PICKUP: f32 = 50.0;
+// Share of incoming damage taken by armor while there's any left
+const ARMOR_ABSORPTION: f32 = 0.66;
 pub const SYNC_FREQUENCY: u32 = 3;
+const MAX_GRENADES: u32 = 2;
+const GRENADE_COOLDOWN: f32 = 1.0;
+// How quickly horizontal knockback velocity fades per second
+const KNOCKBACK_DECAY: f32 = 4.0;
+// Converts horizontal impulse into knockback velocity
+const KNOCKBACK_SCALE: f32 = 8.0;
+// Muzzle is this far in front of the gun hand along the shot
+#[cfg(not(feature = "server"))]
+const MUZZLE_OFFSET: f32 = 0.2;
+#[cfg(not(feature = "server"))]
+const MUZZLE_FLASH_LIFETIME: f32 = 0.05;
+// Horizontal speed below which footsteps stop
+#[cfg(not(feature = "server"))]
+const MIN_STEP_SPEED: f32 = 0.3;
+// How far back each shot pushes the first person gun, how quickly it gets there and how quickly
+// it comes back
+#[cfg(not(feature = "server"))]
+const RECOIL_KICK: f32 = 0.035;
+#[cfg(not(feature = "server"))]
+const RECOIL_SPEED: f32 = 30.0;
+#[cfg(not(feature = "server"))]
+const RECOIL_RECOVERY: f32 = 10.0;
+// Share of the recoil kick left while aiming
+#[cfg(not(feature = "server"))]
+const AIM_RECOIL_MULTIPLIER: f32 = 0.4;
 
 #[derive(Default)]
 pub struct PlayerController {
@@ -60,38 +132,93 @@ pub struct PlayerController {
     pub move_backward: bool,
     pub move_left: bool,
     pub move_right: bool,
+    pub move_axis: Vector2<f32>, // Analog movement, x is right and y is forward
     pub move_up: bool,
     pub jump: bool,
+    pub crouch: bool,
+    pub sprint: bool,
     pub fly: bool,
+    pub aim: bool,
     pub pitch: f32,
     pub yaw: f32,
     pub dest_pitch: f32,
     pub dest_yaw: f32,
     pub shoot: bool,
-    pub new_states: Vec<PlayerState>,
-    pub previous_states: Vec<PlayerState>,
+    pub new_states: History<PlayerState>, // Latest state from the server
+    pub previous_states: History<PlayerState>, // Local states since the last snapshot
     pub smoothing_speed: f32,
 }
 
 pub struct Player {
-    barrel: Handle<Node>,
+    first_person_barrel: Handle<Node>,
+    third_person_barrel: Handle<Node>,
     spine: Handle<Node>,
     camera: Handle<Node>,
+    listener: Handle<Node>,
     rigid_body: Handle<Node>,
     pub collider: Handle<Node>,
     shot_timer: f32,
+    reload_timer: f32,
+    #[cfg(not(feature = "server"))]
     recoil_offset: Vector3<f32>,
+    #[cfg(not(feature = "server"))]
     recoil_target_offset: Vector3<f32>,
+    #[cfg(not(feature = "server"))]
+    view_model: ViewModel,
     pub index: u32,
+    pub skin: u32, // As picked, shown as the default skin if we don't have it
     pub controller: PlayerController,
     third_person_model: Handle<Node>,
     first_person_model: Handle<Node>,
     firing_sound_buffer: Option<SoundBufferResource>,
-    pub flight_fuel: u32,
+    #[cfg(not(feature = "server"))]
+    muzzle_flash_texture: fyrox::resource::texture::Texture,
+    #[cfg(not(feature = "server"))]
+    footstep_sounds: FootstepSounds,
+    #[cfg(not(feature = "server"))]
+    footsteps: Footsteps,
+    #[cfg(not(feature = "server"))]
+    jetpack: Jetpack,
+    pub flight_fuel: f32,
+    pub stamina: f32,
+    crouched: bool,
+    camera_height: f32,
+    fov_kick: f32,
+    aim_zoom: f32, // 0 to 1, eases in and out so the zoom and lean aren't instant
+    pub health: f32,
+    pub armor: f32,
     current_player: bool,
-    pub ammo: u32,
+    weapons: Vec<Weapon>,
+    current_weapon: usize,
+    bursts: u32,      // Times the trigger was pulled, seeds the recoil pattern
+    burst_shots: u32, // Shots since the trigger was last pulled
+    pub grenades: u32,
+    grenade_timer: f32,
+    knockback: Vector3<f32>,
     first_person_animation_machine: PlayerAnimationMachine,
     third_person_animation_machine: PlayerAnimationMachine,
+    #[cfg(not(feature = "server"))]
+    pub impacts: Vec<Impact>, // Shots that hit something since the level last took them
+    #[cfg(not(feature = "server"))]
+    pub block_hits: Vec<Handle<Node>>, // Blocks the local player shot, for predicting breaks
+    #[cfg(not(feature = "server"))]
+    pub shot_rays: Vec<(Vector3<f32>, Vector3<f32>)>, // Where hitscan shots went, for the debug overlay
+    #[cfg(not(feature = "server"))]
+    pub render_from: Vector3<f32>, // Body position before the last simulation step
+    #[cfg(not(feature = "server"))]
+    model_position: Vector3<f32>, // Local position of the third person model
+    // Since the level last counted them, clients only count the local player's
+    pub shots_fired: u32,
+    #[cfg(not(feature = "server"))]
+    color: Color, // Tint of the third person model
+}
+
+// Where a shot hit, turned into effects by the level
+#[cfg(not(feature = "server"))]
+pub struct Impact {
+    pub position: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub decal: bool, // Only walls and static geometry, blocks and players don't stay put
 }
 
 #[derive(Default, Debug)]
@@ -102,7 +229,7 @@ pub struct PlayerState {
     pub yaw: f32,
     pub pitch: f32,
     pub shoot: bool,
-    pub fuel: u32,
+    pub fuel: f32,
 }
 
 // impl Serialize for PlayerState {
@@ -122,26 +249,38 @@ pub struct PlayerState {
 // }
 
 impl Player {
-    pub async fn new(
+    pub fn new(
         scene: &mut Scene,
         state: PlayerState,
-        resource_manager: ResourceManager,
+        resources: &PlayerResources,
         current_player: bool,
         index: u32,
-    ) -> Self {
-        // TODO: Resources should only need to be loaded once and shared among players
-        let first_person_resource = resource_manager
-            .request_model("data/models/walking_1st.fbx")
-            .await
-            .unwrap();
-
-        let third_person_resource = resource_manager
-            .request_model("data/models/idle.fbx")
-            .await
-            .unwrap();
-
-        let first_person_model = first_person_resource.instantiate(scene).root;
-        let third_person_model = third_person_resource.instantiate(scene).root;
+        skin: u32,
+        fov: f32,
+    ) -> Result<Self, GameError> {
+        let skin_model = resources.skin(skin);
+        let first_person_model = resources.first_person_model.instantiate(scene).root;
+        let third_person_model = skin_model.model.instantiate(scene).root;
+
+        // Models from somewhere else may not have the nodes we move around, don't leave half a
+        // player in the scene
+        let gun = scene.graph.find_by_name(first_person_model, "gun_LOD0");
+        let spine = scene.graph.find_by_name(third_person_model, "Bind_Spine");
+        let missing = if gun.is_none() {
+            Some((FIRST_PERSON_MODEL_PATH, "gun_LOD0"))
+        } else if spine.is_none() {
+            Some((skin_model.path.as_str(), "Bind_Spine"))
+        } else {
+            None
+        };
+        if let Some((model, node)) = missing {
+            scene.graph.remove_node(first_person_model);
+            scene.graph.remove_node(third_person_model);
+            return Err(GameError::MissingNode {
+                model: model.to_string(),
+                node: node.to_string(),
+            });
+        }
 
         // let animation_resource = resource_manager
         //     .request_model("data/models/walking_1st.fbx")
@@ -159,7 +298,7 @@ impl Player {
         //     .unwrap();
         // println!("animations: {:?}", animations.len());
 
-        let camera_pos = Vector3::new(0.0, 0.37, 0.00);
+        let camera_pos = Vector3::new(0.0, CAMERA_HEIGHT, 0.00);
         let model_pos = Vector3::new(0.0, -0.82, -0.09);
 
         scene.graph[first_person_model]
@@ -177,23 +316,24 @@ impl Player {
         scene.graph[first_person_model].set_visibility(current_player);
 
         // Workaround for gun getting culled
-        let gun = scene.graph.find_by_name(first_person_model, "gun_LOD0");
         scene.graph[gun]
             .local_transform_mut()
             .set_position(Vector3::new(0.0, 1.0, 0.5));
 
-        let spine = scene.graph.find_by_name(third_person_model, "Bind_Spine");
+        // Shots are drawn from the gun of whichever model is visible
+        let first_person_barrel = create_barrel(scene, first_person_model);
+        let third_person_barrel = create_barrel(scene, third_person_model);
 
-        // TODO: Need separate pivots for third or first person to make shots appear from correct position in third person
-        let barrel = scene.graph.find_by_name(first_person_model, "gun_LOD0");
+        let listener = if current_player {
+            ListenerBuilder::new(BaseBuilder::new()).build(&mut scene.graph)
+        } else {
+            Handle::NONE
+        };
 
         let camera = if current_player {
             CameraBuilder::new(
                 BaseBuilder::new()
-                    .with_children(&[
-                        first_person_model,
-                        ListenerBuilder::new(BaseBuilder::new()).build(&mut scene.graph),
-                    ])
+                    .with_children(&[first_person_model, listener])
                     .with_local_transform(
                         TransformBuilder::new()
                             .with_local_position(camera_pos)
@@ -201,7 +341,8 @@ impl Player {
                     ),
             )
             .enabled(current_player)
-            .with_skybox(create_skybox(resource_manager.clone()).await)
+            .with_fov(fov.to_radians())
+            .with_skybox(resources.skybox.clone())
             .build(&mut scene.graph)
         } else {
             CameraBuilder::new(
@@ -214,7 +355,8 @@ impl Player {
                     ),
             )
             .enabled(current_player)
-            .with_skybox(create_skybox(resource_manager.clone()).await)
+            .with_fov(fov.to_radians())
+            .with_skybox(resources.skybox.clone())
             .build(&mut scene.graph)
         };
 
@@ -222,11 +364,6 @@ impl Player {
             .as_camera_mut()
             .set_exposure(Exposure::Manual(std::f32::consts::E));
 
-        // let pivot = BaseBuilder::new()
-        //     .with_children(&[camera, third_person_model])
-        //     .with_tag("player".to_string()) // TODO: Use collider groups instead
-        //     .build(&mut scene.graph);
-
         // Create rigid body, it will be used for interaction with the world.
         // let rigid_body =
         //     RigidBodyBuilder::new_dynamic()
@@ -255,14 +392,17 @@ impl Player {
         // );
 
         let collider = ColliderBuilder::new(BaseBuilder::new())
-            .with_shape(ColliderShape::capsule_y(0.25, 0.20))
+            .with_shape(ColliderShape::capsule_y(
+                CAPSULE_HALF_HEIGHT,
+                CAPSULE_RADIUS,
+            ))
+            .with_collision_groups(SurfaceKind::Player.interaction_groups())
             .with_friction_combine_rule(CoefficientCombineRule::Min)
             .with_friction(0.0)
             .build(&mut scene.graph);
 
         let rigid_body = RigidBodyBuilder::new(
             BaseBuilder::new()
-                .with_tag("player".to_string())
                 .with_local_transform(
                     TransformBuilder::new()
                         .with_local_position(Vector3::new(
@@ -287,29 +427,35 @@ impl Player {
         .with_can_sleep(false)
         .build(&mut scene.graph);
 
-        let firing_sound_buffer = Some(
-            resource_manager
-                .request_sound_buffer("data/sounds/laser4.ogg")
-                .await
-                .unwrap(),
-        );
+        #[cfg(not(feature = "server"))]
+        let jetpack = Jetpack::new(&mut scene.graph, rigid_body, &resources.jetpack);
+
+        let firing_sound_buffer = Some(resources.firing_sound.clone());
 
         let first_person_animation_machine =
-            PlayerAnimationMachine::new(scene, first_person_model, resource_manager.clone()).await;
+            PlayerAnimationMachine::new(scene, first_person_model, &resources.animations);
 
         let third_person_animation_machine =
-            PlayerAnimationMachine::new(scene, third_person_model, resource_manager.clone()).await;
+            PlayerAnimationMachine::new(scene, third_person_model, &resources.animations);
 
-        Self {
-            barrel,
+        Ok(Self {
+            first_person_barrel,
+            third_person_barrel,
             spine,
             camera: camera,
+            listener,
             rigid_body,
             collider,
             shot_timer: 0.0,
+            reload_timer: 0.0,
+            #[cfg(not(feature = "server"))]
             recoil_offset: Default::default(),
+            #[cfg(not(feature = "server"))]
             recoil_target_offset: Default::default(),
+            #[cfg(not(feature = "server"))]
+            view_model: ViewModel::new(first_person_model, model_pos),
             index,
+            skin,
             controller: PlayerController {
                 shoot: state.shoot,
                 yaw: state.yaw,
@@ -319,18 +465,65 @@ impl Player {
             first_person_model,
             third_person_model,
             firing_sound_buffer,
+            #[cfg(not(feature = "server"))]
+            muzzle_flash_texture: resources.muzzle_flash.clone(),
+            #[cfg(not(feature = "server"))]
+            footstep_sounds: resources.footsteps.clone(),
+            #[cfg(not(feature = "server"))]
+            footsteps: Footsteps::default(),
+            #[cfg(not(feature = "server"))]
+            jetpack,
             flight_fuel: MAX_FUEL,
+            stamina: MAX_STAMINA,
+            crouched: false,
+            camera_height: CAMERA_HEIGHT,
+            fov_kick: 0.0,
+            aim_zoom: 0.0,
+            health: MAX_HEALTH,
+            armor: 0.0,
             current_player,
-            ammo: 20,
+            weapons: weapon::create_loadout(),
+            current_weapon: 0,
+            bursts: 0,
+            burst_shots: 0,
+            grenades: MAX_GRENADES,
+            grenade_timer: 0.0,
+            knockback: Vector3::default(),
             first_person_animation_machine,
             third_person_animation_machine,
+            #[cfg(not(feature = "server"))]
+            impacts: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            block_hits: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            shot_rays: Vec::new(),
+            #[cfg(not(feature = "server"))]
+            render_from: state.position,
+            #[cfg(not(feature = "server"))]
+            model_position: model_pos + camera_pos,
+            shots_fired: 0,
+            #[cfg(not(feature = "server"))]
+            color: Color::WHITE,
+        })
+    }
+
+    // The color the player picked, so others can tell them apart
+    #[cfg(not(feature = "server"))]
+    pub fn set_color(&mut self, scene: &mut Scene, color: Color) {
+        if color != self.color {
+            self.color = color;
+            tint_model(&mut scene.graph, self.third_person_model, color);
         }
     }
 
-    pub fn set_camera(&self, scene: &mut Scene, enabled: bool) {
-        if enabled {
-            let listener = ListenerBuilder::new(BaseBuilder::new()).build(&mut scene.graph);
-            scene.graph.link_nodes(listener, self.camera);
+    pub fn set_camera(&mut self, scene: &mut Scene, enabled: bool) {
+        // Only the player being looked through should have a listener
+        if enabled && self.listener.is_none() {
+            self.listener = ListenerBuilder::new(BaseBuilder::new()).build(&mut scene.graph);
+            scene.graph.link_nodes(self.listener, self.camera);
+        } else if !enabled && self.listener.is_some() {
+            scene.remove_node(self.listener);
+            self.listener = Handle::NONE;
         }
 
         scene.graph[self.camera]
@@ -339,6 +532,56 @@ impl Player {
 
         scene.graph[self.third_person_model].set_visibility(!enabled);
         scene.graph[self.first_person_model].set_visibility(enabled);
+        #[cfg(not(feature = "server"))]
+        self.jetpack.set_third_person(scene, !enabled);
+    }
+
+    // Shows the local player's own body for the third person camera. Their camera stays where it is
+    // for aiming and hearing, it just stops drawing.
+    #[cfg(not(feature = "server"))]
+    pub fn set_third_person(&mut self, scene: &mut Scene, third_person: bool) {
+        scene.graph[self.camera]
+            .as_camera_mut()
+            .set_enabled(!third_person);
+
+        scene.graph[self.third_person_model].set_visibility(third_person);
+        scene.graph[self.first_person_model].set_visibility(!third_person);
+        self.jetpack.set_third_person(scene, third_person);
+    }
+
+    pub fn camera(&self) -> Handle<Node> {
+        self.camera
+    }
+
+    pub fn is_current_player(&self) -> bool {
+        self.current_player
+    }
+
+    pub fn set_fov(&self, scene: &mut Scene, fov: f32, kick: bool) {
+        let kick = if kick { self.fov_kick } else { 0.0 };
+        let zoom = 1.0 - self.aim_zoom * (1.0 - AIM_FOV_SCALE);
+        set_camera_fov(scene, self.camera, (fov + kick) * zoom);
+    }
+
+    // Draws the camera and third person model `alpha` of the way from where the body was before the
+    // last step to where it is now. Only what's seen moves, the body and collider stay put.
+    #[cfg(not(feature = "server"))]
+    pub fn set_render_alpha(&self, scene: &mut Scene, alpha: f32) {
+        let mut offset = (self.render_from - self.get_position(scene)) * (1.0 - alpha);
+        if offset.norm() > MAX_RENDER_OFFSET {
+            offset = Vector3::default();
+        }
+        // Children of the body turn with it
+        let offset =
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.controller.yaw.to_radians())
+                .inverse_transform_vector(&offset);
+
+        scene.graph[self.camera]
+            .local_transform_mut()
+            .set_position(Vector3::new(0.0, self.camera_height, 0.0) + offset);
+        scene.graph[self.third_person_model]
+            .local_transform_mut()
+            .set_position(self.model_position + offset);
     }
 
     pub fn update(
@@ -349,18 +592,46 @@ impl Player {
         resource_manager: ResourceManager,
         network_manager: &mut NetworkManager,
         event_sender: &Sender<PlayerEvent>,
+        // Shots do their own damage, on the server or in practice
+        authoritative: bool,
         interface: &Interface, // client_address: &mut String,
                                // action_sender: &mpsc::Sender<PlayerEvent>
     ) {
         let scene = &mut engine.scenes[scene];
 
         self.shot_timer = (self.shot_timer - dt).max(0.0);
+        self.grenade_timer = (self.grenade_timer - dt).max(0.0);
+
+        if self.is_reloading() {
+            self.reload_timer = (self.reload_timer - dt).max(0.0);
+            if !self.is_reloading() {
+                self.weapon_mut().refill();
+            }
+        }
+
+        if self.controller.crouch != self.crouched {
+            self.set_crouched(scene, self.controller.crouch);
+        }
 
         let has_ground_contact = self.has_ground_contact(scene);
+        let sprinting = self.is_sprinting();
+        let speed = MOVEMENT_SPEED
+            * if self.crouched {
+                CROUCH_MULTIPLIER
+            } else if self.is_aiming() {
+                AIM_MULTIPLIER
+            } else if sprinting {
+                SPRINT_MULTIPLIER
+            } else {
+                1.0
+            };
 
         let mut animation_input: PlayerAnimationMachineInput = PlayerAnimationMachineInput {
             on_ground: has_ground_contact,
-            walk_forward: self.controller.move_forward,
+            walk_forward: self.controller.move_forward || self.controller.move_axis.y > 0.0,
+            reload: self.is_reloading(),
+            crouch: self.crouched,
+            sprint: sprinting,
             ..Default::default()
         };
 
@@ -378,34 +649,50 @@ impl Player {
         // Change the velocity depending on the keys pressed.
         if self.controller.move_forward {
             // If we moving forward then add "look" vector of the pivot.
-            velocity += body.look_vector().normalize() * MOVEMENT_SPEED;
+            velocity += body.look_vector().normalize() * speed;
         }
         if self.controller.move_backward {
             // If we moving backward then subtract "look" vector of the pivot.
-            velocity -= body.look_vector().normalize() * MOVEMENT_SPEED;
+            velocity -= body.look_vector().normalize() * speed;
         }
         if self.controller.move_left {
             // If we moving left then add "side" vector of the pivot.
-            velocity += body.side_vector().normalize() * MOVEMENT_SPEED;
+            velocity += body.side_vector().normalize() * speed;
         }
         if self.controller.move_right {
             // If we moving right then subtract "side" vector of the pivot.
-            velocity -= body.side_vector().normalize() * MOVEMENT_SPEED;
+            velocity -= body.side_vector().normalize() * speed;
+        }
+        // Analog input scales speed with how far the stick is pushed
+        velocity += body.look_vector().normalize() * self.controller.move_axis.y * speed;
+        velocity -= body.side_vector().normalize() * self.controller.move_axis.x * speed;
+
+        // Stamina only drains while actually moving
+        if sprinting && velocity.x.abs() + velocity.z.abs() > f32::EPSILON {
+            self.stamina = (self.stamina - STAMINA_DRAIN * dt).max(0.0);
+        } else if !self.controller.sprint {
+            self.stamina = (self.stamina + STAMINA_REGEN * dt).min(MAX_STAMINA);
         }
 
+        // Horizontal knockback is added on top of movement and fades out
+        velocity += self.knockback;
+        self.knockback *= (1.0 - KNOCKBACK_DECAY * dt).max(0.0);
+
         // Finally new linear velocity.
         body.set_lin_vel(velocity);
 
+        // Clients run the same numbers to predict their own fuel, the server's count wins when
+        // snapshots arrive
         if self.controller.fly && self.has_fuel() {
             if body.lin_vel().y < 3.0 {
-                body.apply_impulse(body.up_vector().normalize() * JET_SPEED);
-                self.flight_fuel = (self.flight_fuel - 3).clamp(0, MAX_FUEL);
+                body.apply_impulse(body.up_vector().normalize() * JET_THRUST * dt);
+                self.flight_fuel = (self.flight_fuel - FUEL_BURN * dt).max(0.0);
             }
 
             animation_input.fly = true;
         }
 
-        self.flight_fuel = (self.flight_fuel + 1).clamp(0, MAX_FUEL);
+        self.flight_fuel = (self.flight_fuel + FUEL_REGEN * dt).min(MAX_FUEL);
 
         if self.controller.jump && has_ground_contact && self.can_jump() {
             // TODO: Add "ready_to_jump" for cooldown
@@ -435,43 +722,6 @@ impl Player {
 
         self.controller.jump = false;
 
-        // else if self.has_fuel() {
-        //         #[cfg(feature = "server")]
-        //         {
-        //             self.controller.fly = true;
-
-        //             let event = PlayerEvent::Fly {
-        //                 index: self.index,
-        //                 active: true,
-        //                 fuel: self.flight_fuel,
-        //             };
-        //             let message = NetworkMessage::PlayerEvent {
-        //                 index: self.index,
-        //                 event,
-        //             };
-
-        //             #[cfg(feature = "server")]
-        //             network_manager.send_to_all_reliably(&message);
-        //         }
-        //     }
-
-        // #[cfg(feature = "server")]
-        // if !self.controller.fly {
-        //     self.controller.fly = false;
-
-        //     let event = PlayerEvent::Fly {
-        //         index: self.index,
-        //         active: false,
-        //         fuel: self.flight_fuel,
-        //     };
-        //     let message = NetworkMessage::PlayerEvent {
-        //         index: self.index,
-        //         event,
-        //     };
-
-        //     network_manager.send_to_all_reliably(&message);
-        // }
-
         // Change the rotation of the rigid body according to current yaw. These lines responsible for
         // left-right rotation.
         // let mut position = *body.position();
@@ -484,18 +734,48 @@ impl Player {
                 self.controller.yaw.to_radians(),
             ));
 
+        // Lower or raise the camera smoothly when crouching
+        let target_height = if self.crouched {
+            CAMERA_HEIGHT - CROUCH_DROP
+        } else {
+            CAMERA_HEIGHT
+        };
+        self.camera_height +=
+            (target_height - self.camera_height) * (CROUCH_CAMERA_SPEED * dt).min(1.0);
+
+        // Widen the view a little when moving fast
+        let target_kick = if self.is_sprinting() {
+            SPRINT_FOV_KICK
+        } else if self.controller.fly && self.has_fuel() {
+            FLY_FOV_KICK
+        } else {
+            0.0
+        };
+        self.fov_kick += (target_kick - self.fov_kick) * (FOV_KICK_SPEED * dt).min(1.0);
+
+        let target_zoom = if self.is_aiming() { 1.0 } else { 0.0 };
+        self.aim_zoom += (target_zoom - self.aim_zoom) * (AIM_ZOOM_SPEED * dt).min(1.0);
+
         // Set pitch for the camera. These lines responsible for up-down camera rotation.
-        scene.graph[self.camera].local_transform_mut().set_rotation(
-            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.controller.pitch.to_radians()),
-        );
+        scene.graph[self.camera]
+            .local_transform_mut()
+            .set_position(Vector3::new(0.0, self.camera_height, 0.0))
+            .set_rotation(UnitQuaternion::from_axis_angle(
+                &Vector3::x_axis(),
+                self.controller.pitch.to_radians(),
+            ));
 
+        // Aiming shows on the third person model as the upper body leaning into the gun
+        let lean = self.aim_zoom * AIM_LEAN;
         scene.graph[self.spine].local_transform_mut().set_rotation(
-            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.controller.pitch.to_radians()),
+            UnitQuaternion::from_axis_angle(
+                &Vector3::x_axis(),
+                (self.controller.pitch + lean).to_radians(),
+            ),
         );
 
         if self.controller.shoot {
-            // TODO: Ammo check here
-            self.shoot_weapon(scene, resource_manager, network_manager, &event_sender);
+            self.shoot_weapon(scene, resource_manager, &event_sender, authoritative);
             animation_input.shoot = true;
         }
 
@@ -513,27 +793,70 @@ impl Player {
         //     listener.set_basis(listener_basis);
         // }
 
-        #[cfg(feature = "server")]
-        if scene.graph[self.rigid_body].global_position().y < -12.0 {
-            event_sender
-                .send(PlayerEvent::KillPlayerFromIntersection {
-                    collider: self.collider,
-                })
-                .unwrap();
-        }
-
         if self.current_player {
-            engine.user_interface.send_message(TextMessage::text(
-                interface.fuel,
-                MessageDirection::ToWidget,
-                format!("{} / {}", self.flight_fuel, MAX_FUEL),
-            ));
+            interface.status.update(
+                &engine.user_interface,
+                (self.health, MAX_HEALTH),
+                (self.armor, MAX_ARMOR),
+                (self.stamina, MAX_STAMINA),
+                (self.flight_fuel, MAX_FUEL),
+            );
+            interface.status.set_ammo(
+                &engine.user_interface,
+                if self.is_reloading() {
+                    format!("{} Reloading", self.weapon().definition().name)
+                } else {
+                    format!(
+                        "{} {} / {}  G {}",
+                        self.weapon().definition().name,
+                        self.ammo(),
+                        self.weapon().definition().max_ammo,
+                        self.grenades
+                    )
+                },
+            );
         }
 
         self.first_person_animation_machine
             .update(scene, dt, animation_input);
         self.third_person_animation_machine
             .update(scene, dt, animation_input);
+
+        #[cfg(not(feature = "server"))]
+        {
+            let phase = self.third_person_animation_machine.walk_phase(scene);
+            let position = self.get_position(scene);
+            let velocity = self.get_velocity(scene);
+            let walking = has_ground_contact
+                && Vector3::new(velocity.x, 0.0, velocity.z).norm() > MIN_STEP_SPEED;
+            self.footsteps.update(
+                scene,
+                &self.footstep_sounds,
+                phase,
+                position,
+                walking,
+                self.crouched,
+            );
+            self.jetpack.update(scene, animation_input.fly);
+
+            // Recoil snaps back quickly and then settles
+            self.recoil_offset +=
+                (self.recoil_target_offset - self.recoil_offset) * (RECOIL_SPEED * dt).min(1.0);
+            self.recoil_target_offset *= (1.0 - RECOIL_RECOVERY * dt).max(0.0);
+
+            self.view_model.update(
+                scene,
+                ViewModelInput {
+                    yaw: self.controller.yaw,
+                    pitch: self.controller.pitch,
+                    velocity,
+                    on_ground: has_ground_contact,
+                    recoil: self.recoil_offset,
+                    aim: self.aim_zoom,
+                },
+                dt,
+            );
+        }
     }
 
     fn can_jump(&self) -> bool {
@@ -548,9 +871,9 @@ impl Player {
         //         .previous_states
         //         .drain(0..length - buffer_length + 1);
         // }
-        if let Some(new_state) = &self.controller.new_states.first_mut() {
+        if let Some(new_state) = self.controller.new_states.oldest() {
             // self.controller.new_state = None;
-            if let Some(previous_state) = self.controller.previous_states.first_mut() {
+            if let Some(previous_state) = self.controller.previous_states.oldest() {
                 // Only sync vertical velocity
                 // let mut velocity_diff: Vector3<f32> =
                 //     Vector3::new(0.0, new_state.velocity.y - previous_state.velocity.y, 0.0);
@@ -592,38 +915,293 @@ impl Player {
                     // let new_pos = Translation3::from(pos_diff) * (*body.global_position());
                     body.local_transform_mut().offset(pos_diff);
 
-                    for previous_state in self.controller.previous_states.iter_mut() {
+                    for previous_state in self.controller.previous_states.values_mut() {
                         previous_state.position += pos_diff;
                     }
 
                     if (move_dist - pos_diff_mag).abs() < f32::EPSILON {
                         self.controller.smoothing_speed = 0.0;
-                        self.controller.new_states.remove(0);
+                        self.controller.new_states.pop_oldest();
                     }
                 } else {
                     self.controller.smoothing_speed = 0.0;
                     // self.controller
                     //     .previous_states
                     //     .remove(SYNC_FREQUENCY as usize);
-                    self.controller.new_states.remove(0);
+                    self.controller.new_states.pop_oldest();
                 }
             }
         }
     }
 
     pub fn has_fuel(&self) -> bool {
-        self.flight_fuel >= 3
+        self.flight_fuel > 0.0
+    }
+
+    pub fn has_stamina(&self) -> bool {
+        self.stamina > 0.0
+    }
+
+    // Can't sprint while crouched or aiming
+    pub fn is_sprinting(&self) -> bool {
+        self.controller.sprint && self.has_stamina() && !self.crouched && !self.is_aiming()
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn recoil_kick(&self) -> Vector3<f32> {
+        let scale = if self.is_aiming() {
+            AIM_RECOIL_MULTIPLIER
+        } else {
+            1.0
+        };
+        Vector3::new(0.0, 0.0, -RECOIL_KICK * scale)
+    }
+
+    // Reloading lowers the gun
+    pub fn is_aiming(&self) -> bool {
+        self.controller.aim && !self.is_reloading()
+    }
+
+    // Shrinks the capsule from the top so the player stays on the ground
+    fn set_crouched(&mut self, scene: &mut Scene, crouched: bool) {
+        self.crouched = crouched;
+
+        let (half_height, offset) = if crouched {
+            (CROUCH_HALF_HEIGHT, -CROUCH_DROP / 2.0)
+        } else {
+            (CAPSULE_HALF_HEIGHT, 0.0)
+        };
+
+        let collider = &mut scene.graph[self.collider];
+        collider
+            .local_transform_mut()
+            .set_position(Vector3::new(0.0, offset, 0.0));
+        collider
+            .as_collider_mut()
+            .set_shape(ColliderShape::capsule_y(half_height, CAPSULE_RADIUS));
+
+        // No crouch animation yet, squash the third person model down to the capsule height
+        let height_scale = if crouched {
+            (CROUCH_HALF_HEIGHT + CAPSULE_RADIUS) / (CAPSULE_HALF_HEIGHT + CAPSULE_RADIUS)
+        } else {
+            1.0
+        };
+        scene.graph[self.third_person_model]
+            .local_transform_mut()
+            .set_scale(Vector3::new(0.1, 0.1 * height_scale, 0.1));
     }
 
     pub fn can_shoot(&self) -> bool {
-        self.shot_timer <= 0.0
+        self.shot_timer <= 0.0 && self.ammo() > 0 && !self.is_reloading()
+    }
+
+    pub fn weapon(&self) -> &Weapon {
+        &self.weapons[self.current_weapon]
+    }
+
+    fn weapon_mut(&mut self) -> &mut Weapon {
+        &mut self.weapons[self.current_weapon]
+    }
+
+    pub fn ammo(&self) -> u32 {
+        self.weapon().ammo
+    }
+
+    // The slot comes with the count, a state sent before a switch is still for the old weapon
+    pub fn set_ammo(&mut self, slot: usize, ammo: u32) {
+        if let Some(weapon) = self.weapons.get_mut(slot) {
+            weapon.ammo = ammo;
+        }
+    }
+
+    pub fn take_damage(&mut self, amount: f32) {
+        let absorbed = (amount * ARMOR_ABSORPTION).min(self.armor);
+        self.armor -= absorbed;
+        self.health = (self.health - (amount - absorbed)).max(0.0);
+    }
+
+    // Whether walking over an item would do anything, so full players leave it for others
+    #[cfg(feature = "server")]
+    pub fn wants_item(&self, kind: ItemKind) -> bool {
+        match kind {
+            ItemKind::Armor => self.armor < MAX_ARMOR,
+            ItemKind::Fuel => self.flight_fuel < MAX_FUEL,
+            ItemKind::Ammo => {
+                self.grenades < MAX_GRENADES
+                    || self
+                        .weapons
+                        .iter()
+                        .any(|weapon| weapon.ammo < weapon.definition().max_ammo)
+            }
+        }
+    }
+
+    pub fn pick_up(&mut self, kind: ItemKind) {
+        match kind {
+            ItemKind::Armor => self.armor = (self.armor + ARMOR_PICKUP).min(MAX_ARMOR),
+            ItemKind::Fuel => self.flight_fuel = MAX_FUEL,
+            ItemKind::Ammo => {
+                for weapon in self.weapons.iter_mut() {
+                    weapon.refill();
+                }
+                self.grenades = MAX_GRENADES;
+            }
+        }
+    }
+
+    pub fn current_weapon_slot(&self) -> usize {
+        self.current_weapon
+    }
+
+    pub fn switch_weapon(&mut self, slot: usize) {
+        if slot != self.current_weapon && slot < self.weapons.len() {
+            // Switching cancels any reload in progress
+            self.current_weapon = slot;
+            self.reload_timer = 0.0;
+            self.shot_timer = SWITCH_TIME;
+        }
+    }
+
+    pub fn can_throw_grenade(&self) -> bool {
+        self.grenades > 0 && self.grenade_timer <= 0.0
+    }
+
+    // Returns false if the player can't throw a grenade right now
+    pub fn throw_grenade(&mut self) -> bool {
+        if self.can_throw_grenade() {
+            self.grenades -= 1;
+            self.grenade_timer = GRENADE_COOLDOWN;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Position and direction the player is aiming from
+    pub fn get_aim(&self, scene: &Scene) -> (Vector3<f32>, Vector3<f32>) {
+        let camera = &scene.graph[self.camera];
+        (camera.global_position(), camera.look_vector().normalize())
+    }
+
+    // Each pull of the trigger starts the recoil pattern over with a new seed. Shots only happen
+    // once the server has relayed the pull, so it and the shooter count the same pulls.
+    pub fn pull_trigger(&mut self) {
+        self.bursts = self.bursts.wrapping_add(1);
+        self.burst_shots = 0;
+    }
+
+    // Share of full movement speed, moving widens the spread
+    fn movement(&self, scene: &Scene) -> f32 {
+        let velocity = self.get_velocity(scene);
+        (velocity.x.hypot(velocity.z) / MOVEMENT_SPEED).min(1.0)
+    }
+
+    // Half angle in degrees of the cone the next shot lands in
+    pub fn spread(&self, scene: &Scene) -> f32 {
+        let shots = if self.controller.shoot {
+            self.burst_shots
+        } else {
+            0
+        };
+        self.weapon()
+            .definition()
+            .spread_at(shots, self.movement(scene), self.is_aiming())
+    }
+
+    // Aim pushed off by spread and recoil for the next shot of the burst
+    fn shot_direction(&self, scene: &Scene) -> Vector3<f32> {
+        let (up, side) = self.weapon().definition().shot_offset(
+            weapon::burst_seed(self.index, self.bursts),
+            self.burst_shots,
+            self.movement(scene),
+            self.is_aiming(),
+        );
+
+        let camera = &scene.graph[self.camera];
+        (camera.look_vector().normalize()
+            + camera.up_vector().normalize() * up.to_radians().tan()
+            + camera.side_vector().normalize() * side.to_radians().tan())
+        .normalize()
+    }
+
+    // Vertical part is applied to the body directly, horizontal part is kept as knockback
+    // velocity since horizontal velocity is overwritten by movement every update
+    pub fn apply_impulse(&mut self, scene: &mut Scene, impulse: Vector3<f32>) {
+        let body = scene.graph[self.rigid_body].as_rigid_body_mut();
+        body.apply_impulse(Vector3::new(0.0, impulse.y, 0.0));
+
+        self.knockback += Vector3::new(impulse.x, 0.0, impulse.z) * KNOCKBACK_SCALE;
+    }
+
+    pub fn is_reloading(&self) -> bool {
+        self.reload_timer > 0.0
+    }
+
+    pub fn can_reload(&self) -> bool {
+        !self.is_reloading() && self.ammo() < self.weapon().definition().max_ammo
+    }
+
+    pub fn reload(&mut self) {
+        if self.can_reload() {
+            self.reload_timer = self.weapon().definition().reload_time;
+        }
+    }
+
+    // Gun of the model that's being shown, first person when looking through this player
+    fn barrel(&self, scene: &Scene) -> Handle<Node> {
+        if scene.graph[self.camera].as_camera().is_enabled() {
+            self.first_person_barrel
+        } else {
+            self.third_person_barrel
+        }
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn muzzle_position(&self, scene: &Scene, direction: Vector3<f32>) -> Vector3<f32> {
+        scene.graph[self.barrel(scene)].global_position() + direction.normalize() * MUZZLE_OFFSET
+    }
+
+    #[cfg(not(feature = "server"))]
+    fn show_muzzle_flash(&self, scene: &mut Scene, position: Vector3<f32>) {
+        use fyrox::scene::{
+            light::{point::PointLightBuilder, BaseLightBuilder},
+            sprite::SpriteBuilder,
+        };
+
+        let transform = || {
+            TransformBuilder::new()
+                .with_local_position(position)
+                .build()
+        };
+
+        PointLightBuilder::new(
+            BaseLightBuilder::new(
+                BaseBuilder::new()
+                    .with_local_transform(transform())
+                    .with_lifetime(MUZZLE_FLASH_LIFETIME),
+            )
+            .with_color(Color::from_rgba(255, 200, 120, 255))
+            .cast_shadows(false),
+        )
+        .with_radius(3.0)
+        .build(&mut scene.graph);
+
+        SpriteBuilder::new(
+            BaseBuilder::new()
+                .with_local_transform(transform())
+                .with_lifetime(MUZZLE_FLASH_LIFETIME),
+        )
+        .with_texture(self.muzzle_flash_texture.clone())
+        .with_color(Color::from_rgba(255, 220, 160, 255))
+        .with_size(0.08)
+        .build(&mut scene.graph);
     }
 
     fn play_shoot_sound(&self, scene: &mut Scene) {
         let source = SoundBuilder::new(
             BaseBuilder::new().with_local_transform(
                 TransformBuilder::new()
-                    .with_local_position(scene.graph[self.barrel].global_position())
+                    .with_local_position(scene.graph[self.barrel(scene)].global_position())
                     .build(),
             ),
         )
@@ -643,7 +1221,7 @@ impl Player {
         //             .build()
         //             .unwrap(),
         //     )
-        //     .with_position(scene.graph[self.barrel].global_position())
+        //     .with_position(scene.graph[self.barrel(scene)].global_position())
         //     // .with_rolloff_factor(1.5)
         //     .build_source(),
         // );
@@ -653,112 +1231,112 @@ impl Player {
         &mut self,
         scene: &mut Scene,
         resource_manager: ResourceManager,
-        network_manager: &mut NetworkManager,
         event_sender: &Sender<PlayerEvent>,
+        authoritative: bool,
     ) {
         if self.can_shoot() {
-            self.shot_timer = 0.1;
+            let definition = self.weapon().definition();
+            let direction = self.shot_direction(scene);
+            self.burst_shots += 1;
+            self.shot_timer = definition.fire_interval;
+            self.weapon_mut().ammo -= 1;
+            if self.current_player || cfg!(feature = "server") {
+                self.shots_fired += 1;
+            }
 
-            // self.recoil_target_offset = Vector3::new(0.0, 0.0, -0.035);
+            if let FireMode::Projectile {
+                speed,
+                splash_radius,
+            } = definition.fire_mode
+            {
+                // Projectiles are only spawned by the server, or by the client in practice
+                if authoritative {
+                    let (origin, _) = self.get_aim(scene);
+
+                    event_sender
+                        .send(PlayerEvent::LaunchProjectile {
+                            owner: self.index,
+                            kind: projectile::ProjectileKind::Rocket,
+                            position: origin + direction * 0.5,
+                            velocity: direction * speed,
+                            damage: definition.damage,
+                            radius: splash_radius,
+                            knockback: definition.knockback,
+                        })
+                        .unwrap();
+                }
 
-            let mut intersections = Vec::new();
+                #[cfg(not(feature = "server"))]
+                {
+                    let muzzle = self.muzzle_position(scene, direction);
+                    self.show_muzzle_flash(scene, muzzle);
+                    self.play_shoot_sound(scene);
+                    self.recoil_target_offset = self.recoil_kick();
+                }
 
-            // TODO: Need to use a third person weapon pivot if camera is not enabled
+                return;
+            }
 
-            // Make a ray that starts at the weapon's position in the world and look toward
-            // "look" vector of the camera.
+            // Make a ray that starts at the camera and goes where spread and recoil push the shot
             let ray = Ray::new(
                 scene.graph[self.camera].global_position(),
-                scene.graph[self.camera]
-                    .look_vector()
-                    .normalize()
-                    .scale(1000.0),
+                direction.scale(1000.0),
             );
 
-            scene.graph.physics.cast_ray(
-                RayCastOptions {
-                    ray_origin: ray.origin.into(),
-                    ray_direction: ray.dir,
-                    max_len: ray.dir.norm(),
-                    groups: Default::default(),
-                    sort_results: true, // We need intersections to be sorted from closest to furthest.
-                },
-                &mut intersections,
+            // The ray starts inside the shooter's own capsule so that's skipped
+            let hit = collision::cast_ray(
+                &scene.graph,
+                ray.origin,
+                ray.dir,
+                ray.dir.norm(),
+                collision::SOLID_GROUPS,
+                self.collider,
             );
 
-            // Ignore intersections with player's capsule.
-            let trail_length = if let Some(intersection) =
-                intersections.iter().find(|i| i.collider != self.collider)
-            {
-                let node_handle = scene.graph[intersection.collider].parent();
-                let node = &mut scene.graph[node_handle];
-                if node.is_rigid_body() {
-                    let tag = node.tag();
-
-                    #[cfg(feature = "server")]
-                    let mut destroy_block = false;
-                    #[cfg(feature = "server")]
-                    let mut kill_player = false;
-
-                    // TODO: Should probably use collider groups instead of tag?
-                    match tag {
-                        "wall" => (),
-                        "player" => {
-                            #[cfg(feature = "server")]
-                            node.set_tag("player_1_hp".to_string());
-                        }
-                        #[cfg(feature = "server")]
-                        "player_1_hp" => {
-                            kill_player = true;
+            let trail_length = if let Some(intersection) = hit {
+                let surface = SurfaceKind::of(&scene.graph, intersection.collider);
+
+                if authoritative {
+                    match surface {
+                        Some(SurfaceKind::Player) => {
+                            let distance = (intersection.position.coords - ray.origin).norm();
+                            let event = PlayerEvent::DamagePlayerFromIntersection {
+                                collider: intersection.collider,
+                                amount: definition.damage_at(distance),
+                                source: self.index,
+                                impulse: ray.dir.normalize() * definition.knockback,
+                                position: intersection.position.coords,
+                                headshot_multiplier: definition.headshot_multiplier,
+                            };
+                            event_sender.send(event).unwrap();
                         }
-                        #[cfg(feature = "server")]
-                        "destructable" => {
-                            destroy_block = true;
+                        Some(SurfaceKind::Block) => {
+                            let block = scene.graph[intersection.collider].parent();
+                            event_sender
+                                .send(PlayerEvent::HitBlock {
+                                    index: block.index(),
+                                    source: self.index,
+                                })
+                                .unwrap();
                         }
-                        _ => {
-                            #[cfg(feature = "server")]
-                            node.set_tag("destructable".to_string());
-                        }
-                    }
-
-                    #[cfg(feature = "server")]
-                    if destroy_block {
-                        let event = PlayerEvent::DestroyBlock {
-                            index: node_handle.index(),
-                        };
-                        let message = NetworkMessage::PlayerEvent {
-                            index: node_handle.index(),
-                            event: event,
-                        };
-
-                        // network_manager.send_to_all_unreliably(&message, 2);
-                        network_manager.send_to_all_reliably(&message);
-                        event_sender.send(event).unwrap();
-                    }
-
-                    #[cfg(feature = "server")]
-                    if kill_player {
-                        let event = PlayerEvent::KillPlayerFromIntersection {
-                            collider: intersection.collider,
-                        };
-                        event_sender.send(event).unwrap();
+                        _ => (),
                     }
                 }
 
                 // Add bullet impact effect.
-                // let effect_orientation = if intersection.normal.normalize() == Vector3::y() {
-                //     // Handle singularity when normal of impact point is collinear with Y axis.
-                //     UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.0)
-                // } else {
-                //     UnitQuaternion::face_towards(&intersection.normal, &Vector3::y())
-                // };
-
-                // create_bullet_impact(
-                //     &mut scene.graph,
-                //     resource_manager.clone(),
-                //     intersection.position.coords,
-                //     effect_orientation,
-                // );
+                #[cfg(not(feature = "server"))]
+                {
+                    self.impacts.push(Impact {
+                        position: intersection.position.coords,
+                        normal: intersection.normal,
+                        decal: surface == Some(SurfaceKind::World),
+                    });
+
+                    if self.current_player && surface == Some(SurfaceKind::Block) {
+                        self.block_hits
+                            .push(scene.graph[intersection.collider].parent());
+                    }
+                }
 
                 // Trail length will be the length of line between intersection point and ray origin.
                 (intersection.position.coords - ray.origin).norm()
@@ -767,11 +1345,19 @@ impl Player {
                 ray.dir.norm()
             };
 
-            // #[cfg(not(feature = "server"))]
-            // create_shot_trail(&mut scene.graph, ray.origin, ray.dir, trail_length);
-
+            // Hits are worked out from the camera but the shot is drawn from the gun
             #[cfg(not(feature = "server"))]
-            self.play_shoot_sound(scene);
+            {
+                let target = ray.origin + ray.dir.normalize() * trail_length;
+                self.shot_rays.push((ray.origin, target));
+                let muzzle = self.muzzle_position(scene, ray.dir);
+                let trail = target - muzzle;
+
+                create_shot_trail(&mut scene.graph, muzzle, trail, trail.norm());
+                self.show_muzzle_flash(scene, muzzle);
+                self.play_shoot_sound(scene);
+                self.recoil_target_offset = self.recoil_kick();
+            }
 
             // Reset camera rotation
             // scene.graph[self.camera]
@@ -792,6 +1378,11 @@ impl Player {
         body.global_position()
     }
 
+    // The camera sits at eye height and drops with crouching, so the head is measured from it
+    pub fn is_headshot(&self, scene: &Scene, position: Vector3<f32>) -> bool {
+        position.y >= scene.graph[self.camera].global_position().y - HEAD_DEPTH
+    }
+
     pub fn get_yaw(&self) -> f32 {
         self.controller.yaw
     }
@@ -819,90 +1410,185 @@ impl Player {
     }
 }
 
-async fn create_skybox(resource_manager: ResourceManager) -> SkyBox {
+// Field of view is in degrees
+pub fn set_camera_fov(scene: &mut Scene, camera: Handle<Node>, fov: f32) {
+    scene.graph[camera]
+        .as_camera_mut()
+        .set_projection(Projection::Perspective(PerspectiveProjection {
+            fov: fov.to_radians(),
+            ..Default::default()
+        }));
+}
+
+// Everything a player is built from. Loaded once when a level starts and instantiated for each
+// player that spawns.
+pub struct PlayerResources {
+    first_person_model: Model,
+    skins: Vec<SkinModel>, // Default skin first
+    animations: PlayerAnimations,
+    firing_sound: SoundBufferResource,
+    #[cfg(not(feature = "server"))]
+    muzzle_flash: fyrox::resource::texture::Texture,
+    #[cfg(not(feature = "server"))]
+    footsteps: FootstepSounds,
+    #[cfg(not(feature = "server"))]
+    jetpack: JetpackResources,
+    skybox: SkyBox,
+}
+
+struct SkinModel {
+    id: u32,
+    path: String,
+    model: Model,
+}
+
+const FIRST_PERSON_MODEL_PATH: &str = "data/models/walking_1st.fbx";
+const FIRING_SOUND_PATH: &str = "data/sounds/laser4.ogg";
+const SKYBOX_PATH: &str = "data/textures/skybox/";
+
+impl PlayerResources {
+    pub async fn load(resource_manager: ResourceManager) -> Result<Self, GameError> {
+        let (first_person_model, firing_sound, animations, skybox) = fyrox::core::futures::join!(
+            resource_manager.request_model(FIRST_PERSON_MODEL_PATH),
+            resource_manager.request_sound_buffer(FIRING_SOUND_PATH),
+            PlayerAnimations::load(resource_manager.clone()),
+            create_skybox(resource_manager.clone()),
+        );
+
+        // Every skin is loaded up front so nobody has to wait for a model when someone spawns
+        let manifest = skins::load_manifest();
+        let models = fyrox::core::futures::future::join_all(
+            manifest
+                .iter()
+                .map(|skin| resource_manager.request_model(&skin.model)),
+        )
+        .await;
+        let mut skin_models = Vec::new();
+        for (skin, model) in manifest.into_iter().zip(models) {
+            match model {
+                Ok(model) => skin_models.push(SkinModel {
+                    id: skin.id,
+                    path: skin.model,
+                    model,
+                }),
+                // Only the default one has to be there
+                Err(_) if skin.id != skins::DEFAULT_SKIN => {
+                    warn!("failed to load skin {} from {}", skin.name, skin.model)
+                }
+                Err(_) => return Err(GameError::Resource(skin.model)),
+            }
+        }
+
+        #[cfg(not(feature = "server"))]
+        let footsteps = FootstepSounds::load(resource_manager.clone()).await;
+        #[cfg(not(feature = "server"))]
+        let jetpack = JetpackResources::load(resource_manager.clone()).await;
+
+        Ok(Self {
+            first_person_model: first_person_model
+                .map_err(|_| GameError::resource(FIRST_PERSON_MODEL_PATH))?,
+            skins: skin_models,
+            animations: animations?,
+            firing_sound: firing_sound.map_err(|_| GameError::resource(FIRING_SOUND_PATH))?,
+            #[cfg(not(feature = "server"))]
+            muzzle_flash: resource_manager.request_texture("data/textures/spark.png"),
+            #[cfg(not(feature = "server"))]
+            footsteps,
+            #[cfg(not(feature = "server"))]
+            jetpack,
+            skybox: skybox?,
+        })
+    }
+
+    // Players using a skin we don't have get the default one
+    fn skin(&self, id: u32) -> &SkinModel {
+        self.skins
+            .iter()
+            .find(|skin| skin.id == id)
+            .unwrap_or(&self.skins[0])
+    }
+}
+
+pub async fn create_skybox(resource_manager: ResourceManager) -> Result<SkyBox, GameError> {
+    let path = |face: &str| [SKYBOX_PATH, face, ".png"].concat();
+
     // Load skybox textures in parallel.
     let (front, back, left, right, top, bottom) = fyrox::core::futures::join!(
-        resource_manager.request_texture("data/textures/skybox/front.png"),
-        resource_manager.request_texture("data/textures/skybox/back.png"),
-        resource_manager.request_texture("data/textures/skybox/left.png"),
-        resource_manager.request_texture("data/textures/skybox/right.png"),
-        resource_manager.request_texture("data/textures/skybox/top.png"),
-        resource_manager.request_texture("data/textures/skybox/down.png")
+        resource_manager.request_texture(path("front")),
+        resource_manager.request_texture(path("back")),
+        resource_manager.request_texture(path("left")),
+        resource_manager.request_texture(path("right")),
+        resource_manager.request_texture(path("top")),
+        resource_manager.request_texture(path("down"))
     );
 
-    // Unwrap everything.
+    let face = |texture: Result<_, _>, name: &str| {
+        texture
+            .map(Some)
+            .map_err(|_| GameError::Resource(path(name)))
+    };
     let skybox = SkyBoxBuilder {
-        front: Some(front.unwrap()),
-        back: Some(back.unwrap()),
-        left: Some(left.unwrap()),
-        right: Some(right.unwrap()),
-        top: Some(top.unwrap()),
-        bottom: Some(bottom.unwrap()),
+        front: face(front, "front")?,
+        back: face(back, "back")?,
+        left: face(left, "left")?,
+        right: face(right, "right")?,
+        top: face(top, "top")?,
+        bottom: face(bottom, "down")?,
     }
     .build()
-    .unwrap();
+    .map_err(|_| GameError::resource(SKYBOX_PATH))?;
 
     // Set S and T coordinate wrap mode, ClampToEdge will remove any possible seams on edges
     // of the skybox.
-    let cubemap = skybox.cubemap();
-    let mut data = cubemap.as_ref().unwrap().data_ref();
-    data.set_s_wrap_mode(TextureWrapMode::ClampToEdge);
-    data.set_t_wrap_mode(TextureWrapMode::ClampToEdge);
+    if let Some(cubemap) = skybox.cubemap() {
+        let mut data = cubemap.data_ref();
+        data.set_s_wrap_mode(TextureWrapMode::ClampToEdge);
+        data.set_t_wrap_mode(TextureWrapMode::ClampToEdge);
+    }
 
-    skybox
+    Ok(skybox)
 }
 
-// #[cfg(not(feature = "server"))]
-// fn create_bullet_impact(
-//     graph: &mut Graph,
-//     resource_manager: ResourceManager,
-//     pos: Vector3<f32>,
-//     orientation: UnitQuaternion<f32>,
-// ) -> Handle<Node> {
-//     // Create sphere emitter first.
-//     let emitter = SphereEmitterBuilder::new(
-//         BaseEmitterBuilder::new()
-//             .with_max_particles(200)
-//             .with_spawn_rate(1000)
-//             .with_size_modifier_range(NumericRange::new(-0.01, -0.0125))
-//             .with_size_range(NumericRange::new(0.0010, 0.01))
-//             .with_x_velocity_range(NumericRange::new(-0.01, 0.01))
-//             .with_y_velocity_range(NumericRange::new(0.017, 0.02))
-//             .with_z_velocity_range(NumericRange::new(-0.01, 0.01))
-//             .resurrect_particles(false),
-//     )
-//     .with_radius(0.01)
-//     .build();
-
-//     // Color gradient will be used to modify color of each particle over its lifetime.
-//     let color_gradient = {
-//         let mut gradient = ColorGradient::new();
-//         gradient.add_point(GradientPoint::new(0.00, Color::from_rgba(255, 255, 0, 0)));
-//         gradient.add_point(GradientPoint::new(0.05, Color::from_rgba(255, 160, 0, 255)));
-//         gradient.add_point(GradientPoint::new(0.95, Color::from_rgba(255, 120, 0, 255)));
-//         gradient.add_point(GradientPoint::new(1.00, Color::from_rgba(255, 60, 0, 0)));
-//         gradient
-//     };
-
-//     // Create new transform to orient and position particle system.
-//     let transform = TransformBuilder::new()
-//         .with_local_position(pos)
-//         .with_local_rotation(orientation)
-//         .build();
-
-//     // Finally create particle system with limited lifetime.
-//     ParticleSystemBuilder::new(
-//         BaseBuilder::new()
-//             .with_lifetime(1.0)
-//             .with_local_transform(transform),
-//     )
-//     .with_acceleration(Vector3::new(0.0, -10.0, 0.0))
-//     .with_color_over_lifetime_gradient(color_gradient)
-//     .with_emitters(vec![emitter])
-//     // We'll use simple spark texture for each particle.
-//     .with_texture(resource_manager.request_texture(Path::new("data/textures/spark.png")))
-//     .build(graph)
-// }
+// Instances share materials with the model resource, so every surface gets its own copy before
+// it's tinted or every player would change color
+#[cfg(not(feature = "server"))]
+fn tint_model(graph: &mut Graph, model: Handle<Node>, color: Color) {
+    use std::sync::Arc;
+
+    use fyrox::core::{parking_lot::Mutex, sstorage::ImmutableString};
+
+    let meshes: Vec<Handle<Node>> = graph
+        .traverse_handle_iter(model)
+        .filter(|&handle| graph[handle].is_mesh())
+        .collect();
+
+    for mesh in meshes {
+        for surface in graph[mesh].as_mesh_mut().surfaces_mut() {
+            let mut material = surface.material().lock().clone();
+            if let Err(e) = material.set_property(
+                &ImmutableString::new("diffuseColor"),
+                PropertyValue::Color(color),
+            ) {
+                warn!("failed to tint player model: {:?}", e);
+            }
+            surface.set_material(Arc::new(Mutex::new(material)));
+        }
+    }
+}
+
+// Pivot in the gun hand that shots are drawn from. The gun mesh is skinned so its own node stays
+// at the model origin whatever the animation is doing.
+fn create_barrel(scene: &mut Scene, model: Handle<Node>) -> Handle<Node> {
+    let hand = scene.graph.find_by_name(model, "Bind_RightHand");
+    let barrel = BaseBuilder::new()
+        .with_name("Barrel")
+        .build(&mut scene.graph);
+
+    let parent = if hand.is_some() { hand } else { model };
+    scene.graph.link_nodes(barrel, parent);
+
+    barrel
+}
 
 #[cfg(not(feature = "server"))]
 fn create_shot_trail(
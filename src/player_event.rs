@@ -7,49 +7,81 @@ use fyrox::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::{codec, kill_zones::ZoneKind, projectile::ProjectileKind};
+
 #[derive(Debug, Serialize, Deserialize, Copy, Clone)]
 pub enum PlayerEvent {
     ShootWeapon {
         index: u32,
         active: bool,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
     },
     MoveForward {
         index: u32,
         active: bool,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
     },
     MoveBackward {
         index: u32,
         active: bool,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
     },
     MoveLeft {
         index: u32,
         active: bool,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
     },
     MoveRight {
         index: u32,
         active: bool,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
     },
-    MoveUp {
+    // Analog stick movement, forward and right are between -1 and 1
+    MoveAnalog {
         index: u32,
-        active: bool,
+        forward: f32,
+        right: f32,
+        #[serde(with = "codec::angle")]
+        yaw: f32,
+        #[serde(with = "codec::angle")]
+        pitch: f32,
     },
     Jump {
         index: u32,
     },
+    Crouch {
+        index: u32,
+        active: bool,
+    },
+    Sprint {
+        index: u32,
+        active: bool,
+        stamina: f32,
+    },
+    // Fuel is only counted by the server, clients get it back in snapshots
     Fly {
         index: u32,
         active: bool,
-        fuel: u32,
+    },
+    // Aiming down sights, others see the player lean into the gun
+    Aim {
+        index: u32,
+        active: bool,
     },
     LookAround {
         index: u32,
@@ -60,46 +92,131 @@ pub enum PlayerEvent {
     UpdateState {
         timestamp: f32,
         index: u32,
-        position: SerializableVector,
-        velocity: SerializableVector,
+        #[serde(with = "codec::fixed_vector")]
+        position: Vector3<f32>,
+        #[serde(with = "codec::fixed_vector")]
+        velocity: Vector3<f32>,
+        #[serde(with = "codec::angle")]
         yaw: f32,
+        #[serde(with = "codec::angle")]
         pitch: f32,
         shoot: bool,
-        fuel: u32,
+        fuel: f32,
+        weapon: u8, // Slot the ammo count is for
+        ammo: u32,
     },
     DestroyBlock {
         index: u32,
     },
+    // Sent by the server when a player walks into an item
+    PickupItem {
+        index: u32,
+        item: u32, // Index of the item in the level's pickups
+    },
+    // Server only, a shot hit a block. Blocks break on their second hit.
+    HitBlock {
+        index: u32,
+        source: u32, // Index of the player that shot it
+    },
+    // Sent by the server when a block has gone without support for a moment and starts falling
+    SpawnFallingBlock {
+        index: u32,
+    },
+    // Sent by the server when a hit doesn't break a block, stage goes up to CRACK_STAGES
+    CrackBlock {
+        index: u32,
+        stage: u32,
+        hits_left: u32, // So clients know when their next shots should break it
+    },
     KillPlayer {
         index: u32,
+        killer: Option<u32>,    // None for falling out of the level or leaving
+        zone: Option<ZoneKind>, // What killed the player if it wasn't another player
     },
-    KillPlayerFromIntersection {
+    DamagePlayerFromIntersection {
         #[serde(skip)]
         collider: Handle<Node>,
+        amount: f32, // After distance falloff
+        source: u32,
+        #[serde(with = "codec::vector")]
+        impulse: Vector3<f32>, // Knockback along the shot direction
+        #[serde(with = "codec::vector")]
+        position: Vector3<f32>, // Where the shot hit, to tell headshots apart
+        headshot_multiplier: f32,
+    },
+    Damage {
+        index: u32,
+        amount: f32,
+        source: u32, // Index of the player that caused the damage
+        headshot: bool,
+    },
+    // Only sent to the player whose shot did the damage
+    HitConfirmed {
+        index: u32,
+        target: u32,
+        killed: bool,
+        headshot: bool,
     },
     SpawnPlayer {
-        state: SerializablePlayerState, // TODO: Should probably just serialize PlayerState
+        state: SerializablePlayerState,
         index: u32,
         current_player: bool,
+        skin: u32,
     },
     Reload {
         index: u32,
     },
+    SwitchWeapon {
+        index: u32,
+        slot: u32,
+    },
+    // Server only, the level assigns an id and sends SpawnProjectile to clients
+    LaunchProjectile {
+        owner: u32,
+        kind: ProjectileKind,
+        #[serde(with = "codec::fixed_vector")]
+        position: Vector3<f32>,
+        #[serde(with = "codec::fixed_vector")]
+        velocity: Vector3<f32>,
+        damage: f32,
+        radius: f32,
+        knockback: f32,
+    },
+    SpawnProjectile {
+        id: u32,
+        owner: u32,
+        kind: ProjectileKind,
+        #[serde(with = "codec::fixed_vector")]
+        position: Vector3<f32>,
+        #[serde(with = "codec::fixed_vector")]
+        velocity: Vector3<f32>,
+    },
+    DetonateProjectile {
+        id: u32,
+        #[serde(with = "codec::fixed_vector")]
+        position: Vector3<f32>,
+    },
+    ThrowGrenade {
+        index: u32,
+    },
+    ApplyImpulse {
+        index: u32,
+        #[serde(with = "codec::vector")]
+        impulse: Vector3<f32>,
+    },
 }
 
+// What a player is spawned with
 #[derive(Default, Debug, Serialize, Deserialize, Copy, Clone)]
 pub struct SerializablePlayerState {
-    pub position: SerializableVector,
-    pub velocity: SerializableVector,
+    #[serde(with = "codec::fixed_vector")]
+    pub position: Vector3<f32>,
+    #[serde(with = "codec::fixed_vector")]
+    pub velocity: Vector3<f32>,
+    #[serde(with = "codec::angle")]
     pub yaw: f32,
+    #[serde(with = "codec::angle")]
     pub pitch: f32,
     pub shoot: bool,
-    pub fuel: u32,
-}
-
-#[derive(Default, Debug, Serialize, Deserialize, Clone, Copy)]
-pub struct SerializableVector {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+    pub fuel: f32,
 }
@@ -0,0 +1,104 @@
+// Lifetime numbers for the local player, kept per profile name in STATS_PATH so people sharing a
+// computer each get their own. The level counts what happens to the local player and the game adds
+// it to the profile of whoever is playing. Only games on a server count, practice and demos don't.
+
+use std::{collections::BTreeMap, error::Error, fs::File, io::BufReader, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+pub const STATS_PATH: &str = "stats.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlayerStats {
+    pub kills: u32,
+    pub deaths: u32,
+    pub blocks_destroyed: u32,
+    pub shots: u32,
+    pub hits: u32, // Confirmed by the server, splash damage can hit several players at once
+    pub playtime: f32, // Seconds
+}
+
+impl PlayerStats {
+    pub fn add(&mut self, other: &PlayerStats) {
+        self.kills += other.kills;
+        self.deaths += other.deaths;
+        self.blocks_destroyed += other.blocks_destroyed;
+        self.shots += other.shots;
+        self.hits += other.hits;
+        self.playtime += other.playtime;
+    }
+
+    // Percent of shots that hit someone, None before the first shot
+    pub fn accuracy(&self) -> Option<f32> {
+        if self.shots == 0 {
+            return None;
+        }
+        Some((self.hits as f32 / self.shots as f32 * 100.0).min(100.0))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StatsBook {
+    profiles: BTreeMap<String, PlayerStats>,
+    unsaved: bool,
+}
+
+impl StatsBook {
+    // A missing file means nobody has played yet, anything else wrong with it is logged
+    pub fn load() -> Self {
+        if !Path::new(STATS_PATH).exists() {
+            return Self::default();
+        }
+
+        match read_profiles(STATS_PATH) {
+            Ok(profiles) => Self {
+                profiles,
+                unsaved: false,
+            },
+            Err(e) => {
+                warn!("failed to read {}: {}", STATS_PATH, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn get(&self, profile: &str) -> PlayerStats {
+        self.profiles.get(profile).cloned().unwrap_or_default()
+    }
+
+    pub fn add(&mut self, profile: &str, stats: &PlayerStats) {
+        self.profiles
+            .entry(profile.to_string())
+            .or_default()
+            .add(stats);
+        self.unsaved = true;
+    }
+
+    // Only writes the file if something changed since it was last saved
+    pub fn save(&mut self) {
+        if !self.unsaved {
+            return;
+        }
+
+        match write_profiles(STATS_PATH, &self.profiles) {
+            Ok(()) => self.unsaved = false,
+            Err(e) => warn!("failed to save {}: {}", STATS_PATH, e),
+        }
+    }
+}
+
+fn read_profiles<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, PlayerStats>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+fn write_profiles<P: AsRef<Path>>(
+    path: P,
+    profiles: &BTreeMap<String, PlayerStats>,
+) -> Result<(), Box<dyn Error>> {
+    let file = File::create(path)?;
+    serde_json::to_writer_pretty(file, profiles)?;
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::collision::{self, SurfaceKind};
+
+use fyrox::{
+    core::{
+        algebra::{Matrix4, Vector3},
+        color::Color,
+        parking_lot::Mutex,
+        pool::Handle,
+        sstorage::ImmutableString,
+    },
+    material::{Material, PropertyValue},
+    scene::{
+        base::BaseBuilder,
+        collider::{ColliderBuilder, ColliderShape},
+        mesh::{
+            surface::{SurfaceBuilder, SurfaceData},
+            MeshBuilder,
+        },
+        node::Node,
+        rigidbody::RigidBodyBuilder,
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+
+const PROJECTILE_RADIUS: f32 = 0.1;
+const PROJECTILE_GRAVITY_SCALE: f32 = 0.2;
+// Projectiles that don't hit anything detonate after this many seconds
+const MAX_LIFETIME: f32 = 5.0;
+
+pub const GRENADE_FUSE: f32 = 2.5;
+pub const GRENADE_SPEED: f32 = 9.0;
+pub const GRENADE_RADIUS: f32 = 2.0; // Explosion radius
+pub const GRENADE_KNOCKBACK: f32 = 0.6;
+const GRENADE_GRAVITY_SCALE: f32 = 1.0;
+
+#[derive(Debug, Serialize, Deserialize, Copy, Clone, PartialEq)]
+pub enum ProjectileKind {
+    // Explodes on impact
+    Rocket,
+    // Bounces around and explodes when the fuse runs out
+    Grenade,
+}
+
+// How a projectile is launched and what it does when it goes off. Clients get zeros for the
+// damage, radius and knockback since only the server applies the explosion.
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectileSpawn {
+    pub kind: ProjectileKind,
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub damage: f32,
+    pub radius: f32,    // Explosion radius
+    pub knockback: f32, // Strength of the impulse applied to players caught in the explosion
+}
+
+pub struct Projectile {
+    pub id: u32,
+    pub owner: u32,
+    pub kind: ProjectileKind,
+    pub damage: f32,
+    pub radius: f32,    // Explosion radius
+    pub knockback: f32, // Strength of the impulse applied to players caught in the explosion
+    rigid_body: Handle<Node>,
+    owner_collider: Handle<Node>,
+    last_position: Vector3<f32>,
+    lifetime: f32,
+}
+
+impl Projectile {
+    pub fn new(
+        scene: &mut Scene,
+        id: u32,
+        owner: u32,
+        owner_collider: Handle<Node>,
+        spawn: ProjectileSpawn,
+    ) -> Self {
+        let ProjectileSpawn {
+            kind,
+            position,
+            velocity,
+            damage,
+            radius,
+            knockback,
+        } = spawn;
+
+        let mut material = Material::standard();
+        material
+            .set_property(
+                &ImmutableString::new("diffuseColor"),
+                PropertyValue::Color(match kind {
+                    ProjectileKind::Rocket => Color::from_rgba(255, 140, 40, 255),
+                    ProjectileKind::Grenade => Color::from_rgba(60, 120, 40, 255),
+                }),
+            )
+            .unwrap();
+
+        let mesh = MeshBuilder::new(BaseBuilder::new())
+            .with_surfaces(vec![SurfaceBuilder::new(Arc::new(Mutex::new(
+                SurfaceData::make_sphere(8, 8, PROJECTILE_RADIUS, &Matrix4::identity()),
+            )))
+            .with_material(Arc::new(Mutex::new(material)))
+            .build()])
+            .with_cast_shadows(false)
+            .build(&mut scene.graph);
+
+        // Rockets are sensors so they don't bounce off things, hits are found by sweeping a ray instead
+        let collider = ColliderBuilder::new(BaseBuilder::new())
+            .with_shape(ColliderShape::ball(PROJECTILE_RADIUS))
+            .with_sensor(kind == ProjectileKind::Rocket)
+            .with_collision_groups(SurfaceKind::Projectile.interaction_groups())
+            .with_restitution(0.4)
+            .build(&mut scene.graph);
+
+        let gravity_scale = match kind {
+            ProjectileKind::Rocket => PROJECTILE_GRAVITY_SCALE,
+            ProjectileKind::Grenade => GRENADE_GRAVITY_SCALE,
+        };
+
+        let rigid_body = RigidBodyBuilder::new(
+            BaseBuilder::new()
+                .with_local_transform(
+                    TransformBuilder::new()
+                        .with_local_position(position)
+                        .build(),
+                )
+                .with_children(&[mesh, collider]),
+        )
+        .with_lin_vel(velocity)
+        .with_gravity_scale(gravity_scale)
+        .with_can_sleep(false)
+        .build(&mut scene.graph);
+
+        Self {
+            id,
+            owner,
+            kind,
+            damage,
+            radius,
+            knockback,
+            rigid_body,
+            owner_collider,
+            last_position: position,
+            lifetime: 0.0,
+        }
+    }
+
+    // Returns the impact position if the projectile hit something since the last update or
+    // ran out of lifetime
+    pub fn update(&mut self, scene: &mut Scene, dt: f32) -> Option<Vector3<f32>> {
+        self.lifetime += dt;
+
+        let position = scene.graph[self.rigid_body].global_position();
+
+        if self.kind == ProjectileKind::Grenade {
+            return if self.lifetime >= GRENADE_FUSE {
+                Some(position)
+            } else {
+                None
+            };
+        }
+
+        let travel = position - self.last_position;
+        let distance = travel.norm();
+
+        // Projectiles aren't solid so the rocket doesn't hit itself
+        let hit = if distance > f32::EPSILON {
+            collision::cast_ray(
+                &scene.graph,
+                self.last_position,
+                travel,
+                distance,
+                collision::SOLID_GROUPS,
+                self.owner_collider,
+            )
+        } else {
+            None
+        };
+
+        let origin = self.last_position;
+        self.last_position = position;
+
+        if let Some(intersection) = hit {
+            // Explode slightly in front of the surface so blocks behind it aren't favoured, a hit
+            // right at the start of the ray has no direction to back off along
+            let impact = intersection.position.coords;
+            Some(
+                (impact - origin)
+                    .try_normalize(f32::EPSILON)
+                    .map_or(impact, |direction| impact - direction * PROJECTILE_RADIUS),
+            )
+        } else if self.lifetime >= MAX_LIFETIME {
+            Some(position)
+        } else {
+            None
+        }
+    }
+
+    pub fn clean_up(&self, scene: &mut Scene) {
+        scene.remove_node(self.rigid_body);
+    }
+}
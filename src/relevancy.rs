@@ -0,0 +1,73 @@
+// Picks which players go into the snapshot sent to each client. Players near the receiver or in
+// front of them are sent every snapshot, everyone else only now and then so their position
+// doesn't drift too far. Input events are still relayed to everyone, this only cuts snapshot
+// traffic.
+
+use crate::snapshot::{PlayerSnapshot, WorldSnapshot};
+
+// Distant players are included in every this many snapshots
+const KEEPALIVE_INTERVAL: u32 = 10;
+// Players in front of the receiver are relevant this many times further out
+const VIEW_DISTANCE_SCALE: f32 = 3.0;
+const VIEW_CONE_ANGLE: f32 = 50.0; // Degrees either side of where the receiver is looking
+
+pub struct Relevancy {
+    radius: f32, // 0 sends everyone every snapshot
+}
+
+impl Relevancy {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius: radius.max(0.0),
+        }
+    }
+
+    // Snapshot as it should be sent to the given player
+    pub fn filter(&self, snapshot: &WorldSnapshot, receiver: u32) -> WorldSnapshot {
+        let viewer = snapshot.players.iter().find(|p| p.index == receiver);
+
+        let players = match viewer {
+            // Spectators and dead players can look anywhere
+            Some(viewer) if self.radius > 0.0 => {
+                // Spread keepalives for different receivers over different snapshots
+                let keepalive = (snapshot.sequence + receiver) % KEEPALIVE_INTERVAL == 0;
+
+                snapshot
+                    .players
+                    .iter()
+                    .filter(|player| keepalive || self.is_relevant(viewer, player))
+                    .copied()
+                    .collect()
+            }
+            _ => snapshot.players.clone(),
+        };
+
+        WorldSnapshot {
+            sequence: snapshot.sequence,
+            timestamp: snapshot.timestamp,
+            players,
+        }
+    }
+
+    fn is_relevant(&self, viewer: &PlayerSnapshot, player: &PlayerSnapshot) -> bool {
+        if player.index == viewer.index {
+            return true;
+        }
+
+        // Only the horizontal direction matters, players are rarely far above or below
+        let dx = player.position.x - viewer.position.x;
+        let dz = player.position.z - viewer.position.z;
+        let distance = (dx * dx + dz * dz).sqrt();
+        if distance <= self.radius {
+            return true;
+        }
+        if distance > self.radius * VIEW_DISTANCE_SCALE {
+            return false;
+        }
+
+        // Players look along +Z rotated by yaw around the Y axis
+        let yaw = viewer.yaw.to_radians();
+        let cos_angle = (dx * yaw.sin() + dz * yaw.cos()) / distance;
+        cos_angle >= VIEW_CONE_ANGLE.to_radians().cos()
+    }
+}
@@ -0,0 +1,171 @@
+use std::{collections::HashMap, error::Error, fs::File, io::BufReader, path::Path};
+
+use serde::Deserialize;
+
+use crate::match_state::GameMode;
+
+pub const SERVER_CONFIG_PATH: &str = "server.json";
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub maps: Vec<String>,   // Played in this order unless players vote otherwise
+    pub warmup_time: f32,    // Countdown before a round starts once enough players joined
+    pub score_limit: u32,    // Kills needed to win a round, 0 for no limit
+    pub time_limit: f32,     // Round length in seconds, 0 for no limit
+    pub round_end_time: f32, // Seconds results are shown and players can vote for the next map
+    pub mode: GameMode,
+    pub shrink_interval: f32, // Seconds between the floor shrinking in elimination mode
+    // Simulation steps per second, clients switch to this when they join
+    pub tick_rate: u32,
+    pub sync_rate: u32, // Snapshots sent per second, at most one per tick
+    // Players further than this from someone, and not in front of them, are only sent to them
+    // every few snapshots. 0 sends everyone every snapshot.
+    pub relevancy_radius: f32,
+    // Bytes per second the server sends each player at most, state updates are skipped for
+    // players past it. 0 for no limit.
+    pub send_budget: u32,
+    pub max_players: u32, // Players turned away once this many are in, 0 for no limit
+    pub password: Option<String>, // Players have to enter it in the menu to join
+    // Entered instead of the password to join as an observer, who watches without playing and
+    // doesn't take a player slot. None turns observing off.
+    pub observer_password: Option<String>,
+    // Seconds without any input before a player is kicked to free up their slot, 0 to never kick
+    pub idle_kick_time: f32,
+    // Prometheus metrics are served over HTTP on this port, 0 to not serve them
+    pub metrics_port: u16,
+    pub bot_count: u32,    // Computer players added to every level
+    pub bot_accuracy: f32, // 0 to 1, how well bots aim and how fast they react
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            maps: vec![String::from("block_test")],
+            warmup_time: 10.0,
+            score_limit: 10,
+            time_limit: 300.0,
+            round_end_time: 15.0,
+            mode: GameMode::Standard,
+            shrink_interval: 20.0,
+            tick_rate: 60,
+            sync_rate: 20,
+            relevancy_radius: 20.0,
+            send_budget: 64 * 1024,
+            max_players: 16,
+            password: None,
+            observer_password: None,
+            idle_kick_time: 300.0,
+            metrics_port: 0,
+            bot_count: 0,
+            bot_accuracy: 0.5,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn read_from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+pub struct MapRotation {
+    maps: Vec<String>,
+    current: usize,
+    votes: HashMap<u32, usize>, // Player index -> map index
+    voting: bool,
+}
+
+impl MapRotation {
+    pub fn new(config: &ServerConfig) -> Self {
+        let maps = if config.maps.is_empty() {
+            ServerConfig::default().maps
+        } else {
+            config.maps.clone()
+        };
+
+        Self {
+            maps,
+            current: 0,
+            votes: HashMap::new(),
+            voting: false,
+        }
+    }
+
+    pub fn maps(&self) -> &[String] {
+        &self.maps
+    }
+
+    pub fn current_map(&self) -> &str {
+        &self.maps[self.current]
+    }
+
+    pub fn is_voting(&self) -> bool {
+        self.voting
+    }
+
+    // Returns false if there is nothing to vote on
+    pub fn start_vote(&mut self) -> bool {
+        if self.maps.len() < 2 {
+            return false;
+        }
+
+        self.votes.clear();
+        self.voting = true;
+        true
+    }
+
+    // Players can change their vote until voting ends
+    pub fn cast_vote(&mut self, index: u32, choice: u32) -> bool {
+        let choice = choice as usize;
+        if !self.is_voting() || choice >= self.maps.len() {
+            return false;
+        }
+
+        self.votes.insert(index, choice);
+        true
+    }
+
+    pub fn remove_voter(&mut self, index: u32) {
+        self.votes.remove(&index);
+    }
+
+    // Number of votes for each map, in rotation order
+    pub fn tally(&self) -> Vec<u32> {
+        let mut tally = vec![0; self.maps.len()];
+        for &choice in self.votes.values() {
+            tally[choice] += 1;
+        }
+        tally
+    }
+
+    // Ends voting and returns the map that won
+    pub fn finish_vote(&mut self) -> String {
+        self.voting = false;
+        self.current = self.winner();
+        self.votes.clear();
+
+        self.current_map().to_string()
+    }
+
+    // Moves on to the next map in the rotation without voting
+    pub fn advance(&mut self) -> String {
+        self.current = (self.current + 1) % self.maps.len();
+        self.current_map().to_string()
+    }
+
+    // Map with the most votes. Ties (and no votes at all) go to whichever comes first after the
+    // current map in the rotation.
+    fn winner(&self) -> usize {
+        let tally = self.tally();
+
+        (1..=self.maps.len())
+            .map(|offset| (self.current + offset) % self.maps.len())
+            .fold(None, |best: Option<usize>, map| match best {
+                Some(best) if tally[best] >= tally[map] => Some(best),
+                _ => Some(map),
+            })
+            .unwrap_or(self.current)
+    }
+}
@@ -0,0 +1,122 @@
+// Saves the rendered level as a PNG in SCREENSHOT_DIR. A render pass copies the frame out of the
+// renderer as soon as the scene is finished, before it's put on screen, and a worker thread encodes
+// it so writing the file doesn't hitch the game. The HUD and menus are drawn over the frame after
+// the scene passes so they aren't in the shot.
+
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fs::{self, File},
+    io::BufWriter,
+    path::PathBuf,
+    rc::Rc,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use fyrox::renderer::{
+    framework::{error::FrameworkError, gpu_texture::GpuTextureKind},
+    SceneRenderPass, SceneRenderPassContext,
+};
+use tracing::{info, warn};
+
+use crate::GameEngine;
+
+pub const SCREENSHOT_DIR: &str = "screenshots";
+
+struct Frame {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>, // RGBA rows from the bottom of the frame up, the way GL stores them
+}
+
+// Runs after every scene is rendered, only copies the frame out when one was asked for
+struct CapturePass {
+    requested: Rc<Cell<bool>>,
+    sender: Sender<Frame>,
+}
+
+impl SceneRenderPass for CapturePass {
+    fn on_ldr_render(&mut self, ctx: SceneRenderPassContext) -> Result<(), FrameworkError> {
+        if !self.requested.take() {
+            return Ok(());
+        }
+
+        let texture = ctx.framebuffer.color_attachments()[0].texture.borrow();
+        if let GpuTextureKind::Rectangle { width, height } = texture.kind() {
+            // Nothing to see while minimized
+            if width > 0 && height > 0 {
+                let _ = self.sender.send(Frame {
+                    width: width as u32,
+                    height: height as u32,
+                    pixels: texture.read_pixels(ctx.pipeline_state),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct Screenshots {
+    requested: Rc<Cell<bool>>, // Shared with the render pass
+}
+
+impl Screenshots {
+    pub fn new(engine: &mut GameEngine) -> Self {
+        let (sender, receiver) = mpsc::channel::<Frame>();
+        thread::spawn(move || {
+            for frame in receiver {
+                match save(frame) {
+                    Ok(path) => info!("saved screenshot {}", path.display()),
+                    Err(e) => warn!("failed to save screenshot: {}", e),
+                }
+            }
+        });
+
+        let requested = Rc::new(Cell::new(false));
+        engine
+            .renderer
+            .add_render_pass(Rc::new(RefCell::new(CapturePass {
+                requested: requested.clone(),
+                sender,
+            })));
+
+        Self { requested }
+    }
+
+    // Taken from the next scene the renderer draws
+    pub fn request(&mut self) {
+        self.requested.set(true);
+    }
+}
+
+fn save(frame: Frame) -> Result<PathBuf, Box<dyn Error>> {
+    fs::create_dir_all(SCREENSHOT_DIR)?;
+
+    // Milliseconds since the epoch, so screenshots sort by when they were taken
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = PathBuf::from(SCREENSHOT_DIR).join(format!("breakfloor-{}.png", time));
+
+    // Flipped to top down and without alpha, the frame is opaque anyway
+    let row = frame.width as usize * 4;
+    let flipped: Vec<u8> = frame
+        .pixels
+        .chunks_exact(row)
+        .rev()
+        .flat_map(|row| row.chunks_exact(4).flat_map(|pixel| &pixel[..3]))
+        .copied()
+        .collect();
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(&path)?),
+        frame.width,
+        frame.height,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&flipped)?;
+
+    Ok(path)
+}
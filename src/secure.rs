@@ -0,0 +1,190 @@
+// Encryption for everything sent after the handshake. The client sends a fresh public key with its
+// handshake and the server answers with one of its own in Connected, both in the clear. From then
+// on every packet between them is sealed with a NaCl box (X25519 and XSalsa20-Poly1305), so
+// packets that don't come from whoever did the handshake fail to open and are dropped no matter
+// what address they claim to come from.
+//
+// The server also hands out a random token with Connected. It goes in the clear at the start of
+// every sealed packet so the server can still tell whose packet it is when a player's address
+// changes, like when their router picks a new port.
+//
+// A sealed packet is a marker byte, the token, a random nonce and the box. Inside is whether it's
+// reliable, a counter and the payload. Reliable and unreliable packets are counted separately and a
+// window of recent counters for each catches old packets sent again by someone else.
+
+use std::convert::TryInto;
+
+use crypto_box::{
+    aead::{rand_core::RngCore, Aead, AeadCore, OsRng},
+    PublicKey, SalsaBox, SecretKey,
+};
+
+// Can't start a plain message, compressed payload or framed payload
+const SEALED_MARKER: u8 = 0xfd;
+const TOKEN_SIZE: usize = 16;
+const NONCE_SIZE: usize = 24;
+// Unreliable packets can arrive this far out of order before they count as replayed
+const REPLAY_WINDOW: u64 = 64;
+// Laminar resends a lost reliable packet as it was, counter and all, and keeps at most
+// MAX_PACKETS_IN_FLIGHT waiting on acks. A resend is never further behind than that.
+pub const MAX_PACKETS_IN_FLIGHT: u16 = 512;
+const RELIABLE_REPLAY_WINDOW: u64 = 2 * MAX_PACKETS_IN_FLIGHT as u64;
+
+pub type PublicKeyBytes = [u8; 32];
+pub type SessionToken = [u8; TOKEN_SIZE];
+
+pub fn generate_secret() -> SecretKey {
+    SecretKey::generate(&mut OsRng)
+}
+
+pub fn public_key(secret: &SecretKey) -> PublicKeyBytes {
+    *secret.public_key().as_bytes()
+}
+
+#[cfg(feature = "server")]
+pub fn generate_token() -> SessionToken {
+    let mut token = [0; TOKEN_SIZE];
+    OsRng.fill_bytes(&mut token);
+    token
+}
+
+// Counters seen lately, each in the slot its value picks. Anything newer than the window has a slot
+// to itself, so a slot holding the same counter means it's been seen.
+struct ReplayWindow {
+    next: u64, // For sealing, counters start at 1 so empty slots never match
+    highest: u64,
+    slots: Vec<u64>,
+}
+
+impl ReplayWindow {
+    fn new(size: u64) -> Self {
+        Self {
+            next: 1,
+            highest: 0,
+            slots: vec![0; size as usize],
+        }
+    }
+
+    fn next_counter(&mut self) -> u64 {
+        let counter = self.next;
+        self.next += 1;
+        counter
+    }
+
+    fn accept(&mut self, counter: u64) -> bool {
+        let size = self.slots.len() as u64;
+        if counter == 0 || counter + size <= self.highest {
+            return false;
+        }
+
+        let slot = &mut self.slots[(counter % size) as usize];
+        if *slot == counter {
+            return false;
+        }
+        *slot = counter;
+        self.highest = self.highest.max(counter);
+        true
+    }
+}
+
+pub struct Session {
+    cipher: SalsaBox,
+    token: SessionToken,
+    unreliable: ReplayWindow,
+    reliable: ReplayWindow,
+}
+
+impl Session {
+    pub fn new(secret: &SecretKey, their_key: PublicKeyBytes, token: SessionToken) -> Self {
+        Self {
+            cipher: SalsaBox::new(&PublicKey::from(their_key), secret),
+            token,
+            unreliable: ReplayWindow::new(REPLAY_WINDOW),
+            reliable: ReplayWindow::new(RELIABLE_REPLAY_WINDOW),
+        }
+    }
+
+    fn window(&mut self, reliable: bool) -> &mut ReplayWindow {
+        if reliable {
+            &mut self.reliable
+        } else {
+            &mut self.unreliable
+        }
+    }
+
+    pub fn seal(&mut self, payload: &[u8], reliable: bool) -> Vec<u8> {
+        let counter = self.window(reliable).next_counter();
+        let mut plain = Vec::with_capacity(payload.len() + 9);
+        plain.push(reliable as u8);
+        plain.extend_from_slice(&counter.to_le_bytes());
+        plain.extend_from_slice(payload);
+
+        let nonce = SalsaBox::generate_nonce(&mut OsRng);
+        // Only fails for payloads far bigger than anything sent
+        let sealed = self.cipher.encrypt(&nonce, plain.as_slice()).unwrap();
+
+        let mut packet = Vec::with_capacity(1 + TOKEN_SIZE + NONCE_SIZE + sealed.len());
+        packet.push(SEALED_MARKER);
+        packet.extend_from_slice(&self.token);
+        packet.extend_from_slice(&nonce);
+        packet.extend_from_slice(&sealed);
+        packet
+    }
+
+    // None if the packet isn't sealed, was tampered with, isn't from the other end of this
+    // session or has been seen before
+    pub fn open(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        self.open_counted(packet, false)
+    }
+
+    // Like open, but the packet also has to be newer than any seen so far. One held up on the way
+    // can't take a player back to an address they've left.
+    #[cfg(feature = "server")]
+    pub fn open_newest(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        self.open_counted(packet, true)
+    }
+
+    fn open_counted(&mut self, packet: &[u8], newest: bool) -> Option<Vec<u8>> {
+        if token_of(packet)? != self.token {
+            return None;
+        }
+
+        let sealed = &packet[1 + TOKEN_SIZE..];
+        if sealed.len() <= NONCE_SIZE {
+            return None;
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_SIZE);
+        let plain = self.cipher.decrypt(nonce.into(), sealed).ok()?;
+        let reliable = match plain.first()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let counter = u64::from_le_bytes(plain.get(1..9)?.try_into().ok()?);
+        if newest && counter <= self.window(reliable).highest {
+            return None;
+        }
+        if !self.window(reliable).accept(counter) {
+            return None;
+        }
+
+        Some(plain[9..].to_vec())
+    }
+
+    #[cfg(feature = "server")]
+    pub fn token(&self) -> SessionToken {
+        self.token
+    }
+}
+
+pub fn is_sealed(packet: &[u8]) -> bool {
+    packet.first() == Some(&SEALED_MARKER)
+}
+
+// Who a sealed packet claims to be from, None if it isn't sealed
+pub fn token_of(packet: &[u8]) -> Option<SessionToken> {
+    match packet.split_first() {
+        Some((&SEALED_MARKER, rest)) => rest.get(..TOKEN_SIZE)?.try_into().ok(),
+        _ => None,
+    }
+}
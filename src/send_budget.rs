@@ -0,0 +1,60 @@
+// Caps how much the server sends each client. The budget refills at a fixed number of bytes per
+// second and everything sent is taken from it. Reliable messages always go out and can leave it
+// in debt, state updates are skipped while it's empty so a client on a slow link gets fewer of
+// them instead of a growing backlog.
+
+use std::time::Instant;
+
+// Most of a second's worth can be saved up, so a burst after a quiet moment isn't cut short
+const MAX_SAVED: f32 = 1.0;
+
+#[derive(Debug)]
+pub struct SendBudget {
+    bytes_per_second: f32,
+    available: f32,
+    last_refill: Instant,
+    skipped: u32, // State updates dropped since the budget last ran out, for the log
+}
+
+impl SendBudget {
+    // 0 means no limit
+    pub fn new(bytes_per_second: u32) -> Self {
+        Self {
+            bytes_per_second: bytes_per_second as f32,
+            available: bytes_per_second as f32 * MAX_SAVED,
+            last_refill: Instant::now(),
+            skipped: 0,
+        }
+    }
+
+    pub fn spend(&mut self, bytes: usize) {
+        self.available -= bytes as f32;
+    }
+
+    // Returns false if state updates should be held back for now
+    pub fn allows_state(&mut self) -> bool {
+        if self.bytes_per_second <= 0.0 {
+            return true;
+        }
+
+        self.refill();
+        if self.available > 0.0 {
+            return true;
+        }
+
+        self.skipped += 1;
+        false
+    }
+
+    // How many state updates were skipped since this was last called
+    pub fn take_skipped(&mut self) -> u32 {
+        std::mem::take(&mut self.skipped)
+    }
+
+    fn refill(&mut self) {
+        let elapsed = self.last_refill.elapsed().as_secs_f32();
+        self.last_refill = Instant::now();
+        self.available = (self.available + elapsed * self.bytes_per_second)
+            .min(self.bytes_per_second * MAX_SAVED);
+    }
+}
@@ -0,0 +1,217 @@
+// Public server list. Servers announce themselves to a master server over HTTP and clients fetch
+// the list from it.
+//
+// Master server API:
+//   POST {url}/servers with a Heartbeat as JSON. The master uses the source IP of the request
+//   together with the posted port as the server address and drops servers it hasn't heard from
+//   in a while.
+//   GET {url}/servers returns a JSON array of ServerInfo.
+//
+// Ping is measured with a plain UDP echo on the port after the game port, the game socket can't be
+// used because laminar treats any reply as a new connection.
+
+#[cfg(not(feature = "server"))]
+use std::{
+    net::ToSocketAddrs,
+    time::{Duration, Instant},
+};
+use std::{net::UdpSocket, thread};
+
+#[cfg(not(feature = "server"))]
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use tracing::warn;
+
+pub const MASTER_SERVER_URL: &str = "http://wtblife.ddns.net:12350";
+const QUERY_MAGIC: &[u8] = b"BREAKFLOOR_PING";
+#[cfg(feature = "server")]
+const HEARTBEAT_INTERVAL: f32 = 30.0;
+#[cfg(not(feature = "server"))]
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[cfg(feature = "server")]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub name: String,
+    pub port: u16,
+    pub map: String,
+    pub players: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub address: String, // host:port
+    pub name: String,
+    pub map: String,
+    pub players: u32,
+}
+
+pub fn query_port(game_port: u16) -> u16 {
+    game_port.wrapping_add(1)
+}
+
+// Announces the server to the master server and answers pings
+#[cfg(feature = "server")]
+pub struct ServerAnnouncer {
+    url: String,
+    name: String,
+    port: u16,
+    timer: f32,
+}
+
+#[cfg(feature = "server")]
+impl ServerAnnouncer {
+    pub fn new(url: &str, name: &str, port: u16) -> Self {
+        match UdpSocket::bind(("0.0.0.0", query_port(port))) {
+            Ok(socket) => {
+                thread::spawn(move || answer_pings(socket));
+            }
+            Err(e) => warn!("failed to bind query port: {}", e),
+        }
+
+        Self {
+            url: url.to_string(),
+            name: name.to_string(),
+            port,
+            timer: 0.0, // Announce right away
+        }
+    }
+
+    pub fn update(&mut self, dt: f32, map: &str, players: u32) {
+        if self.url.is_empty() {
+            return;
+        }
+
+        self.timer -= dt;
+        if self.timer > 0.0 {
+            return;
+        }
+        self.timer = HEARTBEAT_INTERVAL;
+
+        let url = format!("{}/servers", self.url);
+        let heartbeat = Heartbeat {
+            name: self.name.clone(),
+            port: self.port,
+            map: map.to_string(),
+            players,
+        };
+
+        // Requests can take a while so don't hold up the game loop
+        thread::spawn(move || {
+            let body = serde_json::to_string(&heartbeat).unwrap();
+            if let Err(e) = ureq::post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&body)
+            {
+                warn!("failed to announce server: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(feature = "server")]
+fn answer_pings(socket: UdpSocket) {
+    let mut buffer = [0; 64];
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, address)) if &buffer[..size] == QUERY_MAGIC => {
+                let _ = socket.send_to(QUERY_MAGIC, address);
+            }
+            Ok(_) => (),
+            Err(e) => warn!("query socket error: {}", e),
+        }
+    }
+}
+
+#[cfg(not(feature = "server"))]
+pub enum ServerListEvent {
+    Servers(Vec<ServerInfo>),
+    Ping { address: String, ping: Duration },
+    Failed(String),
+}
+
+// Fetches servers from the master server in the background, results are picked up with poll
+#[cfg(not(feature = "server"))]
+pub struct ServerList {
+    url: String,
+    sender: Sender<ServerListEvent>,
+    receiver: Receiver<ServerListEvent>,
+}
+
+#[cfg(not(feature = "server"))]
+impl ServerList {
+    pub fn new(url: &str) -> Self {
+        let (sender, receiver) = unbounded();
+
+        Self {
+            url: url.to_string(),
+            sender,
+            receiver,
+        }
+    }
+
+    pub fn refresh(&self) {
+        let url = format!("{}/servers", self.url);
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let servers = match fetch_servers(&url) {
+                Ok(servers) => servers,
+                Err(e) => {
+                    let _ = sender.send(ServerListEvent::Failed(e));
+                    return;
+                }
+            };
+
+            // List goes out first so pings have a row to land in
+            let addresses: Vec<String> = servers.iter().map(|s| s.address.clone()).collect();
+            let _ = sender.send(ServerListEvent::Servers(servers));
+
+            for address in addresses {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if let Some(ping) = ping(&address) {
+                        let _ = sender.send(ServerListEvent::Ping { address, ping });
+                    }
+                });
+            }
+        });
+    }
+
+    pub fn poll(&self) -> Option<ServerListEvent> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+#[cfg(not(feature = "server"))]
+fn fetch_servers(url: &str) -> Result<Vec<ServerInfo>, String> {
+    let body = ureq::get(url)
+        .call()
+        .map_err(|e| format!("Failed to fetch servers: {}", e))?
+        .into_string()
+        .map_err(|e| format!("Failed to read server list: {}", e))?;
+
+    serde_json::from_str(&body).map_err(|e| format!("Invalid server list: {}", e))
+}
+
+// Round trip time of the query echo, None if the server doesn't answer
+#[cfg(not(feature = "server"))]
+fn ping(address: &str) -> Option<Duration> {
+    let mut address = address.to_socket_addrs().ok()?.next()?;
+    address.set_port(query_port(address.port()));
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.set_read_timeout(Some(PING_TIMEOUT)).ok()?;
+
+    let start = Instant::now();
+    socket.send_to(QUERY_MAGIC, address).ok()?;
+
+    let mut buffer = [0; 64];
+    let (size, _) = socket.recv_from(&mut buffer).ok()?;
+    if &buffer[..size] == QUERY_MAGIC {
+        Some(start.elapsed())
+    } else {
+        None
+    }
+}
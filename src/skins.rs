@@ -0,0 +1,44 @@
+// Third person models players can choose between. The list is read from SKINS_PATH so new ones
+// can be added without a rebuild, players are told which skin everyone uses by id. Anyone using a
+// skin that isn't in the local list shows up with the default one.
+
+use std::{error::Error, fs::File, io::BufReader, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+pub const SKINS_PATH: &str = "data/models/skins.json";
+pub const DEFAULT_SKIN: u32 = 0;
+const DEFAULT_MODEL: &str = "data/models/idle.fbx";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkinInfo {
+    pub id: u32,
+    pub name: String,
+    pub model: String, // Path of the model file, needs the same bones as the default one
+}
+
+fn read_manifest<P: AsRef<Path>>(path: P) -> Result<Vec<SkinInfo>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+// Always has the default skin, even if the manifest is missing or leaves it out
+pub fn load_manifest() -> Vec<SkinInfo> {
+    let mut skins = read_manifest(SKINS_PATH).unwrap_or_else(|e| {
+        warn!("failed to read {}: {}", SKINS_PATH, e);
+        Vec::new()
+    });
+
+    if !skins.iter().any(|skin| skin.id == DEFAULT_SKIN) {
+        skins.push(SkinInfo {
+            id: DEFAULT_SKIN,
+            name: String::from("Default"),
+            model: DEFAULT_MODEL.to_string(),
+        });
+    }
+    // Default first, it's the fallback
+    skins.sort_by_key(|skin| skin.id != DEFAULT_SKIN);
+
+    skins
+}
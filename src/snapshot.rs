@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+
+use fyrox::{core::algebra::Vector3, scene::Scene};
+use serde::{Deserialize, Serialize};
+
+use crate::{codec, player::Player, player_event::PlayerEvent};
+
+// Number of snapshots kept on both ends. If the last acknowledged snapshot falls out of this
+// window the server has nothing to diff against and sends a full snapshot instead.
+pub const SNAPSHOT_HISTORY: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct PlayerSnapshot {
+    pub index: u32,
+    #[serde(with = "codec::fixed_vector")]
+    pub position: Vector3<f32>,
+    #[serde(with = "codec::fixed_vector")]
+    pub velocity: Vector3<f32>,
+    #[serde(with = "codec::angle")]
+    pub yaw: f32,
+    #[serde(with = "codec::angle")]
+    pub pitch: f32,
+    pub shoot: bool,
+    pub fuel: f32,
+    pub weapon: u8, // Slot the ammo count is for, the client may have switched since
+    pub ammo: u32,
+}
+
+impl PlayerSnapshot {
+    pub fn from_player(player: &Player, scene: &Scene) -> Self {
+        Self {
+            index: player.index,
+            position: player.get_position(scene),
+            velocity: player.get_velocity(scene),
+            yaw: player.get_yaw(),
+            pitch: player.get_pitch(),
+            shoot: player.controller.shoot,
+            fuel: player.flight_fuel,
+            weapon: player.current_weapon_slot() as u8,
+            ammo: player.ammo(),
+        }
+    }
+
+    pub fn to_update_event(&self, timestamp: f32) -> PlayerEvent {
+        PlayerEvent::UpdateState {
+            timestamp,
+            index: self.index,
+            position: self.position,
+            velocity: self.velocity,
+            yaw: self.yaw,
+            pitch: self.pitch,
+            shoot: self.shoot,
+            fuel: self.fuel,
+            weapon: self.weapon,
+            ammo: self.ammo,
+        }
+    }
+
+    // Only fields that changed since the base are sent
+    fn delta_from(&self, base: &PlayerSnapshot) -> Option<PlayerDelta> {
+        if self == base {
+            return None;
+        }
+
+        Some(PlayerDelta {
+            index: self.index,
+            position: (self.position != base.position).then(|| self.position),
+            velocity: (self.velocity != base.velocity).then(|| self.velocity),
+            yaw: (self.yaw != base.yaw).then(|| self.yaw),
+            pitch: (self.pitch != base.pitch).then(|| self.pitch),
+            shoot: (self.shoot != base.shoot).then(|| self.shoot),
+            fuel: (self.fuel != base.fuel).then(|| self.fuel),
+            weapon: (self.weapon != base.weapon).then(|| self.weapon),
+            ammo: (self.ammo != base.ammo).then(|| self.ammo),
+        })
+    }
+
+    fn apply_delta(&mut self, delta: &PlayerDelta) {
+        if let Some(position) = delta.position {
+            self.position = position;
+        }
+        if let Some(velocity) = delta.velocity {
+            self.velocity = velocity;
+        }
+        if let Some(yaw) = delta.yaw {
+            self.yaw = yaw;
+        }
+        if let Some(pitch) = delta.pitch {
+            self.pitch = pitch;
+        }
+        if let Some(shoot) = delta.shoot {
+            self.shoot = shoot;
+        }
+        if let Some(fuel) = delta.fuel {
+            self.fuel = fuel;
+        }
+        if let Some(weapon) = delta.weapon {
+            self.weapon = weapon;
+        }
+        if let Some(ammo) = delta.ammo {
+            self.ammo = ammo;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PlayerDelta {
+    pub index: u32,
+    #[serde(with = "codec::fixed_vector::option")]
+    pub position: Option<Vector3<f32>>,
+    #[serde(with = "codec::fixed_vector::option")]
+    pub velocity: Option<Vector3<f32>>,
+    #[serde(with = "codec::angle::option")]
+    pub yaw: Option<f32>,
+    #[serde(with = "codec::angle::option")]
+    pub pitch: Option<f32>,
+    pub shoot: Option<bool>,
+    pub fuel: Option<f32>,
+    pub weapon: Option<u8>,
+    pub ammo: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WorldSnapshot {
+    pub sequence: u32,
+    pub timestamp: f32,
+    pub players: Vec<PlayerSnapshot>,
+}
+
+impl WorldSnapshot {
+    pub fn delta_from(&self, base: &WorldSnapshot) -> SnapshotPayload {
+        let mut changed = Vec::new();
+        let mut added = Vec::new();
+
+        for player in self.players.iter() {
+            match base.players.iter().find(|p| p.index == player.index) {
+                Some(base_player) => {
+                    if let Some(delta) = player.delta_from(base_player) {
+                        changed.push(delta);
+                    }
+                }
+                None => added.push(*player),
+            }
+        }
+
+        let removed = base
+            .players
+            .iter()
+            .filter(|base_player| !self.players.iter().any(|p| p.index == base_player.index))
+            .map(|base_player| base_player.index)
+            .collect();
+
+        SnapshotPayload::Delta {
+            base: base.sequence,
+            sequence: self.sequence,
+            timestamp: self.timestamp,
+            changed,
+            added,
+            removed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum SnapshotPayload {
+    Full(WorldSnapshot),
+    Delta {
+        base: u32,
+        sequence: u32,
+        timestamp: f32,
+        changed: Vec<PlayerDelta>,
+        added: Vec<PlayerSnapshot>,
+        removed: Vec<u32>,
+    },
+}
+
+impl SnapshotPayload {
+    // Rebuilds the full snapshot, returns None if the base snapshot is no longer known
+    pub fn decode(&self, history: &SnapshotHistory) -> Option<WorldSnapshot> {
+        match self {
+            SnapshotPayload::Full(snapshot) => Some(snapshot.clone()),
+            SnapshotPayload::Delta {
+                base,
+                sequence,
+                timestamp,
+                changed,
+                added,
+                removed,
+            } => {
+                let mut snapshot = history.get(*base)?.clone();
+                snapshot.sequence = *sequence;
+                snapshot.timestamp = *timestamp;
+                snapshot.players.retain(|p| !removed.contains(&p.index));
+
+                for delta in changed.iter() {
+                    if let Some(player) =
+                        snapshot.players.iter_mut().find(|p| p.index == delta.index)
+                    {
+                        player.apply_delta(delta);
+                    }
+                }
+
+                snapshot.players.extend_from_slice(added);
+
+                Some(snapshot)
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<WorldSnapshot>,
+}
+
+impl SnapshotHistory {
+    pub fn push(&mut self, snapshot: WorldSnapshot) {
+        if self.snapshots.len() >= SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn get(&self, sequence: u32) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().find(|s| s.sequence == sequence)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
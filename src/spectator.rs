@@ -0,0 +1,318 @@
+use fyrox::{
+    core::{
+        algebra::{UnitQuaternion, Vector3},
+        pool::Handle,
+    },
+    event::{MouseButton, VirtualKeyCode},
+    scene::{
+        base::BaseBuilder,
+        camera::{CameraBuilder, Exposure, SkyBox},
+        node::Node,
+        sound::listener::ListenerBuilder,
+        transform::TransformBuilder,
+        Scene,
+    },
+};
+
+use crate::{
+    input::{Action, Binding, InputEvent, KeyBindings},
+    player::{self, Player},
+};
+
+const FLY_SPEED: f32 = 8.0;
+const FAST_FLY_SPEED: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectatorMode {
+    FreeFly,
+    Following(u32), // Player index
+}
+
+#[derive(Default)]
+struct FlyController {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    fast: bool,
+}
+
+// Camera used while the local player isn't alive, either flying freely or looking through the
+// camera of another player
+pub struct Spectator {
+    camera: Handle<Node>,
+    listener: Handle<Node>,
+    mode: SpectatorMode,
+    active: bool,
+    position: Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+    controller: FlyController,
+}
+
+impl Spectator {
+    // Spectating is the default state until the local player spawns
+    pub fn new(scene: &mut Scene, skybox: SkyBox, position: Vector3<f32>) -> Self {
+        let camera = CameraBuilder::new(
+            BaseBuilder::new().with_local_transform(
+                TransformBuilder::new()
+                    .with_local_position(position)
+                    .build(),
+            ),
+        )
+        .with_skybox(skybox)
+        .build(&mut scene.graph);
+
+        scene.graph[camera]
+            .as_camera_mut()
+            .set_exposure(Exposure::Manual(std::f32::consts::E));
+
+        let mut spectator = Self {
+            camera,
+            listener: Handle::NONE,
+            mode: SpectatorMode::FreeFly,
+            active: false,
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            controller: FlyController::default(),
+        };
+        spectator.start(scene, position);
+
+        spectator
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn mode(&self) -> SpectatorMode {
+        self.mode
+    }
+
+    pub fn camera(&self) -> Handle<Node> {
+        self.camera
+    }
+
+    pub fn set_fov(&self, scene: &mut Scene, fov: f32) {
+        player::set_camera_fov(scene, self.camera, fov);
+    }
+
+    // Start free flying from the given position
+    pub fn start(&mut self, scene: &mut Scene, position: Vector3<f32>) {
+        self.active = true;
+        self.position = position;
+        self.controller = FlyController::default();
+        self.set_free_camera(scene, true);
+    }
+
+    // Puts the free flying camera somewhere else, looking in the given direction
+    pub fn set_view(&mut self, position: Vector3<f32>, yaw: f32, pitch: f32) {
+        self.position = position;
+        self.yaw = yaw;
+        self.pitch = pitch;
+    }
+
+    // Called when the local player spawns
+    pub fn stop(&mut self, scene: &mut Scene, players: &mut [Player]) {
+        if let SpectatorMode::Following(index) = self.mode {
+            if let Some(player) = players.iter_mut().find(|p| p.index == index) {
+                player.set_camera(scene, false);
+            }
+        }
+
+        self.active = false;
+        self.mode = SpectatorMode::FreeFly;
+        self.set_free_camera(scene, false);
+    }
+
+    fn set_free_camera(&mut self, scene: &mut Scene, enabled: bool) {
+        scene.graph[self.camera]
+            .as_camera_mut()
+            .set_enabled(enabled);
+
+        // Only one listener should exist at a time, followed players bring their own
+        if enabled && self.listener.is_none() {
+            self.listener = ListenerBuilder::new(BaseBuilder::new()).build(&mut scene.graph);
+            scene.graph.link_nodes(self.listener, self.camera);
+        } else if !enabled && self.listener.is_some() {
+            scene.remove_node(self.listener);
+            self.listener = Handle::NONE;
+        }
+    }
+
+    fn follow(&mut self, scene: &mut Scene, players: &mut [Player], mode: SpectatorMode) {
+        if self.mode == mode {
+            return;
+        }
+
+        if let SpectatorMode::Following(index) = self.mode {
+            if let Some(player) = players.iter_mut().find(|p| p.index == index) {
+                player.set_camera(scene, false);
+            }
+        }
+
+        match mode {
+            SpectatorMode::FreeFly => self.set_free_camera(scene, true),
+            SpectatorMode::Following(index) => {
+                if let Some(player) = players.iter_mut().find(|p| p.index == index) {
+                    self.set_free_camera(scene, false);
+                    player.set_camera(scene, true);
+                } else {
+                    return;
+                }
+            }
+        }
+
+        self.mode = mode;
+    }
+
+    // Cycle through alive players in the order they are stored in the level
+    fn follow_next(&mut self, scene: &mut Scene, players: &mut [Player]) {
+        if players.is_empty() {
+            return;
+        }
+
+        let next = match self.mode {
+            SpectatorMode::FreeFly => 0,
+            SpectatorMode::Following(index) => players
+                .iter()
+                .position(|p| p.index == index)
+                .map_or(0, |i| (i + 1) % players.len()),
+        };
+
+        let index = players[next].index;
+        self.follow(scene, players, SpectatorMode::Following(index));
+    }
+
+    pub fn process_input(
+        &mut self,
+        event: &InputEvent,
+        scene: &mut Scene,
+        players: &mut [Player],
+        bindings: &KeyBindings,
+    ) {
+        if !self.active {
+            return;
+        }
+
+        match *event {
+            InputEvent::Button(binding, pressed) => {
+                // Movement follows the player bindings, jetpack flies faster
+                match bindings.action(binding) {
+                    Some(Action::MoveForward) => self.controller.forward = pressed,
+                    Some(Action::MoveBackward) => self.controller.backward = pressed,
+                    Some(Action::MoveLeft) => self.controller.left = pressed,
+                    Some(Action::MoveRight) => self.controller.right = pressed,
+                    Some(Action::Jump) => self.controller.up = pressed,
+                    Some(Action::Crouch) => self.controller.down = pressed,
+                    Some(Action::Fly) => self.controller.fast = pressed,
+                    Some(Action::Shoot) if pressed => self.follow_next(scene, players),
+                    _ => match binding {
+                        Binding::Mouse(MouseButton::Right) if pressed => {
+                            self.follow(scene, players, SpectatorMode::FreeFly)
+                        }
+                        // Number keys jump straight to a player, 0 goes back to free flying
+                        Binding::Key(key) if pressed => {
+                            if let Some(number) = number_key(key) {
+                                if number == 0 {
+                                    self.follow(scene, players, SpectatorMode::FreeFly);
+                                } else if let Some(player) = players.get(number - 1) {
+                                    let index = player.index;
+                                    self.follow(scene, players, SpectatorMode::Following(index));
+                                }
+                            }
+                        }
+                        _ => (),
+                    },
+                }
+            }
+            InputEvent::MouseMotion(yaw, pitch) => {
+                if self.mode == SpectatorMode::FreeFly {
+                    self.yaw -= yaw;
+                    self.pitch = (self.pitch + pitch).clamp(-90.0, 90.0);
+                }
+            }
+            InputEvent::Scroll(_) => (),
+        }
+    }
+
+    pub fn update(&mut self, scene: &mut Scene, players: &mut [Player], dt: f32) {
+        if !self.active {
+            return;
+        }
+
+        match self.mode {
+            SpectatorMode::Following(index) => {
+                match players.iter().find(|p| p.index == index) {
+                    // Keep track of where we are so free flying continues from here
+                    Some(player) => self.position = player.get_position(scene),
+                    // Followed player died or left
+                    None => self.follow(scene, players, SpectatorMode::FreeFly),
+                }
+            }
+            SpectatorMode::FreeFly => {
+                let rotation =
+                    UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.yaw.to_radians())
+                        * UnitQuaternion::from_axis_angle(
+                            &Vector3::x_axis(),
+                            self.pitch.to_radians(),
+                        );
+
+                // Noclip, the camera isn't part of the physics world
+                let mut direction = Vector3::default();
+                if self.controller.forward {
+                    direction += rotation * Vector3::z();
+                }
+                if self.controller.backward {
+                    direction -= rotation * Vector3::z();
+                }
+                if self.controller.left {
+                    direction += rotation * Vector3::x();
+                }
+                if self.controller.right {
+                    direction -= rotation * Vector3::x();
+                }
+                if self.controller.up {
+                    direction += Vector3::y();
+                }
+                if self.controller.down {
+                    direction -= Vector3::y();
+                }
+
+                let speed = if self.controller.fast {
+                    FAST_FLY_SPEED
+                } else {
+                    FLY_SPEED
+                };
+
+                if let Some(direction) = direction.try_normalize(f32::EPSILON) {
+                    self.position += direction * speed * dt;
+                }
+
+                scene.graph[self.camera]
+                    .local_transform_mut()
+                    .set_position(self.position)
+                    .set_rotation(rotation);
+            }
+        }
+    }
+}
+
+fn number_key(key: VirtualKeyCode) -> Option<usize> {
+    match key {
+        VirtualKeyCode::Key0 => Some(0),
+        VirtualKeyCode::Key1 => Some(1),
+        VirtualKeyCode::Key2 => Some(2),
+        VirtualKeyCode::Key3 => Some(3),
+        VirtualKeyCode::Key4 => Some(4),
+        VirtualKeyCode::Key5 => Some(5),
+        VirtualKeyCode::Key6 => Some(6),
+        VirtualKeyCode::Key7 => Some(7),
+        VirtualKeyCode::Key8 => Some(8),
+        VirtualKeyCode::Key9 => Some(9),
+        _ => None,
+    }
+}
@@ -0,0 +1,135 @@
+// Optional camera behind the local player's shoulder, for recording footage and checking animations.
+// The player's own camera keeps doing the aiming and listening, this one only changes what's drawn.
+// The boom is shortened when a wall or block is between the player and where the camera wants to be.
+
+use fyrox::{
+    core::{
+        algebra::{UnitQuaternion, Vector3},
+        pool::Handle,
+    },
+    scene::{
+        base::BaseBuilder,
+        camera::{CameraBuilder, Exposure, SkyBox},
+        node::Node,
+        Scene,
+    },
+};
+
+use crate::{
+    collision,
+    player::{self, Player},
+};
+
+const BOOM_LENGTH: f32 = 1.2;
+// To the right, so the player doesn't block the crosshair
+const SHOULDER_OFFSET: f32 = 0.3;
+// Kept between the camera and whatever the boom hit so the near plane doesn't clip into it
+const BOOM_MARGIN: f32 = 0.1;
+
+pub struct ThirdPersonCamera {
+    camera: Handle<Node>,
+    enabled: bool,
+    following: Handle<Node>, // Camera of the player being shown, they respawn with a new one
+}
+
+impl ThirdPersonCamera {
+    pub fn new(scene: &mut Scene, skybox: SkyBox) -> Self {
+        let camera = CameraBuilder::new(BaseBuilder::new())
+            .enabled(false)
+            .with_skybox(skybox)
+            .build(&mut scene.graph);
+
+        scene.graph[camera]
+            .as_camera_mut()
+            .set_exposure(Exposure::Manual(std::f32::consts::E));
+
+        Self {
+            camera,
+            enabled: false,
+            following: Handle::NONE,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.following.is_some()
+    }
+
+    pub fn camera(&self) -> Handle<Node> {
+        self.camera
+    }
+
+    pub fn set_fov(&self, scene: &mut Scene, fov: f32) {
+        player::set_camera_fov(scene, self.camera, fov);
+    }
+
+    // Call every frame with the local player, None while they're dead. Global positions have to be
+    // up to date since the boom starts at the player's eyes.
+    pub fn update(&mut self, scene: &mut Scene, player: Option<&mut Player>) {
+        let player = match player {
+            Some(player) => player,
+            // The player's nodes went with them, only ours is left to switch off
+            None => {
+                if self.following.is_some() {
+                    self.following = Handle::NONE;
+                    self.set_camera_enabled(scene, false);
+                }
+                return;
+            }
+        };
+
+        if self.enabled != (self.following == player.camera()) {
+            player.set_third_person(scene, self.enabled);
+            self.set_camera_enabled(scene, self.enabled);
+            self.following = if self.enabled {
+                player.camera()
+            } else {
+                Handle::NONE
+            };
+        }
+
+        if self.enabled {
+            self.place(scene, player);
+        }
+    }
+
+    fn set_camera_enabled(&self, scene: &mut Scene, enabled: bool) {
+        scene.graph[self.camera]
+            .as_camera_mut()
+            .set_enabled(enabled);
+    }
+
+    fn place(&self, scene: &mut Scene, player: &Player) {
+        let eye = scene.graph[player.camera()].global_position();
+        let rotation =
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), player.get_yaw().to_radians())
+                * UnitQuaternion::from_axis_angle(
+                    &Vector3::x_axis(),
+                    player.get_pitch().to_radians(),
+                );
+
+        // Cameras look along +Z and +X is their left
+        let boom = rotation * Vector3::new(-SHOULDER_OFFSET, 0.0, -BOOM_LENGTH);
+        let direction = boom.normalize();
+        // Other players walking past don't pull the camera in
+        let length = collision::cast_ray(
+            &scene.graph,
+            eye,
+            direction,
+            boom.norm(),
+            collision::GROUND_GROUPS,
+            player.collider,
+        )
+        .map_or(boom.norm(), |hit| {
+            ((hit.position.coords - eye).norm() - BOOM_MARGIN).max(0.0)
+        });
+
+        scene.graph[self.camera]
+            .local_transform_mut()
+            .set_position(eye + direction * length)
+            .set_rotation(rotation);
+    }
+}
@@ -0,0 +1,166 @@
+// Server side checks on input from clients. Anything out of range is clamped or dropped and
+// counts as a violation, violations wear off over time and a client that keeps racking them up
+// gets kicked. The time of the last input is kept too, for kicking players who stopped playing.
+
+use std::time::Instant;
+
+use crate::player_event::PlayerEvent;
+
+// Mouse events are sent as they come in so a fast mouse can send quite a few per tick
+const MAX_INPUT_EVENTS_PER_TICK: u32 = 40;
+// Degrees, anything bigger is a flick no mouse produces between two events
+const MAX_LOOK_DELTA: f32 = 45.0;
+const MAX_PITCH: f32 = 90.0;
+// Shots are allowed slightly early to make up for jitter
+const FIRE_INTERVAL_TOLERANCE: f32 = 0.8;
+const KICK_VIOLATIONS: f32 = 10.0;
+// Per second
+const VIOLATION_DECAY: f32 = 1.0;
+// Players can click faster than the fire rate, so early shots count for less
+const EARLY_SHOT_VIOLATION: f32 = 0.2;
+
+#[derive(Debug)]
+pub struct InputValidator {
+    events_this_tick: u32,
+    last_tick: Instant,
+    last_shot: Option<Instant>,
+    last_input: Instant,
+    violations: f32,
+}
+
+impl Default for InputValidator {
+    fn default() -> Self {
+        Self {
+            events_this_tick: 0,
+            last_tick: Instant::now(),
+            last_shot: None,
+            last_input: Instant::now(),
+            violations: 0.0,
+        }
+    }
+}
+
+impl InputValidator {
+    pub fn start_tick(&mut self) {
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_tick).as_secs_f32();
+        self.last_tick = now;
+
+        self.events_this_tick = 0;
+        self.violations = (self.violations - VIOLATION_DECAY * dt).max(0.0);
+    }
+
+    // Checks that don't need the player's state. Returns false if the event should be dropped.
+    pub fn check_event(&mut self, event: &mut PlayerEvent) -> bool {
+        self.last_input = Instant::now();
+        if !self.allow_input() {
+            return false;
+        }
+
+        match event {
+            PlayerEvent::LookAround {
+                yaw_delta,
+                pitch_delta,
+                ..
+            } => self.check_look_delta(yaw_delta, pitch_delta),
+            PlayerEvent::ShootWeapon { yaw, pitch, .. }
+            | PlayerEvent::MoveForward { yaw, pitch, .. }
+            | PlayerEvent::MoveBackward { yaw, pitch, .. }
+            | PlayerEvent::MoveLeft { yaw, pitch, .. }
+            | PlayerEvent::MoveRight { yaw, pitch, .. } => self.check_view(yaw, pitch),
+            PlayerEvent::MoveAnalog {
+                forward,
+                right,
+                yaw,
+                pitch,
+                ..
+            } => self.check_analog(forward, right) && self.check_view(yaw, pitch),
+            _ => true,
+        }
+    }
+
+    pub fn should_kick(&self) -> bool {
+        self.violations >= KICK_VIOLATIONS
+    }
+
+    // Seconds since the player last sent any input, or since joining
+    pub fn idle_time(&self) -> f32 {
+        self.last_input.elapsed().as_secs_f32()
+    }
+
+    fn violation(&mut self, weight: f32) {
+        self.violations += weight;
+    }
+
+    // Returns false if the event should be dropped. Going over the limit only counts once a tick.
+    fn allow_input(&mut self) -> bool {
+        self.events_this_tick += 1;
+        if self.events_this_tick == MAX_INPUT_EVENTS_PER_TICK + 1 {
+            self.violation(1.0);
+        }
+        self.events_this_tick <= MAX_INPUT_EVENTS_PER_TICK
+    }
+
+    // Returns false if the deltas aren't numbers, otherwise they're clamped
+    fn check_look_delta(&mut self, yaw_delta: &mut f32, pitch_delta: &mut f32) -> bool {
+        if !yaw_delta.is_finite() || !pitch_delta.is_finite() {
+            self.violation(1.0);
+            return false;
+        }
+
+        if yaw_delta.abs() > MAX_LOOK_DELTA || pitch_delta.abs() > MAX_LOOK_DELTA {
+            self.violation(1.0);
+            *yaw_delta = yaw_delta.clamp(-MAX_LOOK_DELTA, MAX_LOOK_DELTA);
+            *pitch_delta = pitch_delta.clamp(-MAX_LOOK_DELTA, MAX_LOOK_DELTA);
+        }
+
+        true
+    }
+
+    // Yaw and pitch sent along with movement and shooting
+    fn check_view(&mut self, yaw: &mut f32, pitch: &mut f32) -> bool {
+        if !yaw.is_finite() || !pitch.is_finite() {
+            self.violation(1.0);
+            return false;
+        }
+
+        // Yaw wraps around so any value is fine
+        if pitch.abs() > MAX_PITCH {
+            self.violation(1.0);
+            *pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        true
+    }
+
+    fn check_analog(&mut self, forward: &mut f32, right: &mut f32) -> bool {
+        if !forward.is_finite() || !right.is_finite() {
+            self.violation(1.0);
+            return false;
+        }
+
+        if forward.abs() > 1.0 || right.abs() > 1.0 {
+            self.violation(1.0);
+            *forward = forward.clamp(-1.0, 1.0);
+            *right = right.clamp(-1.0, 1.0);
+        }
+
+        true
+    }
+
+    // Returns false if the trigger was pulled again before the weapon could fire
+    pub fn allow_shot(&mut self, fire_interval: f32) -> bool {
+        let now = Instant::now();
+
+        if let Some(last_shot) = self.last_shot {
+            if now.duration_since(last_shot).as_secs_f32() < fire_interval * FIRE_INTERVAL_TOLERANCE
+            {
+                self.violation(EARLY_SHOT_VIOLATION);
+                return false;
+            }
+        }
+
+        self.last_shot = Some(now);
+        true
+    }
+}
@@ -0,0 +1,118 @@
+// Moves the first person model around under the camera so the gun doesn't look glued to the
+// screen. It lags behind when looking around, bobs while walking, dips when landing and gets
+// pushed back by recoil. Aiming steadies it. All offsets are in camera space, in metres.
+
+use fyrox::{
+    core::{
+        algebra::{Vector2, Vector3},
+        pool::Handle,
+    },
+    scene::{node::Node, Scene},
+};
+
+// Offset per degree turned in a frame, and how far the gun can lag
+const SWAY_SCALE: f32 = 0.002;
+const MAX_SWAY: f32 = 0.03;
+const SWAY_SPEED: f32 = 8.0; // How quickly the gun catches up
+const BOB_FREQUENCY: f32 = 1.6; // Bob cycles per metre walked
+const BOB_SIDE: f32 = 0.006;
+const BOB_UP: f32 = 0.005;
+const BOB_FADE_SPEED: f32 = 6.0;
+// Dip per m/s of falling speed when landing, and how quickly the gun comes back up
+const LAND_DIP_SCALE: f32 = 0.006;
+const MAX_LAND_DIP: f32 = 0.04;
+const LAND_RECOVERY: f32 = 6.0;
+// Share of the sway, bob and dip left when fully aimed in
+const AIM_STEADINESS: f32 = 0.3;
+
+pub struct ViewModelInput {
+    pub yaw: f32, // Degrees
+    pub pitch: f32,
+    pub velocity: Vector3<f32>,
+    pub on_ground: bool,
+    pub recoil: Vector3<f32>,
+    pub aim: f32, // 0 to 1
+}
+
+pub struct ViewModel {
+    model: Handle<Node>,
+    rest_position: Vector3<f32>,
+    last_look: Option<Vector2<f32>>,
+    sway: Vector2<f32>,
+    bob_phase: f32,
+    bob_weight: f32, // Fades the bob in and out when starting and stopping
+    was_on_ground: bool,
+    fall_speed: f32, // Downward speed in the air, for the dip when touching down
+    dip: f32,
+}
+
+impl ViewModel {
+    pub fn new(model: Handle<Node>, rest_position: Vector3<f32>) -> Self {
+        Self {
+            model,
+            rest_position,
+            last_look: None,
+            sway: Vector2::default(),
+            bob_phase: 0.0,
+            bob_weight: 0.0,
+            was_on_ground: true,
+            fall_speed: 0.0,
+            dip: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, scene: &mut Scene, input: ViewModelInput, dt: f32) {
+        // Sway against the direction of the turn, so the gun trails behind the view. Yaw goes down
+        // turning right and camera space X points left, pitch goes up looking down.
+        let look = Vector2::new(input.yaw, input.pitch);
+        let delta = self.last_look.map_or(Vector2::default(), |last| {
+            // Yaw may have wrapped
+            Vector2::new(
+                (look.x - last.x + 180.0).rem_euclid(360.0) - 180.0,
+                look.y - last.y,
+            )
+        });
+        self.last_look = Some(look);
+
+        let target = Vector2::new(
+            (-delta.x * SWAY_SCALE).clamp(-MAX_SWAY, MAX_SWAY),
+            (delta.y * SWAY_SCALE).clamp(-MAX_SWAY, MAX_SWAY),
+        );
+        self.sway += (target - self.sway) * (SWAY_SPEED * dt).min(1.0);
+
+        let horizontal_speed = Vector3::new(input.velocity.x, 0.0, input.velocity.z).norm();
+        let walking = input.on_ground && horizontal_speed > f32::EPSILON;
+        let target_weight = if walking { 1.0 } else { 0.0 };
+        self.bob_weight += (target_weight - self.bob_weight) * (BOB_FADE_SPEED * dt).min(1.0);
+        if walking {
+            self.bob_phase = (self.bob_phase
+                + horizontal_speed * dt * BOB_FREQUENCY * std::f32::consts::TAU)
+                % std::f32::consts::TAU;
+        }
+        // Side to side once per cycle, up and down twice like footsteps
+        let bob = Vector3::new(
+            self.bob_phase.sin() * BOB_SIDE,
+            -(self.bob_phase * 2.0).sin().abs() * BOB_UP,
+            0.0,
+        ) * self.bob_weight;
+
+        if !input.on_ground {
+            self.fall_speed = (-input.velocity.y).max(0.0);
+        } else if !self.was_on_ground {
+            self.dip = (self.fall_speed * LAND_DIP_SCALE).min(MAX_LAND_DIP);
+            self.fall_speed = 0.0;
+        }
+        self.was_on_ground = input.on_ground;
+        self.dip *= (1.0 - LAND_RECOVERY * dt).max(0.0);
+
+        let steadiness = 1.0 - input.aim * (1.0 - AIM_STEADINESS);
+        let offset =
+            (Vector3::new(self.sway.x, self.sway.y, 0.0) + bob + Vector3::new(0.0, -self.dip, 0.0))
+                * steadiness
+                + input.recoil;
+
+        scene.graph[self.model]
+            .local_transform_mut()
+            .set_position(self.rest_position + offset);
+    }
+}
@@ -0,0 +1,187 @@
+// Time it takes before a weapon can be fired after switching to it
+pub const SWITCH_TIME: f32 = 0.3;
+// Share of spread and recoil left while aiming down sights
+const AIM_ACCURACY: f32 = 0.5;
+
+pub enum FireMode {
+    Hitscan,
+    Projectile { speed: f32, splash_radius: f32 },
+}
+
+pub struct WeaponDefinition {
+    pub name: &'static str,
+    pub fire_mode: FireMode,
+    pub fire_interval: f32, // Seconds between shots
+    pub damage: f32,
+    pub max_ammo: u32,
+    pub reload_time: f32,
+    pub knockback: f32, // Impulse applied to players that are hit, along the shot direction
+    // Hitscan damage is full up to falloff_start and drops linearly to min_damage (a fraction of
+    // full damage) at falloff_end. Projectiles use their splash radius instead.
+    pub falloff_start: f32,
+    pub falloff_end: f32,
+    pub min_damage: f32,
+    pub headshot_multiplier: f32,
+    // Shots land somewhere in a cone around the aim, these are its half angle in degrees. It widens
+    // with each shot of a burst and while moving at full speed, up to max_spread.
+    pub spread: f32,
+    pub spread_per_shot: f32,
+    pub move_spread: f32,
+    pub max_spread: f32,
+    // Degrees the aim climbs with each shot of a burst, the last one repeats for long bursts. The
+    // sideways drift, up to recoil_drift degrees a shot, comes from the burst's seed.
+    pub recoil_pattern: &'static [f32],
+    pub recoil_drift: f32,
+}
+
+impl WeaponDefinition {
+    // Before any headshot multiplier
+    pub fn damage_at(&self, distance: f32) -> f32 {
+        if distance <= self.falloff_start || self.falloff_end <= self.falloff_start {
+            return self.damage;
+        }
+
+        let t =
+            ((distance - self.falloff_start) / (self.falloff_end - self.falloff_start)).min(1.0);
+        self.damage * (1.0 - t * (1.0 - self.min_damage))
+    }
+
+    // Half angle of the cone in degrees, movement is the share of full movement speed
+    pub fn spread_at(&self, shot: u32, movement: f32, aiming: bool) -> f32 {
+        let spread = self.spread
+            + self.spread_per_shot * shot as f32
+            + self.move_spread * movement.clamp(0.0, 1.0);
+        spread.min(self.max_spread) * accuracy(aiming)
+    }
+
+    // Degrees up and sideways from the aim that a shot of a burst goes. It only depends on what's
+    // passed in, so the server's shot goes where the shooter saw theirs go.
+    pub fn shot_offset(&self, seed: u32, shot: u32, movement: f32, aiming: bool) -> (f32, f32) {
+        let (mut up, mut side) = (0.0, 0.0);
+        for i in 0..shot {
+            up += self
+                .recoil_pattern
+                .get(i as usize)
+                .or_else(|| self.recoil_pattern.last())
+                .copied()
+                .unwrap_or(0.0);
+            side += self.recoil_drift * noise(seed, i, 0);
+        }
+
+        // Square root spreads shots evenly over the cone instead of bunching them in the middle
+        let radius =
+            self.spread_at(shot, movement, aiming) * ((noise(seed, shot, 1) + 1.0) / 2.0).sqrt();
+        let angle = noise(seed, shot, 2) * std::f32::consts::PI;
+        let scale = accuracy(aiming);
+        (
+            up * scale + radius * angle.sin(),
+            side * scale + radius * angle.cos(),
+        )
+    }
+}
+
+fn accuracy(aiming: bool) -> f32 {
+    if aiming {
+        AIM_ACCURACY
+    } else {
+        1.0
+    }
+}
+
+// Seed for a player's recoil pattern, bursts count the times they've pulled the trigger
+pub fn burst_seed(index: u32, burst: u32) -> u32 {
+    hash(index ^ hash(burst))
+}
+
+// Between -1 and 1, always the same for the same arguments
+fn noise(seed: u32, shot: u32, channel: u32) -> f32 {
+    let value = hash(seed ^ hash(shot.wrapping_mul(3).wrapping_add(channel)));
+    value as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+// Integer hash with good mixing, from Chris Wellons' hash prospector
+fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+// Weapon slots, index in this array is the slot number used by SwitchWeapon
+pub const WEAPONS: [WeaponDefinition; 2] = [
+    WeaponDefinition {
+        name: "Rifle",
+        fire_mode: FireMode::Hitscan,
+        fire_interval: 0.1,
+        damage: 50.0,
+        max_ammo: 20,
+        reload_time: 1.5,
+        knockback: 0.05,
+        falloff_start: 15.0,
+        falloff_end: 40.0,
+        min_damage: 0.5,
+        headshot_multiplier: 2.0,
+        spread: 0.15,
+        spread_per_shot: 0.25,
+        move_spread: 1.5,
+        max_spread: 3.0,
+        recoil_pattern: &[0.3, 0.4, 0.5, 0.5, 0.4, 0.3, 0.2],
+        recoil_drift: 0.25,
+    },
+    WeaponDefinition {
+        name: "Launcher",
+        fire_mode: FireMode::Projectile {
+            speed: 20.0,
+            splash_radius: 2.5,
+        },
+        fire_interval: 0.8,
+        damage: 80.0,
+        max_ammo: 4,
+        reload_time: 2.5,
+        knockback: 0.5,
+        falloff_start: 0.0,
+        falloff_end: 0.0,
+        min_damage: 1.0,
+        headshot_multiplier: 1.0,
+        // Rockets only wander a little, and only on the move
+        spread: 0.0,
+        spread_per_shot: 0.0,
+        move_spread: 0.5,
+        max_spread: 0.5,
+        recoil_pattern: &[],
+        recoil_drift: 0.0,
+    },
+];
+
+pub struct Weapon {
+    slot: usize,
+    pub ammo: u32,
+}
+
+impl Weapon {
+    pub fn new(slot: usize) -> Self {
+        Self {
+            slot,
+            ammo: WEAPONS[slot].max_ammo,
+        }
+    }
+
+    pub fn definition(&self) -> &'static WeaponDefinition {
+        &WEAPONS[self.slot]
+    }
+
+    pub fn refill(&mut self) {
+        self.ammo = self.definition().max_ammo;
+    }
+}
+
+// Every player carries one of each weapon
+pub fn create_loadout() -> Vec<Weapon> {
+    (0..WEAPONS.len()).map(Weapon::new).collect()
+}
+
+pub fn is_valid_slot(slot: u32) -> bool {
+    (slot as usize) < WEAPONS.len()
+}